@@ -1,9 +1,21 @@
+use crate::plot::{self, TangentExplorer, TangentPlot};
+use crate::settings::{is_variable, local_storage, Settings};
 use derivative_calculator::{
+    behavior::{analyze, classify_extrema, critical_points},
+    diagnostic::Severity,
+    eval::{equivalent, with_angle_mode, AngleMode, Equivalence},
+    examples::EXAMPLES,
+    geometry::{curvature, tangent_line},
     lexer::Token,
-    parser::{ExprVisitor, Parser},
-    transformations::{derivative::derivative, prettify::Prettify, simplify::Simplify},
+    limits::Limits,
+    parser::{Expr, ExprVisitor, Parser},
+    pipeline::{run_batch, run_pipeline_with_options, PipelineReport},
+    symbols::{last_word, SymbolRegistry},
+    table::{sample, to_csv, SampleRange},
+    transformations::{custom::parse_rules, derivative::EvaluateDerivatives, RuleTransformSet},
 };
 use logos::Logos;
+use std::ops::RangeInclusive;
 use sycamore::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::{Event, KeyboardEvent};
@@ -12,8 +24,23 @@ use web_sys::{Event, KeyboardEvent};
 enum ItemKind {
     Input,
     ParsedAs,
+    Simplified,
     Derivative,
+    Curvature,
+    /// The tangent line of a function at a point, which is its linear approximation there.
+    Linearization,
+    Check,
     DebugMsg,
+    AstTree,
+    /// The timings of the phases and the rules, as aligned tables.
+    Profile,
+    /// Sampled values in CSV format.
+    Table,
+    /// A report on the behavior of a function, e.g. its extrema, as aligned tables.
+    Analysis,
+    /// A plot with a movable tangent line, see [`plot::item_text`] for the text.
+    Tangent,
+    Warning,
     Error,
 }
 
@@ -27,8 +54,25 @@ fn window() -> web_sys::Window {
     web_sys::window().unwrap()
 }
 
+/// The key of the custom rules in `localStorage`.
+const RULES_STORAGE_KEY: &str = "custom-rules";
+
+#[derive(Prop)]
+struct HeaderProps<'a> {
+    settings: &'a Signal<Settings>,
+    batch_mode: &'a Signal<bool>,
+    rules_open: &'a Signal<bool>,
+    settings_open: &'a Signal<bool>,
+}
+
 #[component]
-fn Header<'a, G: Html>(cx: Scope<'a>, debug_mode: &'a Signal<bool>) -> View<G> {
+fn Header<'a, G: Html>(cx: Scope<'a>, props: HeaderProps<'a>) -> View<G> {
+    let HeaderProps {
+        settings,
+        batch_mode,
+        rules_open,
+        settings_open,
+    } = props;
     view! { cx,
         header {
             "Derivative machine - Source: "
@@ -36,17 +80,331 @@ fn Header<'a, G: Html>(cx: Scope<'a>, debug_mode: &'a Signal<bool>) -> View<G> {
                 "lukechu10/derivative-machine"
             }
 
-            i(
-                class="debug-mode-toggle",
-                on:click=|_| debug_mode.set(!*debug_mode.get()),
-            ) {
-                "Debug mode "
-                (if *debug_mode.get() { "on" } else { "off" })
+            span(class="toggles") {
+                i(
+                    class="toggle",
+                    on:click=|_| settings_open.set(!*settings_open.get()),
+                ) {
+                    "Settings"
+                }
+                i(
+                    class="toggle",
+                    title="Define extra simplification rules",
+                    on:click=|_| rules_open.set(!*rules_open.get()),
+                ) {
+                    "Custom rules"
+                }
+                i(
+                    class="toggle",
+                    title="Differentiate many expressions at once, one per line",
+                    on:click=|_| batch_mode.set(!*batch_mode.get()),
+                ) {
+                    "Batch mode "
+                    (if *batch_mode.get() { "on" } else { "off" })
+                }
+                i(
+                    class="toggle",
+                    title="Also show the simplified expression that is differentiated",
+                    on:click=|_| {
+                        let raw_output = settings.get().raw_output;
+                        settings.modify().raw_output = !raw_output;
+                    },
+                ) {
+                    "Raw output "
+                    (if settings.get().raw_output { "on" } else { "off" })
+                }
+                i(
+                    class="toggle",
+                    on:click=|_| {
+                        let debug_mode = settings.get().debug_mode;
+                        settings.modify().debug_mode = !debug_mode;
+                    },
+                ) {
+                    "Debug mode "
+                    (if settings.get().debug_mode { "on" } else { "off" })
+                }
+            }
+        }
+    }
+}
+
+#[derive(Prop)]
+struct SettingsPanelProps<'a> {
+    settings: &'a Signal<Settings>,
+}
+
+/// A panel for the settings that are not toggled from the header.
+#[component]
+fn SettingsPanel<'a, G: Html>(cx: Scope<'a>, props: SettingsPanelProps<'a>) -> View<G> {
+    let settings = props.settings;
+    let precision = create_signal(
+        cx,
+        settings
+            .get_untracked()
+            .precision
+            .map(|precision| precision.to_string())
+            .unwrap_or_default(),
+    );
+    let variable = create_signal(cx, settings.get_untracked().variable.clone());
+
+    // only valid values are applied, e.g. the variable is not changed while the field is empty
+    create_effect(cx, || {
+        let mut new = settings.get_untracked().as_ref().clone();
+        new.precision = precision.get().trim().parse().ok();
+        let variable = variable.get();
+        if is_variable(variable.trim()) {
+            new.variable = variable.trim().to_string();
+        }
+        if new != *settings.get_untracked() {
+            settings.set(new);
+        }
+    });
+
+    view! { cx,
+        div(class="settings") {
+            p {
+                i(
+                    class="toggle",
+                    title="Rewrite the results in a more readable form",
+                    on:click=|_| {
+                        let prettify = settings.get().prettify;
+                        settings.modify().prettify = !prettify;
+                    },
+                ) {
+                    "Prettify "
+                    (if settings.get().prettify { "on" } else { "off" })
+                }
+            }
+            p {
+                i(
+                    class="toggle",
+                    title="Rewrite ln(x * y) as ln(x) + ln(y), which only holds for positive arguments",
+                    on:click=|_| {
+                        let expand_logarithms = settings.get().expand_logarithms;
+                        settings.modify().expand_logarithms = !expand_logarithms;
+                    },
+                ) {
+                    "Expand logarithms "
+                    (if settings.get().expand_logarithms { "on" } else { "off" })
+                }
+            }
+            p {
+                i(
+                    class="toggle",
+                    title="The unit of the angles of the trigonometric functions in the inputs and commands",
+                    on:click=|_| {
+                        let angle_mode = match settings.get().angle_mode {
+                            AngleMode::Radians => AngleMode::Degrees,
+                            AngleMode::Degrees => AngleMode::Radians,
+                        };
+                        settings.modify().angle_mode = angle_mode;
+                    },
+                ) {
+                    "Angles in "
+                    (match settings.get().angle_mode {
+                        AngleMode::Radians => "radians",
+                        AngleMode::Degrees => "degrees",
+                    })
+                }
+            }
+            p {
+                label {
+                    "Decimal places "
+                    input(type="number", min="0", placeholder="all", bind:value=precision)
+                }
+            }
+            p {
+                label {
+                    "Differentiate with respect to "
+                    input(type="text", bind:value=variable)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Prop)]
+struct RulesEditorProps<'a> {
+    rules: &'a Signal<RuleTransformSet<'static>>,
+}
+
+/// A panel for defining extra simplification rules in the rule document format, e.g. one per line as
+/// `pattern => output`. Valid rules are saved in `localStorage` and used for the rest of the session.
+#[component]
+fn RulesEditor<'a, G: Html>(cx: Scope<'a>, props: RulesEditorProps<'a>) -> View<G> {
+    let rules = props.rules;
+    let saved = local_storage()
+        .and_then(|storage| storage.get_item(RULES_STORAGE_KEY).ok().flatten())
+        .unwrap_or_default();
+    let text = create_signal(cx, saved);
+    let status = create_signal(
+        cx,
+        Item {
+            kind: ItemKind::DebugMsg,
+            text: String::new(),
+        },
+    );
+
+    let save = |_| match parse_rules(&text.get()) {
+        Ok(parsed) => {
+            if let Some(storage) = local_storage() {
+                let _ = storage.set_item(RULES_STORAGE_KEY, &text.get());
+            }
+            status.set(Item {
+                kind: ItemKind::DebugMsg,
+                text: format!("Saved {} rule(s)", parsed.rules().len()),
+            });
+            rules.set(parsed);
+        }
+        Err(err) => status.set(Item {
+            kind: ItemKind::Error,
+            text: err.to_string(),
+        }),
+    };
+
+    view! { cx,
+        div(class="rules-editor") {
+            textarea(
+                placeholder="One rule per line, e.g. _1 * _1 => _1 ^ 2",
+                rows="5",
+                bind:value=text,
+            )
+            button(on:click=save) { "Save rules" }
+            (if status.get().text.is_empty() {
+                View::empty()
+            } else {
+                let item = status.get().as_ref().clone();
+                view! { cx, ItemView(item) }
+            })
+        }
+    }
+}
+
+/// A row of the table in batch mode.
+#[derive(PartialEq, Eq, Clone)]
+struct BatchItem {
+    line: usize,
+    input: String,
+    parsed: String,
+    /// The derivative or the error message.
+    derivative: String,
+    is_error: bool,
+}
+
+#[derive(Prop)]
+struct BatchProps<'a> {
+    settings: &'a Signal<Settings>,
+    rules: &'a Signal<RuleTransformSet<'static>>,
+}
+
+/// A textarea for differentiating many expressions at once and a table of the results.
+#[component]
+fn Batch<'a, G: Html>(cx: Scope<'a>, props: BatchProps<'a>) -> View<G> {
+    let BatchProps { settings, rules } = props;
+    let text = create_signal(cx, String::new());
+    let rows = create_signal(cx, Vec::<BatchItem>::new());
+
+    let run = |_| {
+        let performance = window().performance().unwrap();
+        let text = text.get();
+        let (settings, rules) = (settings.get(), rules.get());
+        let formatter = settings.formatter();
+        let batch = run_batch(&text, &settings.pipeline_options(&rules), || {
+            performance.now()
+        });
+        rows.set(
+            batch
+                .into_iter()
+                .map(|row| {
+                    let display = |expr: &Option<Expr>| {
+                        expr.as_ref()
+                            .map(|expr| formatter.format(expr))
+                            .unwrap_or_default()
+                    };
+                    let (derivative, is_error) = match (&row.report.derivative, &row.report.error) {
+                        (_, Some(err)) => (err.to_string(), true),
+                        (derivative, None) => (display(derivative), false),
+                    };
+                    BatchItem {
+                        line: row.line,
+                        input: row.input.to_string(),
+                        parsed: display(&row.report.prettified),
+                        derivative,
+                        is_error,
+                    }
+                })
+                .collect(),
+        );
+    };
+
+    view! { cx,
+        div(class="batch") {
+            textarea(
+                placeholder="Enter one expression per line",
+                rows="10",
+                bind:value=text,
+            )
+            button(on:click=run) { "Differentiate" }
+            table {
+                tr {
+                    th { "#" }
+                    th { "Input" }
+                    th { "f(x)" }
+                    th { "f'(x)" }
+                }
+                Indexed(
+                    iterable=rows,
+                    view=|cx, row| {
+                        let BatchItem { line, input, parsed, derivative, is_error } = row;
+                        let derivative_class = if is_error { "error" } else { "derivative" };
+                        view! { cx,
+                            tr {
+                                td(class="sub") { (line.to_string()) }
+                                td(class="input") { (input) }
+                                td(class="parsed-as") { (parsed) }
+                                td(class=derivative_class) { (derivative) }
+                            }
+                        }
+                    }
+                )
             }
         }
     }
 }
 
+#[derive(Prop)]
+struct ExamplesProps<'a> {
+    input: &'a Signal<String>,
+}
+
+/// Buttons that fill the input with an example expression.
+#[component]
+fn Examples<'a, G: Html>(cx: Scope<'a>, props: ExamplesProps<'a>) -> View<G> {
+    let input = props.input;
+    let buttons = View::new_fragment(
+        EXAMPLES
+            .iter()
+            .map(|example| {
+                view! { cx,
+                    button(
+                        class="example",
+                        title=example.input,
+                        on:click=move |_| input.set(example.input.to_string()),
+                    ) {
+                        (example.name)
+                    }
+                }
+            })
+            .collect(),
+    );
+    view! { cx,
+        p(class="examples") {
+            i(class="sub") { "Examples: " }
+            (buttons)
+        }
+    }
+}
+
 #[component]
 fn ItemView<G: Html>(cx: Scope, item: Item) -> View<G> {
     match item.kind {
@@ -60,16 +418,69 @@ fn ItemView<G: Html>(cx: Scope, item: Item) -> View<G> {
                 i(class="sub") { "f(x)  = " } (item.text)
             }
         },
+        ItemKind::Simplified => view! { cx,
+            p(class="simplified") {
+                i(class="sub") { "raw   = " } (item.text)
+            }
+        },
         ItemKind::Derivative => view! { cx,
             p(class="derivative") {
                 i(class="sub") { "f'(x) = " } (item.text)
             }
         },
+        ItemKind::Curvature => view! { cx,
+            p(class="curvature") {
+                i(class="sub") { "κ(x)  = " } (item.text)
+            }
+        },
+        ItemKind::Linearization => view! { cx,
+            p(class="linearization") {
+                i(class="sub") { "L(x)  = " } (item.text)
+            }
+        },
+        ItemKind::Check => view! { cx,
+            p(class="check") {
+                i(class="sub") { "check = " } (item.text)
+            }
+        },
         ItemKind::DebugMsg => view! { cx,
             p(class="debug-msg") {
                 i(class="sub") { "[DEBUG]: " } (item.text)
             }
         },
+        ItemKind::AstTree => view! { cx,
+            details(class="ast-tree") {
+                summary(class="sub") { "[DEBUG]: AST" }
+                pre { (item.text) }
+            }
+        },
+        ItemKind::Profile => view! { cx,
+            details(class="profile") {
+                summary(class="sub") { "[DEBUG]: Profile" }
+                pre { (item.text) }
+            }
+        },
+        ItemKind::Table => {
+            let href = format!("data:text/csv;charset=utf-8,{}", percent_encode(&item.text));
+            view! { cx,
+                details(class="table") {
+                    summary(class="sub") {
+                        "CSV "
+                        a(href=href, download="table.csv") { "Download" }
+                    }
+                    pre { (item.text) }
+                }
+            }
+        }
+        ItemKind::Analysis => view! { cx,
+            pre(class="analysis") { (item.text) }
+        },
+        ItemKind::Tangent => view! { cx, TangentExplorer(item.text) },
+        ItemKind::Warning => view! { cx,
+            p(class="warning") {
+                i(class="warning-msg") { "[WARNING]: " (item.text) }
+            }
+        },
         ItemKind::Error => view! { cx,
             p(class="error") {
                 i(class="error-msg") { "[ERROR]: " (item.text) }
@@ -78,117 +489,490 @@ fn ItemView<G: Html>(cx: Scope, item: Item) -> View<G> {
     }
 }
 
-fn add_item(items: &Signal<Vec<Item>>, input: &str, debug_mode: bool) {
+/// The command for checking whether two expressions are equivalent, e.g. `:check 2 * x == x + x`.
+const CHECK_COMMAND: &str = ":check";
+
+/// Parses an expression of a command, e.g. one side of a `:check` command, converts its angles to the angle mode like
+/// the pipeline does and evaluates the derivative requests in it.
+fn parse_command_expr(input: &str, settings: &Settings) -> Result<Expr, String> {
+    let limits = Limits::default();
+    let mut parser = Parser::with_limits(Token::lexer(input).spanned(), limits);
+    let expr = parser.parse_checked().map_err(|err| err.to_string())?;
+    if let Some(diagnostic) = parser
+        .diagnostics()
+        .iter()
+        .find(|diagnostic| diagnostic.severity == Severity::Error)
+    {
+        return Err(format!("`{}`: {}", input.trim(), diagnostic.message));
+    }
+    let mut expr = with_angle_mode(&expr, settings.angle_mode);
+    let mut evaluate = EvaluateDerivatives::new(&limits);
+    evaluate.visit(&mut expr);
+    match evaluate.error {
+        Some(err) => Err(format!("`{}`: {}", input.trim(), err)),
+        None => Ok(expr),
+    }
+}
+
+/// The command for computing the curvature of the graph of a function, e.g. `:curvature x ^ 2`.
+const CURVATURE_COMMAND: &str = ":curvature";
+
+/// The command for classifying the critical points of a function, e.g. `:extrema x ^ 3 - 3 * x`.
+const EXTREMA_COMMAND: &str = ":extrema";
+
+/// The range where the [`EXTREMA_COMMAND`] looks for critical points that cannot be solved for, e.g. of `sin(x)`.
+const EXTREMA_RANGE: RangeInclusive<f64> = -10.0..=10.0;
+
+/// The command for reporting where a function is increasing and convex, e.g. `:analyze -2 2 x ^ 3 - 3 * x`.
+const ANALYZE_COMMAND: &str = ":analyze";
+
+/// The command for plotting a function with a movable tangent line, e.g. `:tangent -2 2 x ^ 3 - 3 * x`.
+const TANGENT_COMMAND: &str = ":tangent";
+
+/// The command for computing the tangent line of a function at a point, e.g. `:linearize 1 x ^ 3`.
+const LINEARIZE_COMMAND: &str = ":linearize";
+
+/// The command for exporting the values of a function and its derivative, e.g. `:table 0 1 0.1 x ^ 2`.
+const TABLE_COMMAND: &str = ":table";
+
+/// Escapes `text` for a `data:` URL.
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Runs a `:table <start> <end> <step> <expr>` command.
+fn table_item(
+    items: &Signal<Vec<Item>>,
+    input: &str,
+    args: &str,
+    settings: &Settings,
+    rules: &RuleTransformSet,
+) {
+    let mut tmp = items.get().as_ref().clone();
+    tmp.push(Item {
+        kind: ItemKind::Input,
+        text: input.to_string(),
+    });
+
+    let usage = || format!("usage: {} <start> <end> <step> <expr>", TABLE_COMMAND);
+    let result = (|| {
+        let mut args = args.trim().splitn(4, char::is_whitespace);
+        let mut number = || -> Result<f64, String> {
+            args.next()
+                .and_then(|arg| arg.parse().ok())
+                .ok_or_else(usage)
+        };
+        let range = SampleRange {
+            start: number()?,
+            end: number()?,
+            step: number()?,
+        };
+        let expr = args.next().ok_or_else(usage)?;
+
+        let performance = window().performance().unwrap();
+        let report = run_pipeline_with_options(expr, &settings.pipeline_options(rules), || {
+            performance.now()
+        });
+        if let Some(err) = &report.error {
+            return Err(err.to_string());
+        }
+        match (&report.simplified, &report.derivative) {
+            (Some(f), Some(derivative)) => {
+                let rows = sample(f, derivative, &settings.variable, &range)
+                    .map_err(|err| err.to_string())?;
+                Ok(to_csv(&rows, &settings.variable))
+            }
+            _ => Err(format!("`{}` could not be differentiated", expr.trim())),
+        }
+    })();
+    tmp.push(match result {
+        Ok(csv) => Item {
+            kind: ItemKind::Table,
+            text: csv,
+        },
+        Err(err) => Item {
+            kind: ItemKind::Error,
+            text: err,
+        },
+    });
+    items.set(tmp);
+}
+
+/// Runs a `:check <expr1> == <expr2>` command.
+fn check_item(items: &Signal<Vec<Item>>, input: &str, args: &str, settings: &Settings) {
+    let mut tmp = items.get().as_ref().clone();
+    tmp.push(Item {
+        kind: ItemKind::Input,
+        text: input.to_string(),
+    });
+
+    let result = match args.split_once("==") {
+        Some((left, right)) => parse_command_expr(left, settings).and_then(|left| {
+            let right = parse_command_expr(right, settings)?;
+            equivalent(&left, &right).map_err(|err| err.to_string())
+        }),
+        None => Err(format!("usage: {} <expr1> == <expr2>", CHECK_COMMAND)),
+    };
+    tmp.push(match result {
+        Ok(Equivalence::Equivalent) => Item {
+            kind: ItemKind::Check,
+            text: "the expressions are equivalent".to_string(),
+        },
+        Ok(Equivalence::Different { point, left, right }) => {
+            let formatter = settings.formatter();
+            let value = |value: f64| formatter.format(&Expr::Literal(value));
+            let point = point
+                .iter()
+                .map(|(ident, x)| format!("{} = {}", ident, value(*x)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let at = if point.is_empty() {
+                String::new()
+            } else {
+                format!(" at {}", point)
+            };
+            Item {
+                kind: ItemKind::Error,
+                text: format!(
+                    "the expressions differ{}: {} != {}",
+                    at,
+                    value(left),
+                    value(right)
+                ),
+            }
+        }
+        Err(err) => Item {
+            kind: ItemKind::Error,
+            text: err,
+        },
+    });
+    items.set(tmp);
+}
+
+/// Runs a `:curvature <expr>` command.
+fn curvature_item(items: &Signal<Vec<Item>>, input: &str, args: &str, settings: &Settings) {
+    let mut tmp = items.get().as_ref().clone();
+    tmp.push(Item {
+        kind: ItemKind::Input,
+        text: input.to_string(),
+    });
+
+    let result = if args.trim().is_empty() {
+        Err(format!("usage: {} <expr>", CURVATURE_COMMAND))
+    } else {
+        parse_command_expr(args, settings).and_then(|expr| {
+            curvature(&expr, &settings.variable, &Limits::default()).map_err(|err| err.to_string())
+        })
+    };
+    tmp.push(match result {
+        Ok(curvature) => Item {
+            kind: ItemKind::Curvature,
+            text: settings.formatter().format(&curvature),
+        },
+        Err(err) => Item {
+            kind: ItemKind::Error,
+            text: err,
+        },
+    });
+    items.set(tmp);
+}
+
+/// Runs an `:extrema <expr>` command.
+fn extrema_item(items: &Signal<Vec<Item>>, input: &str, args: &str, settings: &Settings) {
+    let mut tmp = items.get().as_ref().clone();
+    tmp.push(Item {
+        kind: ItemKind::Input,
+        text: input.to_string(),
+    });
+
+    let result = if args.trim().is_empty() {
+        Err(format!("usage: {} <expr>", EXTREMA_COMMAND))
+    } else {
+        parse_command_expr(args, settings).and_then(|expr| {
+            let limits = Limits::default();
+            critical_points(&expr, &settings.variable, EXTREMA_RANGE, &limits)
+                .and_then(|points| classify_extrema(&expr, &settings.variable, &points, &limits))
+                .map_err(|err| err.to_string())
+        })
+    };
+    tmp.push(match result {
+        Ok(report) if report.points.is_empty() => Item {
+            kind: ItemKind::Check,
+            text: "the function has no critical points".to_string(),
+        },
+        Ok(report) => Item {
+            kind: ItemKind::Analysis,
+            text: format!(
+                "f''(x) = {}\n\n{}",
+                settings.formatter().format(&report.second_derivative),
+                report
+            ),
+        },
+        Err(err) => Item {
+            kind: ItemKind::Error,
+            text: err,
+        },
+    });
+    items.set(tmp);
+}
+
+/// Runs an `:analyze <start> <end> <expr>` command.
+fn analyze_item(items: &Signal<Vec<Item>>, input: &str, args: &str, settings: &Settings) {
+    let mut tmp = items.get().as_ref().clone();
+    tmp.push(Item {
+        kind: ItemKind::Input,
+        text: input.to_string(),
+    });
+
+    let usage = || format!("usage: {} <start> <end> <expr>", ANALYZE_COMMAND);
+    let result = (|| {
+        let mut args = args.trim().splitn(3, char::is_whitespace);
+        let mut number = || -> Result<f64, String> {
+            args.next()
+                .and_then(|arg| arg.parse().ok())
+                .ok_or_else(usage)
+        };
+        let (start, end) = (number()?, number()?);
+        let expr = parse_command_expr(args.next().ok_or_else(usage)?, settings)?;
+        analyze(&expr, &settings.variable, start..=end, &Limits::default())
+            .map_err(|err| err.to_string())
+    })();
+    tmp.push(match result {
+        Ok(report) => {
+            let formatter = settings.formatter();
+            Item {
+                kind: ItemKind::Analysis,
+                text: format!(
+                    "f'(x)  = {}\nf''(x) = {}\n\n{}",
+                    formatter.format(&report.derivative),
+                    formatter.format(&report.second_derivative),
+                    report
+                ),
+            }
+        }
+        Err(err) => Item {
+            kind: ItemKind::Error,
+            text: err,
+        },
+    });
+    items.set(tmp);
+}
+
+/// Runs a `:tangent <start> <end> <expr>` command.
+fn tangent_item(items: &Signal<Vec<Item>>, input: &str, args: &str, settings: &Settings) {
+    let mut tmp = items.get().as_ref().clone();
+    tmp.push(Item {
+        kind: ItemKind::Input,
+        text: input.to_string(),
+    });
+
+    let usage = || format!("usage: {} <start> <end> <expr>", TANGENT_COMMAND);
+    let result = (|| {
+        let mut args = args.trim().splitn(3, char::is_whitespace);
+        let mut number = || -> Result<f64, String> {
+            args.next()
+                .and_then(|arg| arg.parse().ok())
+                .ok_or_else(usage)
+        };
+        let (start, end) = (number()?, number()?);
+        let expr = parse_command_expr(args.next().ok_or_else(usage)?, settings)?;
+        // checked here so that errors are shown like the errors of the other commands
+        TangentPlot::new(&expr, &settings.variable, start, end)?;
+        Ok(plot::item_text(&settings.variable, start, end, &expr))
+    })();
+    tmp.push(match result {
+        Ok(text) => Item {
+            kind: ItemKind::Tangent,
+            text,
+        },
+        Err(err) => Item {
+            kind: ItemKind::Error,
+            text: err,
+        },
+    });
+    items.set(tmp);
+}
+
+/// Runs a `:linearize <a> <expr>` command.
+fn linearize_item(items: &Signal<Vec<Item>>, input: &str, args: &str, settings: &Settings) {
+    let mut tmp = items.get().as_ref().clone();
+    tmp.push(Item {
+        kind: ItemKind::Input,
+        text: input.to_string(),
+    });
+
+    let usage = || format!("usage: {} <a> <expr>", LINEARIZE_COMMAND);
+    let result = (|| {
+        let mut args = args.trim().splitn(2, char::is_whitespace);
+        let a: f64 = args
+            .next()
+            .and_then(|arg| arg.parse().ok())
+            .ok_or_else(usage)?;
+        let expr = parse_command_expr(args.next().ok_or_else(usage)?, settings)?;
+        tangent_line(&expr, &settings.variable, a, &Limits::default())
+            .map_err(|err| err.to_string())
+    })();
+    tmp.push(match result {
+        Ok(line) => Item {
+            kind: ItemKind::Linearization,
+            text: settings.formatter().format(&line),
+        },
+        Err(err) => Item {
+            kind: ItemKind::Error,
+            text: err,
+        },
+    });
+    items.set(tmp);
+}
+
+/// Formats the durations of the phases and the statistics of the rules of `report` for [`ItemKind::Profile`].
+fn profile_text(report: &PipelineReport) -> String {
+    let width = report
+        .phases
+        .iter()
+        .map(|phase| phase.phase.to_string().len())
+        .max()
+        .unwrap_or(0);
+    let mut text = format!("{:width$}  {:>8}  {:>8}", "phase", "nodes", "ms");
+    for phase in &report.phases {
+        text += &format!(
+            "\n{:width$}  {:>8}  {:>8.3}",
+            phase.phase.to_string(),
+            phase.nodes,
+            phase.millis
+        );
+    }
+    text += &format!(
+        "\n{:width$}  {:>8}  {:>8.3}",
+        "total",
+        "",
+        report.total_millis()
+    );
+    if let Some(profile) = &report.rule_profile {
+        text += &format!("\n\n{}", profile);
+    }
+    text
+}
+
+fn add_item(
+    items: &Signal<Vec<Item>>,
+    symbols: &Signal<SymbolRegistry>,
+    settings: &Settings,
+    rules: &RuleTransformSet,
+    input: &str,
+) {
+    let debug_mode = settings.debug_mode;
+    let formatter = settings.formatter();
     let push_item = |item: Item| {
         let mut tmp = items.get().as_ref().clone();
         tmp.push(item);
         items.set(tmp);
     };
 
-    let mut start: f64 = window().performance().unwrap().now();
-    let initial_start = start;
-
     push_item(Item {
         kind: ItemKind::Input,
         text: input.to_string(),
     });
 
-    // compute folded expression and derivative
-    let mut tokens = Token::lexer(input);
-    let mut tokens2 = tokens.clone();
-    if tokens2.next().is_none() {
-        push_item(Item {
-            kind: ItemKind::Error,
-            text: "no input found, skipping".to_string(),
-        });
-        return;
-    }
-
-    let mut parser = Parser::from(&mut tokens);
-    let mut ast = parser.parse();
+    let performance = window().performance().unwrap();
+    let report = run_pipeline_with_options(input, &settings.pipeline_options(rules), || {
+        performance.now()
+    });
+    let push_profile = || {
+        if debug_mode {
+            push_item(Item {
+                kind: ItemKind::Profile,
+                text: profile_text(&report),
+            });
+        }
+    };
 
-    if debug_mode {
-        let now = window().performance().unwrap().now();
-        push_item(Item {
-            kind: ItemKind::DebugMsg,
-            text: format!("Parsed input - took {}ms", now - start),
-        });
-        start = now;
+    if let Some(parsed) = &report.parsed {
+        // suggest the identifiers of previous inputs
+        symbols.modify().add_identifiers(parsed);
+        if debug_mode {
+            push_item(Item {
+                kind: ItemKind::AstTree,
+                text: parsed.to_tree_string(),
+            });
+        }
     }
 
-    if !parser.errors().is_empty() {
-        for item in parser.errors().iter().map(|error| Item {
-            kind: ItemKind::Error,
-            text: error.clone(),
-        }) {
-            push_item(item);
-        }
+    for item in report.diagnostics.iter().map(|diagnostic| Item {
+        kind: match diagnostic.severity {
+            Severity::Error => ItemKind::Error,
+            Severity::Warning => ItemKind::Warning,
+        },
+        text: diagnostic.message.clone(),
+    }) {
+        push_item(item);
     }
 
-    Simplify.visit(&mut ast);
-    if debug_mode {
-        let now = window().performance().unwrap().now();
+    if let Some(prettified) = &report.prettified {
         push_item(Item {
-            kind: ItemKind::DebugMsg,
-            text: format!("Simplify input - took {}ms", now - start),
+            kind: ItemKind::ParsedAs,
+            text: formatter.format(prettified),
         });
-        start = now;
     }
-
-    // do not prettify expr used for derivative
-    let mut ast2 = ast.clone();
-    Prettify.visit(&mut ast2);
-    Simplify.visit(&mut ast2);
-
-    if debug_mode {
-        let now = window().performance().unwrap().now();
+    if let (true, Some(simplified)) = (settings.raw_output, &report.simplified) {
+        // the expression that is actually differentiated
         push_item(Item {
-            kind: ItemKind::DebugMsg,
-            text: format!("Prettify input - took {}ms", now - start),
+            kind: ItemKind::Simplified,
+            text: formatter.format(simplified),
         });
-        start = now;
     }
 
-    push_item(Item {
-        kind: ItemKind::ParsedAs,
-        text: format!("{}", ast2),
-    });
-
-    let mut derivative = derivative(&ast);
-    if debug_mode {
-        let now = window().performance().unwrap().now();
+    if let Some(variable) = report
+        .variable
+        .as_ref()
+        .filter(|variable| **variable != settings.variable)
+    {
         push_item(Item {
-            kind: ItemKind::DebugMsg,
-            text: format!("Compute derivative - took {}ms", now - start),
+            kind: ItemKind::Warning,
+            text: format!(
+                "the input does not contain `{}`, differentiating with respect to `{}` instead",
+                settings.variable, variable
+            ),
         });
-        start = now;
     }
 
-    Simplify.visit(&mut derivative);
-    Prettify.visit(&mut derivative);
-    Simplify.visit(&mut derivative);
-
-    if debug_mode {
-        let now = window().performance().unwrap().now();
+    if let Some(err) = &report.error {
         push_item(Item {
-            kind: ItemKind::DebugMsg,
-            text: format!("Simplify and prettify derivative - took {}ms", now - start),
+            kind: ItemKind::Error,
+            text: err.to_string(),
         });
+        push_profile();
+        return;
     }
 
-    push_item(Item {
-        kind: ItemKind::Derivative,
-        text: format!("{}", derivative),
-    });
-
     if debug_mode {
-        let now = window().performance().unwrap().now();
+        if let Some(stats) = report.derivative_cache {
+            push_item(Item {
+                kind: ItemKind::DebugMsg,
+                text: format!(
+                    "Derivative cache - {} hits, {} misses",
+                    stats.hits, stats.misses
+                ),
+            });
+        }
+    }
+
+    if let Some(derivative) = &report.derivative {
         push_item(Item {
-            kind: ItemKind::DebugMsg,
-            text: format!("Total time elapsed - {}ms", now - initial_start),
+            kind: ItemKind::Derivative,
+            text: formatter.format(derivative),
         });
     }
 
+    push_profile();
+
     window().scroll_to_with_x_and_y(
         0.0,
         web_sys::window()
@@ -207,13 +991,91 @@ pub fn App<G: Html>(cx: Scope) -> View<G> {
 
     let items = create_signal(cx, Vec::<Item>::new());
     let input = create_signal(cx, String::new());
-    let debug_mode = create_signal(cx, false);
+    let settings = create_signal(cx, Settings::load());
+    create_effect(cx, || settings.get().save());
+    let settings_open = create_signal(cx, false);
+    let batch_mode = create_signal(cx, false);
+    let rules_open = create_signal(cx, false);
+    // the rules saved by the `RulesEditor` in a previous session
+    let saved_rules = local_storage()
+        .and_then(|storage| storage.get_item(RULES_STORAGE_KEY).ok().flatten())
+        .unwrap_or_default();
+    let rules = create_signal(
+        cx,
+        parse_rules(&saved_rules).unwrap_or_else(|err| {
+            log::warn!("ignoring saved rules: {}", err);
+            RuleTransformSet::new_from_str(&[], &[])
+        }),
+    );
+    let symbols = create_signal(cx, SymbolRegistry::new());
+
+    let suggestions = create_memo(cx, || {
+        let input = input.get();
+        let prefix = last_word(&input);
+        if prefix.is_empty() {
+            return String::new();
+        }
+        symbols
+            .get()
+            .suggestions(prefix)
+            .iter()
+            .map(|symbol| symbol.name)
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
 
+    let keydown = |ev: Event| {
+        let ev = ev.unchecked_into::<KeyboardEvent>();
+        // the keyup event is too late to keep the focus in the input
+        if ev.code() == "Tab" {
+            ev.prevent_default();
+            let completion = symbols.get().complete(&input.get());
+            if let Some(completion) = completion {
+                input.set(completion);
+            }
+        }
+    };
     let keyup = |ev: Event| {
         let ev = ev.unchecked_into::<KeyboardEvent>();
         if ev.code() == "Enter" {
+            let line = input.get();
+            if let Some(args) = line.trim_start().strip_prefix(CHECK_COMMAND) {
+                check_item(items, &line, args, &settings.get());
+                input.set(String::new());
+                return;
+            }
+            if let Some(args) = line.trim_start().strip_prefix(CURVATURE_COMMAND) {
+                curvature_item(items, &line, args, &settings.get());
+                input.set(String::new());
+                return;
+            }
+            if let Some(args) = line.trim_start().strip_prefix(EXTREMA_COMMAND) {
+                extrema_item(items, &line, args, &settings.get());
+                input.set(String::new());
+                return;
+            }
+            if let Some(args) = line.trim_start().strip_prefix(ANALYZE_COMMAND) {
+                analyze_item(items, &line, args, &settings.get());
+                input.set(String::new());
+                return;
+            }
+            if let Some(args) = line.trim_start().strip_prefix(TANGENT_COMMAND) {
+                tangent_item(items, &line, args, &settings.get());
+                input.set(String::new());
+                return;
+            }
+            if let Some(args) = line.trim_start().strip_prefix(LINEARIZE_COMMAND) {
+                linearize_item(items, &line, args, &settings.get());
+                input.set(String::new());
+                return;
+            }
+            if let Some(args) = line.trim_start().strip_prefix(TABLE_COMMAND) {
+                table_item(items, &line, args, &settings.get(), &rules.get());
+                input.set(String::new());
+                return;
+            }
             // Add new item
-            add_item(items, &input.get(), *debug_mode.get());
+            add_item(items, symbols, &settings.get(), &rules.get(), &input.get());
             // Reset input
             input.set(String::new());
         }
@@ -221,19 +1083,58 @@ pub fn App<G: Html>(cx: Scope) -> View<G> {
 
     view! { cx,
         div {
-            Header(debug_mode)
-            div(class="output-area") {
-                Indexed(
-                    iterable=items,
-                    view=|cx, item| view! { cx, ItemView(item) }
+            Header(
+                settings=settings,
+                batch_mode=batch_mode,
+                rules_open=rules_open,
+                settings_open=settings_open,
+            )
+            (if *settings_open.get() {
+                view! { cx, SettingsPanel(settings=settings) }
+            } else {
+                View::empty()
+            })
+            (if *rules_open.get() {
+                view! { cx, RulesEditor(rules=rules) }
+            } else {
+                View::empty()
+            })
+            (if *batch_mode.get() {
+                view! { cx, Batch(settings=settings, rules=rules) }
+            } else {
+                View::empty()
+            })
+            div(style=if *batch_mode.get() { "display: none" } else { "" }) {
+                div(class="output-area") {
+                    Examples(input=input)
+                    Indexed(
+                        iterable=items,
+                        view=move |cx, item| if item.kind == ItemKind::Input {
+                            // clicking a previous input copies it back into the input box to edit it
+                            let text = item.text.clone();
+                            view! { cx,
+                                div(
+                                    class="editable",
+                                    title="Click to edit",
+                                    on:click=move |_| input.set(text.clone()),
+                                ) {
+                                    ItemView(item)
+                                }
+                            }
+                        } else {
+                            view! { cx, ItemView(item) }
+                        }
+                    )
+                }
+                p(class="suggestions") { (suggestions.get()) }
+                input(
+                    type="text",
+                    placeholder="Enter expression here, e.g. 2 * x ^ 2",
+                    bind:value=input,
+                    on:keydown=keydown,
+                    on:keyup=keyup,
                 )
             }
-            input(
-                type="text",
-                placeholder="Enter expression here, e.g. 2 * x ^ 2",
-                bind:value=input,
-                on:keyup=keyup,
-            )
         }
     }
 }
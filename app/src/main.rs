@@ -1,4 +1,6 @@
 mod app;
+mod plot;
+mod settings;
 
 fn main() {
     console_error_panic_hook::set_once();
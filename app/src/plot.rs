@@ -0,0 +1,203 @@
+//! A plot of a function with a tangent line that follows a slider.
+
+use derivative_calculator::{
+    eval::compiled::CompiledExpr,
+    format::ExprFormatter,
+    geometry::TangentLine,
+    lexer::Token,
+    limits::Limits,
+    parser::{Expr, ExprVisitor, Parser},
+    transformations::{derivative::try_partial_derivative, simplify::Simplify},
+};
+use logos::Logos;
+use sycamore::prelude::*;
+
+/// The size of the plot in SVG units.
+const WIDTH: f64 = 400.0;
+const HEIGHT: f64 = 250.0;
+
+/// The number of intervals of the plotted curve and of the slider.
+const SAMPLES: usize = 200;
+
+/// A function and its derivative compiled for plotting over `[start, end]`.
+pub struct TangentPlot {
+    variable: String,
+    start: f64,
+    end: f64,
+    f: CompiledExpr,
+    derivative: CompiledExpr,
+    /// The values of the function at the bottom and the top of the plot.
+    y_range: (f64, f64),
+}
+
+impl TangentPlot {
+    /// Differentiates `expr` and compiles it and its derivative as functions of `variable`.
+    pub fn new(expr: &Expr, variable: &str, start: f64, end: f64) -> Result<Self, String> {
+        if !start.is_finite() || !end.is_finite() || start >= end {
+            return Err(
+                "the range must be finite and the start must be less than the end".to_string(),
+            );
+        }
+        let mut derivative = try_partial_derivative(expr, variable, &Limits::default())
+            .map_err(|err| err.to_string())?;
+        Simplify.visit(&mut derivative);
+        let compile =
+            |expr: &Expr| CompiledExpr::new(expr, variable).map_err(|err| err.to_string());
+        let f = compile(expr)?;
+        let derivative = compile(&derivative)?;
+
+        let (min, max) = (0..=SAMPLES)
+            .map(|i| f.eval(x_at(start, end, i)))
+            .filter(|y| y.is_finite())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), y| {
+                (min.min(y), max.max(y))
+            });
+        let y_range = if min > max {
+            (-1.0, 1.0)
+        } else if min == max {
+            (min - 1.0, max + 1.0)
+        } else {
+            let padding = (max - min) * 0.1;
+            (min - padding, max + padding)
+        };
+        Ok(Self {
+            variable: variable.to_string(),
+            start,
+            end,
+            f,
+            derivative,
+            y_range,
+        })
+    }
+
+    /// Parses the text of an item created by [`item_text`].
+    fn from_item_text(text: &str) -> Result<Self, String> {
+        let mut args = text.splitn(4, ' ');
+        let (variable, start, end, expr) =
+            match (args.next(), args.next(), args.next(), args.next()) {
+                (Some(variable), Some(start), Some(end), Some(expr)) => {
+                    (variable, start, end, expr)
+                }
+                _ => return Err("invalid plot".to_string()),
+            };
+        let number = |arg: &str| arg.parse().map_err(|_| "invalid plot".to_string());
+        let expr = Parser::from(Token::lexer(expr).spanned()).parse();
+        Self::new(&expr, variable, number(start)?, number(end)?)
+    }
+
+    /// Converts a point of the graph to SVG coordinates.
+    fn to_svg(&self, x: f64, y: f64) -> (f64, f64) {
+        let (bottom, top) = self.y_range;
+        (
+            (x - self.start) / (self.end - self.start) * WIDTH,
+            (top - y) / (top - bottom) * HEIGHT,
+        )
+    }
+
+    /// Returns the path of the curve, which is interrupted where the function is not finite.
+    fn curve_path(&self) -> String {
+        let mut path = String::new();
+        let mut pen_down = false;
+        for i in 0..=SAMPLES {
+            let x = x_at(self.start, self.end, i);
+            let y = self.f.eval(x);
+            if !y.is_finite() {
+                pen_down = false;
+                continue;
+            }
+            let (px, py) = self.to_svg(x, y);
+            path += &format!("{}{:.2} {:.2} ", if pen_down { "L" } else { "M" }, px, py);
+            pen_down = true;
+        }
+        path
+    }
+
+    /// Returns the path of `tangent` across the whole plot, or nothing if it is not finite.
+    fn line_path(&self, tangent: &TangentLine) -> String {
+        let (y1, y2) = (tangent.eval(self.start), tangent.eval(self.end));
+        if !y1.is_finite() || !y2.is_finite() {
+            return String::new();
+        }
+        let (x1, y1) = self.to_svg(self.start, y1);
+        let (x2, y2) = self.to_svg(self.end, y2);
+        format!("M{:.2} {:.2} L{:.2} {:.2}", x1, y1, x2, y2)
+    }
+
+    /// Returns the path of a dot at the point of tangency, or nothing if the function is not finite there.
+    fn point_path(&self, tangent: &TangentLine) -> String {
+        if !tangent.value.is_finite() {
+            return String::new();
+        }
+        let (x, y) = self.to_svg(tangent.a, tangent.value);
+        // two half circles, since a single arc cannot end where it starts
+        format!("M{:.2} {:.2} a4 4 0 1 0 8 0 a4 4 0 1 0 -8 0", x - 4.0, y)
+    }
+}
+
+/// Returns the `i`-th of the `SAMPLES + 1` evenly spaced values from `start` to `end`.
+fn x_at(start: f64, end: f64, i: usize) -> f64 {
+    start + (end - start) * i as f64 / SAMPLES as f64
+}
+
+/// Returns the text of an item that plots `expr` as a function of `variable` over `[start, end]` with
+/// [`TangentExplorer`]. `expr` should have been checked with [`TangentPlot::new`].
+pub fn item_text(variable: &str, start: f64, end: f64, expr: &Expr) -> String {
+    format!("{} {} {} {}", variable, start, end, expr)
+}
+
+/// Plots the function of an item created with [`item_text`] along with its tangent line at the point chosen with a
+/// slider. The values at the point are evaluated with the compiled function and derivative as the slider moves.
+#[component]
+pub fn TangentExplorer<G: Html>(cx: Scope, text: String) -> View<G> {
+    let plot = match TangentPlot::from_item_text(&text) {
+        Ok(plot) => create_ref(cx, plot),
+        Err(err) => {
+            return view! { cx,
+                p(class="error") {
+                    i(class="error-msg") { "[ERROR]: " (err) }
+                }
+            }
+        }
+    };
+    let curve = plot.curve_path();
+    let step = ((plot.end - plot.start) / SAMPLES as f64).to_string();
+    let position = create_signal(cx, ((plot.start + plot.end) / 2.0).to_string());
+    let tangent = create_memo(cx, || {
+        let a = position
+            .get()
+            .parse()
+            .unwrap_or((plot.start + plot.end) / 2.0);
+        TangentLine::new(&plot.f, &plot.derivative, a)
+    });
+    let formatter = ExprFormatter::default().with_precision(Some(4));
+    let info = create_memo(cx, move || {
+        let tangent = *tangent.get();
+        let value = |num: f64| formatter.format(&Expr::Literal(num));
+        format!(
+            "{} = {}, f({0}) = {}, f'({0}) = {}, tangent: y = {}",
+            plot.variable,
+            value(tangent.a),
+            value(tangent.value),
+            value(tangent.slope),
+            formatter.format(&tangent.to_expr(&plot.variable))
+        )
+    });
+
+    view! { cx,
+        div(class="tangent") {
+            svg(class="plot", viewBox=format!("0 0 {} {}", WIDTH, HEIGHT)) {
+                path(class="curve", d=curve.clone())
+                path(class="tangent-line", d=plot.line_path(&tangent.get()))
+                path(class="tangent-point", d=plot.point_path(&tangent.get()))
+            }
+            input(
+                type="range",
+                min=plot.start.to_string(),
+                max=plot.end.to_string(),
+                step=step.clone(),
+                bind:value=position,
+            )
+            p(class="sub") { (info.get().as_ref().clone()) }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use derivative_calculator::{
+    eval::AngleMode,
+    format::ExprFormatter,
+    pipeline::PipelineOptions,
+    transformations::{derivative::pick_variable, RuleTransformSet},
+};
+
+/// The prefix of the keys of the settings in `localStorage`.
+const STORAGE_PREFIX: &str = "settings.";
+
+pub fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+/// The user settings, persisted in `localStorage` between sessions.
+#[derive(PartialEq, Eq, Clone)]
+pub struct Settings {
+    pub debug_mode: bool,
+    /// Also show the simplified expression that is differentiated.
+    pub raw_output: bool,
+    pub prettify: bool,
+    /// Expand the logarithms of products, quotients and powers, which assumes that their arguments are positive.
+    pub expand_logarithms: bool,
+    /// The max number of decimal places of the numbers that are displayed, `None` for all of them.
+    pub precision: Option<usize>,
+    /// The variable that inputs are differentiated with respect to.
+    pub variable: String,
+    /// The unit of the angles of the trigonometric functions of the inputs and the commands, e.g. in plots.
+    pub angle_mode: AngleMode,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            debug_mode: false,
+            raw_output: false,
+            prettify: true,
+            expand_logarithms: false,
+            precision: None,
+            variable: "x".to_string(),
+            angle_mode: AngleMode::Radians,
+        }
+    }
+}
+
+impl Settings {
+    /// Loads the settings saved by [`Settings::save`]. Missing or invalid settings keep their default value.
+    pub fn load() -> Self {
+        let mut settings = Self::default();
+        let storage = match local_storage() {
+            Some(storage) => storage,
+            None => return settings,
+        };
+        let get = |key: &str| {
+            storage
+                .get_item(&format!("{}{}", STORAGE_PREFIX, key))
+                .ok()
+                .flatten()
+        };
+
+        if let Some(debug_mode) = get("debug-mode").and_then(|value| value.parse().ok()) {
+            settings.debug_mode = debug_mode;
+        }
+        if let Some(raw_output) = get("raw-output").and_then(|value| value.parse().ok()) {
+            settings.raw_output = raw_output;
+        }
+        if let Some(prettify) = get("prettify").and_then(|value| value.parse().ok()) {
+            settings.prettify = prettify;
+        }
+        if let Some(expand_logarithms) =
+            get("expand-logarithms").and_then(|value| value.parse().ok())
+        {
+            settings.expand_logarithms = expand_logarithms;
+        }
+        settings.precision = get("precision").and_then(|value| value.parse().ok());
+        if let Some(variable) = get("variable").filter(|value| is_variable(value)) {
+            settings.variable = variable;
+        }
+        match get("angle-mode").as_deref() {
+            Some("radians") => settings.angle_mode = AngleMode::Radians,
+            Some("degrees") => settings.angle_mode = AngleMode::Degrees,
+            _ => {}
+        }
+        settings
+    }
+
+    pub fn save(&self) {
+        let storage = match local_storage() {
+            Some(storage) => storage,
+            None => return,
+        };
+        let set = |key: &str, value: String| {
+            let _ = storage.set_item(&format!("{}{}", STORAGE_PREFIX, key), &value);
+        };
+
+        set("debug-mode", self.debug_mode.to_string());
+        set("raw-output", self.raw_output.to_string());
+        set("prettify", self.prettify.to_string());
+        set("expand-logarithms", self.expand_logarithms.to_string());
+        set(
+            "precision",
+            self.precision.map(|p| p.to_string()).unwrap_or_default(),
+        );
+        set("variable", self.variable.clone());
+        set(
+            "angle-mode",
+            match self.angle_mode {
+                AngleMode::Radians => "radians",
+                AngleMode::Degrees => "degrees",
+            }
+            .to_string(),
+        );
+    }
+
+    /// Returns the options of the library pipeline for these settings. Inputs that do not contain the variable but a
+    /// single other identifier are differentiated with respect to it, e.g. `t ^ 2`.
+    pub fn pipeline_options<'a>(&self, rules: &'a RuleTransformSet<'a>) -> PipelineOptions<'a> {
+        PipelineOptions::default()
+            .with_rules(rules)
+            .with_prettify(self.prettify)
+            .with_expand_logarithms(self.expand_logarithms)
+            .with_angle_mode(self.angle_mode)
+            .with_variable(self.variable.as_str())
+            .with_variable_picker(Some(pick_variable))
+            .with_derivative_cache(true)
+            .with_rule_profile(self.debug_mode.then_some(performance_now as fn() -> f64))
+    }
+
+    /// Returns the formatter used to display the expressions.
+    pub fn formatter(&self) -> ExprFormatter {
+        ExprFormatter::default().with_precision(self.precision)
+    }
+}
+
+/// Returns the current time in milliseconds, for timing the rules in debug mode.
+fn performance_now() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map_or(0.0, |performance| performance.now())
+}
+
+/// Returns `true` if `name` can be parsed as a single identifier.
+pub fn is_variable(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic())
+}
@@ -0,0 +1,668 @@
+//! Structural analysis of expressions, e.g. whether an expression is a polynomial in a variable.
+
+use crate::parser::{BinOpKind, Expr, ExprPath, ExprVisitor, UnaryOpKind};
+use crate::rule::Tolerance;
+use crate::transformations::simplify::Simplify;
+use std::collections::BTreeSet;
+
+/// The kind of function an expression is in a variable, from the most to the least specific.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExprClass {
+    /// Does not depend on the variable, e.g. `2 * a`.
+    Constant,
+    /// A polynomial of degree 1, e.g. `2 * x + 1`.
+    Linear,
+    /// A polynomial of degree 2 or more, e.g. `x ^ 2 + 1`.
+    Polynomial,
+    /// A quotient of polynomials, e.g. `1 / (x + 1)`.
+    Rational,
+    /// Anything else, e.g. `2 ^ x` or `abs(x)`. Algebraic functions such as `x ^ 0.5` are included as well.
+    Transcendental,
+}
+
+/// The class of a sub-expression while classifying.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Kind {
+    Polynomial { degree: u32 },
+    Rational,
+    Transcendental,
+}
+
+impl Kind {
+    const CONSTANT: Kind = Kind::Polynomial { degree: 0 };
+
+    /// Combines the kinds of the operands of a sum or a product, with `degree` combining the degrees of polynomials.
+    fn combine(self, other: Kind, degree: fn(u32, u32) -> u32) -> Kind {
+        match (self, other) {
+            (Kind::Polynomial { degree: d1 }, Kind::Polynomial { degree: d2 }) => {
+                Kind::Polynomial {
+                    degree: degree(d1, d2),
+                }
+            }
+            (Kind::Transcendental, _) | (_, Kind::Transcendental) => Kind::Transcendental,
+            _ => Kind::Rational,
+        }
+    }
+}
+
+/// Returns the class of `expr` as a function of `var`. The classification is structural, e.g. `x ^ 2 / x` is
+/// [`ExprClass::Rational`] even though it is equal to `x`. Run [`crate::transformations::simplify::Simplify`] first
+/// for a more precise result.
+pub fn classify(expr: &Expr, var: &str) -> ExprClass {
+    match kind(expr, var) {
+        Kind::Polynomial { degree: 0 } => ExprClass::Constant,
+        Kind::Polynomial { degree: 1 } => ExprClass::Linear,
+        Kind::Polynomial { .. } => ExprClass::Polynomial,
+        Kind::Rational => ExprClass::Rational,
+        Kind::Transcendental => ExprClass::Transcendental,
+    }
+}
+
+/// A pending step when classifying an expression.
+enum Task<'a> {
+    /// Classify the expression and push the kind onto the kind stack.
+    Classify(&'a Expr),
+    /// Pop the kinds of the operands of the expression off the kind stack and combine them.
+    Combine(&'a Expr),
+}
+
+fn kind(expr: &Expr, var: &str) -> Kind {
+    let mut tasks = vec![Task::Classify(expr)];
+    let mut kinds: Vec<Kind> = Vec::new();
+
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Classify(expr) => match expr {
+                Expr::Literal(_) | Expr::Constant(_) => kinds.push(Kind::CONSTANT),
+                Expr::Identifier(ident) if ident == var => {
+                    kinds.push(Kind::Polynomial { degree: 1 })
+                }
+                Expr::Identifier(_) => kinds.push(Kind::CONSTANT),
+                Expr::Binary { left, op: _, right } => {
+                    tasks.push(Task::Combine(expr));
+                    tasks.push(Task::Classify(right));
+                    tasks.push(Task::Classify(left));
+                }
+                Expr::Unary { op: _, right } => tasks.push(Task::Classify(right)),
+                Expr::Call { func: _, args } => {
+                    tasks.push(Task::Combine(expr));
+                    tasks.extend(args.iter().rev().map(Task::Classify));
+                }
+                // integrals are only treated as constant, e.g. `int(t ^ 2, t, 0, 1)`
+                Expr::Integral { .. } if !expr.identifiers().contains(&var) => {
+                    kinds.push(Kind::CONSTANT)
+                }
+                Expr::Integral { .. } | Expr::Vector(_) | Expr::Undefined | Expr::Error => {
+                    kinds.push(Kind::Transcendental)
+                }
+            },
+            Task::Combine(expr) => {
+                let kind = match expr {
+                    Expr::Binary { left: _, op, right } => {
+                        let right_kind = kinds.pop().expect("right operand should be classified");
+                        let left_kind = kinds.pop().expect("left operand should be classified");
+                        match op {
+                            BinOpKind::Plus | BinOpKind::Minus => {
+                                left_kind.combine(right_kind, u32::max)
+                            }
+                            BinOpKind::Asterisk => {
+                                left_kind.combine(right_kind, u32::saturating_add)
+                            }
+                            BinOpKind::Slash => match (left_kind, right_kind) {
+                                (_, Kind::CONSTANT) => left_kind,
+                                (Kind::Transcendental, _) | (_, Kind::Transcendental) => {
+                                    Kind::Transcendental
+                                }
+                                _ => Kind::Rational,
+                            },
+                            BinOpKind::Exponent => power(left_kind, right, right_kind),
+                        }
+                    }
+                    Expr::Call { func: _, args } => {
+                        let args = kinds.split_off(kinds.len() - args.len());
+                        if args.iter().all(|kind| *kind == Kind::CONSTANT) {
+                            Kind::CONSTANT
+                        } else {
+                            Kind::Transcendental
+                        }
+                    }
+                    _ => unreachable!("only binary operations and calls are combined"),
+                };
+                kinds.push(kind);
+            }
+        }
+    }
+
+    kinds
+        .pop()
+        .expect("kind of the input should be on the kind stack")
+}
+
+/// Returns the kind of `base ^ exponent`.
+fn power(base: Kind, exponent: &Expr, exponent_kind: Kind) -> Kind {
+    match (base, exponent_kind) {
+        (Kind::CONSTANT, Kind::CONSTANT) => Kind::CONSTANT,
+        (_, Kind::CONSTANT) => match exponent {
+            Expr::Literal(num) if num.fract() == 0.0 => match base {
+                Kind::Polynomial { degree } if *num >= 0.0 => Kind::Polynomial {
+                    degree: degree.saturating_mul(num.min(u32::MAX as f64) as u32),
+                },
+                Kind::Polynomial { .. } | Kind::Rational => Kind::Rational,
+                Kind::Transcendental => Kind::Transcendental,
+            },
+            // e.g. x ^ 0.5 or x ^ a
+            _ => Kind::Transcendental,
+        },
+        // e.g. 2 ^ x
+        _ => Kind::Transcendental,
+    }
+}
+
+/// The max degree of a polynomial that is expanded by [`polynomial_coefficients`].
+const MAX_DEGREE: usize = 1_000;
+
+/// Returns the coefficients of `expr` as a polynomial in `var`, starting with the constant term. The coefficients may
+/// contain other identifiers, e.g. `a * x ^ 2 + 1` has the coefficients `[1, 0, a]`. Returns `None` if `expr` is not a
+/// polynomial in `var`.
+///
+/// Products and powers are expanded and every coefficient is simplified. Trailing zero coefficients are removed so the
+/// last coefficient is the leading one, except for the zero polynomial which is `[0]`.
+pub fn polynomial_coefficients(expr: &Expr, var: &str) -> Option<Vec<Expr>> {
+    let mut tasks = vec![Task::Classify(expr)];
+    let mut polys: Vec<Vec<Expr>> = Vec::new();
+
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Classify(expr) => match expr {
+                Expr::Identifier(ident) if ident == var => {
+                    polys.push(vec![Expr::Literal(0.0), Expr::Literal(1.0)])
+                }
+                Expr::Literal(_) | Expr::Identifier(_) | Expr::Constant(_) => {
+                    polys.push(vec![expr.clone()])
+                }
+                Expr::Binary { left, op: _, right } => {
+                    tasks.push(Task::Combine(expr));
+                    tasks.push(Task::Classify(right));
+                    tasks.push(Task::Classify(left));
+                }
+                Expr::Unary { op: _, right } => {
+                    tasks.push(Task::Combine(expr));
+                    tasks.push(Task::Classify(right));
+                }
+                // a vector is not a number, even if it is constant
+                Expr::Vector(_) => return None,
+                Expr::Call { func: _, args: _ }
+                | Expr::Integral { .. }
+                | Expr::Undefined
+                | Expr::Error => {
+                    if expr.identifiers().contains(&var) {
+                        return None;
+                    }
+                    polys.push(vec![expr.clone()]);
+                }
+            },
+            Task::Combine(expr) => {
+                let poly = match expr {
+                    Expr::Binary { left: _, op, right } => {
+                        let right_poly = polys.pop().expect("right operand should be expanded");
+                        let left_poly = polys.pop().expect("left operand should be expanded");
+                        match op {
+                            BinOpKind::Plus => add(left_poly, right_poly, BinOpKind::Plus),
+                            BinOpKind::Minus => add(left_poly, right_poly, BinOpKind::Minus),
+                            BinOpKind::Asterisk => mul(&left_poly, &right_poly)?,
+                            BinOpKind::Slash => match right_poly.as_slice() {
+                                [divisor] if !is_zero(divisor) => left_poly
+                                    .into_iter()
+                                    .map(|coef| binary(coef, BinOpKind::Slash, divisor.clone()))
+                                    .collect(),
+                                _ => return None,
+                            },
+                            BinOpKind::Exponent => match **right {
+                                Expr::Literal(num) if num.fract() == 0.0 && num >= 0.0 => {
+                                    if (left_poly.len() - 1) as f64 * num > MAX_DEGREE as f64 {
+                                        return None;
+                                    }
+                                    let mut res = vec![Expr::Literal(1.0)];
+                                    for _ in 0..num as usize {
+                                        res = mul(&res, &left_poly)?;
+                                    }
+                                    res
+                                }
+                                _ if right_poly.len() == 1 && left_poly.len() == 1 => {
+                                    vec![expr.clone()]
+                                }
+                                _ => return None,
+                            },
+                        }
+                    }
+                    Expr::Unary {
+                        op: UnaryOpKind::Minus,
+                        right: _,
+                    } => polys
+                        .pop()
+                        .expect("operand should be expanded")
+                        .into_iter()
+                        .map(|coef| Expr::Unary {
+                            op: UnaryOpKind::Minus,
+                            right: Box::new(coef),
+                        })
+                        .collect(),
+                    _ => unreachable!("only binary and unary operations are combined"),
+                };
+                polys.push(normalize(poly));
+            }
+        }
+    }
+
+    Some(polys.pop().expect("input should be expanded"))
+}
+
+/// Returns the coefficient of `var ^ power` in `expr`, or `None` if `expr` is not a polynomial in `var`.
+pub fn coefficient(expr: &Expr, var: &str, power: usize) -> Option<Expr> {
+    let coefs = polynomial_coefficients(expr, var)?;
+    Some(coefs.get(power).cloned().unwrap_or(Expr::Literal(0.0)))
+}
+
+/// Returns the term of `expr` with the highest power of `var`, e.g. `3 * (x ^ 2)` for `3 * x ^ 2 + x`, or `None` if
+/// `expr` is not a polynomial in `var`.
+pub fn leading_term(expr: &Expr, var: &str) -> Option<Expr> {
+    let mut coefs = polynomial_coefficients(expr, var)?;
+    let degree = coefs.len() - 1;
+    let coef = coefs
+        .pop()
+        .expect("polynomial should have at least 1 coefficient");
+    let mut term = match degree {
+        0 => coef,
+        1 => binary(coef, BinOpKind::Asterisk, Expr::Identifier(var.to_string())),
+        _ => binary(
+            coef,
+            BinOpKind::Asterisk,
+            binary(
+                Expr::Identifier(var.to_string()),
+                BinOpKind::Exponent,
+                Expr::Literal(degree as f64),
+            ),
+        ),
+    };
+    Simplify.visit(&mut term);
+    Some(term)
+}
+
+/// Facts about the identifiers that hold wherever the expression is used, e.g. that `x` is a length and so is never
+/// negative. See [`crate::transformations::simplify::SimplifyAssuming`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Assumptions {
+    nonnegative: BTreeSet<String>,
+    positive: BTreeSet<String>,
+}
+
+impl Assumptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Assumes that `ident` is greater than or equal to `0`.
+    pub fn with_nonnegative(mut self, ident: impl Into<String>) -> Self {
+        self.nonnegative.insert(ident.into());
+        self
+    }
+
+    /// Assumes that `ident` is greater than `0`.
+    pub fn with_positive(mut self, ident: impl Into<String>) -> Self {
+        self.positive.insert(ident.into());
+        self
+    }
+
+    /// Returns whether `expr` is known to be greater than or equal to `0` (or undefined) under the assumptions, e.g.
+    /// `abs(x) + y ^ 2`. The check is structural, so `false` means that the sign is unknown rather than negative.
+    pub fn is_nonnegative(&self, expr: &Expr) -> bool {
+        // the sub-expressions that must all be nonnegative
+        let mut stack = vec![expr];
+        while let Some(expr) = stack.pop() {
+            match expr {
+                Expr::Literal(num) if *num >= 0.0 => {}
+                Expr::Identifier(ident)
+                    if self.nonnegative.contains(ident) || self.positive.contains(ident) => {}
+                // all the built-in constants are positive
+                Expr::Constant(_) => {}
+                Expr::Binary {
+                    left: _,
+                    op: BinOpKind::Exponent,
+                    right,
+                } if matches!(**right, Expr::Literal(num) if is_even(num)) => {}
+                Expr::Binary {
+                    left,
+                    op: BinOpKind::Exponent,
+                    right: _,
+                } => stack.push(left),
+                Expr::Binary {
+                    left,
+                    op: BinOpKind::Plus | BinOpKind::Asterisk | BinOpKind::Slash,
+                    right,
+                } => stack.extend([left.as_ref(), right.as_ref()]),
+                Expr::Call { func, args } if func == "abs" && args.len() == 1 => {}
+                Expr::Call { func, args } if func == "sign" && args.len() == 1 => {
+                    stack.push(&args[0])
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Returns whether `expr` is known to be greater than `0` (or undefined) under the assumptions, e.g.
+    /// `exp(x) * (y ^ 2 + 1)`. Like [`Assumptions::is_nonnegative`], `false` means that the sign is unknown.
+    pub fn is_positive(&self, expr: &Expr) -> bool {
+        // the sub-expressions that must all be positive
+        let mut stack = vec![expr];
+        while let Some(expr) = stack.pop() {
+            match expr {
+                Expr::Literal(num) if *num > 0.0 => {}
+                Expr::Identifier(ident) if self.positive.contains(ident) => {}
+                // all the built-in constants are positive
+                Expr::Constant(_) => {}
+                Expr::Call { func, args } if func == "exp" && args.len() == 1 => {}
+                Expr::Binary {
+                    left,
+                    op: BinOpKind::Exponent,
+                    right: _,
+                } => stack.push(left),
+                Expr::Binary {
+                    left,
+                    op: BinOpKind::Asterisk | BinOpKind::Slash,
+                    right,
+                } => stack.extend([left.as_ref(), right.as_ref()]),
+                // a sum of nonnegative terms is positive if one of the terms is, e.g. `x ^ 2 + 1`
+                Expr::Binary {
+                    left: _,
+                    op: BinOpKind::Plus,
+                    right: _,
+                } if self.is_nonnegative(expr) && self.has_positive_term(expr) => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Returns whether one of the terms of the sum `expr` is a positive atom, e.g. `1` in `x ^ 2 + 1`.
+    fn has_positive_term(&self, expr: &Expr) -> bool {
+        let mut terms = vec![expr];
+        while let Some(term) = terms.pop() {
+            match term {
+                Expr::Binary {
+                    left,
+                    op: BinOpKind::Plus,
+                    right,
+                } => terms.extend([left.as_ref(), right.as_ref()]),
+                Expr::Literal(num) if *num > 0.0 => return true,
+                Expr::Identifier(ident) if self.positive.contains(ident) => return true,
+                Expr::Constant(_) => return true,
+                _ => {}
+            }
+        }
+        false
+    }
+}
+
+/// Returns whether `num` is an even integer.
+pub(crate) fn is_even(num: f64) -> bool {
+    Tolerance::EXACT.is_integer(num / 2.0)
+}
+
+/// A sub-expression that was replaced, see [`diff_exprs`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// The location of the sub-expression, which is the same in both expressions.
+    pub path: ExprPath,
+    pub before: Expr,
+    pub after: Expr,
+}
+
+/// Returns the smallest sub-expressions that differ between `before` and `after`, e.g. to highlight what a rewrite
+/// changed. Nodes with the same operator (or function) and number of children are compared child by child, any other
+/// difference replaces the whole sub-expression. The changes are in the order of the nodes in the expressions.
+pub fn diff_exprs(before: &Expr, after: &Expr) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut stack = vec![(before, after, ExprPath::new())];
+    while let Some((before, after, path)) = stack.pop() {
+        if before == after {
+            continue;
+        }
+        let same_node = match (before, after) {
+            (
+                Expr::Binary {
+                    left: _,
+                    op: op1,
+                    right: _,
+                },
+                Expr::Binary {
+                    left: _,
+                    op: op2,
+                    right: _,
+                },
+            ) => op1 == op2,
+            (Expr::Unary { op: op1, right: _ }, Expr::Unary { op: op2, right: _ }) => op1 == op2,
+            (
+                Expr::Call {
+                    func: func1,
+                    args: args1,
+                },
+                Expr::Call {
+                    func: func2,
+                    args: args2,
+                },
+            ) => func1 == func2 && args1.len() == args2.len(),
+            // different atoms or kinds of nodes
+            _ => false,
+        };
+        if same_node {
+            // pushed in reverse so that the changes are in order
+            for (i, (before, after)) in before
+                .children()
+                .into_iter()
+                .zip(after.children())
+                .enumerate()
+                .rev()
+            {
+                let mut path = path.clone();
+                path.push(i);
+                stack.push((before, after, path));
+            }
+        } else {
+            changes.push(Change {
+                path,
+                before: before.clone(),
+                after: after.clone(),
+            });
+        }
+    }
+    changes
+}
+
+fn binary(left: Expr, op: BinOpKind, right: Expr) -> Expr {
+    Expr::Binary {
+        left: Box::new(left),
+        op,
+        right: Box::new(right),
+    }
+}
+
+fn is_zero(expr: &Expr) -> bool {
+    matches!(expr, Expr::Literal(num) if *num == 0.0)
+}
+
+/// Adds or subtracts the coefficients of two polynomials.
+fn add(left: Vec<Expr>, right: Vec<Expr>, op: BinOpKind) -> Vec<Expr> {
+    let len = left.len().max(right.len());
+    let mut left = left.into_iter();
+    let mut right = right.into_iter();
+    (0..len)
+        .map(|_| {
+            binary(
+                left.next().unwrap_or(Expr::Literal(0.0)),
+                op,
+                right.next().unwrap_or(Expr::Literal(0.0)),
+            )
+        })
+        .collect()
+}
+
+/// Multiplies two polynomials.
+fn mul(left: &[Expr], right: &[Expr]) -> Option<Vec<Expr>> {
+    if left.len() + right.len() - 2 > MAX_DEGREE {
+        return None;
+    }
+    let mut res: Vec<Option<Expr>> = vec![None; left.len() + right.len() - 1];
+    for (i, left_coef) in left.iter().enumerate() {
+        for (j, right_coef) in right.iter().enumerate() {
+            let term = binary(left_coef.clone(), BinOpKind::Asterisk, right_coef.clone());
+            res[i + j] = Some(match res[i + j].take() {
+                Some(sum) => binary(sum, BinOpKind::Plus, term),
+                None => term,
+            });
+        }
+    }
+    Some(res.into_iter().map(Option::unwrap).collect())
+}
+
+/// Simplifies the coefficients and removes the trailing zero coefficients.
+fn normalize(mut poly: Vec<Expr>) -> Vec<Expr> {
+    for coef in &mut poly {
+        Simplify.visit(coef);
+    }
+    while poly.len() > 1 && poly.last().is_some_and(is_zero) {
+        poly.pop();
+    }
+    poly
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+
+    #[test]
+    fn test_classify() {
+        let cases = [
+            ("2 * a + 1", ExprClass::Constant),
+            ("abs(a) ^ 0.5", ExprClass::Constant),
+            ("2 * x + a", ExprClass::Linear),
+            ("-(x / 2)", ExprClass::Linear),
+            ("x ^ 2 + 1", ExprClass::Polynomial),
+            ("(x + 1) * (x - 1)", ExprClass::Polynomial),
+            ("1 / (x + 1)", ExprClass::Rational),
+            ("x ^ -2", ExprClass::Rational),
+            ("(1 / x) ^ 2 + x", ExprClass::Rational),
+            ("2 ^ x", ExprClass::Transcendental),
+            ("x ^ 0.5", ExprClass::Transcendental),
+            ("x ^ a", ExprClass::Transcendental),
+            ("abs(x) / x", ExprClass::Transcendental),
+        ];
+        for (input, expected) in cases {
+            let expr = Parser::from(Token::lexer(input).spanned()).parse();
+            assert_eq!(classify(&expr, "x"), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_polynomial_coefficients() {
+        let coefficients = |input: &str| {
+            let expr = Parser::from(Token::lexer(input).spanned()).parse();
+            polynomial_coefficients(&expr, "x").map(|coefs| {
+                coefs
+                    .iter()
+                    .map(Expr::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+        };
+        assert_eq!(
+            coefficients("3 * x ^ 2 + x - 5").as_deref(),
+            Some("(-5), 1, 3")
+        );
+        assert_eq!(coefficients("(x + 1) ^ 2").as_deref(), Some("1, 2, 1"));
+        assert_eq!(
+            coefficients("(a * x + b) / 2").as_deref(),
+            Some("(b / 2), (a / 2)")
+        );
+        assert_eq!(coefficients("x ^ 2 - x * x").as_deref(), Some("0"));
+        assert_eq!(coefficients("abs(a) * x").as_deref(), Some("0, abs(a)"));
+        assert_eq!(coefficients("1 / x"), None);
+        assert_eq!(coefficients("abs(x)"), None);
+        assert_eq!(coefficients("x ^ 0.5"), None);
+    }
+
+    #[test]
+    fn test_leading_term_and_coefficient() {
+        let expr = Parser::from(Token::lexer("3 * x ^ 2 + a * x - 5").spanned()).parse();
+        assert_eq!(
+            leading_term(&expr, "x").unwrap().to_string(),
+            "(3 * (x ^ 2))"
+        );
+        assert_eq!(coefficient(&expr, "x", 1).unwrap().to_string(), "a");
+        assert_eq!(coefficient(&expr, "x", 0).unwrap().to_string(), "(-5)");
+        assert_eq!(coefficient(&expr, "x", 7).unwrap().to_string(), "0");
+        assert_eq!(leading_term(&expr, "a").unwrap().to_string(), "(x * a)");
+
+        let expr = Parser::from(Token::lexer("2 ^ x").spanned()).parse();
+        assert_eq!(leading_term(&expr, "x"), None);
+    }
+
+    #[test]
+    fn test_is_nonnegative() {
+        let assumptions = Assumptions::new().with_nonnegative("a");
+        let is_nonnegative = |input: &str| {
+            let expr = Parser::from(Token::lexer(input).spanned()).parse();
+            assumptions.is_nonnegative(&expr)
+        };
+        assert!(is_nonnegative("abs(x) + y ^ 2"));
+        assert!(is_nonnegative("a * 2 / (a + 1)"));
+        assert!(is_nonnegative("a ^ x + sign(a)"));
+        assert!(!is_nonnegative("x"));
+        assert!(!is_nonnegative("a - 1"));
+        assert!(!is_nonnegative("x ^ 3"));
+        assert!(!is_nonnegative("-a"));
+    }
+
+    #[test]
+    fn test_is_positive() {
+        let assumptions = Assumptions::new().with_nonnegative("a").with_positive("b");
+        let is_positive = |input: &str| {
+            let expr = Parser::from(Token::lexer(input).spanned()).parse();
+            assumptions.is_positive(&expr)
+        };
+        assert!(is_positive("exp(x) * (y ^ 2 + 1)"));
+        assert!(is_positive("b / (a + pi) ^ x"));
+        assert!(is_positive("a + abs(x) + b"));
+        assert!(!is_positive("a"));
+        assert!(!is_positive("a + abs(x)"));
+        assert!(!is_positive("0"));
+        assert!(!is_positive("b - 1"));
+        assert!(assumptions.is_nonnegative(&Expr::Identifier("b".to_string())));
+    }
+
+    #[test]
+    fn test_diff_exprs() {
+        let diff = |before: &str, after: &str| {
+            let before = Parser::from(Token::lexer(before).spanned()).parse();
+            let after = Parser::from(Token::lexer(after).spanned()).parse();
+            diff_exprs(&before, &after)
+                .into_iter()
+                .map(|change| format!("{:?}: {} -> {}", change.path, change.before, change.after))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(diff("2 * x + 1", "2 * x + 1"), Vec::<String>::new());
+        assert_eq!(diff("2 * x + 0", "2 * x"), ["[]: ((2 * x) + 0) -> (2 * x)"]);
+        assert_eq!(
+            diff("(x + 0) * (y * 1)", "x * y"),
+            ["[0]: (x + 0) -> x", "[1]: (y * 1) -> y"]
+        );
+        assert_eq!(
+            diff("abs(-(-x)) + 2 ^ 3", "abs(x) + 8"),
+            ["[0, 0]: (-(-x)) -> x", "[1]: (2 ^ 3) -> 8"]
+        );
+        assert_eq!(diff("x - y", "x + y"), ["[]: (x - y) -> (x + y)"]);
+    }
+}
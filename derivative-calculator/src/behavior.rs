@@ -0,0 +1,566 @@
+//! The behavior of a function of one variable, e.g. where it has a minimum or a maximum.
+
+use crate::analysis::{classify, ExprClass};
+use crate::equation::{solve, Equation, SolveError};
+use crate::eval::compiled::CompiledExpr;
+use crate::eval::roots::{find_roots, find_sign_changes, DEFAULT_SAMPLES};
+use crate::eval::{eval, EvalError};
+use crate::limits::Limits;
+use crate::parser::{Expr, ExprVisitor};
+use crate::transformations::derivative::{try_partial_derivative, DerivativeError};
+use crate::transformations::simplify::Simplify;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Derivatives closer to 0 than this are treated as 0, since critical points computed from roots are rarely exact.
+const ZERO_TOLERANCE: f64 = 1e-9;
+
+/// An error that occurred while analyzing the behavior of a function.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BehaviorError {
+    /// A derivative could not be computed.
+    Derivative(DerivativeError),
+    /// The function or a derivative could not be compiled, e.g. because it contains an identifier other than the
+    /// variable.
+    Eval(EvalError),
+    /// The points where a derivative is 0 could not be found.
+    Solve(SolveError),
+}
+
+impl fmt::Display for BehaviorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BehaviorError::Derivative(err) => write!(f, "{}", err),
+            BehaviorError::Eval(err) => write!(f, "{}", err),
+            BehaviorError::Solve(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for BehaviorError {}
+
+impl From<DerivativeError> for BehaviorError {
+    fn from(err: DerivativeError) -> Self {
+        BehaviorError::Derivative(err)
+    }
+}
+
+impl From<EvalError> for BehaviorError {
+    fn from(err: EvalError) -> Self {
+        BehaviorError::Eval(err)
+    }
+}
+
+impl From<SolveError> for BehaviorError {
+    fn from(err: SolveError) -> Self {
+        BehaviorError::Solve(err)
+    }
+}
+
+/// The result of the second derivative test at a critical point.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExtremumKind {
+    /// `f'' > 0`, the function has a local minimum.
+    Minimum,
+    /// `f'' < 0`, the function has a local maximum.
+    Maximum,
+    /// `f''` is 0 or undefined, e.g. for `x ^ 3` and `x ^ 4` at `0`.
+    Inconclusive,
+}
+
+impl fmt::Display for ExtremumKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtremumKind::Minimum => write!(f, "minimum"),
+            ExtremumKind::Maximum => write!(f, "maximum"),
+            ExtremumKind::Inconclusive => write!(f, "inconclusive"),
+        }
+    }
+}
+
+/// A critical point classified by [`classify_extrema`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CriticalPoint {
+    pub x: f64,
+    /// The value of the function at `x`.
+    pub y: f64,
+    /// The value of the second derivative at `x`.
+    pub second_derivative: f64,
+    pub kind: ExtremumKind,
+}
+
+/// The second derivative of a function and its classified critical points.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtremaReport {
+    /// The simplified second derivative.
+    pub second_derivative: Expr,
+    pub points: Vec<CriticalPoint>,
+}
+
+/// Writes the critical points as a table with aligned columns, one point per line.
+impl fmt::Display for ExtremaReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>12}  {:>12}  {:>12}  kind", "x", "f(x)", "f''(x)")?;
+        for point in &self.points {
+            write!(
+                f,
+                "\n{:>12.6}  {:>12.6}  {:>12.6}  {}",
+                point.x, point.y, point.second_derivative, point.kind
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Classifies the critical points `points` of `expr` as a function of `var` with the second derivative test. The
+/// points are not checked, see [`critical_points`] to find them.
+pub fn classify_extrema(
+    expr: &Expr,
+    var: &str,
+    points: &[f64],
+    limits: &Limits,
+) -> Result<ExtremaReport, BehaviorError> {
+    let first = simplified_derivative(expr, var, limits)?;
+    let second_derivative = simplified_derivative(&first, var, limits)?;
+    let (f, second) = (
+        CompiledExpr::new(expr, var)?,
+        CompiledExpr::new(&second_derivative, var)?,
+    );
+    let points = points
+        .iter()
+        .map(|&x| {
+            let value = second.eval(x);
+            let kind = if value > ZERO_TOLERANCE {
+                ExtremumKind::Minimum
+            } else if value < -ZERO_TOLERANCE {
+                ExtremumKind::Maximum
+            } else {
+                ExtremumKind::Inconclusive
+            };
+            CriticalPoint {
+                x,
+                y: f.eval(x),
+                second_derivative: value,
+                kind,
+            }
+        })
+        .collect();
+    Ok(ExtremaReport {
+        second_derivative,
+        points,
+    })
+}
+
+/// Returns the points in `range` where the derivative of `expr` as a function of `var` is 0 in ascending order, e.g.
+/// `-1` and `1` for `x ^ 3 - 3 * x`. The equation `f' = 0` is solved symbolically if possible, see [`solve`].
+/// Otherwise, e.g. for `x ^ 3 - 3 * x ^ 2` or `sin(x)`, or if a solution does not evaluate to a real number, e.g.
+/// `(-2) ^ (1 / 3)`, the zeros of the compiled derivative are located numerically with [`find_roots`]. Solutions
+/// where the derivative is not 0 are left out, e.g. `1` for `x ^ 0.5 + 1 = 0`.
+///
+/// There are no critical points if the derivative does not depend on the variable, even if it is 0.
+pub fn critical_points(
+    expr: &Expr,
+    var: &str,
+    range: RangeInclusive<f64>,
+    limits: &Limits,
+) -> Result<Vec<f64>, BehaviorError> {
+    let derivative = simplified_derivative(expr, var, limits)?;
+    let equation = Equation {
+        lhs: derivative.clone(),
+        rhs: Expr::Literal(0.0),
+    };
+    let solutions = match solve(&equation, var) {
+        Ok(solutions) => real_values(&solutions),
+        Err(SolveError::NoVariable(_)) => return Ok(Vec::new()),
+        Err(_) => None,
+    };
+    let compiled = CompiledExpr::new(&derivative, var)?;
+    Ok(match solutions {
+        Some(solutions) => verified_zeros(solutions, &compiled, range),
+        None => {
+            let mut points = find_roots(|x| compiled.eval(x), range, DEFAULT_SAMPLES);
+            points.sort_by(f64::total_cmp);
+            points.dedup();
+            points
+        }
+    })
+}
+
+/// Returns the `solutions` in `range` where the compiled function is 0 in ascending order. Solving an equation can
+/// introduce solutions that are not, e.g. squaring both sides of `x ^ 0.5 = -1` gives `x = 1`.
+fn verified_zeros(
+    solutions: Vec<f64>,
+    compiled: &CompiledExpr,
+    range: RangeInclusive<f64>,
+) -> Vec<f64> {
+    let mut zeros: Vec<f64> = solutions
+        .into_iter()
+        .filter(|x| range.contains(x) && compiled.eval(*x).abs() <= ZERO_TOLERANCE)
+        .collect();
+    zeros.sort_by(f64::total_cmp);
+    zeros.dedup();
+    zeros
+}
+
+/// A point where the graph of a function changes from convex to concave or the other way around.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct InflectionPoint {
+    pub x: f64,
+    /// The value of the function at `x`.
+    pub y: f64,
+}
+
+/// Returns the inflection points of `expr` as a function of `var` in `range` in ascending order, e.g. `(0, 0)` for
+/// `x ^ 3`.
+///
+/// The zeros of the second derivative are solved for symbolically if it is a polynomial and located numerically with
+/// [`find_sign_changes`] otherwise. A zero is only an inflection point if the second derivative changes sign there,
+/// e.g. `0` is not one for `x ^ 4`.
+pub fn inflection_points(
+    expr: &Expr,
+    var: &str,
+    range: RangeInclusive<f64>,
+    limits: &Limits,
+) -> Result<Vec<InflectionPoint>, BehaviorError> {
+    let first = simplified_derivative(expr, var, limits)?;
+    let second_derivative = simplified_derivative(&first, var, limits)?;
+    let (f, second) = (
+        CompiledExpr::new(expr, var)?,
+        CompiledExpr::new(&second_derivative, var)?,
+    );
+
+    let candidates = zeros(&second_derivative, &second, var, range);
+    Ok(candidates
+        .into_iter()
+        .filter(|&x| {
+            let h = 1e-4 * x.abs().max(1.0);
+            second.eval(x - h).signum() * second.eval(x + h).signum() < 0.0
+        })
+        .map(|x| InflectionPoint { x, y: f.eval(x) })
+        // a pole where the second derivative changes sign, e.g. of `1 / x`
+        .filter(|point| point.y.is_finite())
+        .collect())
+}
+
+/// Whether a function is positive or negative on an interval.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Sign {
+    Positive,
+    Negative,
+}
+
+/// An interval where a derivative has the same sign.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SignInterval {
+    pub start: f64,
+    pub end: f64,
+    pub sign: Sign,
+}
+
+/// Where a function is increasing or decreasing and convex or concave, see [`analyze`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BehaviorReport {
+    /// The simplified derivative.
+    pub derivative: Expr,
+    /// The simplified second derivative.
+    pub second_derivative: Expr,
+    /// The intervals where the function is increasing (`f' > 0`) or decreasing (`f' < 0`).
+    pub monotonicity: Vec<SignInterval>,
+    /// The intervals where the function is convex (`f'' > 0`) or concave (`f'' < 0`).
+    pub concavity: Vec<SignInterval>,
+}
+
+/// Writes the intervals, one per line, e.g. `increasing on [0, 1]`.
+impl fmt::Display for BehaviorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines = self
+            .monotonicity
+            .iter()
+            .map(|interval| (interval, ("increasing", "decreasing")))
+            .chain(
+                self.concavity
+                    .iter()
+                    .map(|interval| (interval, ("convex", "concave"))),
+            );
+        for (i, (interval, (positive, negative))) in lines.enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let name = match interval.sign {
+                Sign::Positive => positive,
+                Sign::Negative => negative,
+            };
+            write!(f, "{:<10} on [{}, {}]", name, interval.start, interval.end)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the intervals of `range` where `expr` as a function of `var` is increasing or decreasing and convex or
+/// concave, e.g. increasing on `[0, 2]` and convex on `[-2, 2]` for `x ^ 2` in `-2..=2`.
+///
+/// The range is split at the zeros of the derivatives, which are found like in [`inflection_points`], and at their
+/// poles, although a pole where the sign does not change is only found if it is one of the sampled values. Parts where
+/// a derivative is 0 or undefined, e.g. `x < 0` for `x ^ 0.5`, are left out.
+pub fn analyze(
+    expr: &Expr,
+    var: &str,
+    range: RangeInclusive<f64>,
+    limits: &Limits,
+) -> Result<BehaviorReport, BehaviorError> {
+    let derivative = simplified_derivative(expr, var, limits)?;
+    let second_derivative = simplified_derivative(&derivative, var, limits)?;
+    let monotonicity = sign_intervals(&derivative, var, range.clone())?;
+    let concavity = sign_intervals(&second_derivative, var, range)?;
+    Ok(BehaviorReport {
+        derivative,
+        second_derivative,
+        monotonicity,
+        concavity,
+    })
+}
+
+/// Splits `range` into the intervals where `expr` as a function of `var` has the same sign.
+fn sign_intervals(
+    expr: &Expr,
+    var: &str,
+    range: RangeInclusive<f64>,
+) -> Result<Vec<SignInterval>, BehaviorError> {
+    let compiled = CompiledExpr::new(expr, var)?;
+    let (start, end) = (*range.start(), *range.end());
+    let mut bounds = vec![start];
+    bounds.extend(
+        zeros(expr, &compiled, var, range)
+            .into_iter()
+            .filter(|&x| x > start && x < end),
+    );
+    bounds.push(end);
+
+    let mut intervals: Vec<SignInterval> = Vec::new();
+    for bound in bounds.windows(2) {
+        let (a, b) = (bound[0], bound[1]);
+        let value = compiled.eval(a + (b - a) / 2.0);
+        let sign = if !value.is_finite() || value == 0.0 {
+            continue;
+        } else if value > 0.0 {
+            Sign::Positive
+        } else {
+            Sign::Negative
+        };
+        match intervals.last_mut() {
+            // a zero where the sign does not change, e.g. of `x ^ 2`, but not a pole
+            Some(last) if last.end == a && last.sign == sign && compiled.eval(a).is_finite() => {
+                last.end = b
+            }
+            _ => intervals.push(SignInterval {
+                start: a,
+                end: b,
+                sign,
+            }),
+        }
+    }
+    Ok(intervals)
+}
+
+/// Returns the zeros of `expr` as a function of `var` in `range` in ascending order. Polynomials are solved for
+/// symbolically, other functions are compiled to `compiled` and their sign changes, including poles, are located
+/// numerically.
+fn zeros(expr: &Expr, compiled: &CompiledExpr, var: &str, range: RangeInclusive<f64>) -> Vec<f64> {
+    let numeric = |range: RangeInclusive<f64>| {
+        find_sign_changes(|x| compiled.eval(x), range, DEFAULT_SAMPLES)
+    };
+    match classify(expr, var) {
+        ExprClass::Constant => Vec::new(),
+        ExprClass::Linear | ExprClass::Polynomial => {
+            let equation = Equation {
+                lhs: expr.clone(),
+                rhs: Expr::Literal(0.0),
+            };
+            match solve(&equation, var)
+                .ok()
+                .and_then(|solutions| real_values(&solutions))
+            {
+                Some(solutions) => verified_zeros(solutions, compiled, range),
+                None => numeric(range),
+            }
+        }
+        ExprClass::Rational | ExprClass::Transcendental => numeric(range),
+    }
+}
+
+/// Returns the values of the `solutions` of an equation, or `None` if one of them is not a finite real number, e.g.
+/// `(-2) ^ (1 / 3)`, in which case the equation should be solved numerically instead.
+fn real_values(solutions: &[Expr]) -> Option<Vec<f64>> {
+    solutions
+        .iter()
+        .map(|solution| {
+            eval(solution, &HashMap::new())
+                .ok()
+                .filter(|x| x.is_finite())
+        })
+        .collect()
+}
+
+/// Computes the derivative of `expr` with respect to `var` and simplifies it.
+fn simplified_derivative(expr: &Expr, var: &str, limits: &Limits) -> Result<Expr, DerivativeError> {
+    let mut res = try_partial_derivative(expr, var, limits)?;
+    Simplify.visit(&mut res);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+
+    fn parse(input: &str) -> Expr {
+        Parser::from(Token::lexer(input).spanned()).parse()
+    }
+
+    #[test]
+    fn test_classify_extrema() {
+        let expr = parse("x ^ 3 - 3 * x");
+        let points = critical_points(&expr, "x", -4.0..=4.0, &Limits::default()).unwrap();
+        assert_eq!(points, [-1.0, 1.0]);
+        let report = classify_extrema(&expr, "x", &points, &Limits::default()).unwrap();
+        assert_eq!(report.second_derivative.to_string(), "(6 * x)");
+        let kinds: Vec<_> = report.points.iter().map(|point| point.kind).collect();
+        assert_eq!(kinds, [ExtremumKind::Maximum, ExtremumKind::Minimum]);
+        assert_eq!(report.points[0].y, 2.0);
+        assert_eq!(report.points[1].y, -2.0);
+
+        let report = classify_extrema(&parse("x ^ 4"), "x", &[0.0], &Limits::default()).unwrap();
+        assert_eq!(report.points[0].kind, ExtremumKind::Inconclusive);
+        assert_eq!(report.to_string().lines().count(), 2);
+    }
+
+    #[test]
+    fn test_critical_points() {
+        let critical_points = |input: &str, var: &str| {
+            critical_points(&parse(input), var, -4.0..=4.0, &Limits::default())
+        };
+        assert_eq!(critical_points("(x - 2) ^ 2", "x"), Ok(vec![2.0]));
+        // `3 * x ^ 2 + 3 = 0` has no real solutions
+        assert_eq!(critical_points("x ^ 3 + 3 * x", "x"), Ok(vec![]));
+        assert_eq!(critical_points("x ^ 2 + 1", "t"), Ok(vec![]));
+        assert_eq!(critical_points("(x - 5) ^ 2", "x"), Ok(vec![]));
+        assert!(matches!(
+            critical_points("x ^ 3 - 3 * x ^ 2 * y", "x"),
+            Err(BehaviorError::Eval(_))
+        ));
+
+        // located numerically since `3 * x ^ 2 - 6 * x = 0` and `cos(x) = 0` cannot be solved for `x`
+        let assert_close = |points: Vec<f64>, expected: &[f64]| {
+            assert_eq!(
+                points.len(),
+                expected.len(),
+                "{:?} != {:?}",
+                points,
+                expected
+            );
+            for (point, expected) in points.iter().zip(expected) {
+                assert!((point - expected).abs() < 1e-9, "{} != {}", point, expected);
+            }
+        };
+        assert_close(
+            critical_points("x ^ 3 - 3 * x ^ 2", "x").unwrap(),
+            &[0.0, 2.0],
+        );
+        // the solution `(-2) ^ (1 / 3)` is not a real number
+        assert_close(
+            critical_points("x ^ 4 / 4 + 2 * x", "x").unwrap(),
+            &[-(2.0f64.cbrt())],
+        );
+        // solutions of the equation that are not critical points
+        assert_eq!(critical_points("2 / 3 * x ^ 1.5 + x", "x"), Ok(vec![]));
+        assert_eq!(critical_points("int(abs(t) + 1, t, 0, x)", "x"), Ok(vec![]));
+        let half_pi = std::f64::consts::FRAC_PI_2;
+        assert_close(
+            critical_points("sin(x)", "x").unwrap(),
+            &[-half_pi, half_pi],
+        );
+    }
+
+    #[test]
+    fn test_inflection_points() {
+        let inflection_points =
+            |input: &str| inflection_points(&parse(input), "x", -4.0..=4.0, &Limits::default());
+        assert_eq!(
+            inflection_points("x ^ 3 - 3 * x ^ 2"),
+            Ok(vec![InflectionPoint { x: 1.0, y: -2.0 }])
+        );
+        // the second derivative is 0 at 0 but does not change sign
+        assert_eq!(inflection_points("x ^ 4"), Ok(vec![]));
+        assert_eq!(inflection_points("x ^ 2 + 1"), Ok(vec![]));
+
+        // located numerically at `x = ±1 / sqrt(3)` since the second derivative is rational
+        let points = inflection_points("1 / (x ^ 2 + 1)").unwrap();
+        assert_eq!(points.len(), 2);
+        let x = 1.0 / 3.0_f64.sqrt();
+        for (point, expected) in points.iter().zip([-x, x]) {
+            assert!(
+                (point.x - expected).abs() < 1e-9,
+                "{} != {}",
+                point.x,
+                expected
+            );
+            assert!((point.y - 0.75).abs() < 1e-9);
+        }
+        assert_eq!(inflection_points("1 / x"), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_analyze() {
+        let analyze = |input: &str| analyze(&parse(input), "x", -2.0..=2.0, &Limits::default());
+        let interval = |start, end, sign| SignInterval { start, end, sign };
+
+        let report = analyze("x ^ 3 - 3 * x").unwrap();
+        assert_eq!(
+            report.monotonicity,
+            [
+                interval(-2.0, -1.0, Sign::Positive),
+                interval(-1.0, 1.0, Sign::Negative),
+                interval(1.0, 2.0, Sign::Positive),
+            ]
+        );
+        assert_eq!(
+            report.concavity,
+            [
+                interval(-2.0, 0.0, Sign::Negative),
+                interval(0.0, 2.0, Sign::Positive),
+            ]
+        );
+        assert_eq!(
+            report.to_string().lines().next(),
+            Some("increasing on [-2, -1]")
+        );
+
+        // the derivative is 0 at 0 without changing sign
+        let report = analyze("x ^ 3").unwrap();
+        assert_eq!(report.monotonicity, [interval(-2.0, 2.0, Sign::Positive)]);
+
+        // split at the pole
+        let report = analyze("1 / x").unwrap();
+        assert_eq!(
+            report.monotonicity,
+            [
+                interval(-2.0, 0.0, Sign::Negative),
+                interval(0.0, 2.0, Sign::Negative),
+            ]
+        );
+        assert_eq!(
+            report.concavity,
+            [
+                interval(-2.0, 0.0, Sign::Negative),
+                interval(0.0, 2.0, Sign::Positive),
+            ]
+        );
+
+        let report = analyze("3 * x + 1").unwrap();
+        assert_eq!(report.monotonicity, [interval(-2.0, 2.0, Sign::Positive)]);
+        assert_eq!(report.concavity, []);
+    }
+}
@@ -0,0 +1,47 @@
+//! Built-in constants that can be used in expressions, e.g. `pi`.
+
+use std::fmt;
+
+/// A built-in constant. Constants are kept symbolic by the transformations, e.g. `2 * pi` is not folded, and are
+/// replaced by their value when evaluating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Constant {
+    /// The ratio of the circumference of a circle to its diameter, written `pi` or `π`.
+    Pi,
+    /// Euler's number, the base of the natural logarithm, written `e`.
+    E,
+}
+
+impl Constant {
+    /// All the built-in constants.
+    pub const ALL: [Constant; 2] = [Constant::Pi, Constant::E];
+
+    /// Returns the name of the constant, e.g. `pi`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Constant::Pi => "pi",
+            Constant::E => "e",
+        }
+    }
+
+    /// Returns the value of the constant as the nearest `f64`.
+    pub fn value(self) -> f64 {
+        match self {
+            Constant::Pi => std::f64::consts::PI,
+            Constant::E => std::f64::consts::E,
+        }
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Returns the built-in constant called `name`.
+pub fn lookup(name: &str) -> Option<Constant> {
+    Constant::ALL
+        .into_iter()
+        .find(|constant| constant.name() == name)
+}
@@ -0,0 +1,263 @@
+//! Errors and warnings reported while processing an input.
+
+use std::fmt;
+
+/// A range of byte offsets into the source string.
+pub type Span = std::ops::Range<usize>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Severity::Error => "ERROR",
+                Severity::Warning => "WARNING",
+            }
+        )
+    }
+}
+
+/// Identifies the kind of a [`Diagnostic`] independently of its message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DiagnosticCode {
+    /// The lexer found a character sequence that is not a valid token.
+    InvalidToken,
+    /// The lexer found a sequence of digits and dots that is not a valid number, e.g. `1.2.3`.
+    MalformedNumber,
+    /// The parser found a token where it was not expected.
+    UnexpectedToken,
+    /// The input exceeded one of the [`crate::limits::Limits`].
+    ExprTooLarge,
+    /// The output of a rewrite rule uses a wildcard that does not appear in its pattern.
+    UnboundWildcard,
+    /// A computed expression in the output of a rewrite rule, e.g. `{_lit1 + 1}`, uses a wildcard that does not match a
+    /// literal, so it cannot be evaluated.
+    NonLiteralComputation,
+    /// A multi-letter identifier is made of letters that are also used as identifiers on their own, e.g. `xy` in
+    /// `xy + x`. The user probably meant a product.
+    AdjacentLetters,
+    /// An operation is undefined, e.g. `1 / 0`, and is kept as written. See
+    /// [`crate::transformations::simplify::NonFinitePolicy`].
+    UndefinedOperation,
+    /// Tokens after a complete expression were skipped, see [`crate::parser::ParseMode::Lenient`].
+    TrailingInput,
+}
+
+impl DiagnosticCode {
+    /// Returns a short, stable identifier for the code.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiagnosticCode::InvalidToken => "E0001",
+            DiagnosticCode::UnexpectedToken => "E0002",
+            DiagnosticCode::ExprTooLarge => "E0003",
+            DiagnosticCode::UnboundWildcard => "E0004",
+            DiagnosticCode::MalformedNumber => "E0005",
+            DiagnosticCode::NonLiteralComputation => "E0006",
+            DiagnosticCode::AdjacentLetters => "W0001",
+            DiagnosticCode::UndefinedOperation => "W0002",
+            DiagnosticCode::TrailingInput => "W0003",
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// An error or warning pointing at a location in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: DiagnosticCode,
+    pub message: String,
+    /// The location in the source the diagnostic refers to.
+    pub span: Span,
+    /// A suggested replacement for the source in `span`.
+    pub suggestion: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: DiagnosticCode, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            span,
+            suggestion: None,
+        }
+    }
+
+    pub fn warning(code: DiagnosticCode, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            code,
+            message: message.into(),
+            span,
+            suggestion: None,
+        }
+    }
+
+    /// Attaches a suggested replacement for the source in `span`.
+    pub fn with_suggestion(mut self, suggestion: impl Into<String>) -> Self {
+        self.suggestion = Some(suggestion.into());
+        self
+    }
+
+    /// Renders the diagnostic for a terminal, with the source line it refers to and carets underlining the span.
+    ///
+    /// ```text
+    /// ERROR[E0002]: unexpected token
+    ///   |
+    /// 1 | 1 + 2)
+    ///   |      ^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let start = self.span.start.min(source.len());
+        let end = self.span.end.clamp(start, source.len());
+
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line = &source[line_start..line_end];
+        let line_number = source[..line_start].matches('\n').count() + 1;
+
+        // columns are counted in chars rather than bytes to line up with the printed source
+        let offset = source[line_start..start].chars().count();
+        let width = source[start..end.min(line_end)].chars().count().max(1);
+
+        let gutter = " ".repeat(line_number.to_string().len());
+        let mut rendered = format!(
+            "{}[{}]: {}\n{} |\n{} | {}\n{} | {}{}",
+            self.severity,
+            self.code,
+            self.message,
+            gutter,
+            line_number,
+            line,
+            gutter,
+            " ".repeat(offset),
+            "^".repeat(width)
+        );
+        if let Some(suggestion) = &self.suggestion {
+            rendered += &format!("\n{} = help: replace with `{}`", gutter, suggestion);
+        }
+        rendered
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Collects the [`Diagnostic`]s emitted while processing an input.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Removes all the diagnostics collected so far.
+    pub fn clear(&mut self) {
+        self.diagnostics.clear();
+    }
+
+    /// Returns `true` if at least one diagnostic has [`Severity::Error`].
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    /// Renders all the diagnostics with [`Diagnostic::render`], separated by empty lines.
+    pub fn render(&self, source: &str) -> String {
+        self.diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.render(source))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Extend<Diagnostic> for Diagnostics {
+    fn extend<I: IntoIterator<Item = Diagnostic>>(&mut self, iter: I) {
+        self.diagnostics.extend(iter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::expect;
+
+    #[test]
+    fn test_render() {
+        let diagnostic =
+            Diagnostic::error(DiagnosticCode::UnexpectedToken, "unexpected token", 5..6);
+        expect![[r#"
+            ERROR[E0002]: unexpected token
+              |
+            1 | 1 + 2)
+              |      ^"#]]
+        .assert_eq(&diagnostic.render("1 + 2)"));
+
+        // empty span at the end of the input
+        let diagnostic = Diagnostic::warning(DiagnosticCode::UnexpectedToken, "missing ')'", 6..6);
+        expect![[r#"
+            WARNING[E0002]: missing ')'
+              |
+            1 | (1 + 2
+              |       ^"#]]
+        .assert_eq(&diagnostic.render("(1 + 2"));
+
+        // second line
+        let diagnostic =
+            Diagnostic::error(DiagnosticCode::InvalidToken, "unrecognized token", 6..9);
+        expect![[r#"
+            ERROR[E0001]: unrecognized token
+              |
+            2 | x + $$$
+              |     ^^^"#]]
+        .assert_eq(&diagnostic.render("1\nx + $$$"));
+    }
+}
@@ -0,0 +1,196 @@
+//! Equations and solving them for a variable, e.g. to find where a derivative is zero.
+
+use crate::analysis::polynomial_coefficients;
+use crate::eval::eval;
+use crate::parser::{BinOpKind, Expr, ExprVisitor, UnaryOpKind};
+use crate::transformations::simplify::Simplify;
+use std::collections::HashMap;
+use std::fmt;
+
+/// An equation `lhs = rhs`, parsed with [`crate::parser::Parser::parse_equation`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Equation {
+    pub lhs: Expr,
+    pub rhs: Expr,
+}
+
+impl fmt::Display for Equation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {}", self.lhs, self.rhs)
+    }
+}
+
+/// An error that occurred while solving an equation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SolveError {
+    /// The variable does not occur in the simplified equation, e.g. `x - x = 1`.
+    NoVariable(String),
+    /// The variable cannot be isolated from the expression, either because it occurs more than once in an equation
+    /// that is not linear, e.g. `x ^ 2 + x = 1`, or because an operation cannot be inverted, e.g. `sign(x) = 1`.
+    NotIsolatable(Expr),
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::NoVariable(var) => write!(f, "`{}` does not occur in the equation", var),
+            SolveError::NotIsolatable(expr) => {
+                write!(f, "cannot isolate the variable in `{}`", expr)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SolveError {}
+
+/// Solves `equation` for `var` and returns the simplified solutions without duplicates.
+///
+/// Linear equations are solved directly, even if the variable occurs several times, e.g. `2 * x = x + 1`. Otherwise the
+/// variable must occur once and is isolated by inverting the operations around it one at a time, e.g. `abs(x - 1) = 3`
+/// gives `4` and `-2`. The solutions are not checked, e.g. `x ^ 2 = -1` gives `(-1) ^ (1 / 2)`, which is undefined.
+pub fn solve(equation: &Equation, var: &str) -> Result<Vec<Expr>, SolveError> {
+    let (mut lhs, mut rhs) = (equation.lhs.clone(), equation.rhs.clone());
+    Simplify.visit(&mut lhs);
+    Simplify.visit(&mut rhs);
+
+    let mut difference = lhs.clone() - rhs.clone();
+    Simplify.visit(&mut difference);
+    match polynomial_coefficients(&difference, var).as_deref() {
+        Some([_]) => return Err(SolveError::NoVariable(var.to_string())),
+        // `a * x + b = 0` gives `x = -b / a`
+        Some([constant, coefficient]) => {
+            let mut solution =
+                Expr::binary(-constant.clone(), BinOpKind::Slash, coefficient.clone());
+            Simplify.visit(&mut solution);
+            return Ok(vec![solution]);
+        }
+        _ => {}
+    }
+
+    let (side, other) = match (occurrences(&lhs, var), occurrences(&rhs, var)) {
+        (1, 0) => (lhs, rhs),
+        (0, 1) => (rhs, lhs),
+        _ => return Err(SolveError::NotIsolatable(difference)),
+    };
+    let mut solutions: Vec<Expr> = Vec::new();
+    // (side containing the variable, other side) of the equations left to solve
+    let mut pending = vec![(side, other)];
+    while let Some((mut side, other)) = pending.pop() {
+        match &mut side {
+            Expr::Identifier(ident) if ident == var => {
+                let mut solution = other;
+                Simplify.visit(&mut solution);
+                if !solutions.contains(&solution) {
+                    solutions.push(solution);
+                }
+            }
+            Expr::Binary { left, op, right } => {
+                let (op, var_on_left) = (*op, occurrences(left, var) == 1);
+                let (left, right) = (left.take(), right.take());
+                match (op, var_on_left) {
+                    (BinOpKind::Plus, true) => pending.push((left, other - right)),
+                    (BinOpKind::Plus, false) => pending.push((right, other - left)),
+                    (BinOpKind::Minus, true) => pending.push((left, other + right)),
+                    (BinOpKind::Minus, false) => pending.push((right, left - other)),
+                    (BinOpKind::Asterisk, true) => {
+                        pending.push((left, Expr::binary(other, BinOpKind::Slash, right)))
+                    }
+                    (BinOpKind::Asterisk, false) => {
+                        pending.push((right, Expr::binary(other, BinOpKind::Slash, left)))
+                    }
+                    (BinOpKind::Slash, true) => pending.push((left, other * right)),
+                    (BinOpKind::Slash, false) => {
+                        pending.push((right, Expr::binary(left, BinOpKind::Slash, other)))
+                    }
+                    // `u ^ n = r` gives `u = r ^ (1 / n)`, and also `u = -(r ^ (1 / n))` if `n` is even
+                    (BinOpKind::Exponent, true) => {
+                        let even = matches!(right, Expr::Literal(n) if n % 2.0 == 0.0);
+                        let odd = matches!(right, Expr::Literal(n) if n.abs() % 2.0 == 1.0);
+                        let inverse = Expr::binary(Expr::Literal(1.0), BinOpKind::Slash, right);
+                        // the real root of a negative number is negative for an odd `n`, e.g. `x ^ 3 = -8` gives
+                        // `-(8 ^ (1 / 3))` since `(-8) ^ (1 / 3)` is undefined
+                        let negative = eval(&other, &HashMap::new()).is_ok_and(|r| r < 0.0);
+                        let root = if odd && negative {
+                            -Expr::pow(-other, inverse)
+                        } else {
+                            Expr::pow(other, inverse)
+                        };
+                        // pushed before the positive root so that it is solved last
+                        if even {
+                            pending.push((left.clone(), -root.clone()));
+                        }
+                        pending.push((left, root));
+                    }
+                    (BinOpKind::Exponent, false) => {
+                        return Err(SolveError::NotIsolatable(Expr::binary(left, op, right)))
+                    }
+                }
+            }
+            Expr::Unary {
+                op: UnaryOpKind::Minus,
+                right,
+            } => pending.push((right.take(), -other)),
+            // `abs(u) = r` gives `u = r` and `u = -r`
+            Expr::Call { func, args } if func == "abs" && args.len() == 1 => {
+                let arg = args.pop().expect("the length was checked");
+                pending.push((arg.clone(), -other.clone()));
+                pending.push((arg, other));
+            }
+            _ => return Err(SolveError::NotIsolatable(side)),
+        }
+    }
+    Ok(solutions)
+}
+
+/// Returns the number of times the identifier `var` occurs in `expr`.
+fn occurrences(expr: &Expr, var: &str) -> usize {
+    expr.find_paths(|expr| matches!(expr, Expr::Identifier(ident) if ident == var))
+        .len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+
+    fn solve_str(input: &str) -> Result<Vec<String>, SolveError> {
+        let equation = Parser::from(Token::lexer(input).spanned()).parse_equation();
+        solve(&equation, "x").map(|solutions| solutions.iter().map(Expr::to_string).collect())
+    }
+
+    #[test]
+    fn test_solve() {
+        let cases: [(&str, &[&str]); 9] = [
+            ("2 * x + 1 = 5", &["2"]),
+            ("2 * x = x + 1", &["1"]),
+            ("a * x + b = 0", &["((-b) / a)"]),
+            ("x ^ 2 = 4", &["(4 ^ (1 / 2))", "(-(4 ^ (1 / 2)))"]),
+            ("abs(x - 1) = 3", &["4", "(-2)"]),
+            ("3 / x = 6", &["(1 / 2)"]),
+            ("abs(x) = 0", &["0"]),
+            ("x ^ 3 = -8", &["(-(8 ^ (1 / 3)))"]),
+            ("x ^ 3 + 2 = 0", &["(-(2 ^ (1 / 3)))"]),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(solve_str(input).unwrap(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_solve_errors() {
+        assert_eq!(
+            solve_str("x - x = 1"),
+            Err(SolveError::NoVariable("x".to_string()))
+        );
+        assert!(matches!(
+            solve_str("sign(x) = 1"),
+            Err(SolveError::NotIsolatable(_))
+        ));
+        assert!(matches!(
+            solve_str("x ^ 2 + x = 1"),
+            Err(SolveError::NotIsolatable(_))
+        ));
+    }
+}
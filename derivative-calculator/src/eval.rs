@@ -0,0 +1,655 @@
+//! Numeric evaluation of expressions.
+
+pub mod compiled;
+#[cfg(feature = "arbitrary-precision")]
+pub mod exact;
+pub mod quadrature;
+pub mod roots;
+
+use crate::constants::Constant;
+use crate::functions;
+use crate::parser::{walk_expr, BinOpKind, Expr, ExprVisitor, UnaryOpKind};
+use crate::transformations::derivative::partial_derivative;
+use crate::transformations::simplify::Simplify;
+use num_traits::{Signed, ToPrimitive};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// An error that occurred while evaluating an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    /// No value was provided for the identifier.
+    UnboundIdentifier(String),
+    /// The function is not a built-in function.
+    UnknownFunction(String),
+    /// A built-in function was called with the wrong number of arguments.
+    WrongArgumentCount {
+        func: String,
+        expected: usize,
+        found: usize,
+    },
+    /// The expression contains an [`Expr::Error`].
+    InvalidExpr,
+    /// The expression divides by zero. Only returned by exact evaluation, `f64` evaluation returns infinity instead.
+    DivisionByZero,
+    /// The value of the expression cannot be represented exactly, e.g. `2 ^ 0.5`. Only returned by exact evaluation.
+    Inexact,
+    /// The expression contains an [`Expr::Undefined`]. `f64` evaluation returns NaN instead.
+    Undefined,
+    /// The expression contains an [`Expr::Integral`], which is never evaluated.
+    Integral,
+    /// The expression contains an [`Expr::Vector`] where a number is expected. See [`eval_vector`] to evaluate the
+    /// components of a vector.
+    Vector,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::UnboundIdentifier(ident) => write!(f, "no value for `{}`", ident),
+            EvalError::UnknownFunction(func) => write!(f, "unknown function `{}`", func),
+            EvalError::WrongArgumentCount {
+                func,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` takes {} argument(s) but {} were given",
+                func, expected, found
+            ),
+            EvalError::InvalidExpr => write!(f, "cannot evaluate an invalid expression"),
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::Inexact => write!(f, "the value cannot be represented exactly"),
+            EvalError::Undefined => write!(f, "the value is undefined"),
+            EvalError::Integral => write!(f, "cannot evaluate an integral"),
+            EvalError::Vector => write!(f, "expected a number, found a vector"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// A pending step when evaluating an expression.
+pub(crate) enum Task<'a, T = f64> {
+    /// Evaluate the expression and push the value onto the value stack.
+    Eval(&'a Expr<T>),
+    /// Pop the values of the operands of the expression off the value stack and combine them.
+    Apply(&'a Expr<T>),
+}
+
+/// Evaluates `expr` with the values of the identifiers in `bindings`.
+///
+/// Like the other passes, the expression is traversed with an explicit work stack to support deeply nested
+/// expressions.
+pub fn eval(expr: &Expr, bindings: &HashMap<String, f64>) -> Result<f64, EvalError> {
+    let mut tasks = vec![Task::Eval(expr)];
+    let mut values: Vec<f64> = Vec::new();
+
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Eval(expr) => match expr {
+                Expr::Literal(num) => values.push(*num),
+                Expr::Constant(constant) => values.push(constant.value()),
+                Expr::Undefined => values.push(f64::NAN),
+                Expr::Identifier(ident) => match bindings.get(ident) {
+                    Some(value) => values.push(*value),
+                    None => return Err(EvalError::UnboundIdentifier(ident.clone())),
+                },
+                Expr::Binary { left, op: _, right } => {
+                    tasks.push(Task::Apply(expr));
+                    tasks.push(Task::Eval(right));
+                    tasks.push(Task::Eval(left));
+                }
+                Expr::Unary { op: _, right } => {
+                    tasks.push(Task::Apply(expr));
+                    tasks.push(Task::Eval(right));
+                }
+                Expr::Call { func, args } => {
+                    let function = functions::lookup(func)
+                        .ok_or_else(|| EvalError::UnknownFunction(func.clone()))?;
+                    if function.arity != args.len() {
+                        return Err(EvalError::WrongArgumentCount {
+                            func: func.clone(),
+                            expected: function.arity,
+                            found: args.len(),
+                        });
+                    }
+                    tasks.push(Task::Apply(expr));
+                    // pushed in reverse so that the first argument ends up lowest on the value stack
+                    tasks.extend(args.iter().rev().map(Task::Eval));
+                }
+                Expr::Integral { .. } => return Err(EvalError::Integral),
+                Expr::Vector(_) => return Err(EvalError::Vector),
+                Expr::Error => return Err(EvalError::InvalidExpr),
+            },
+            Task::Apply(expr) => {
+                let value = match expr {
+                    Expr::Binary {
+                        left: _,
+                        op,
+                        right: _,
+                    } => {
+                        let right = values.pop().expect("right operand should be evaluated");
+                        let left = values.pop().expect("left operand should be evaluated");
+                        match op {
+                            BinOpKind::Plus => left + right,
+                            BinOpKind::Minus => left - right,
+                            BinOpKind::Asterisk => left * right,
+                            BinOpKind::Slash => left / right,
+                            BinOpKind::Exponent => left.powf(right),
+                        }
+                    }
+                    Expr::Unary {
+                        op: UnaryOpKind::Minus,
+                        right: _,
+                    } => -values.pop().expect("operand should be evaluated"),
+                    Expr::Call { func, args } => {
+                        let function =
+                            functions::lookup(func).expect("function was looked up before");
+                        let args = values.split_off(values.len() - args.len());
+                        (function.eval)(&args)
+                    }
+                    Expr::Literal(_)
+                    | Expr::Identifier(_)
+                    | Expr::Constant(_)
+                    | Expr::Integral { .. }
+                    | Expr::Vector(_)
+                    | Expr::Undefined
+                    | Expr::Error => {
+                        unreachable!("atoms are evaluated directly")
+                    }
+                };
+                values.push(value);
+            }
+        }
+    }
+
+    Ok(values
+        .pop()
+        .expect("value of the input should be on the value stack"))
+}
+
+/// Evaluates the components of the vector `expr` with the values of the identifiers in `bindings`, e.g. the position
+/// on a parametric curve. Any other expression is evaluated as a vector with a single component.
+pub fn eval_vector(expr: &Expr, bindings: &HashMap<String, f64>) -> Result<Vec<f64>, EvalError> {
+    match expr {
+        Expr::Vector(components) => components
+            .iter()
+            .map(|component| eval(component, bindings))
+            .collect(),
+        expr => Ok(vec![eval(expr, bindings)?]),
+    }
+}
+
+/// Same as [`eval`] except that the expression is evaluated with the number type `T` of its literals, e.g. `i64` or
+/// `f32`. See [`Expr::map_literals`] to convert the literals of a parsed expression.
+///
+/// The operations are those of `T`, e.g. division truncates for integers. Exponents must be integers, otherwise
+/// [`EvalError::Inexact`] is returned. Unlike [`eval`], division by zero returns [`EvalError::DivisionByZero`].
+pub fn eval_num<T>(expr: &Expr<T>, bindings: &HashMap<String, T>) -> Result<T, EvalError>
+where
+    T: Clone + Signed + ToPrimitive,
+{
+    let mut tasks = vec![Task::Eval(expr)];
+    let mut values: Vec<T> = Vec::new();
+
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Eval(expr) => match expr {
+                Expr::Literal(num) => values.push(num.clone()),
+                // the built-in constants are irrational
+                Expr::Constant(_) => return Err(EvalError::Inexact),
+                Expr::Undefined => return Err(EvalError::Undefined),
+                Expr::Identifier(ident) => match bindings.get(ident) {
+                    Some(value) => values.push(value.clone()),
+                    None => return Err(EvalError::UnboundIdentifier(ident.clone())),
+                },
+                Expr::Binary { left, op: _, right } => {
+                    tasks.push(Task::Apply(expr));
+                    tasks.push(Task::Eval(right));
+                    tasks.push(Task::Eval(left));
+                }
+                Expr::Unary { op: _, right } => {
+                    tasks.push(Task::Apply(expr));
+                    tasks.push(Task::Eval(right));
+                }
+                Expr::Call { func, args } => {
+                    if !matches!(func.as_str(), "abs" | "sign") {
+                        return Err(EvalError::UnknownFunction(func.clone()));
+                    }
+                    if args.len() != 1 {
+                        return Err(EvalError::WrongArgumentCount {
+                            func: func.clone(),
+                            expected: 1,
+                            found: args.len(),
+                        });
+                    }
+                    tasks.push(Task::Apply(expr));
+                    tasks.push(Task::Eval(&args[0]));
+                }
+                Expr::Integral { .. } => return Err(EvalError::Integral),
+                Expr::Vector(_) => return Err(EvalError::Vector),
+                Expr::Error => return Err(EvalError::InvalidExpr),
+            },
+            Task::Apply(expr) => {
+                let value = match expr {
+                    Expr::Binary {
+                        left: _,
+                        op,
+                        right: _,
+                    } => {
+                        let right = values.pop().expect("right operand should be evaluated");
+                        let left = values.pop().expect("left operand should be evaluated");
+                        match op {
+                            BinOpKind::Plus => left + right,
+                            BinOpKind::Minus => left - right,
+                            BinOpKind::Asterisk => left * right,
+                            BinOpKind::Slash => {
+                                if right.is_zero() {
+                                    return Err(EvalError::DivisionByZero);
+                                }
+                                left / right
+                            }
+                            BinOpKind::Exponent => pow_num(left, right)?,
+                        }
+                    }
+                    Expr::Unary {
+                        op: UnaryOpKind::Minus,
+                        right: _,
+                    } => -values.pop().expect("operand should be evaluated"),
+                    Expr::Call { func, args: _ } => {
+                        let arg = values.pop().expect("argument should be evaluated");
+                        match func.as_str() {
+                            "abs" => arg.abs(),
+                            // `Signed::signum` is not zero for negative zero or NaN floats
+                            "sign" if arg.is_positive() => T::one(),
+                            "sign" if arg.is_negative() => -T::one(),
+                            "sign" => T::zero(),
+                            _ => unreachable!("unknown functions are rejected before"),
+                        }
+                    }
+                    Expr::Literal(_)
+                    | Expr::Identifier(_)
+                    | Expr::Constant(_)
+                    | Expr::Integral { .. }
+                    | Expr::Vector(_)
+                    | Expr::Undefined
+                    | Expr::Error => {
+                        unreachable!("atoms are evaluated directly")
+                    }
+                };
+                values.push(value);
+            }
+        }
+    }
+
+    Ok(values
+        .pop()
+        .expect("value of the input should be on the value stack"))
+}
+
+/// Raises `base` to an integer `exponent` for [`eval_num`].
+fn pow_num<T>(base: T, exponent: T) -> Result<T, EvalError>
+where
+    T: Clone + Signed + ToPrimitive,
+{
+    if !(exponent.clone() % T::one()).is_zero() {
+        return Err(EvalError::Inexact);
+    }
+    let n = exponent.abs().to_usize().ok_or(EvalError::Inexact)?;
+    if exponent.is_negative() {
+        if base.is_zero() {
+            return Err(EvalError::DivisionByZero);
+        }
+        Ok(T::one() / num_traits::pow(base, n))
+    } else {
+        Ok(num_traits::pow(base, n))
+    }
+}
+
+/// Replaces the identifiers in `bindings` with their values and simplifies the result. Unlike [`eval`], unbound
+/// identifiers are left as is, e.g. `a * x` with `a = 2` becomes `2 * x`.
+pub fn partial_eval(expr: &Expr, bindings: &HashMap<String, f64>) -> Expr {
+    let mut res = expr.clone();
+    Substitute { bindings }.visit(&mut res);
+    Simplify.visit(&mut res);
+    res
+}
+
+/// Replaces bound identifiers with literals.
+struct Substitute<'a> {
+    bindings: &'a HashMap<String, f64>,
+}
+
+impl ExprVisitor for Substitute<'_> {
+    fn visit(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Identifier(ident) => {
+                if let Some(value) = self.bindings.get(ident) {
+                    *expr = Expr::Literal(*value);
+                }
+            }
+            // the variable of the integral is a different identifier inside of it
+            Expr::Integral { var, bounds, .. } if self.bindings.contains_key(var) => {
+                if let Some((lower, upper)) = bounds.as_deref_mut() {
+                    self.visit(lower);
+                    self.visit(upper);
+                }
+            }
+            _ => walk_expr(expr, self),
+        }
+    }
+}
+
+/// The unit of the angles of the trigonometric functions, see [`with_angle_mode`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum AngleMode {
+    #[default]
+    Radians,
+    Degrees,
+}
+
+/// Returns `expr` with the angles of the trigonometric functions in `mode`, e.g. in degrees `sin(x)` becomes
+/// `sin(pi / 180 * x)` and `asin(x)` becomes `180 / pi * asin(x)`. The built-in functions always use radians, so the
+/// result can be evaluated, differentiated and plotted like any other expression.
+pub fn with_angle_mode(expr: &Expr, mode: AngleMode) -> Expr {
+    let mut res = expr.clone();
+    if mode == AngleMode::Degrees {
+        DegreesToRadians.visit(&mut res);
+    }
+    res
+}
+
+/// Converts the angles of the trigonometric functions from degrees to radians.
+struct DegreesToRadians;
+
+impl ExprVisitor for DegreesToRadians {
+    fn visit(&mut self, expr: &mut Expr) {
+        walk_expr(expr, self);
+        let ratio = |numerator: Expr, denominator: Expr| Expr::Binary {
+            left: Box::new(numerator),
+            op: BinOpKind::Slash,
+            right: Box::new(denominator),
+        };
+        let (pi, half_turn) = (Expr::Constant(Constant::Pi), Expr::Literal(180.0));
+        let (func, args) = match expr {
+            Expr::Call { func, args } if args.len() == 1 => (func.as_str(), args),
+            _ => return,
+        };
+        match func {
+            "sin" | "cos" | "tan" => {
+                let arg = args.pop().expect("call should have an argument");
+                args.push(ratio(pi, half_turn) * arg);
+            }
+            "asin" | "acos" | "atan" => {
+                let call = std::mem::replace(expr, Expr::Undefined);
+                *expr = ratio(half_turn, pi) * call;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The number of points at which [`equivalent`] compares the expressions.
+const EQUIVALENCE_SAMPLES: usize = 32;
+
+/// The result of [`equivalent`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Equivalence {
+    /// The expressions have the same value at all the sample points.
+    Equivalent,
+    /// The expressions have different values at `point`.
+    Different {
+        /// The value of every identifier of the expressions.
+        point: BTreeMap<String, f64>,
+        left: f64,
+        right: f64,
+    },
+}
+
+/// Checks whether `left` and `right` are equivalent by evaluating them at sample points, e.g. to check a derivative
+/// computed by hand. Points where either expression is undefined (e.g. divides by zero) are skipped.
+///
+/// Equivalence is only probable since the expressions could still differ elsewhere, but [`Equivalence::Different`]
+/// comes with a counterexample.
+pub fn equivalent(left: &Expr, right: &Expr) -> Result<Equivalence, EvalError> {
+    let mut identifiers = left.identifiers();
+    identifiers.extend(right.identifiers());
+    identifiers.sort_unstable();
+    identifiers.dedup();
+
+    for sample in 0..EQUIVALENCE_SAMPLES {
+        // spread the values over [-4, 4], avoiding integers where expressions are often special (e.g. `x ^ 0`)
+        let bindings: HashMap<String, f64> = identifiers
+            .iter()
+            .enumerate()
+            .map(|(i, ident)| {
+                let step = (sample * 7 + i * 13) % 29;
+                (ident.to_string(), step as f64 * 0.283 - 3.97)
+            })
+            .collect();
+        let (left_value, right_value) = (eval(left, &bindings)?, eval(right, &bindings)?);
+        if !left_value.is_finite() || !right_value.is_finite() {
+            continue;
+        }
+        let tolerance = 1e-9 * left_value.abs().max(right_value.abs()).max(1.0);
+        if (left_value - right_value).abs() > tolerance {
+            return Ok(Equivalence::Different {
+                point: bindings.into_iter().collect(),
+                left: left_value,
+                right: right_value,
+            });
+        }
+    }
+    Ok(Equivalence::Equivalent)
+}
+
+/// Checks whether `antiderivative` is an antiderivative of `f` with respect to `var`, i.e. whether its derivative is
+/// [`equivalent`] to `f`, e.g. to check an integral computed by hand. Antiderivatives are only unique up to a constant,
+/// e.g. both `x ^ 2 / 2` and `x ^ 2 / 2 + 1` are antiderivatives of `x`. [`Equivalence::Different`] compares the value
+/// of `f` to the value of the derivative.
+///
+/// The parts of `antiderivative` that cannot be differentiated make the derivative invalid, in which case
+/// [`EvalError::InvalidExpr`] is returned.
+pub fn verify_antiderivative(
+    f: &Expr,
+    antiderivative: &Expr,
+    var: &str,
+) -> Result<Equivalence, EvalError> {
+    let mut derivative = partial_derivative(antiderivative, var);
+    Simplify.visit(&mut derivative);
+    equivalent(f, &derivative)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+
+    fn eval_str(input: &str, bindings: &[(&str, f64)]) -> Result<f64, EvalError> {
+        let expr = Parser::from(Token::lexer(input).spanned()).parse();
+        let bindings = bindings
+            .iter()
+            .map(|(ident, value)| (ident.to_string(), *value))
+            .collect();
+        eval(&expr, &bindings)
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval_str("1 + 2 * 3 ^ 2", &[]), Ok(19.0));
+        assert_eq!(eval_str("-x / 4", &[("x", 2.0)]), Ok(-0.5));
+        assert_eq!(eval_str("abs(x - 5)", &[("x", 2.0)]), Ok(3.0));
+        assert_eq!(eval_str("sign(x)", &[("x", -2.0)]), Ok(-1.0));
+        assert_eq!(eval_str("sign(0)", &[]), Ok(0.0));
+        assert_eq!(eval_str("2 * pi", &[]), Ok(std::f64::consts::TAU));
+        assert_eq!(eval_str("ln(e ^ x)", &[("x", 2.0)]), Ok(2.0));
+    }
+
+    #[test]
+    fn test_eval_vector() {
+        let parse = |input| Parser::from(Token::lexer(input).spanned()).parse();
+        let bindings = HashMap::from([("t".to_string(), 2.0)]);
+        assert_eq!(
+            eval_vector(&parse("[t ^ 2, -t, 1]"), &bindings),
+            Ok(vec![4.0, -2.0, 1.0])
+        );
+        assert_eq!(eval_vector(&parse("t + 1"), &bindings), Ok(vec![3.0]));
+        assert_eq!(
+            eval_vector(&parse("[t, [t]]"), &bindings),
+            Err(EvalError::Vector)
+        );
+        assert_eq!(eval(&parse("2 * [t]"), &bindings), Err(EvalError::Vector));
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        assert_eq!(
+            eval_str("x + y", &[("x", 1.0)]),
+            Err(EvalError::UnboundIdentifier("y".to_string()))
+        );
+        assert_eq!(
+            eval_str("f(1)", &[]),
+            Err(EvalError::UnknownFunction("f".to_string()))
+        );
+        assert_eq!(
+            eval_str("abs(1, 2)", &[]),
+            Err(EvalError::WrongArgumentCount {
+                func: "abs".to_string(),
+                expected: 1,
+                found: 2
+            })
+        );
+        assert_eq!(eval_str("1 +", &[]), Err(EvalError::InvalidExpr));
+    }
+
+    #[test]
+    fn test_eval_num() {
+        let parse = |input: &str| Parser::from(Token::lexer(input).spanned()).parse();
+
+        let expr = parse("7 / 2 + x ^ 3 - abs(-4)").map_literals(|num| *num as i64);
+        let bindings = HashMap::from([("x".to_string(), 2)]);
+        assert_eq!(eval_num(&expr, &bindings), Ok(7));
+        let expr = parse("sign(x) * 2 ^ -1 + 1 / (x - x)").map_literals(|num| *num as i64);
+        assert_eq!(eval_num(&expr, &bindings), Err(EvalError::DivisionByZero));
+
+        let expr = parse("sign(-x) + 2 ^ -2 + x ^ 0.5").map_literals(|num| *num as f32);
+        let bindings = HashMap::from([("x".to_string(), 0.5f32)]);
+        assert_eq!(eval_num(&expr, &bindings), Err(EvalError::Inexact));
+        let expr = parse("sign(-x) + 2 ^ -2").map_literals(|num| *num as f32);
+        assert_eq!(eval_num(&expr, &bindings), Ok(-0.75));
+        assert_eq!(
+            eval_num(&parse("pi").map_literals(|num| *num as f32), &bindings),
+            Err(EvalError::Inexact)
+        );
+        assert_eq!(
+            eval_num(&parse("f(1)").map_literals(|num| *num as f32), &bindings),
+            Err(EvalError::UnknownFunction("f".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_partial_eval() {
+        let partial_eval_str = |input: &str, bindings: &[(&str, f64)]| {
+            let expr = Parser::from(Token::lexer(input).spanned()).parse();
+            let bindings = bindings
+                .iter()
+                .map(|(ident, value)| (ident.to_string(), *value))
+                .collect();
+            partial_eval(&expr, &bindings).to_string()
+        };
+        assert_eq!(
+            partial_eval_str("a * x + b", &[("a", 2.0)]),
+            "((2 * x) + b)"
+        );
+        assert_eq!(
+            partial_eval_str("a * x + b", &[("a", 2.0), ("b", 3.0)]),
+            "(3 + (2 * x))"
+        );
+        assert_eq!(
+            partial_eval_str("(a + 1) * x ^ a", &[("a", 2.0)]),
+            "(3 * (x ^ 2))"
+        );
+        assert_eq!(partial_eval_str("x * y", &[("x", 1.5), ("y", 2.0)]), "3");
+        assert_eq!(partial_eval_str("x + y", &[]), "(x + y)");
+    }
+
+    #[test]
+    fn test_with_angle_mode() {
+        let parse = |input| Parser::from(Token::lexer(input).spanned()).parse();
+        let expr = parse("sin(x) + asin(cos(2 * x))");
+        assert_eq!(with_angle_mode(&expr, AngleMode::Radians), expr);
+        let degrees = with_angle_mode(&expr, AngleMode::Degrees);
+        assert_eq!(
+            degrees.to_string(),
+            "(sin((pi / 180) * x) + ((180 / pi) * asin(cos((pi / 180) * (2 * x)))))"
+        );
+        let bindings = HashMap::from([("x".to_string(), 30.0)]);
+        let value = eval(&degrees, &bindings).unwrap();
+        assert!((value - 30.5).abs() < 1e-9, "value: {}", value);
+    }
+
+    #[test]
+    fn test_equivalent() {
+        let equivalent_str = |left: &str, right: &str| {
+            let left = Parser::from(Token::lexer(left).spanned()).parse();
+            let right = Parser::from(Token::lexer(right).spanned()).parse();
+            equivalent(&left, &right)
+        };
+        assert_eq!(
+            equivalent_str("2 * x * (x + 1)", "2 * x ^ 2 + 2 * x"),
+            Ok(Equivalence::Equivalent)
+        );
+        assert_eq!(equivalent_str("x / x", "1"), Ok(Equivalence::Equivalent));
+        assert_eq!(
+            equivalent_str("abs(x) * sign(x)", "x"),
+            Ok(Equivalence::Equivalent)
+        );
+        match equivalent_str("(x + y) ^ 2", "x ^ 2 + y ^ 2") {
+            Ok(Equivalence::Different { point, left, right }) => {
+                let (x, y) = (point["x"], point["y"]);
+                assert_eq!(left, (x + y).powf(2.0));
+                assert_eq!(right, x.powf(2.0) + y.powf(2.0));
+            }
+            res => panic!("expected a counterexample, got {:?}", res),
+        }
+        assert_eq!(
+            equivalent_str("f(x)", "x"),
+            Err(EvalError::UnknownFunction("f".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_verify_antiderivative() {
+        let verify = |f: &str, antiderivative: &str| {
+            let f = Parser::from(Token::lexer(f).spanned()).parse();
+            let antiderivative = Parser::from(Token::lexer(antiderivative).spanned()).parse();
+            verify_antiderivative(&f, &antiderivative, "x")
+        };
+        assert_eq!(verify("x", "x ^ 2 / 2"), Ok(Equivalence::Equivalent));
+        assert_eq!(verify("x", "x ^ 2 / 2 + 1"), Ok(Equivalence::Equivalent));
+        assert_eq!(
+            verify("abs(x)", "x * abs(x) / 2"),
+            Ok(Equivalence::Equivalent)
+        );
+        assert_eq!(
+            verify("y * x", "y * x ^ 2 / 2 + y"),
+            Ok(Equivalence::Equivalent)
+        );
+        assert_eq!(
+            verify("x ^ 2", "int(x ^ 2, x)"),
+            Ok(Equivalence::Equivalent)
+        );
+        match verify("x ^ 2", "x ^ 3 / 2") {
+            Ok(Equivalence::Different { point, left, right }) => {
+                let x = point["x"];
+                assert_eq!(left, x.powf(2.0));
+                assert!((right - 1.5 * x.powf(2.0)).abs() < 1e-9);
+            }
+            res => panic!("expected a counterexample, got {:?}", res),
+        }
+        assert_eq!(verify("x", "abs(x, 2)"), Err(EvalError::InvalidExpr));
+    }
+}
@@ -0,0 +1,248 @@
+//! Evaluation of an expression at many values of a variable, e.g. to plot it.
+//!
+//! [`eval`](super::eval) walks the tree for every value. [`CompiledExpr`] walks it once to produce a list of
+//! operations in postfix order and then applies every operation to a chunk of values at a time, which keeps the
+//! inner loops free of branches on the shape of the expression.
+
+use super::{EvalError, Task};
+use crate::functions;
+use crate::parser::{BinOpKind, Expr, UnaryOpKind};
+
+/// The number of values evaluated together by [`CompiledExpr::eval_many`].
+const CHUNK: usize = 64;
+
+/// An operation on the value stack of a [`CompiledExpr`].
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    /// Push a constant.
+    Literal(f64),
+    /// Push the value of the variable.
+    Variable,
+    /// Pop the two operands and push the result.
+    Binary(BinOpKind),
+    /// Negate the top of the stack.
+    Negate,
+    /// Pop `arity` arguments and push the result.
+    Call {
+        eval: fn(&[f64]) -> f64,
+        arity: usize,
+    },
+}
+
+/// An expression of a single variable compiled for fast repeated evaluation. The results are the same as those of
+/// [`eval`](super::eval) with the variable bound to the value.
+#[derive(Debug, Clone)]
+pub struct CompiledExpr {
+    /// The operations in postfix order.
+    ops: Vec<Op>,
+    /// The most values on the stack at any time.
+    stack_size: usize,
+}
+
+impl CompiledExpr {
+    /// Compiles `expr` as a function of `variable`. Other identifiers are an error, see
+    /// [`partial_eval`](super::partial_eval) to substitute their values first.
+    pub fn new(expr: &Expr, variable: &str) -> Result<Self, EvalError> {
+        let mut ops = Vec::new();
+        let (mut depth, mut stack_size) = (0, 0);
+        let mut tasks = vec![Task::Eval(expr)];
+        while let Some(task) = tasks.pop() {
+            let op = match task {
+                Task::Eval(expr) => match expr {
+                    Expr::Literal(num) => Op::Literal(*num),
+                    Expr::Constant(constant) => Op::Literal(constant.value()),
+                    Expr::Undefined => Op::Literal(f64::NAN),
+                    Expr::Identifier(ident) if ident == variable => Op::Variable,
+                    Expr::Identifier(ident) => {
+                        return Err(EvalError::UnboundIdentifier(ident.clone()))
+                    }
+                    Expr::Binary { left, op: _, right } => {
+                        tasks.push(Task::Apply(expr));
+                        tasks.push(Task::Eval(right));
+                        tasks.push(Task::Eval(left));
+                        continue;
+                    }
+                    Expr::Unary { op: _, right } => {
+                        tasks.push(Task::Apply(expr));
+                        tasks.push(Task::Eval(right));
+                        continue;
+                    }
+                    Expr::Call { func, args } => {
+                        let function = functions::lookup(func)
+                            .ok_or_else(|| EvalError::UnknownFunction(func.clone()))?;
+                        if function.arity != args.len() {
+                            return Err(EvalError::WrongArgumentCount {
+                                func: func.clone(),
+                                expected: function.arity,
+                                found: args.len(),
+                            });
+                        }
+                        tasks.push(Task::Apply(expr));
+                        tasks.extend(args.iter().rev().map(Task::Eval));
+                        continue;
+                    }
+                    Expr::Integral { .. } => return Err(EvalError::Integral),
+                    Expr::Vector(_) => return Err(EvalError::Vector),
+                    Expr::Error => return Err(EvalError::InvalidExpr),
+                },
+                Task::Apply(expr) => match expr {
+                    Expr::Binary { op, .. } => Op::Binary(*op),
+                    Expr::Unary {
+                        op: UnaryOpKind::Minus,
+                        right: _,
+                    } => Op::Negate,
+                    Expr::Call { func, args } => Op::Call {
+                        eval: functions::lookup(func)
+                            .expect("function was looked up before")
+                            .eval,
+                        arity: args.len(),
+                    },
+                    Expr::Literal(_)
+                    | Expr::Identifier(_)
+                    | Expr::Constant(_)
+                    | Expr::Integral { .. }
+                    | Expr::Vector(_)
+                    | Expr::Undefined
+                    | Expr::Error => {
+                        unreachable!("atoms are compiled directly")
+                    }
+                },
+            };
+            depth = match op {
+                Op::Literal(_) | Op::Variable => depth + 1,
+                Op::Binary(_) => depth - 1,
+                Op::Negate => depth,
+                Op::Call { arity, .. } => depth + 1 - arity,
+            };
+            stack_size = stack_size.max(depth);
+            ops.push(op);
+        }
+        Ok(Self { ops, stack_size })
+    }
+
+    /// Evaluates the expression with the variable set to `x`.
+    pub fn eval(&self, x: f64) -> f64 {
+        let mut out = [0.0];
+        self.eval_many(&[x], &mut out);
+        out[0]
+    }
+
+    /// Evaluates the expression at every value of `xs` and writes the results to the same index of `out`.
+    ///
+    /// # Panics
+    /// Panics if `xs` and `out` have different lengths.
+    pub fn eval_many(&self, xs: &[f64], out: &mut [f64]) {
+        assert_eq!(
+            xs.len(),
+            out.len(),
+            "`xs` and `out` should have the same length"
+        );
+        let mut stack = vec![[0.0; CHUNK]; self.stack_size];
+        let mut args = Vec::new();
+        for (xs, out) in xs.chunks(CHUNK).zip(out.chunks_mut(CHUNK)) {
+            let len = xs.len();
+            // the number of values on the stack
+            let mut top = 0;
+            for op in &self.ops {
+                match *op {
+                    Op::Literal(num) => {
+                        stack[top][..len].fill(num);
+                        top += 1;
+                    }
+                    Op::Variable => {
+                        stack[top][..len].copy_from_slice(xs);
+                        top += 1;
+                    }
+                    Op::Binary(op) => {
+                        top -= 1;
+                        let (lower, upper) = stack.split_at_mut(top);
+                        let (left, right) = (&mut lower[top - 1][..len], &upper[0][..len]);
+                        match op {
+                            BinOpKind::Plus => zip_apply(left, right, |l, r| l + r),
+                            BinOpKind::Minus => zip_apply(left, right, |l, r| l - r),
+                            BinOpKind::Asterisk => zip_apply(left, right, |l, r| l * r),
+                            BinOpKind::Slash => zip_apply(left, right, |l, r| l / r),
+                            BinOpKind::Exponent => zip_apply(left, right, f64::powf),
+                        }
+                    }
+                    Op::Negate => stack[top - 1][..len]
+                        .iter_mut()
+                        .for_each(|value| *value = -*value),
+                    Op::Call { eval, arity } => {
+                        let base = top - arity;
+                        let mut results = [0.0; CHUNK];
+                        for (i, result) in results[..len].iter_mut().enumerate() {
+                            args.clear();
+                            args.extend(stack[base..top].iter().map(|arg| arg[i]));
+                            *result = eval(&args);
+                        }
+                        stack[base] = results;
+                        top = base + 1;
+                    }
+                }
+            }
+            out.copy_from_slice(&stack[0][..len]);
+        }
+    }
+}
+
+/// Sets every element of `left` to `f` of itself and the element of `right` at the same index.
+fn zip_apply(left: &mut [f64], right: &[f64], f: impl Fn(f64, f64) -> f64) {
+    for (left, right) in left.iter_mut().zip(right) {
+        *left = f(*left, *right);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::eval;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+    use std::collections::HashMap;
+
+    fn parse(input: &str) -> Expr {
+        Parser::from(Token::lexer(input).spanned()).parse()
+    }
+
+    #[test]
+    fn test_eval_many() {
+        // more values than a chunk to cover the last partial chunk
+        let xs: Vec<f64> = (0..150).map(|i| i as f64 / 10.0 - 7.0).collect();
+        for input in [
+            "x",
+            "3",
+            "-x ^ 2 + 2 * x - 1 / x",
+            "abs(x - 2) * sign(x) ^ (x / 3)",
+            "2 ^ -(x * (x + (x - (x * 2))))",
+            "undefined + x",
+        ] {
+            let expr = parse(input);
+            let compiled = CompiledExpr::new(&expr, "x").unwrap();
+            let mut out = vec![0.0; xs.len()];
+            compiled.eval_many(&xs, &mut out);
+            for (x, value) in xs.iter().zip(out) {
+                let bindings = HashMap::from([("x".to_string(), *x)]);
+                let expected = eval(&expr, &bindings).unwrap();
+                assert!(
+                    value == expected || value.is_nan() && expected.is_nan(),
+                    "{} at x = {}: {} != {}",
+                    input,
+                    x,
+                    value,
+                    expected
+                );
+            }
+        }
+        assert_eq!(
+            CompiledExpr::new(&parse("x * y"), "x").unwrap_err(),
+            EvalError::UnboundIdentifier("y".to_string())
+        );
+        assert_eq!(
+            CompiledExpr::new(&parse("f(x)"), "x").unwrap_err(),
+            EvalError::UnknownFunction("f".to_string())
+        );
+        assert_eq!(CompiledExpr::new(&parse("y"), "y").unwrap().eval(2.0), 2.0);
+    }
+}
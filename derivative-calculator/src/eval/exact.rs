@@ -0,0 +1,284 @@
+//! Exact evaluation of expressions with arbitrary precision rationals. Enabled by the `arbitrary-precision` feature.
+//!
+//! [`parse_exact`] reads the literals from their source text, e.g. `0.1` is exactly `1/10` rather than the nearest
+//! `f64` and the digits of `100000000000000000001` are all kept. The parsed expression can then be folded with
+//! [`fold_exact`] and evaluated with [`eval_exact`].
+
+use super::{EvalError, Task};
+use crate::diagnostic::Diagnostics;
+use crate::functions;
+use crate::lexer::{superscript_to_ascii, Token};
+use crate::parser::{BinOpKind, Expr, Parser, UnaryOpKind};
+use dashu_int::{IBig, UBig};
+use dashu_ratio::RBig;
+use logos::Logos;
+use std::collections::HashMap;
+
+/// Same as [`Parser::parse`] except that the literals are parsed as rationals from their source text instead of as
+/// `f64`. Syntax errors are reported through the returned diagnostics.
+pub fn parse_exact(input: &str) -> (Expr<RBig>, Diagnostics) {
+    let mut parser = Parser::from(Token::lexer(input).spanned());
+    let expr = parser.parse();
+    let spans = match parser.literal_spans(&expr) {
+        Some(spans) => spans,
+        // the literals of the input are unknown if some of them were discarded after a syntax error
+        None => return (Expr::Error, parser.diagnostics().clone()),
+    };
+
+    let mut spans = spans.iter();
+    let expr =
+        expr.map_literals(
+            |num| match spans.next().expect("every literal should have a span") {
+                Some(span) => parse_rational(&input[span.clone()])
+                    .expect("the source of a literal should be a valid number"),
+                None => literal_to_rational(*num)
+                    .expect("literals that are not written out should be finite"),
+            },
+        );
+    (expr, parser.diagnostics().clone())
+}
+
+/// Parses the source text of a literal, e.g. `-1_000.25` or `⁻²`.
+fn parse_rational(slice: &str) -> Option<RBig> {
+    let normal = superscript_to_ascii(slice).replace('_', "");
+    let (negative, digits) = match normal.strip_prefix(['-', '−']) {
+        Some(digits) => (true, digits.trim_start()),
+        None => (false, normal.as_str()),
+    };
+    let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+    let numerator: IBig = format!("{}{}", int_part, frac_part).parse().ok()?;
+    let value = RBig::from_parts(numerator, UBig::from(10u8).pow(frac_part.len()));
+    Some(if negative { -value } else { value })
+}
+
+/// Converts a literal that is not written in the source, e.g. the result of a transformation, to the decimal number
+/// it is printed as.
+pub fn literal_to_rational(num: f64) -> Result<RBig, EvalError> {
+    if !num.is_finite() {
+        return Err(EvalError::Inexact);
+    }
+    // `f64` is displayed as the shortest decimal that round trips, without an exponent
+    parse_rational(&num.to_string()).ok_or(EvalError::Inexact)
+}
+
+/// Replaces the largest sub-expressions of `expr` that can be evaluated exactly, i.e. that contain no identifiers and
+/// only rational operations, with their value. For example, `x * (0.1 + 0.2)` is folded to `x * 3/10`.
+pub fn fold_exact(expr: &mut Expr<RBig>) {
+    let bindings = HashMap::new();
+    // the paths are in pre-order, so the sub-expressions of a folded expression are visited after it
+    let paths = expr.find_paths(|expr| !matches!(expr, Expr::Literal(_)));
+    let mut folded: Vec<Vec<usize>> = Vec::new();
+    for path in paths {
+        if folded.iter().any(|ancestor| path.starts_with(ancestor)) {
+            continue;
+        }
+        let sub_expr = expr.get(&path).expect("path should be valid");
+        if let Ok(value) = eval_exact(sub_expr, &bindings) {
+            expr.replace_at(&path, Expr::Literal(value));
+            folded.push(path);
+        }
+    }
+}
+
+/// Same as [`crate::eval::eval`] except that the expression is evaluated exactly with rationals.
+///
+/// Operations whose result is not rational (e.g. `2 ^ 0.5`) return [`EvalError::Inexact`].
+pub fn eval_exact(expr: &Expr<RBig>, bindings: &HashMap<String, RBig>) -> Result<RBig, EvalError> {
+    let mut tasks = vec![Task::Eval(expr)];
+    let mut values: Vec<RBig> = Vec::new();
+
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Eval(expr) => match expr {
+                Expr::Literal(num) => values.push(num.clone()),
+                // the built-in constants are irrational
+                Expr::Constant(_) => return Err(EvalError::Inexact),
+                Expr::Undefined => return Err(EvalError::Undefined),
+                Expr::Identifier(ident) => match bindings.get(ident) {
+                    Some(value) => values.push(value.clone()),
+                    None => return Err(EvalError::UnboundIdentifier(ident.clone())),
+                },
+                Expr::Binary { left, op: _, right } => {
+                    tasks.push(Task::Apply(expr));
+                    tasks.push(Task::Eval(right));
+                    tasks.push(Task::Eval(left));
+                }
+                Expr::Unary { op: _, right } => {
+                    tasks.push(Task::Apply(expr));
+                    tasks.push(Task::Eval(right));
+                }
+                Expr::Call { func, args } => {
+                    // all the built-in functions take a single argument
+                    if functions::lookup(func).is_none() {
+                        return Err(EvalError::UnknownFunction(func.clone()));
+                    }
+                    if args.len() != 1 {
+                        return Err(EvalError::WrongArgumentCount {
+                            func: func.clone(),
+                            expected: 1,
+                            found: args.len(),
+                        });
+                    }
+                    tasks.push(Task::Apply(expr));
+                    tasks.push(Task::Eval(&args[0]));
+                }
+                Expr::Integral { .. } => return Err(EvalError::Integral),
+                Expr::Vector(_) => return Err(EvalError::Vector),
+                Expr::Error => return Err(EvalError::InvalidExpr),
+            },
+            Task::Apply(expr) => {
+                let value = match expr {
+                    Expr::Binary {
+                        left: _,
+                        op,
+                        right: _,
+                    } => {
+                        let right = values.pop().expect("right operand should be evaluated");
+                        let left = values.pop().expect("left operand should be evaluated");
+                        match op {
+                            BinOpKind::Plus => left + right,
+                            BinOpKind::Minus => left - right,
+                            BinOpKind::Asterisk => left * right,
+                            BinOpKind::Slash => {
+                                if right == RBig::ZERO {
+                                    return Err(EvalError::DivisionByZero);
+                                }
+                                left / right
+                            }
+                            BinOpKind::Exponent => pow(left, right)?,
+                        }
+                    }
+                    Expr::Unary {
+                        op: UnaryOpKind::Minus,
+                        right: _,
+                    } => -values.pop().expect("operand should be evaluated"),
+                    Expr::Call { func, args: _ } => {
+                        let arg = values.pop().expect("argument should be evaluated");
+                        match func.as_str() {
+                            "abs" => {
+                                if arg < RBig::ZERO {
+                                    -arg
+                                } else {
+                                    arg
+                                }
+                            }
+                            "sign" => {
+                                if arg > RBig::ZERO {
+                                    RBig::ONE
+                                } else if arg < RBig::ZERO {
+                                    -RBig::ONE
+                                } else {
+                                    RBig::ZERO
+                                }
+                            }
+                            "sqrt" if arg == RBig::ZERO || arg == RBig::ONE => arg,
+                            "ln" | "log" if arg == RBig::ONE => RBig::ZERO,
+                            "exp" if arg == RBig::ZERO => RBig::ONE,
+                            "sin" | "tan" | "asin" | "atan" if arg == RBig::ZERO => RBig::ZERO,
+                            "cos" if arg == RBig::ZERO => RBig::ONE,
+                            "acos" if arg == RBig::ONE => RBig::ZERO,
+                            // e.g. `exp(1)`, square roots of other perfect squares such as `sqrt(4)` are not detected
+                            _ => return Err(EvalError::Inexact),
+                        }
+                    }
+                    Expr::Literal(_)
+                    | Expr::Identifier(_)
+                    | Expr::Constant(_)
+                    | Expr::Integral { .. }
+                    | Expr::Vector(_)
+                    | Expr::Undefined
+                    | Expr::Error => {
+                        unreachable!("atoms are evaluated directly")
+                    }
+                };
+                values.push(value);
+            }
+        }
+    }
+
+    Ok(values
+        .pop()
+        .expect("value of the input should be on the value stack"))
+}
+
+/// Raises `base` to an integer `exponent`.
+fn pow(base: RBig, exponent: RBig) -> Result<RBig, EvalError> {
+    if *exponent.denominator() != UBig::ONE {
+        return Err(EvalError::Inexact);
+    }
+    let n = i64::try_from(exponent.numerator())
+        .ok()
+        .and_then(|n| usize::try_from(n.unsigned_abs()).ok())
+        .ok_or(EvalError::Inexact)?;
+    if exponent < RBig::ZERO {
+        if base == RBig::ZERO {
+            return Err(EvalError::DivisionByZero);
+        }
+        Ok(RBig::ONE / base.pow(n))
+    } else {
+        Ok(base.pow(n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_str(input: &str) -> Result<String, EvalError> {
+        let (expr, _) = parse_exact(input);
+        eval_exact(&expr, &HashMap::new()).map(|value| value.to_string())
+    }
+
+    #[test]
+    fn test_eval_exact() {
+        assert_eq!(eval_str("0.1 + 0.2"), Ok("3/10".to_string()));
+        assert_eq!(eval_str("1 / 3 + 1"), Ok("4/3".to_string()));
+        assert_eq!(
+            eval_str("10 ^ 17 + 1"),
+            Ok("100000000000000001".to_string())
+        );
+        assert_eq!(eval_str("2 ^ -2"), Ok("1/4".to_string()));
+        assert_eq!(eval_str("abs(-1 / 2)"), Ok("1/2".to_string()));
+        assert_eq!(eval_str("2 ^ 0.5"), Err(EvalError::Inexact));
+        assert_eq!(eval_str("ln(1) + exp(0)"), Ok("1".to_string()));
+        assert_eq!(eval_str("exp(1)"), Err(EvalError::Inexact));
+        assert_eq!(eval_str("cos(0) + asin(0) - acos(1)"), Ok("1".to_string()));
+        assert_eq!(eval_str("sin(1)"), Err(EvalError::Inexact));
+        assert_eq!(eval_str("1 / 0"), Err(EvalError::DivisionByZero));
+        assert_eq!(eval_str("√4"), Err(EvalError::Inexact));
+        assert_eq!(eval_str("2⁻² + .5"), Ok("3/4".to_string()));
+    }
+
+    #[test]
+    fn test_parse_exact() {
+        // beyond the precision of `f64`
+        assert_eq!(
+            eval_str("100_000_000_000_000_000_001 - 100_000_000_000_000_000_000"),
+            Ok("1".to_string())
+        );
+        assert_eq!(
+            eval_str("0.100000000000000000001 - 0.1"),
+            Ok("1/1000000000000000000000".to_string())
+        );
+        // beyond the range of `f64`
+        let input = format!("1{} / 10 ^ 400", "0".repeat(400));
+        assert_eq!(eval_str(&input), Ok("1".to_string()));
+        assert_eq!(eval_str("-0.1 * 10"), Ok("-1".to_string()));
+
+        let (expr, diagnostics) = parse_exact("1 + (2 3)");
+        assert_eq!(expr, Expr::Error);
+        assert!(diagnostics.has_errors());
+    }
+
+    #[test]
+    fn test_fold_exact() {
+        let (mut expr, _) = parse_exact("x * (0.1 + 0.2) + sin(1 - 1) + 2 ^ 0.5");
+        fold_exact(&mut expr);
+        let rational = |numerator: i32, denominator: u32| {
+            Expr::Literal(RBig::from_parts(numerator.into(), denominator.into()))
+        };
+        assert_eq!(expr.get(&[0, 0, 1]), Some(&rational(3, 10)));
+        assert_eq!(expr.get(&[0, 1]), Some(&rational(0, 1)));
+        assert!(matches!(expr.get(&[1]), Some(Expr::Binary { .. })));
+    }
+}
@@ -0,0 +1,189 @@
+//! Numeric integration of functions of one variable, e.g. for definite integrals that have no closed form.
+//!
+//! [`integrate`] uses adaptive Simpson's rule: an interval is split in half until Simpson's rule gives the same value
+//! on the whole interval as on its halves. The intervals left to refine are kept on an explicit stack like the other
+//! passes.
+
+use std::fmt;
+
+/// The tolerance used by callers that do not need a specific one, e.g. [`crate::geometry::arc_length`].
+pub const DEFAULT_TOLERANCE: f64 = 1e-10;
+
+/// The most intervals [`integrate`] splits, to keep an integrand that never converges from freezing the app.
+pub const MAX_INTERVALS: usize = 100_000;
+
+/// The number of times every interval is split regardless of the error estimate, so that a periodic integrand is not
+/// mistaken for a constant one.
+const MIN_DEPTH: u32 = 4;
+
+/// An error that occurred while integrating numerically.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum QuadratureError {
+    /// A bound or the tolerance is not finite, or the tolerance is not positive.
+    InvalidRange,
+    /// The integrand is not finite at the value, e.g. `1 / x` at `0`.
+    NotFinite(f64),
+    /// The tolerance was not reached after splitting [`MAX_INTERVALS`] intervals.
+    NotConverged,
+}
+
+impl fmt::Display for QuadratureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuadratureError::InvalidRange => write!(
+                f,
+                "the bounds must be finite and the tolerance must be greater than 0"
+            ),
+            QuadratureError::NotFinite(x) => write!(f, "the integrand is not finite at {}", x),
+            QuadratureError::NotConverged => write!(
+                f,
+                "the integral did not converge after {} intervals",
+                MAX_INTERVALS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QuadratureError {}
+
+/// An interval left to refine, with the values of the integrand at its ends and midpoint.
+struct Interval {
+    a: f64,
+    b: f64,
+    fa: f64,
+    fm: f64,
+    fb: f64,
+    /// The value of Simpson's rule on the whole interval.
+    whole: f64,
+    tolerance: f64,
+    depth: u32,
+}
+
+/// Computes the integral of `f` from `a` to `b` to within about `tolerance`. The integral is negated if `b < a`.
+///
+/// The integrand must be finite at every value it is evaluated at, which includes both bounds.
+pub fn integrate(
+    f: impl Fn(f64) -> f64,
+    a: f64,
+    b: f64,
+    tolerance: f64,
+) -> Result<f64, QuadratureError> {
+    if !a.is_finite() || !b.is_finite() || !tolerance.is_finite() || tolerance <= 0.0 {
+        return Err(QuadratureError::InvalidRange);
+    }
+    if b < a {
+        return integrate(f, b, a, tolerance).map(|res| -res);
+    }
+    let eval = |x: f64| {
+        let y = f(x);
+        if y.is_finite() {
+            Ok(y)
+        } else {
+            Err(QuadratureError::NotFinite(x))
+        }
+    };
+
+    let (fa, fm, fb) = (eval(a)?, eval((a + b) / 2.0)?, eval(b)?);
+    let mut intervals = vec![Interval {
+        a,
+        b,
+        fa,
+        fm,
+        fb,
+        whole: simpson(a, b, fa, fm, fb),
+        tolerance,
+        depth: 0,
+    }];
+    let (mut res, mut splits) = (0.0, 0);
+    while let Some(interval) = intervals.pop() {
+        let Interval {
+            a,
+            b,
+            fa,
+            fm,
+            fb,
+            whole,
+            tolerance,
+            depth,
+        } = interval;
+        let m = (a + b) / 2.0;
+        let (flm, frm) = (eval((a + m) / 2.0)?, eval((m + b) / 2.0)?);
+        let (left, right) = (simpson(a, m, fa, flm, fm), simpson(m, b, fm, frm, fb));
+        let error = left + right - whole;
+        // the error of the halves is about 1/15 of the difference, see Richardson extrapolation
+        if depth >= MIN_DEPTH && error.abs() <= 15.0 * tolerance {
+            res += left + right + error / 15.0;
+            continue;
+        }
+
+        splits += 1;
+        if splits > MAX_INTERVALS {
+            return Err(QuadratureError::NotConverged);
+        }
+        let tolerance = tolerance / 2.0;
+        let depth = depth + 1;
+        intervals.push(Interval {
+            a,
+            b: m,
+            fa,
+            fm: flm,
+            fb: fm,
+            whole: left,
+            tolerance,
+            depth,
+        });
+        intervals.push(Interval {
+            a: m,
+            b,
+            fa: fm,
+            fm: frm,
+            fb,
+            whole: right,
+            tolerance,
+            depth,
+        });
+    }
+    Ok(res)
+}
+
+/// Simpson's rule on `[a, b]`, where `fm` is the value at the midpoint.
+fn simpson(a: f64, b: f64, fa: f64, fm: f64, fb: f64) -> f64 {
+    (b - a) / 6.0 * (fa + 4.0 * fm + fb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_integrate() {
+        let res = integrate(|x| x * x, 0.0, 3.0, DEFAULT_TOLERANCE).unwrap();
+        assert!((res - 9.0).abs() < 1e-9);
+        let res = integrate(f64::sin, 0.0, PI, DEFAULT_TOLERANCE).unwrap();
+        assert!((res - 2.0).abs() < 1e-9);
+        // one period of `sin(8 * x)` has the same values at the points of the coarsest intervals
+        let res = integrate(|x| (8.0 * x).sin().powi(2), 0.0, PI, DEFAULT_TOLERANCE).unwrap();
+        assert!((res - PI / 2.0).abs() < 1e-9);
+
+        let res = integrate(f64::exp, 1.0, 0.0, DEFAULT_TOLERANCE).unwrap();
+        assert!((res - (1.0 - 1.0_f64.exp())).abs() < 1e-9);
+        assert_eq!(integrate(f64::exp, 2.0, 2.0, DEFAULT_TOLERANCE), Ok(0.0));
+    }
+
+    #[test]
+    fn test_integrate_errors() {
+        assert_eq!(
+            integrate(|x| 1.0 / x, -1.0, 1.0, DEFAULT_TOLERANCE),
+            Err(QuadratureError::NotFinite(0.0))
+        );
+        assert_eq!(
+            integrate(|x| x, 0.0, f64::INFINITY, DEFAULT_TOLERANCE),
+            Err(QuadratureError::InvalidRange)
+        );
+        assert_eq!(
+            integrate(|x| x, 0.0, 1.0, 0.0),
+            Err(QuadratureError::InvalidRange)
+        );
+    }
+}
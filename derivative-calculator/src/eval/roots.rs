@@ -0,0 +1,133 @@
+//! Numeric root finding for functions of one variable, e.g. to locate the zeros of a derivative that cannot be solved
+//! for symbolically.
+
+use std::ops::RangeInclusive;
+
+/// The number of intervals used by callers that do not need a specific one, e.g.
+/// [`crate::behavior::inflection_points`].
+pub const DEFAULT_SAMPLES: usize = 1000;
+
+/// The number of bisection steps, which is enough to reach the precision of `f64` on any finite interval.
+const MAX_BISECTIONS: usize = 1100;
+
+/// Returns the zeros of `f` in `range` in ascending order.
+///
+/// The range is split into `samples` intervals of the same width and every interval where `f` changes sign is
+/// bisected. Zeros where `f` touches 0 without changing sign, e.g. of `x ^ 2`, are only found if they happen to be at
+/// the end of an interval, and two zeros in the same interval cancel out. Sign changes at a pole, e.g. of `1 / x`, are
+/// not zeros and are left out. An empty or infinite range has no zeros.
+pub fn find_roots(f: impl Fn(f64) -> f64, range: RangeInclusive<f64>, samples: usize) -> Vec<f64> {
+    scan(f, range, samples)
+        .into_iter()
+        .filter(|(_, is_zero)| *is_zero)
+        .map(|(x, _)| x)
+        .collect()
+}
+
+/// Same as [`find_roots`] except that the sign changes at poles are included, e.g. `0` for `1 / x`, as well as the
+/// points where `f` stops being finite, e.g. `0` for `x ^ 0.5`, which are only located up to the width of an interval.
+/// These are the points that split `range` into intervals where `f` is defined and has a single sign.
+pub fn find_sign_changes(
+    f: impl Fn(f64) -> f64,
+    range: RangeInclusive<f64>,
+    samples: usize,
+) -> Vec<f64> {
+    scan(f, range, samples)
+        .into_iter()
+        .map(|(x, _)| x)
+        .collect()
+}
+
+/// Returns the points where `f` is 0 or changes sign in `range` in ascending order, along with whether the point is a
+/// zero rather than a pole.
+fn scan(f: impl Fn(f64) -> f64, range: RangeInclusive<f64>, samples: usize) -> Vec<(f64, bool)> {
+    let (start, end) = range.into_inner();
+    if !start.is_finite() || !end.is_finite() || start > end || samples == 0 {
+        return Vec::new();
+    }
+
+    let width = (end - start) / samples as f64;
+    let point = |i: usize| {
+        if i == samples {
+            end
+        } else {
+            start + i as f64 * width
+        }
+    };
+    let mut points: Vec<(f64, bool)> = Vec::new();
+    let mut push = |x: f64, is_zero: bool| {
+        if points.last().map(|(last, _)| *last) != Some(x) {
+            points.push((x, is_zero));
+        }
+    };
+    let (mut a, mut fa) = (start, f(start));
+    for i in 1..=samples {
+        let b = point(i);
+        let fb = f(b);
+        if fa == 0.0 {
+            push(a, true);
+        } else if fa.is_finite() && fb.is_finite() {
+            if fa.signum() != fb.signum() && fb != 0.0 {
+                let x = bisect(&f, (a, fa), b);
+                // the values get larger towards a pole
+                push(x, f(x).abs() <= fa.abs().min(fb.abs()));
+            }
+        } else if fa.is_finite() != fb.is_finite() {
+            // a pole or the end of the domain, which is only located up to the width of an interval
+            push(if fa.is_finite() { b } else { a }, false);
+        }
+        (a, fa) = (b, fb);
+    }
+    if fa == 0.0 {
+        push(a, true);
+    }
+    points
+}
+
+/// Narrows down `[a, b]`, where `f(a)` and `f(b)` have different signs, to the value where `f` changes sign.
+fn bisect(f: impl Fn(f64) -> f64, (mut a, fa): (f64, f64), mut b: f64) -> f64 {
+    for _ in 0..MAX_BISECTIONS {
+        let m = a + (b - a) / 2.0;
+        if m <= a || m >= b {
+            break;
+        }
+        let fm = f(m);
+        if fm == 0.0 {
+            return m;
+        }
+        if fm.signum() == fa.signum() {
+            a = m;
+        } else {
+            b = m;
+        }
+    }
+    a + (b - a) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_find_roots() {
+        let roots = find_roots(|x| x * x - 2.0, -3.0..=3.0, DEFAULT_SAMPLES);
+        assert_eq!(roots.len(), 2);
+        assert!((roots[0] + 2.0_f64.sqrt()).abs() < 1e-12);
+        assert!((roots[1] - 2.0_f64.sqrt()).abs() < 1e-12);
+
+        let roots = find_roots(f64::sin, -1.0..=7.0, DEFAULT_SAMPLES);
+        assert_eq!(roots.len(), 3);
+        assert!(roots[0].abs() < 1e-12);
+        assert!((roots[2] - 2.0 * PI).abs() < 1e-12);
+
+        // the zeros at the ends of the range are found as well
+        assert_eq!(find_roots(|x| x * (x - 1.0), 0.0..=1.0, 10), [0.0, 1.0]);
+        // not a zero
+        assert!(find_roots(|x| 1.0 / x, -1.0..=2.0, 10).is_empty());
+        assert!(find_roots(|x| x, 1.0..=-1.0, 10).is_empty());
+        assert_eq!(find_sign_changes(|x| 1.0 / x, -1.0..=2.0, 10).len(), 1);
+        assert_eq!(find_sign_changes(|x| 1.0 / x, -1.0..=1.0, 10), [0.0]);
+        assert_eq!(find_sign_changes(f64::sqrt, -1.0..=1.0, 4), [-0.5, 0.0]);
+    }
+}
@@ -0,0 +1,62 @@
+//! Example inputs that show the supported syntax, e.g. for a first-time user of a frontend.
+
+/// An example input.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Example {
+    /// A short description of what the example shows.
+    pub name: &'static str,
+    pub input: &'static str,
+}
+
+/// All the examples, from the simplest to the most advanced.
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        name: "Polynomial",
+        input: "3 * x ^ 3 - 2 * x ^ 2 + x - 5",
+    },
+    Example {
+        name: "Product",
+        input: "x ^ 2 * (x + 1)",
+    },
+    Example {
+        name: "Quotient",
+        input: "(x + 1) / (x - 1)",
+    },
+    Example {
+        name: "Chain rule",
+        input: "(2 * x + 1) ^ 3",
+    },
+    Example {
+        name: "Root",
+        input: "x ^ (1 / 2)",
+    },
+    Example {
+        name: "Absolute value",
+        input: "abs(x ^ 2 - 4)",
+    },
+    Example {
+        name: "Partial derivative",
+        input: "diff(x ^ 2 * y ^ 3, y)",
+    },
+    Example {
+        name: "Undefined function",
+        input: "f(x ^ 2)",
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::Limits;
+    use crate::pipeline::run_pipeline;
+
+    #[test]
+    fn test_examples_are_supported() {
+        for example in EXAMPLES {
+            let report = run_pipeline(example.input, Limits::default(), || 0.0);
+            assert_eq!(report.error, None, "{}", example.name);
+            assert!(report.diagnostics.is_empty(), "{}", example.name);
+            assert!(report.derivative.is_some(), "{}", example.name);
+        }
+    }
+}
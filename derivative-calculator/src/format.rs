@@ -0,0 +1,221 @@
+//! Conversion of expressions to and from the syntax of other computer algebra systems and interchange formats.
+//!
+//! Unlike the [`std::fmt::Display`] impl of [`Expr`], which fully parenthesizes every operation, the foreign syntaxes
+//! only use parens where they are needed. See [`ExprFormatter`] for printing with custom options.
+
+mod formatter;
+pub mod mathjson;
+pub mod mathml;
+pub mod sympy;
+pub mod wolfram;
+
+pub use formatter::{ExprFormatter, Parens, Spacing};
+
+use crate::constants::Constant;
+use crate::parser::{walk_expr, BinOpKind, Expr, ExprVisitor};
+use std::fmt::Write;
+
+/// The differences between the supported foreign syntaxes, in addition to the [`ExprFormatter`] options.
+#[derive(Debug)]
+pub(crate) struct Syntax {
+    /// The brackets around the arguments of a function call.
+    pub call_brackets: (&'static str, &'static str),
+    /// The names of the built-in functions in the foreign syntax, if different.
+    pub function_names: &'static [(&'static str, &'static str)],
+    /// The name of the square root function, which is converted to `^ 0.5` when importing.
+    pub sqrt: &'static str,
+    /// The names of the built-in constants in the foreign syntax, if different.
+    pub constant_names: &'static [(Constant, &'static str)],
+    /// The name of the integral function and the brackets around the variable and the bounds of a definite integral,
+    /// e.g. `Integral(x, (x, 0, 1))`.
+    pub integral: (&'static str, (&'static str, &'static str)),
+    /// The brackets around the components of a vector, e.g. `{` and `}`.
+    pub vector_brackets: (&'static str, &'static str),
+    /// Whether `log` is written as the logarithm with the base 10 as the second argument, e.g. `log(x, 10)` for SymPy,
+    /// which has no function of its own for it.
+    pub log_with_base: bool,
+}
+
+impl Syntax {
+    /// Returns the name of the function `name` in the foreign syntax.
+    pub(crate) fn export_name<'a>(&self, name: &'a str) -> &'a str {
+        self.function_names
+            .iter()
+            .find(|(ours, _)| *ours == name)
+            .map_or(name, |(_, foreign)| foreign)
+    }
+
+    /// Returns the name of the function `func` called with `args` in the foreign syntax and the arguments to append,
+    /// e.g. `log` and `, 10` for `log(x)` if [`Syntax::log_with_base`] is set.
+    pub(crate) fn export_call<'a>(&self, func: &'a str, args: &[Expr]) -> (&'a str, &'static str) {
+        if self.log_with_base && func == "log" && args.len() == 1 {
+            (self.export_name("ln"), ", 10")
+        } else {
+            (self.export_name(func), "")
+        }
+    }
+
+    /// Returns the name of `constant` in the foreign syntax.
+    pub(crate) fn export_constant(&self, constant: Constant) -> &'static str {
+        self.constant_names
+            .iter()
+            .find(|(ours, _)| *ours == constant)
+            .map_or(constant.name(), |(_, foreign)| foreign)
+    }
+}
+
+/// Returns a [Graphviz](https://graphviz.org/) graph of the expression tree in the DOT language, e.g. for rendering
+/// with `dot -Tsvg`. The children of a node are ordered left to right.
+pub fn dot(expr: &Expr) -> String {
+    let mut out = String::from("digraph {\n    ordering=out;\n");
+    let mut next_id = 0;
+    // (expr, id of the parent node)
+    let mut stack = vec![(expr, None)];
+    while let Some((expr, parent)) = stack.pop() {
+        let id = next_id;
+        next_id += 1;
+        let (label, children): (String, Vec<&Expr>) = match expr {
+            Expr::Literal(num) => (num.to_string(), Vec::new()),
+            Expr::Identifier(ident) => (ident.clone(), Vec::new()),
+            Expr::Binary { left, op, right } => (op.to_string(), vec![left, right]),
+            Expr::Unary { op, right } => (op.to_string(), vec![right]),
+            Expr::Call { func, args } => (format!("{}()", func), args.iter().collect()),
+            Expr::Integral { var, .. } => (format!("int d{}", var), expr.children()),
+            Expr::Vector(components) => ("[]".to_string(), components.iter().collect()),
+            Expr::Constant(constant) => (constant.name().to_string(), Vec::new()),
+            Expr::Undefined => ("undefined".to_string(), Vec::new()),
+            Expr::Error => ("err".to_string(), Vec::new()),
+        };
+        let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+        writeln!(out, "    n{} [label=\"{}\"];", id, label).unwrap();
+        if let Some(parent) = parent {
+            writeln!(out, "    n{} -> n{};", parent, id).unwrap();
+        }
+        // pushed in reverse so that the first child is numbered first
+        stack.extend(children.into_iter().rev().map(|child| (child, Some(id))));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Converts the function calls and the constants of an expression parsed from a foreign syntax to the built-in
+/// functions and constants. Calls of the integral function are converted to [`Expr::Integral`] if the bounds are in a
+/// vector, e.g. `Integrate[t^2, {t, 0, x}]` after the brackets are replaced, and a vector in a call of the vector
+/// function, e.g. `Matrix([t, 1])`, is unwrapped. A logarithm with a base, e.g. `log(x, 10)`, is converted to `log(x)`
+/// for the base 10 and to a quotient of natural logarithms otherwise.
+pub(crate) struct ImportFunctions<'a>(pub &'a Syntax);
+
+impl ExprVisitor for ImportFunctions<'_> {
+    fn visit(&mut self, expr: &mut Expr) {
+        walk_expr(expr, self);
+        if let Expr::Identifier(ident) = expr {
+            if let Some((ours, _)) = self
+                .0
+                .constant_names
+                .iter()
+                .find(|(_, foreign)| foreign == ident)
+            {
+                *expr = Expr::Constant(*ours);
+            }
+        } else if let Expr::Call { func, args } = expr {
+            // e.g. `Matrix` for the brackets `Matrix([` and `])`
+            let vector_func = self
+                .0
+                .vector_brackets
+                .0
+                .strip_suffix('[')
+                .and_then(|brackets| brackets.strip_suffix(self.0.call_brackets.0));
+            if func == self.0.integral.0 {
+                if let Some(integral) = import_integral(args) {
+                    *expr = integral;
+                }
+            } else if Some(func.as_str()) == vector_func
+                && matches!(args.as_slice(), [Expr::Vector(_)])
+            {
+                *expr = args.pop().unwrap();
+            } else if self.0.log_with_base && func == self.0.export_name("ln") && args.len() == 2 {
+                let base = args.pop().unwrap();
+                let arg = args.pop().unwrap();
+                *expr = if base == Expr::Literal(10.0) {
+                    Expr::Call {
+                        func: "log".to_string(),
+                        args: vec![arg],
+                    }
+                } else {
+                    let ln = |arg| Expr::Call {
+                        func: "ln".to_string(),
+                        args: vec![arg],
+                    };
+                    Expr::binary(ln(arg), BinOpKind::Slash, ln(base))
+                };
+            } else if func == self.0.sqrt && args.len() == 1 {
+                *expr = Expr::Binary {
+                    left: Box::new(args.pop().unwrap()),
+                    op: BinOpKind::Exponent,
+                    right: Box::new(Expr::Literal(0.5)),
+                };
+            } else if let Some((ours, _)) = self
+                .0
+                .function_names
+                .iter()
+                .find(|(_, foreign)| foreign == func)
+            {
+                *func = ours.to_string();
+            }
+        }
+    }
+}
+
+/// Returns the integral with the arguments `args` of a call of the foreign integral function, which are the integrand
+/// and either the variable or a vector of the variable and the bounds.
+fn import_integral(args: &mut [Expr]) -> Option<Expr> {
+    match args {
+        [integrand, Expr::Identifier(var)] => Some(Expr::Integral {
+            integrand: Box::new(integrand.take()),
+            var: std::mem::take(var),
+            bounds: None,
+        }),
+        [integrand, Expr::Vector(qualifiers)] => match qualifiers.as_mut_slice() {
+            [Expr::Identifier(var), lower, upper] => Some(Expr::Integral {
+                integrand: Box::new(integrand.take()),
+                var: std::mem::take(var),
+                bounds: Some(Box::new((lower.take(), upper.take()))),
+            }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use expect_test::expect;
+    use logos::Logos;
+
+    #[test]
+    fn test_dot() {
+        let expr = Parser::from(Token::lexer("2 * x - abs(-y)").spanned()).parse();
+        expect![[r#"
+            digraph {
+                ordering=out;
+                n0 [label="-"];
+                n1 [label="*"];
+                n0 -> n1;
+                n2 [label="2"];
+                n1 -> n2;
+                n3 [label="x"];
+                n1 -> n3;
+                n4 [label="abs()"];
+                n0 -> n4;
+                n5 [label="-"];
+                n4 -> n5;
+                n6 [label="y"];
+                n5 -> n6;
+            }
+        "#]]
+        .assert_eq(&dot(&expr));
+    }
+}
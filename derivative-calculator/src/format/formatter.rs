@@ -0,0 +1,577 @@
+//! Configurable printing of expressions.
+
+use super::Syntax;
+use crate::constants::Constant;
+use crate::parser::{BinOpKind, Expr, UnaryOpKind};
+use std::fmt;
+
+/// Where to put parens when printing an expression.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Parens {
+    /// Every operation is parenthesized, e.g. `((2 * x) + 1)`.
+    All,
+    /// Only the parens needed to preserve the structure of the expression, e.g. `2 * x + 1`.
+    Minimal,
+}
+
+/// Where to put spaces around binary operators.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Spacing {
+    /// No spaces, e.g. `2*x+1`.
+    None,
+    /// Only around `+` and `-`, e.g. `2*x + 1`.
+    Additive,
+    /// Around every binary operator, e.g. `2 * x + 1`.
+    All,
+}
+
+/// Prints expressions with configurable options. The [`std::fmt::Display`] impl of [`Expr`] uses the default options
+/// and the exporters in [`crate::format`] use their own presets.
+#[derive(Debug, Clone)]
+pub struct ExprFormatter {
+    parens: Parens,
+    spacing: Spacing,
+    pow: &'static str,
+    implicit_mul: bool,
+    precision: Option<usize>,
+    /// The syntax of function calls, `None` for the built-in syntax.
+    syntax: Option<&'static Syntax>,
+}
+
+impl Default for ExprFormatter {
+    fn default() -> Self {
+        Self {
+            parens: Parens::All,
+            spacing: Spacing::All,
+            pow: "^",
+            implicit_mul: false,
+            precision: None,
+            syntax: None,
+        }
+    }
+}
+
+// precedence of the printed operations, higher binds tighter
+const PREC_ADD: u8 = 1;
+const PREC_MUL: u8 = 2;
+const PREC_NEG: u8 = 3;
+const PREC_POW: u8 = 4;
+const PREC_ATOM: u8 = 5;
+
+/// An item on the work stack used when printing an [`Expr`].
+enum Item<'a> {
+    Expr(&'a Expr),
+    Text(&'a str),
+    Op(String),
+}
+
+impl ExprFormatter {
+    /// Sets where to put parens. Defaults to [`Parens::All`].
+    pub fn with_parens(mut self, parens: Parens) -> Self {
+        self.parens = parens;
+        self
+    }
+
+    /// Sets where to put spaces around binary operators. Defaults to [`Spacing::All`].
+    pub fn with_spacing(mut self, spacing: Spacing) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    /// Sets the exponentiation operator, e.g. `**`. Defaults to `^`.
+    pub fn with_pow(mut self, pow: &'static str) -> Self {
+        self.pow = pow;
+        self
+    }
+
+    /// Omits the `*` between a number and a following name, e.g. `2x` instead of `2 * x`. Note that the parser does
+    /// not accept implicit multiplication.
+    pub fn with_implicit_mul(mut self, implicit_mul: bool) -> Self {
+        self.implicit_mul = implicit_mul;
+        self
+    }
+
+    /// Rounds numbers to at most `precision` decimal places, e.g. `0.333` for `0.3333` with a precision of 3.
+    pub fn with_precision(mut self, precision: Option<usize>) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Writes function calls in a foreign syntax.
+    pub(crate) fn with_syntax(mut self, syntax: &'static Syntax) -> Self {
+        self.syntax = Some(syntax);
+        self
+    }
+
+    /// Prints `expr` with the options of the formatter.
+    pub fn format(&self, expr: &Expr) -> String {
+        let mut out = String::new();
+        self.write(expr, &mut out)
+            .expect("writing to a string should not fail");
+        out
+    }
+
+    /// Same as [`ExprFormatter::format`] but writes to `out`.
+    pub fn write(&self, expr: &Expr, out: &mut impl fmt::Write) -> fmt::Result {
+        // Use an explicit stack instead of recursion to not overflow the call stack on deeply nested expressions.
+        // Items are pushed in reverse order of printing.
+        let mut stack = vec![Item::Expr(expr)];
+        if self.parens == Parens::All && prec(expr) < PREC_ATOM {
+            stack = vec![Item::Text(")"), Item::Expr(expr), Item::Text("(")];
+        }
+
+        while let Some(item) = stack.pop() {
+            match item {
+                Item::Expr(expr) => match expr {
+                    Expr::Literal(num) => out.write_str(&self.number(*num))?,
+                    Expr::Identifier(ident) => out.write_str(ident)?,
+                    Expr::Constant(constant) => out.write_str(self.constant_name(*constant))?,
+                    Expr::Binary { left, op, right } => {
+                        let wrap_right = self.wraps_right(*op, right);
+                        // `d / dx` would be parsed as a derivative request, e.g. `(d) / dx` is printed instead
+                        let wrap_left = self.needs_parens(left, min_operand_prec(*op).0)
+                            || *op == BinOpKind::Slash
+                                && !wrap_right
+                                && self.first_char(right) == Some('d')
+                                && self.ends_with_d(left);
+                        let implicit = *op == BinOpKind::Asterisk
+                            && self.implicit_mul
+                            && matches!(**left, Expr::Literal(num) if num >= 0.0)
+                            && !wrap_right
+                            && self.first_char(right).is_some_and(char::is_alphabetic);
+
+                        self.push_operand(&mut stack, right, wrap_right);
+                        if !implicit {
+                            stack.push(Item::Op(self.op(*op)));
+                        }
+                        self.push_operand(&mut stack, left, wrap_left);
+                    }
+                    Expr::Unary {
+                        op: UnaryOpKind::Minus,
+                        right,
+                    } => {
+                        out.write_str("-")?;
+                        // a negated number is parsed as a negative literal, e.g. `-(2)` is printed for the negation of `2`
+                        let wrap = self.needs_parens(right, self.min_negated_prec())
+                            || matches!(**right, Expr::Literal(_));
+                        self.push_operand(&mut stack, right, wrap);
+                    }
+                    Expr::Call { func, args } => {
+                        let ((name, extra_args), brackets) = match self.syntax {
+                            Some(syntax) => (syntax.export_call(func, args), syntax.call_brackets),
+                            None => ((func.as_str(), ""), ("(", ")")),
+                        };
+                        write!(out, "{}{}", name, brackets.0)?;
+                        stack.extend([Item::Text(brackets.1), Item::Text(extra_args)]);
+                        for (i, arg) in args.iter().enumerate().rev() {
+                            // the call already has parens so only negations need them
+                            let wrap = self.parens == Parens::All && prec(arg) == PREC_NEG;
+                            self.push_operand(&mut stack, arg, wrap);
+                            if i > 0 {
+                                stack.push(Item::Text(", "));
+                            }
+                        }
+                    }
+                    Expr::Integral {
+                        integrand,
+                        var,
+                        bounds,
+                    } => {
+                        let (brackets, bounds_brackets) = match self.syntax {
+                            Some(syntax) => (syntax.call_brackets, syntax.integral.1),
+                            None => (("(", ")"), ("", "")),
+                        };
+                        write!(out, "{}{}", self.integral_name(), brackets.0)?;
+                        stack.push(Item::Text(brackets.1));
+                        let wrap = |arg| self.parens == Parens::All && prec(arg) == PREC_NEG;
+                        match bounds.as_deref() {
+                            Some((lower, upper)) => {
+                                stack.push(Item::Text(bounds_brackets.1));
+                                self.push_operand(&mut stack, upper, wrap(upper));
+                                stack.push(Item::Text(", "));
+                                self.push_operand(&mut stack, lower, wrap(lower));
+                                stack.extend([
+                                    Item::Text(", "),
+                                    Item::Text(var),
+                                    Item::Text(bounds_brackets.0),
+                                ]);
+                            }
+                            None => stack.push(Item::Text(var)),
+                        }
+                        stack.push(Item::Text(", "));
+                        self.push_operand(&mut stack, integrand, wrap(integrand));
+                    }
+                    Expr::Vector(components) => {
+                        let brackets = self.vector_brackets();
+                        out.write_str(brackets.0)?;
+                        stack.push(Item::Text(brackets.1));
+                        for (i, component) in components.iter().enumerate().rev() {
+                            // the brackets already delimit the components so only negations need parens
+                            let wrap = self.parens == Parens::All && prec(component) == PREC_NEG;
+                            self.push_operand(&mut stack, component, wrap);
+                            if i > 0 {
+                                stack.push(Item::Text(", "));
+                            }
+                        }
+                    }
+                    Expr::Undefined => out.write_str("undefined")?,
+                    Expr::Error => out.write_str("err")?,
+                },
+                Item::Text(text) => out.write_str(text)?,
+                Item::Op(op) => out.write_str(&op)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pushes an operand onto the work stack, in parens if `wrap` is set.
+    fn push_operand<'a>(&self, stack: &mut Vec<Item<'a>>, operand: &'a Expr, wrap: bool) {
+        if wrap {
+            stack.extend([Item::Text(")"), Item::Expr(operand), Item::Text("(")]);
+        } else {
+            stack.push(Item::Expr(operand));
+        }
+    }
+
+    /// Returns whether `expr` is parenthesized as an operand that must bind at least as tightly as `min_prec`.
+    fn needs_parens(&self, expr: &Expr, min_prec: u8) -> bool {
+        match self.parens {
+            Parens::All => prec(expr) < PREC_ATOM,
+            Parens::Minimal => prec(expr) < min_prec,
+        }
+    }
+
+    /// Returns whether the right operand `right` of `op` is parenthesized.
+    fn wraps_right(&self, op: BinOpKind, right: &Expr) -> bool {
+        // an operand starting with a negation on the right is always parenthesized, e.g. `x - (-1)` rather than
+        // `x - -1`
+        let min_right = if self.first_char(right) == Some('-') {
+            PREC_ATOM
+        } else {
+            min_operand_prec(op).1
+        };
+        self.needs_parens(right, min_right)
+    }
+
+    /// Returns the min precedence of the operand of a negation that can be written without parens. The built-in
+    /// syntax binds the negation tighter than `^`, e.g. `-x ^ 2` is `(-x) ^ 2`, unlike the foreign syntaxes.
+    fn min_negated_prec(&self) -> u8 {
+        match self.syntax {
+            Some(_) => PREC_POW,
+            None => PREC_ATOM,
+        }
+    }
+
+    /// Returns whether the last token that is printed for `expr` is the identifier `d`.
+    fn ends_with_d(&self, mut expr: &Expr) -> bool {
+        // only the right operands can be printed last
+        loop {
+            match expr {
+                Expr::Binary { left: _, op, right } if !self.wraps_right(*op, right) => {
+                    expr = right
+                }
+                Expr::Unary { op: _, right }
+                    if !self.needs_parens(right, self.min_negated_prec()) =>
+                {
+                    expr = right
+                }
+                Expr::Identifier(ident) => return ident == "d",
+                _ => return false,
+            }
+        }
+    }
+
+    /// Returns the first character that is printed for `expr`.
+    fn first_char(&self, mut expr: &Expr) -> Option<char> {
+        // only the left operands can be printed first
+        while let Expr::Binary { left, op, right: _ } = expr {
+            if self.needs_parens(left, min_operand_prec(*op).0) {
+                return Some('(');
+            }
+            expr = left;
+        }
+        match expr {
+            Expr::Literal(num) => self.number(*num).chars().next(),
+            Expr::Identifier(name)
+            | Expr::Call {
+                func: name,
+                args: _,
+            } => name.chars().next(),
+            Expr::Constant(constant) => self.constant_name(*constant).chars().next(),
+            Expr::Unary { op: _, right: _ } => Some('-'),
+            Expr::Integral { .. } => self.integral_name().chars().next(),
+            Expr::Vector(_) => self.vector_brackets().0.chars().next(),
+            Expr::Binary { .. } => unreachable!("binary operations are skipped above"),
+            Expr::Undefined => Some('u'),
+            Expr::Error => Some('e'),
+        }
+    }
+
+    /// Returns the name of `constant`, e.g. `pi`.
+    fn constant_name(&self, constant: Constant) -> &'static str {
+        self.syntax
+            .map_or(constant.name(), |syntax| syntax.export_constant(constant))
+    }
+
+    /// Returns the name of the integral function, e.g. `int`.
+    fn integral_name(&self) -> &'static str {
+        self.syntax.map_or("int", |syntax| syntax.integral.0)
+    }
+
+    /// Returns the brackets around the components of a vector, e.g. `[` and `]`.
+    fn vector_brackets(&self) -> (&'static str, &'static str) {
+        self.syntax
+            .map_or(("[", "]"), |syntax| syntax.vector_brackets)
+    }
+
+    /// Returns the binary operator with the configured spacing.
+    fn op(&self, op: BinOpKind) -> String {
+        let spaced = match op {
+            BinOpKind::Plus | BinOpKind::Minus => self.spacing != Spacing::None,
+            _ => self.spacing == Spacing::All,
+        };
+        let op = match op {
+            BinOpKind::Exponent => self.pow.to_string(),
+            op => op.to_string(),
+        };
+        if spaced {
+            format!(" {} ", op)
+        } else {
+            op
+        }
+    }
+
+    /// Writes a literal, rounded to the precision if there is one.
+    fn number(&self, num: f64) -> String {
+        match self.precision {
+            Some(precision) if num.is_finite() => {
+                let rounded = format!("{:.*}", precision, num);
+                let rounded = if rounded.contains('.') {
+                    rounded.trim_end_matches('0').trim_end_matches('.')
+                } else {
+                    &rounded
+                };
+                // do not print `-0`
+                if rounded.trim_start_matches('-') == "0" {
+                    "0".to_string()
+                } else {
+                    rounded.to_string()
+                }
+            }
+            _ => num.to_string(),
+        }
+    }
+}
+
+/// Returns the min precedence of the left and right operands of `op` that can be written without parens.
+fn min_operand_prec(op: BinOpKind) -> (u8, u8) {
+    match op {
+        BinOpKind::Plus => (PREC_ADD, PREC_ADD),
+        BinOpKind::Minus => (PREC_ADD, PREC_MUL),
+        BinOpKind::Asterisk => (PREC_MUL, PREC_MUL),
+        BinOpKind::Slash => (PREC_MUL, PREC_NEG),
+        // right associative and the exponent is always parenthesized, e.g. `x^(-2)`
+        BinOpKind::Exponent => (PREC_ATOM, PREC_ATOM),
+    }
+}
+
+/// Returns the precedence of the outermost operation of `expr`.
+fn prec(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Literal(num) if *num < 0.0 => PREC_NEG,
+        Expr::Literal(_)
+        | Expr::Identifier(_)
+        | Expr::Constant(_)
+        | Expr::Call { .. }
+        | Expr::Integral { .. }
+        | Expr::Vector(_)
+        | Expr::Undefined
+        | Expr::Error => PREC_ATOM,
+        Expr::Binary {
+            left: _,
+            op,
+            right: _,
+        } => match op {
+            BinOpKind::Plus | BinOpKind::Minus => PREC_ADD,
+            BinOpKind::Asterisk | BinOpKind::Slash => PREC_MUL,
+            BinOpKind::Exponent => PREC_POW,
+        },
+        Expr::Unary { op: _, right: _ } => PREC_NEG,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+    use proptest::prelude::*;
+
+    fn parse(input: &str) -> Expr {
+        Parser::from(Token::lexer(input).spanned()).parse()
+    }
+
+    #[test]
+    fn test_formatter_options() {
+        let expr = parse("2 * x ^ 2 - (-3) * x / (y + 1) + 0.123456");
+        let minimal = ExprFormatter::default().with_parens(Parens::Minimal);
+        assert_eq!(
+            minimal.format(&expr),
+            "2 * x ^ 2 - (-3 * x / (y + 1)) + 0.123456"
+        );
+        let cases = [
+            (
+                minimal.clone().with_spacing(Spacing::None),
+                "2*x^2-(-3*x/(y+1))+0.123456",
+            ),
+            (
+                minimal
+                    .clone()
+                    .with_spacing(Spacing::Additive)
+                    .with_pow("**"),
+                "2*x**2 - (-3*x/(y + 1)) + 0.123456",
+            ),
+            (
+                minimal
+                    .clone()
+                    .with_implicit_mul(true)
+                    .with_precision(Some(3)),
+                "2x ^ 2 - (-3 * x / (y + 1)) + 0.123",
+            ),
+            (
+                ExprFormatter::default().with_precision(Some(2)),
+                "(((2 * (x ^ 2)) - (((-3) * x) / (y + 1))) + 0.12)",
+            ),
+        ];
+        for (formatter, expected) in cases {
+            assert_eq!(formatter.format(&expr), expected);
+        }
+    }
+
+    #[test]
+    fn test_formatter_default_matches_display() {
+        for input in [
+            "1 + 2 * -x ^ 2 - y",
+            "abs(-x) + f(x - 1, -2)",
+            "-(-(x))",
+            "x",
+        ] {
+            let expr = parse(input);
+            assert_eq!(ExprFormatter::default().format(&expr), expr.to_string());
+        }
+    }
+
+    /// Names matching `regex` that are parsed as identifiers, i.e. not the name of a constant such as `e`.
+    fn identifier(regex: &'static str) -> impl Strategy<Value = String> {
+        regex.prop_filter("constants are not identifiers", |name| {
+            constants::lookup(name).is_none()
+        })
+    }
+
+    /// Expressions that can be printed, i.e. without [`Expr::Error`], non-finite literals or names that are not
+    /// identifiers.
+    fn printable_expr() -> impl Strategy<Value = Expr> {
+        let leaf = prop_oneof![
+            prop::num::f64::POSITIVE
+                | prop::num::f64::NEGATIVE
+                | prop::num::f64::NORMAL
+                | prop::num::f64::SUBNORMAL
+                | prop::num::f64::ZERO,
+            // small integers are the most likely to be printed like signed literals or superscripts
+            (-3..=3).prop_map(f64::from),
+        ]
+        .prop_map(Expr::Literal);
+        let leaf = prop_oneof![
+            leaf,
+            identifier("d[a-z]?|[a-z]{1,3}'?").prop_map(Expr::Identifier),
+            prop::sample::select(Constant::ALL.to_vec()).prop_map(Expr::Constant),
+        ];
+        leaf.prop_recursive(6, 48, 3, |inner| {
+            let op = prop_oneof![
+                Just(BinOpKind::Plus),
+                Just(BinOpKind::Minus),
+                Just(BinOpKind::Asterisk),
+                Just(BinOpKind::Slash),
+                Just(BinOpKind::Exponent),
+            ];
+            prop_oneof![
+                (inner.clone(), op, inner.clone()).prop_map(|(left, op, right)| Expr::Binary {
+                    left: Box::new(left),
+                    op,
+                    right: Box::new(right),
+                }),
+                inner.clone().prop_map(|right| Expr::Unary {
+                    op: UnaryOpKind::Minus,
+                    right: Box::new(right),
+                }),
+                // including the names of the derivatives of undefined functions, e.g. `f'` or `f_1`
+                (
+                    "d|[a-z]{1,4}(_[1-9]|'{1,2})?",
+                    prop::collection::vec(inner.clone(), 0..3)
+                )
+                    .prop_filter("`int` is parsed as an integral", |(func, _)| func != "int")
+                    .prop_filter("`sqrt(x)` is parsed as `x ^ 0.5`", |(func, args)| {
+                        func != "sqrt" || args.len() != 1
+                    })
+                    .prop_map(|(func, args)| Expr::Call { func, args }),
+                prop::collection::vec(inner.clone(), 0..3).prop_map(Expr::Vector),
+                (
+                    inner.clone(),
+                    identifier("[a-z]{1,3}"),
+                    prop::option::of((inner.clone(), inner))
+                )
+                    .prop_map(|(integrand, var, bounds)| Expr::Integral {
+                        integrand: Box::new(integrand),
+                        var,
+                        bounds: bounds.map(Box::new),
+                    }),
+            ]
+        })
+    }
+
+    proptest! {
+        // only the fully parenthesized printing round trips, the minimal parens do not preserve the grouping of
+        // associative operations, e.g. `x * (y * z)` is printed as `x * y * z`
+        #[test]
+        fn test_round_trip(expr in printable_expr()) {
+            let printed = expr.to_string();
+            prop_assert_eq!(&parse(&printed), &expr, "printed as {}", printed);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_edge_cases() {
+        let d = || Expr::Identifier("d".to_string());
+        let dx = || Expr::Identifier("dx".to_string());
+        let minimal = ExprFormatter::default().with_parens(Parens::Minimal);
+        let cases = [
+            (parse("-(2)"), "(-(2))", "-(2)"),
+            (parse("-(-2)"), "(-(-2))", "-(-2)"),
+            (parse("-x ^ 2"), "((-x) ^ 2)", "(-x) ^ 2"),
+            (parse("-(x ^ 2)"), "(-(x ^ 2))", "-(x ^ 2)"),
+            (
+                Expr::binary(d(), BinOpKind::Slash, dx()),
+                "((d) / dx)",
+                "(d) / dx",
+            ),
+            (
+                Expr::binary(
+                    Expr::binary(dx(), BinOpKind::Asterisk, d()),
+                    BinOpKind::Slash,
+                    dx(),
+                ),
+                "((dx * d) / dx)",
+                "(dx * d) / dx",
+            ),
+        ];
+        for (expr, display, minimal_str) in cases {
+            assert_eq!(expr.to_string(), display);
+            assert_eq!(minimal.format(&expr), minimal_str);
+            assert_eq!(parse(display), expr);
+            assert_eq!(parse(minimal_str), expr);
+        }
+    }
+}
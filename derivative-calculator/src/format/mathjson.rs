@@ -0,0 +1,464 @@
+//! Conversion to and from [MathJSON](https://cortexjs.io/math-json/), e.g. `["Add", ["Power", "x", 2], 1]`.
+
+use crate::constants::Constant;
+use crate::functions;
+use crate::parser::{BinOpKind, Expr, UnaryOpKind};
+use serde_json::{json, Value};
+use std::fmt;
+
+/// The MathJSON names of the built-in functions.
+const FUNCTION_NAMES: &[(&str, &str)] = &[
+    ("abs", "Abs"),
+    ("sign", "Sign"),
+    ("sqrt", "Sqrt"),
+    ("ln", "Ln"),
+    ("log", "Log"),
+    ("exp", "Exp"),
+    ("sin", "Sin"),
+    ("cos", "Cos"),
+    ("tan", "Tan"),
+    ("asin", "Arcsin"),
+    ("acos", "Arccos"),
+    ("atan", "Arctan"),
+];
+
+/// The MathJSON symbols of the built-in constants.
+const CONSTANT_NAMES: &[(Constant, &str)] = &[(Constant::Pi, "Pi"), (Constant::E, "ExponentialE")];
+
+/// An error that occurred while converting MathJSON to an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MathJsonError {
+    /// The input is not valid JSON.
+    Json(String),
+    /// The value is not a MathJSON expression, e.g. `true`.
+    InvalidValue(String),
+    /// A function was applied to the wrong number of arguments.
+    WrongArgumentCount { func: String, found: usize },
+}
+
+impl fmt::Display for MathJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MathJsonError::Json(err) => write!(f, "invalid JSON: {}", err),
+            MathJsonError::InvalidValue(value) => {
+                write!(f, "`{}` is not a MathJSON expression", value)
+            }
+            MathJsonError::WrongArgumentCount { func, found } => {
+                write!(f, "`{}` cannot be applied to {} argument(s)", func, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MathJsonError {}
+
+/// A pending step when converting an expression.
+enum Task<'a> {
+    /// Convert the expression and push the result onto the output stack.
+    Convert(&'a Expr),
+    /// Pop the converted operands off the output stack and combine them.
+    Combine(&'a Expr),
+}
+
+/// Converts `expr` to MathJSON.
+pub fn to_mathjson(expr: &Expr) -> Value {
+    let mut tasks = vec![Task::Convert(expr)];
+    let mut out: Vec<Value> = Vec::new();
+
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Convert(expr) => match expr {
+                Expr::Literal(num) => out.push(number(*num)),
+                Expr::Identifier(ident) => out.push(json!(ident)),
+                Expr::Constant(constant) => {
+                    let (_, sym) = CONSTANT_NAMES
+                        .iter()
+                        .find(|(ours, _)| ours == constant)
+                        .expect("every constant has a MathJSON symbol");
+                    out.push(json!(sym))
+                }
+                Expr::Binary { left, op: _, right } => {
+                    tasks.push(Task::Combine(expr));
+                    tasks.push(Task::Convert(right));
+                    tasks.push(Task::Convert(left));
+                }
+                Expr::Unary { op: _, right } => {
+                    tasks.push(Task::Combine(expr));
+                    tasks.push(Task::Convert(right));
+                }
+                Expr::Call { func: _, args } => {
+                    tasks.push(Task::Combine(expr));
+                    tasks.extend(args.iter().rev().map(Task::Convert));
+                }
+                Expr::Integral { .. } => {
+                    tasks.push(Task::Combine(expr));
+                    tasks.extend(expr.children().into_iter().rev().map(Task::Convert));
+                }
+                Expr::Vector(components) => {
+                    tasks.push(Task::Combine(expr));
+                    tasks.extend(components.iter().rev().map(Task::Convert));
+                }
+                Expr::Undefined => out.push(json!("Undefined")),
+                Expr::Error => out.push(json!(["Error"])),
+            },
+            Task::Combine(expr) => {
+                let (head, args) = match expr {
+                    Expr::Binary {
+                        left: _,
+                        op,
+                        right: _,
+                    } => {
+                        let head = match op {
+                            BinOpKind::Plus => "Add",
+                            BinOpKind::Minus => "Subtract",
+                            BinOpKind::Asterisk => "Multiply",
+                            BinOpKind::Slash => "Divide",
+                            BinOpKind::Exponent => "Power",
+                        };
+                        (head, out.split_off(out.len() - 2))
+                    }
+                    Expr::Unary {
+                        op: UnaryOpKind::Minus,
+                        right: _,
+                    } => ("Negate", out.split_off(out.len() - 1)),
+                    Expr::Call { func, args } => {
+                        let head = FUNCTION_NAMES
+                            .iter()
+                            .find(|(ours, _)| ours == func)
+                            .map_or(func.as_str(), |(_, mathjson)| mathjson);
+                        (head, out.split_off(out.len() - args.len()))
+                    }
+                    // `["Integrate", f, "x"]` or `["Integrate", f, ["Limits", "x", lower, upper]]`
+                    Expr::Integral { var, bounds, .. } => {
+                        let range = match bounds {
+                            Some(_) => {
+                                let bounds = out.split_off(out.len() - 2);
+                                json!(["Limits", var, bounds[0], bounds[1]])
+                            }
+                            None => json!(var),
+                        };
+                        let integrand = out.pop().expect("integrand should be converted");
+                        ("Integrate", vec![integrand, range])
+                    }
+                    Expr::Vector(components) => {
+                        ("List", out.split_off(out.len() - components.len()))
+                    }
+                    Expr::Literal(_)
+                    | Expr::Identifier(_)
+                    | Expr::Constant(_)
+                    | Expr::Undefined
+                    | Expr::Error => {
+                        unreachable!("atoms are converted directly")
+                    }
+                };
+                let mut value = vec![json!(head)];
+                value.extend(args);
+                out.push(Value::Array(value));
+            }
+        }
+    }
+
+    out.pop()
+        .expect("converted input should be on the output stack")
+}
+
+/// Converts a literal to a MathJSON number. Numbers that JSON cannot represent use the object form.
+fn number(num: f64) -> Value {
+    if num.is_nan() {
+        json!({ "num": "NaN" })
+    } else if num.is_infinite() {
+        json!({ "num": if num > 0.0 { "+Infinity" } else { "-Infinity" } })
+    } else {
+        json!(num)
+    }
+}
+
+/// A pending step when converting MathJSON.
+enum JsonTask<'a> {
+    /// Convert the value and push the expression onto the output stack.
+    Convert(&'a Value),
+    /// Pop the converted arguments off the output stack and apply the function to them.
+    Apply { head: &'a str, args: usize },
+}
+
+/// Converts MathJSON to an expression. Both the shorthand (`["Add", "x", 1]`) and the object forms
+/// (`{"fn": ["Add", {"sym": "x"}, {"num": "1"}]}`) are supported.
+pub fn from_mathjson(value: &Value) -> Result<Expr, MathJsonError> {
+    let mut tasks = vec![JsonTask::Convert(value)];
+    let mut out: Vec<Expr> = Vec::new();
+    let invalid = |value: &Value| MathJsonError::InvalidValue(value.to_string());
+
+    while let Some(task) = tasks.pop() {
+        match task {
+            JsonTask::Convert(value) => match value {
+                Value::Number(num) => {
+                    out.push(Expr::Literal(num.as_f64().ok_or_else(|| invalid(value))?))
+                }
+                Value::String(sym) if sym == "Undefined" => out.push(Expr::Undefined),
+                Value::String(sym) => match CONSTANT_NAMES.iter().find(|(_, name)| name == sym) {
+                    Some((constant, _)) => out.push(Expr::Constant(*constant)),
+                    None => out.push(Expr::Identifier(sym.clone())),
+                },
+                Value::Array(items) => match items.split_first() {
+                    Some((Value::String(head), args)) => {
+                        tasks.push(JsonTask::Apply {
+                            head,
+                            args: args.len(),
+                        });
+                        tasks.extend(args.iter().rev().map(JsonTask::Convert));
+                    }
+                    _ => return Err(invalid(value)),
+                },
+                Value::Object(object) => {
+                    match (object.get("num"), object.get("sym"), object.get("fn")) {
+                        (Some(Value::String(num)), None, None) => {
+                            let num = match num.as_str() {
+                                "+Infinity" => f64::INFINITY,
+                                "-Infinity" => f64::NEG_INFINITY,
+                                num => num.parse().map_err(|_| invalid(value))?,
+                            };
+                            out.push(Expr::Literal(num));
+                        }
+                        (Some(num @ Value::Number(_)), None, None) => {
+                            tasks.push(JsonTask::Convert(num))
+                        }
+                        (None, Some(sym @ Value::String(_)), None) => {
+                            tasks.push(JsonTask::Convert(sym))
+                        }
+                        (None, None, Some(func @ Value::Array(_))) => {
+                            tasks.push(JsonTask::Convert(func))
+                        }
+                        _ => return Err(invalid(value)),
+                    }
+                }
+                Value::Null | Value::Bool(_) => return Err(invalid(value)),
+            },
+            JsonTask::Apply { head, args } => {
+                let args = out.split_off(out.len() - args);
+                out.push(apply(head, args)?);
+            }
+        }
+    }
+
+    Ok(out
+        .pop()
+        .expect("converted input should be on the output stack"))
+}
+
+/// Parses a MathJSON string. See [`from_mathjson`].
+pub fn parse_mathjson(input: &str) -> Result<Expr, MathJsonError> {
+    let value: Value =
+        serde_json::from_str(input).map_err(|err| MathJsonError::Json(err.to_string()))?;
+    from_mathjson(&value)
+}
+
+/// Applies the MathJSON function `head` to `args`.
+fn apply(head: &str, mut args: Vec<Expr>) -> Result<Expr, MathJsonError> {
+    let binary = |left, op, right| Expr::Binary {
+        left: Box::new(left),
+        op,
+        right: Box::new(right),
+    };
+    if let ("Integrate", [integrand, range]) = (head, args.as_slice()) {
+        if let Some(integral) = integral(integrand, range) {
+            return Ok(integral);
+        }
+    }
+    Ok(match (head, args.len()) {
+        // n-ary functions are folded left to right
+        ("Add", 1..) | ("Multiply", 1..) => {
+            let op = if head == "Add" {
+                BinOpKind::Plus
+            } else {
+                BinOpKind::Asterisk
+            };
+            let mut args = args.into_iter();
+            let first = args.next().unwrap();
+            args.fold(first, |acc, arg| binary(acc, op, arg))
+        }
+        ("Negate", 1) => Expr::Unary {
+            op: UnaryOpKind::Minus,
+            right: Box::new(args.pop().unwrap()),
+        },
+        ("Subtract", 2) | ("Divide", 2) | ("Rational", 2) | ("Power", 2) | ("Root", 2) => {
+            let right = args.pop().unwrap();
+            let left = args.pop().unwrap();
+            match head {
+                "Subtract" => binary(left, BinOpKind::Minus, right),
+                "Divide" | "Rational" => binary(left, BinOpKind::Slash, right),
+                "Power" => binary(left, BinOpKind::Exponent, right),
+                _ => binary(
+                    left,
+                    BinOpKind::Exponent,
+                    binary(Expr::Literal(1.0), BinOpKind::Slash, right),
+                ),
+            }
+        }
+        ("Sqrt", 1) => binary(args.pop().unwrap(), BinOpKind::Exponent, Expr::Literal(0.5)),
+        ("List", _) => Expr::Vector(args),
+        ("Error", _) => Expr::Error,
+        (
+            "Add" | "Multiply" | "Negate" | "Subtract" | "Divide" | "Rational" | "Power" | "Root"
+            | "Sqrt",
+            found,
+        ) => {
+            return Err(MathJsonError::WrongArgumentCount {
+                func: head.to_string(),
+                found,
+            })
+        }
+        _ => {
+            let func = FUNCTION_NAMES
+                .iter()
+                .find(|(_, mathjson)| *mathjson == head)
+                .map_or(head, |(ours, _)| ours);
+            if let Some(function) = functions::lookup(func) {
+                if function.arity != args.len() {
+                    return Err(MathJsonError::WrongArgumentCount {
+                        func: head.to_string(),
+                        found: args.len(),
+                    });
+                }
+            }
+            Expr::Call {
+                func: func.to_string(),
+                args,
+            }
+        }
+    })
+}
+
+/// Converts `["Integrate", integrand, range]` where `range` is `"x"` or `["Limits", "x", lower, upper]`. Returns
+/// `None` for other ranges, which are kept as a call.
+fn integral(integrand: &Expr, range: &Expr) -> Option<Expr> {
+    let (var, bounds) = match range {
+        Expr::Identifier(var) => (var, None),
+        Expr::Call { func, args } if func == "Limits" => match args.as_slice() {
+            [Expr::Identifier(var), lower, upper] => {
+                (var, Some(Box::new((lower.clone(), upper.clone()))))
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(Expr::Integral {
+        integrand: Box::new(integrand.clone()),
+        var: var.clone(),
+        bounds,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+
+    fn parse(input: &str) -> Expr {
+        Parser::from(Token::lexer(input).spanned()).parse()
+    }
+
+    #[test]
+    fn test_to_mathjson() {
+        let cases = [
+            ("x ^ 2 + 1", r#"["Add",["Power","x",2.0],1.0]"#),
+            (
+                "-(x - 0.5) / y",
+                r#"["Divide",["Negate",["Subtract","x",0.5]],"y"]"#,
+            ),
+            (
+                "abs(x) * sign(x)",
+                r#"["Multiply",["Abs","x"],["Sign","x"]]"#,
+            ),
+            ("f(x, 1)", r#"["f","x",1.0]"#),
+            (
+                "int(t, t, 0, x)",
+                r#"["Integrate","t",["Limits","t",0.0,"x"]]"#,
+            ),
+            ("[t, 1]", r#"["List","t",1.0]"#),
+            (
+                "e ^ (pi * x)",
+                r#"["Power","ExponentialE",["Multiply","Pi","x"]]"#,
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                to_mathjson(&parse(input)).to_string(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+        assert_eq!(
+            to_mathjson(&Expr::Literal(f64::NEG_INFINITY)).to_string(),
+            r#"{"num":"-Infinity"}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_mathjson() {
+        let cases = [
+            (r#"["Add", ["Power", "x", 2], 1]"#, "((x ^ 2) + 1)"),
+            (r#"["Add", "x", "y", "z"]"#, "((x + y) + z)"),
+            (r#"["Negate", ["Abs", "x"]]"#, "(-abs(x))"),
+            (r#"["Sqrt", "x"]"#, "(x ^ 0.5)"),
+            (r#"["Root", "x", 3]"#, "(x ^ (1 / 3))"),
+            (r#"["Rational", 1, 3]"#, "(1 / 3)"),
+            (r#"["Multiply", "Pi", "ExponentialE"]"#, "(pi * e)"),
+            (
+                r#"{"fn": ["Multiply", {"num": "2"}, {"sym": "x"}]}"#,
+                "(2 * x)",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                parse_mathjson(input).unwrap().to_string(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_mathjson_errors() {
+        assert!(matches!(
+            parse_mathjson("[\"Add\""),
+            Err(MathJsonError::Json(_))
+        ));
+        assert_eq!(
+            parse_mathjson("true"),
+            Err(MathJsonError::InvalidValue("true".to_string()))
+        );
+        assert_eq!(
+            parse_mathjson(r#"["Power", "x"]"#),
+            Err(MathJsonError::WrongArgumentCount {
+                func: "Power".to_string(),
+                found: 1
+            })
+        );
+        assert_eq!(
+            parse_mathjson(r#"["Abs", "x", "y"]"#),
+            Err(MathJsonError::WrongArgumentCount {
+                func: "Abs".to_string(),
+                found: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_mathjson_round_trip() {
+        for input in [
+            "((x ^ 2) + 1)",
+            "(-abs((x / 2)))",
+            "f(x, y)",
+            "int(x, x)",
+            "int(t, t, 0, x)",
+            "[t ^ 2, (-t)]",
+        ] {
+            let expr = parse(input);
+            assert_eq!(from_mathjson(&to_mathjson(&expr)).unwrap(), expr);
+        }
+    }
+}
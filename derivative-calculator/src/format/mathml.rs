@@ -0,0 +1,548 @@
+//! Conversion to and from Content MathML, e.g. `<apply><plus/><ci>x</ci><cn>1</cn></apply>`, which is used by many
+//! educational platforms to store expressions.
+
+use crate::constants::{self, Constant};
+use crate::parser::{BinOpKind, Expr, UnaryOpKind};
+use roxmltree::{Document, Node};
+use std::fmt;
+
+/// The built-in functions that are MathML operator elements, e.g. `<sin/>`, with the name of the element. The other
+/// functions are written with `<ci>`, e.g. `<apply><ci>sign</ci><ci>x</ci></apply>`.
+const FUNCTION_NAMES: &[(&str, &str)] = &[
+    ("abs", "abs"),
+    ("ln", "ln"),
+    ("log", "log"),
+    ("exp", "exp"),
+    ("sin", "sin"),
+    ("cos", "cos"),
+    ("tan", "tan"),
+    ("asin", "arcsin"),
+    ("acos", "arccos"),
+    ("atan", "arctan"),
+];
+
+/// The MathML elements of the built-in constants.
+const CONSTANT_NAMES: &[(Constant, &str)] = &[(Constant::Pi, "pi"), (Constant::E, "exponentiale")];
+
+/// An error that occurred while parsing Content MathML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MathMlError {
+    /// The input is not well-formed XML.
+    Xml(String),
+    /// The element is not supported, e.g. `<lambda>`.
+    UnsupportedElement(String),
+    /// An operator was applied to the wrong number of arguments.
+    WrongArgumentCount { op: String, found: usize },
+    /// The content of a `<cn>` element is not a number.
+    InvalidNumber(String),
+}
+
+impl fmt::Display for MathMlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MathMlError::Xml(err) => write!(f, "invalid XML: {}", err),
+            MathMlError::UnsupportedElement(name) => {
+                write!(f, "unsupported MathML element `<{}>`", name)
+            }
+            MathMlError::WrongArgumentCount { op, found } => {
+                write!(f, "`<{}>` cannot be applied to {} argument(s)", op, found)
+            }
+            MathMlError::InvalidNumber(num) => write!(f, "`{}` is not a valid number", num),
+        }
+    }
+}
+
+impl std::error::Error for MathMlError {}
+
+/// A pending step when converting a MathML element.
+enum Task<'a, 'input> {
+    /// Convert the element and push the expression onto the output stack.
+    Convert(Node<'a, 'input>),
+    /// Pop the converted arguments of an `<apply>` element off the output stack and apply the operator to them.
+    Apply {
+        op: Node<'a, 'input>,
+        args: usize,
+        /// The qualifiers, e.g. `<degree>`, which were pushed before the arguments.
+        qualifiers: Vec<Node<'a, 'input>>,
+    },
+    /// Pop the converted components of a `<vector>` element off the output stack.
+    Vector(usize),
+}
+
+/// A pending step when converting an expression to MathML.
+enum ExportTask<'a> {
+    /// Convert the expression and push the result onto the output stack.
+    Convert(&'a Expr),
+    /// Pop the converted operands off the output stack and combine them.
+    Combine(&'a Expr),
+}
+
+/// Converts `expr` to a Content MathML `<math>` element. [`parse_mathml`] converts it back to the same expression.
+pub fn to_mathml(expr: &Expr) -> String {
+    let mut tasks = vec![ExportTask::Convert(expr)];
+    let mut out: Vec<String> = Vec::new();
+
+    while let Some(task) = tasks.pop() {
+        match task {
+            ExportTask::Convert(expr) => match expr {
+                Expr::Literal(num) => out.push(format!("<cn>{}</cn>", num)),
+                Expr::Identifier(ident) => out.push(format!("<ci>{}</ci>", escape(ident))),
+                Expr::Constant(constant) => {
+                    let (_, element) = CONSTANT_NAMES
+                        .iter()
+                        .find(|(ours, _)| ours == constant)
+                        .expect("every constant has a MathML element");
+                    out.push(format!("<{}/>", element));
+                }
+                Expr::Undefined => out.push("<notanumber/>".to_string()),
+                Expr::Error => out.push("<cerror/>".to_string()),
+                _ => {
+                    tasks.push(ExportTask::Combine(expr));
+                    tasks.extend(expr.children().into_iter().rev().map(ExportTask::Convert));
+                }
+            },
+            ExportTask::Combine(expr) => {
+                let children = out.split_off(out.len() - expr.children().len());
+                let converted = match expr {
+                    Expr::Binary {
+                        left: _,
+                        op,
+                        right: _,
+                    } => {
+                        let element = match op {
+                            BinOpKind::Plus => "plus",
+                            BinOpKind::Minus => "minus",
+                            BinOpKind::Asterisk => "times",
+                            BinOpKind::Slash => "divide",
+                            BinOpKind::Exponent => "power",
+                        };
+                        format!("<apply><{}/>{}</apply>", element, children.concat())
+                    }
+                    Expr::Unary {
+                        op: UnaryOpKind::Minus,
+                        right: _,
+                    } => format!("<apply><minus/>{}</apply>", children.concat()),
+                    Expr::Call { func, args: _ } => {
+                        let op = match FUNCTION_NAMES.iter().find(|(ours, _)| ours == func) {
+                            Some((_, element)) => format!("<{}/>", element),
+                            None => format!("<ci>{}</ci>", escape(func)),
+                        };
+                        format!("<apply>{}{}</apply>", op, children.concat())
+                    }
+                    Expr::Integral { var, bounds, .. } => {
+                        let mut children = children.into_iter();
+                        let integrand = children.next().expect("integrand should be converted");
+                        let limits = match bounds {
+                            Some(_) => format!(
+                                "<lowlimit>{}</lowlimit><uplimit>{}</uplimit>",
+                                children.next().expect("lower bound should be converted"),
+                                children.next().expect("upper bound should be converted")
+                            ),
+                            None => String::new(),
+                        };
+                        format!(
+                            "<apply><int/><bvar><ci>{}</ci></bvar>{}{}</apply>",
+                            escape(var),
+                            limits,
+                            integrand
+                        )
+                    }
+                    Expr::Vector(_) => format!("<vector>{}</vector>", children.concat()),
+                    Expr::Literal(_)
+                    | Expr::Identifier(_)
+                    | Expr::Constant(_)
+                    | Expr::Undefined
+                    | Expr::Error => unreachable!("atoms are converted directly"),
+                };
+                out.push(converted);
+            }
+        }
+    }
+
+    format!(
+        "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">{}</math>",
+        out.pop()
+            .expect("converted input should be on the output stack")
+    )
+}
+
+/// Escapes the characters of `text` that are special in XML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Parses a Content MathML expression. The input may either be a `<math>` element or a bare expression element.
+pub fn parse_mathml(input: &str) -> Result<Expr, MathMlError> {
+    let doc = Document::parse(input).map_err(|err| MathMlError::Xml(err.to_string()))?;
+    let mut tasks = vec![Task::Convert(doc.root_element())];
+    let mut out: Vec<Expr> = Vec::new();
+
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Convert(node) => match node.tag_name().name() {
+                "math" | "semantics" | "degree" | "bvar" | "lowlimit" | "uplimit" => {
+                    tasks.push(Task::Convert(single_child(node)?))
+                }
+                "ci" => out.push(match constants::lookup(text(node)) {
+                    Some(constant) => Expr::Constant(constant),
+                    None => Expr::Identifier(text(node).to_string()),
+                }),
+                "cn" => out.push(number(node)?),
+                "notanumber" => out.push(Expr::Undefined),
+                "cerror" => out.push(Expr::Error),
+                "apply" => {
+                    let mut children = node.children().filter(Node::is_element);
+                    let op = children
+                        .next()
+                        .ok_or_else(|| MathMlError::WrongArgumentCount {
+                            op: "apply".to_string(),
+                            found: 0,
+                        })?;
+                    let (qualifiers, args): (Vec<_>, Vec<_>) = children.partition(|child| {
+                        matches!(
+                            child.tag_name().name(),
+                            "degree" | "bvar" | "lowlimit" | "uplimit"
+                        )
+                    });
+                    tasks.push(Task::Apply {
+                        op,
+                        args: args.len(),
+                        qualifiers: qualifiers.clone(),
+                    });
+                    tasks.extend(args.into_iter().rev().map(Task::Convert));
+                    tasks.extend(qualifiers.into_iter().rev().map(Task::Convert));
+                }
+                "vector" => {
+                    let components: Vec<_> = node.children().filter(Node::is_element).collect();
+                    tasks.push(Task::Vector(components.len()));
+                    tasks.extend(components.into_iter().rev().map(Task::Convert));
+                }
+                name => match CONSTANT_NAMES.iter().find(|(_, mathml)| *mathml == name) {
+                    Some((constant, _)) => out.push(Expr::Constant(*constant)),
+                    None => return Err(MathMlError::UnsupportedElement(name.to_string())),
+                },
+            },
+            Task::Apply {
+                op,
+                args,
+                qualifiers,
+            } => {
+                let args = out.split_off(out.len() - args);
+                let values = out.split_off(out.len() - qualifiers.len());
+                let qualifiers = qualifiers
+                    .iter()
+                    .map(|qualifier| qualifier.tag_name().name())
+                    .zip(values)
+                    .collect();
+                out.push(apply(op, args, qualifiers)?);
+            }
+            Task::Vector(components) => {
+                let components = out.split_off(out.len() - components);
+                out.push(Expr::Vector(components));
+            }
+        }
+    }
+
+    Ok(out
+        .pop()
+        .expect("converted input should be on the output stack"))
+}
+
+/// Applies the operator element `op` (e.g. `<plus/>`) to `args`. `qualifiers` are the converted qualifier elements
+/// with their name, e.g. the `<degree>` of a `<root/>` or the `<bvar>` of an `<int/>`.
+fn apply(
+    op: Node,
+    mut args: Vec<Expr>,
+    qualifiers: Vec<(&str, Expr)>,
+) -> Result<Expr, MathMlError> {
+    let name = op.tag_name().name();
+    let wrong_count = |found| MathMlError::WrongArgumentCount {
+        op: name.to_string(),
+        found,
+    };
+    let names: Vec<&str> = qualifiers.iter().map(|(name, _)| *name).collect();
+    // an integral needs the variable of integration
+    let allowed = matches!(
+        (name, names.as_slice()),
+        ("root", [] | ["degree"]) | ("int", ["bvar"] | ["bvar", "lowlimit", "uplimit"])
+    ) || (name != "int" && names.is_empty());
+    if !allowed {
+        return Err(MathMlError::UnsupportedElement(
+            names.first().unwrap_or(&name).to_string(),
+        ));
+    }
+    let mut qualifiers = qualifiers.into_iter().map(|(_, expr)| expr);
+    let binary = |left, op, right| Expr::Binary {
+        left: Box::new(left),
+        op,
+        right: Box::new(right),
+    };
+
+    Ok(match (name, args.len()) {
+        // n-ary operators are folded left to right
+        ("plus", 1..) | ("times", 1..) => {
+            let op = if name == "plus" {
+                BinOpKind::Plus
+            } else {
+                BinOpKind::Asterisk
+            };
+            let mut args = args.into_iter();
+            let first = args.next().unwrap();
+            args.fold(first, |acc, arg| binary(acc, op, arg))
+        }
+        ("minus", 1) => Expr::Unary {
+            op: UnaryOpKind::Minus,
+            right: Box::new(args.pop().unwrap()),
+        },
+        ("minus", 2) | ("divide", 2) | ("power", 2) => {
+            let op = match name {
+                "minus" => BinOpKind::Minus,
+                "divide" => BinOpKind::Slash,
+                _ => BinOpKind::Exponent,
+            };
+            let right = args.pop().unwrap();
+            let left = args.pop().unwrap();
+            binary(left, op, right)
+        }
+        ("root", 1) => {
+            let exponent = match qualifiers.next() {
+                Some(degree) => binary(Expr::Literal(1.0), BinOpKind::Slash, degree),
+                None => Expr::Literal(0.5),
+            };
+            binary(args.pop().unwrap(), BinOpKind::Exponent, exponent)
+        }
+        // `<apply><int/><bvar><ci>x</ci></bvar><lowlimit>..</lowlimit><uplimit>..</uplimit>integrand</apply>`
+        ("int", 1) => {
+            let var = match qualifiers.next().as_mut() {
+                Some(Expr::Identifier(var)) => std::mem::take(var),
+                _ => return Err(MathMlError::UnsupportedElement("bvar".to_string())),
+            };
+            let bounds = qualifiers.next().zip(qualifiers.next());
+            Expr::Integral {
+                integrand: Box::new(args.pop().unwrap()),
+                var,
+                bounds: bounds.map(Box::new),
+            }
+        }
+        ("plus" | "times" | "minus" | "divide" | "power" | "root" | "int", found) => {
+            return Err(wrong_count(found))
+        }
+        // user defined function, e.g. `<apply><ci>f</ci><ci>x</ci></apply>`
+        ("ci" | "csymbol", _) => Expr::Call {
+            func: text(op).to_string(),
+            args,
+        },
+        // other operators that take arguments, e.g. `<abs/>` or `<sin/>`, are treated as function calls
+        (_, 1..) if !op.has_children() => Expr::Call {
+            func: FUNCTION_NAMES
+                .iter()
+                .find(|(_, mathml)| *mathml == name)
+                .map_or(name, |(ours, _)| ours)
+                .to_string(),
+            args,
+        },
+        _ => return Err(MathMlError::UnsupportedElement(name.to_string())),
+    })
+}
+
+/// Returns the only child element of `node`.
+fn single_child<'a, 'input>(node: Node<'a, 'input>) -> Result<Node<'a, 'input>, MathMlError> {
+    let mut children = node.children().filter(Node::is_element);
+    match (children.next(), children.next()) {
+        (Some(child), None) => Ok(child),
+        (_, found) => Err(MathMlError::WrongArgumentCount {
+            op: node.tag_name().name().to_string(),
+            found: if found.is_some() { 2 } else { 0 },
+        }),
+    }
+}
+
+/// Returns the trimmed text content of `node`.
+fn text<'a>(node: Node<'a, '_>) -> &'a str {
+    node.text().unwrap_or_default().trim()
+}
+
+/// Converts a `<cn>` element. Numbers with a `<sep/>` (`rational` and `e-notation`) are supported as well.
+fn number(node: Node) -> Result<Expr, MathMlError> {
+    let parse = |text: &str| {
+        text.trim()
+            .parse::<f64>()
+            .map_err(|_| MathMlError::InvalidNumber(text.trim().to_string()))
+    };
+    let parts: Vec<&str> = node.children().filter_map(|child| child.text()).collect();
+    match (node.attribute("type"), parts.as_slice()) {
+        (Some("rational"), [numerator, denominator]) => Ok(Expr::Binary {
+            left: Box::new(Expr::Literal(parse(numerator)?)),
+            op: BinOpKind::Slash,
+            right: Box::new(Expr::Literal(parse(denominator)?)),
+        }),
+        (Some("e-notation"), [mantissa, exponent]) => Ok(Expr::Literal(
+            parse(mantissa)? * 10f64.powf(parse(exponent)?),
+        )),
+        (_, [num]) => Ok(Expr::Literal(parse(num)?)),
+        _ => Err(MathMlError::InvalidNumber(parts.concat())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+
+    fn parse(input: &str) -> Expr {
+        Parser::from(Token::lexer(input).spanned()).parse()
+    }
+
+    #[test]
+    fn test_parse_mathml() {
+        let cases = [
+            (
+                "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">
+                    <apply><plus/>
+                        <apply><power/><ci>x</ci><cn>2</cn></apply>
+                        <apply><times/><cn>2</cn><ci>x</ci></apply>
+                        <cn>1</cn>
+                    </apply>
+                </math>",
+                "(((x ^ 2) + (2 * x)) + 1)",
+            ),
+            (
+                "<apply><minus/><apply><divide/><cn>1</cn><ci>x</ci></apply></apply>",
+                "(-(1 / x))",
+            ),
+            (
+                "<apply><abs/><apply><minus/><ci>x</ci><cn>1</cn></apply></apply>",
+                "abs(x - 1)",
+            ),
+            ("<apply><root/><ci>x</ci></apply>", "(x ^ 0.5)"),
+            (
+                "<apply><root/><degree><cn>3</cn></degree><ci>x</ci></apply>",
+                "(x ^ (1 / 3))",
+            ),
+            ("<apply><ci>f</ci><ci>x</ci><ci>y</ci></apply>", "f(x, y)"),
+            ("<cn type=\"rational\">1<sep/>3</cn>", "(1 / 3)"),
+            ("<cn type=\"e-notation\">2.5<sep/>3</cn>", "2500"),
+            (
+                "<vector><ci>t</ci><apply><power/><ci>t</ci><cn>2</cn></apply></vector>",
+                "[t, t ^ 2]",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                parse_mathml(input).unwrap().to_string(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_mathml_constants() {
+        let cases = [
+            ("<apply><times/><cn>2</cn><pi/></apply>", "(2 * pi)"),
+            (
+                "<apply><power/><exponentiale/><ci>x</ci></apply>",
+                "(e ^ x)",
+            ),
+            ("<apply><sin/><ci>pi</ci></apply>", "sin(pi)"),
+            ("<ci>e</ci>", "e"),
+            ("<apply><arctan/><ci>x</ci></apply>", "atan(x)"),
+            ("<notanumber/>", "undefined"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                parse_mathml(input).unwrap().to_string(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+        assert_eq!(
+            parse_mathml("<ci>pi</ci>"),
+            Ok(Expr::Constant(Constant::Pi))
+        );
+    }
+
+    #[test]
+    fn test_parse_mathml_integrals() {
+        let cases = [
+            (
+                "<apply><int/><bvar><ci>x</ci></bvar><apply><power/><ci>x</ci><cn>2</cn></apply></apply>",
+                "int(x ^ 2, x)",
+            ),
+            (
+                "<apply><int/><bvar><ci>t</ci></bvar><lowlimit><cn>0</cn></lowlimit>\
+                    <uplimit><ci>x</ci></uplimit><ci>t</ci></apply>",
+                "int(t, t, 0, x)",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                parse_mathml(input).unwrap().to_string(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+        assert_eq!(
+            parse_mathml("<apply><int/><ci>x</ci></apply>"),
+            Err(MathMlError::UnsupportedElement("int".to_string()))
+        );
+        assert_eq!(
+            parse_mathml("<apply><plus/><bvar><ci>x</ci></bvar><ci>x</ci></apply>"),
+            Err(MathMlError::UnsupportedElement("bvar".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_mathml_round_trip() {
+        for input in [
+            "((x ^ 2) + 1)",
+            "(-abs((x / 2)))",
+            "f(x, y)",
+            "sign(x) * asin(x)",
+            "2 * pi + e ^ x",
+            "log(x) - ln(x)",
+            "(-2.5) * undefined",
+            "int(x, x)",
+            "int(t, t, 0, x)",
+            "[t ^ 2, (-t)]",
+        ] {
+            let expr = parse(input);
+            assert_eq!(
+                parse_mathml(&to_mathml(&expr)).unwrap(),
+                expr,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_mathml_errors() {
+        assert!(matches!(
+            parse_mathml("<apply><plus/>"),
+            Err(MathMlError::Xml(_))
+        ));
+        assert_eq!(
+            parse_mathml("<apply><divide/><cn>1</cn></apply>"),
+            Err(MathMlError::WrongArgumentCount {
+                op: "divide".to_string(),
+                found: 1
+            })
+        );
+        assert_eq!(
+            parse_mathml("<lambda><ci>x</ci></lambda>"),
+            Err(MathMlError::UnsupportedElement("lambda".to_string()))
+        );
+        assert_eq!(
+            parse_mathml("<cn>abc</cn>"),
+            Err(MathMlError::InvalidNumber("abc".to_string()))
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! Interop with the output of SymPy's `str()`, e.g. `x**2 + 2*x - Abs(x)`.
+
+use super::{ExprFormatter, ImportFunctions, Parens, Spacing, Syntax};
+use crate::constants::Constant;
+use crate::diagnostic::Diagnostics;
+use crate::lexer::Token;
+use crate::parser::{Expr, ExprVisitor, Parser};
+use logos::Logos;
+
+const SYNTAX: Syntax = Syntax {
+    call_brackets: ("(", ")"),
+    function_names: &[("abs", "Abs"), ("ln", "log")],
+    sqrt: "sqrt",
+    constant_names: &[(Constant::E, "E")],
+    integral: ("Integral", ("(", ")")),
+    vector_brackets: ("Matrix([", "])"),
+    log_with_base: true,
+};
+
+/// Writes `expr` the way SymPy's `str()` would, so that it can be pasted into a Python session.
+pub fn to_sympy_str(expr: &Expr) -> String {
+    formatter().format(expr)
+}
+
+/// Returns the formatter used by [`to_sympy_str`], which can be customized further, e.g. with
+/// [`ExprFormatter::with_precision`].
+pub fn formatter() -> ExprFormatter {
+    ExprFormatter::default()
+        .with_parens(Parens::Minimal)
+        .with_spacing(Spacing::Additive)
+        .with_pow("**")
+        .with_syntax(&SYNTAX)
+}
+
+/// Parses the output of SymPy's `str()` with Python's operator precedence. SymPy function names (e.g. `Abs`) are
+/// converted to the built-in functions, `sqrt(u)` is converted to `u ^ 0.5`, `log(u, b)` is converted to a logarithm
+/// with the base `b`, and `Integral` and `Matrix` are converted to integrals and vectors.
+pub fn parse_sympy_str(input: &str) -> Result<Expr, Diagnostics> {
+    let input = tuples_to_lists(input);
+    let mut parser = Parser::from(Token::lexer(&input).spanned()).with_python_precedence();
+    let mut expr = parser.parse();
+    if parser.diagnostics().has_errors() {
+        return Err(parser.diagnostics().clone());
+    }
+    ImportFunctions(&SYNTAX).visit(&mut expr);
+    Ok(expr)
+}
+
+/// Replaces the parens of the tuples in `input`, e.g. the variable and bounds `(t, 0, x)` of an integral, with square
+/// brackets so that they are parsed as vectors. The parens of a tuple are the ones that contain a comma but do not
+/// follow a function name. The replacement keeps the spans intact.
+fn tuples_to_lists(input: &str) -> String {
+    let mut bytes = input.as_bytes().to_vec();
+    // (offset of the opening paren if it could be a tuple, whether it contains a comma)
+    let mut open: Vec<(Option<usize>, bool)> = Vec::new();
+    let mut after_name = false;
+    for (tok, span) in Token::lexer(input).spanned() {
+        match tok {
+            Token::OpenParen => open.push(((!after_name).then_some(span.start), false)),
+            Token::OpenBracket => open.push((None, false)),
+            Token::Comma => {
+                if let Some((_, has_comma)) = open.last_mut() {
+                    *has_comma = true;
+                }
+            }
+            Token::CloseParen | Token::CloseBracket => {
+                if let Some((Some(start), true)) = open.pop() {
+                    if tok == Token::CloseParen {
+                        bytes[start] = b'[';
+                        bytes[span.start] = b']';
+                    }
+                }
+            }
+            _ => {}
+        }
+        after_name = matches!(tok, Token::Identifier(_));
+    }
+    String::from_utf8(bytes).expect("only ASCII parens are replaced")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Expr {
+        Parser::from(Token::lexer(input).spanned()).parse()
+    }
+
+    const EXPORT_CASES: [(&str, &str); 17] = [
+        ("x ^ 3 + 2 * x - 5", "x**3 + 2*x - 5"),
+        ("(x + 1) / (x - 1)", "(x + 1)/(x - 1)"),
+        ("x - (y - z)", "x - (y - z)"),
+        ("x / (y * z)", "x/(y*z)"),
+        ("x ^ -2", "x**(-2)"),
+        ("x ^ (1 / 3)", "x**(1/3)"),
+        ("(x ^ 2) ^ 3", "(x**2)**3"),
+        ("-(x ^ 2)", "-x**2"),
+        ("-x ^ 2", "(-x)**2"),
+        ("-(x + 1) * 2", "-(x + 1)*2"),
+        ("x * -y", "x*(-y)"),
+        ("abs(x - 1) * sign(x)", "Abs(x - 1)*sign(x)"),
+        ("int(t ^ 2, t, 0, x)", "Integral(t**2, (t, 0, x))"),
+        ("int(y, x)", "Integral(y, x)"),
+        ("[t ^ 2, -t, 1]", "Matrix([t**2, -t, 1])"),
+        ("e ^ x * pi", "E**x*pi"),
+        ("ln(x) + log(x + 1)", "log(x) + log(x + 1, 10)"),
+    ];
+
+    #[test]
+    fn test_to_sympy_str() {
+        for (input, expected) in EXPORT_CASES {
+            assert_eq!(to_sympy_str(&parse(input)), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_sympy_str() {
+        let cases = [
+            ("x**3 + 2*x - 5", "(((x ^ 3) + (2 * x)) - 5)"),
+            ("Abs(x - 1)*sign(x)", "(abs(x - 1) * sign(x))"),
+            ("sqrt(x + 1)", "((x + 1) ^ 0.5)"),
+            ("-x**2", "(-(x ^ 2))"),
+            ("-2**2", "(-(2 ^ 2))"),
+            ("E**(pi*x)", "(e ^ (pi * x))"),
+            ("log(x, 10)*log(x, 2)", "(log(x) * (ln(x) / ln(2)))"),
+            (
+                "Integral(t**2, (t, 0, (x + 1)*2))",
+                "int(t ^ 2, t, 0, (x + 1) * 2)",
+            ),
+            ("Matrix([(t, 1), t**2])", "[[t, 1], t ^ 2]"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                parse_sympy_str(input).unwrap().to_string(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+        assert!(parse_sympy_str("x**").is_err());
+    }
+
+    #[test]
+    fn test_sympy_round_trip() {
+        for input in [
+            "x**3 + 2*x - 5",
+            "-x**2/(x + 1)",
+            "Abs(x)**(1/2)",
+            "x - (-1)",
+            "(-x)**2",
+        ] {
+            assert_eq!(to_sympy_str(&parse_sympy_str(input).unwrap()), input);
+        }
+        for (input, _) in EXPORT_CASES {
+            let expr = parse(input);
+            assert_eq!(
+                parse_sympy_str(&to_sympy_str(&expr)),
+                Ok(expr),
+                "input: {}",
+                input
+            );
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Interop with Wolfram Language (Mathematica) syntax, e.g. `Abs[x]^2 - 2*x`, for cross-checking results against
+//! Wolfram Alpha.
+
+use super::{ExprFormatter, ImportFunctions, Parens, Spacing, Syntax};
+use crate::constants::Constant;
+use crate::diagnostic::Diagnostics;
+use crate::lexer::Token;
+use crate::parser::{Expr, ExprVisitor, Parser};
+use logos::Logos;
+
+const SYNTAX: Syntax = Syntax {
+    call_brackets: ("[", "]"),
+    function_names: &[
+        ("abs", "Abs"),
+        ("sign", "Sign"),
+        ("sqrt", "Sqrt"),
+        ("ln", "Log"),
+        ("log", "Log10"),
+        ("exp", "Exp"),
+        ("sin", "Sin"),
+        ("cos", "Cos"),
+        ("tan", "Tan"),
+        ("asin", "ArcSin"),
+        ("acos", "ArcCos"),
+        ("atan", "ArcTan"),
+    ],
+    sqrt: "Sqrt",
+    constant_names: &[(Constant::Pi, "Pi"), (Constant::E, "E")],
+    integral: ("Integrate", ("{", "}")),
+    vector_brackets: ("{", "}"),
+    log_with_base: false,
+};
+
+/// Writes `expr` in Wolfram Language syntax.
+pub fn to_wolfram_str(expr: &Expr) -> String {
+    formatter().format(expr)
+}
+
+/// Returns the formatter used by [`to_wolfram_str`], which can be customized further, e.g. with
+/// [`ExprFormatter::with_precision`].
+pub fn formatter() -> ExprFormatter {
+    ExprFormatter::default()
+        .with_parens(Parens::Minimal)
+        .with_spacing(Spacing::Additive)
+        .with_pow("^")
+        .with_syntax(&SYNTAX)
+}
+
+/// Writes the derivative of `expr` with respect to `var` in Wolfram Language syntax, e.g. `D[x^2, x]`.
+pub fn to_wolfram_derivative_str(expr: &Expr, var: &str) -> String {
+    format!("D[{}, {}]", to_wolfram_str(expr), var)
+}
+
+/// Parses an expression in Wolfram Language syntax. Wolfram function names (e.g. `Abs`) are converted to the built-in
+/// functions, `Sqrt[u]` is converted to `u ^ 0.5`, `Integrate` is converted to an integral and lists (e.g.
+/// `{t, t^2}`) are converted to vectors. Implicit multiplication (e.g. `2 x`) is not supported.
+pub fn parse_wolfram_str(input: &str) -> Result<Expr, Diagnostics> {
+    // calls are the only use of square brackets so they can be treated as parens, and lists use the built-in brackets
+    // of vectors, which keeps the spans intact. `Log10` would be lexed as `Log` followed by `10`, so it is replaced by
+    // the built-in name padded to the same length.
+    let input = input
+        .replace("Log10[", "log  (")
+        .replace('[', "(")
+        .replace(']', ")")
+        .replace('{', "[")
+        .replace('}', "]");
+    let mut parser = Parser::from(Token::lexer(&input).spanned()).with_python_precedence();
+    let mut expr = parser.parse();
+    if parser.diagnostics().has_errors() {
+        return Err(parser.diagnostics().clone());
+    }
+    ImportFunctions(&SYNTAX).visit(&mut expr);
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transformations::prettify::Prettify;
+
+    fn parse(input: &str) -> Expr {
+        Parser::from(Token::lexer(input).spanned()).parse()
+    }
+
+    const EXPORT_CASES: [(&str, &str); 10] = [
+        ("x ^ 3 + 2 * x - 5", "x^3 + 2*x - 5"),
+        ("(x + 1) / (x - 1)", "(x + 1)/(x - 1)"),
+        ("x ^ -2", "x^(-2)"),
+        ("-(x ^ 2)", "-x^2"),
+        ("abs(x - 1) ^ 2 * sign(x)", "Abs[x - 1]^2*Sign[x]"),
+        ("int(t ^ 2, t, 0, x)", "Integrate[t^2, {t, 0, x}]"),
+        ("-int(y, x)", "-Integrate[y, x]"),
+        ("[t ^ 2, abs(t)]", "{t^2, Abs[t]}"),
+        ("ln(x) + log(x) * exp(x)", "Log[x] + Log10[x]*Exp[x]"),
+        ("sin(pi * x) + e", "Sin[Pi*x] + E"),
+    ];
+
+    #[test]
+    fn test_to_wolfram_str() {
+        for (input, expected) in EXPORT_CASES {
+            assert_eq!(to_wolfram_str(&parse(input)), expected, "input: {}", input);
+        }
+        assert_eq!(
+            to_wolfram_derivative_str(&parse("x ^ 2 + y"), "x"),
+            "D[x^2 + y, x]"
+        );
+
+        // the square roots written by `Prettify`
+        let mut expr = parse("(x + 1) ^ 0.5");
+        Prettify.visit(&mut expr);
+        assert_eq!(to_wolfram_str(&expr), "Sqrt[x + 1]");
+    }
+
+    #[test]
+    fn test_parse_wolfram_str() {
+        let cases = [
+            ("Abs[x]^2", "(abs(x) ^ 2)"),
+            ("Sign[x - 1]*x", "(sign(x - 1) * x)"),
+            ("Sqrt[x + 1]", "((x + 1) ^ 0.5)"),
+            ("-x^2", "(-(x ^ 2))"),
+            ("{Sign[t], t^2}", "[sign(t), t ^ 2]"),
+            ("Log[Exp[x]]", "ln(exp(x))"),
+            ("E^x*Pi", "((e ^ x) * pi)"),
+            ("Log10[x]*Log[x]", "(log(x) * ln(x))"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                parse_wolfram_str(input).unwrap().to_string(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+        assert!(parse_wolfram_str("Abs[x").is_err());
+    }
+
+    #[test]
+    fn test_wolfram_round_trip() {
+        for (input, _) in EXPORT_CASES {
+            let expr = parse(input);
+            assert_eq!(
+                parse_wolfram_str(&to_wolfram_str(&expr)),
+                Ok(expr),
+                "input: {}",
+                input
+            );
+        }
+    }
+}
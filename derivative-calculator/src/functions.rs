@@ -0,0 +1,95 @@
+//! Built-in functions that can be called in expressions, e.g. `abs(x)`.
+
+/// A built-in function.
+pub struct Function {
+    pub name: &'static str,
+    /// The number of arguments the function takes.
+    pub arity: usize,
+    /// Evaluates the function. `args` always has `arity` elements.
+    pub eval: fn(&[f64]) -> f64,
+}
+
+/// All the built-in functions.
+pub const FUNCTIONS: &[Function] = &[
+    Function {
+        name: "abs",
+        arity: 1,
+        eval: |args| args[0].abs(),
+    },
+    Function {
+        name: "sign",
+        arity: 1,
+        eval: |args| sign(args[0]),
+    },
+    Function {
+        name: "ln",
+        arity: 1,
+        eval: |args| args[0].ln(),
+    },
+    // the logarithm in base 10
+    Function {
+        name: "log",
+        arity: 1,
+        eval: |args| args[0].log10(),
+    },
+    Function {
+        name: "exp",
+        arity: 1,
+        eval: |args| args[0].exp(),
+    },
+    // only produced by prettifying `u ^ 0.5`, the parser reads `sqrt(u)` as the power
+    Function {
+        name: "sqrt",
+        arity: 1,
+        eval: |args| args[0].sqrt(),
+    },
+    Function {
+        name: "sin",
+        arity: 1,
+        eval: |args| args[0].sin(),
+    },
+    Function {
+        name: "cos",
+        arity: 1,
+        eval: |args| args[0].cos(),
+    },
+    Function {
+        name: "tan",
+        arity: 1,
+        eval: |args| args[0].tan(),
+    },
+    // the inverse trigonometric functions return the principal value, e.g. `asin(x)` is in `[-π / 2, π / 2]`
+    Function {
+        name: "asin",
+        arity: 1,
+        eval: |args| args[0].asin(),
+    },
+    Function {
+        name: "acos",
+        arity: 1,
+        eval: |args| args[0].acos(),
+    },
+    Function {
+        name: "atan",
+        arity: 1,
+        eval: |args| args[0].atan(),
+    },
+];
+
+/// Returns the built-in function called `name`.
+pub fn lookup(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|func| func.name == name)
+}
+
+/// Returns `1` for positive numbers, `-1` for negative numbers and `0` for `0`. Unlike [`f64::signum`], `sign(0)` is
+/// `0` which makes `abs(x) = sign(x) * x` hold everywhere.
+pub fn sign(x: f64) -> f64 {
+    if x > 0.0 {
+        1.0
+    } else if x < 0.0 {
+        -1.0
+    } else {
+        // 0 or NaN
+        x
+    }
+}
@@ -0,0 +1,310 @@
+//! Geometric properties of the graph `y = f(x)` of a function, computed from its derivatives, e.g. its curvature,
+//! arc length and tangent lines.
+
+use crate::eval::compiled::CompiledExpr;
+use crate::eval::quadrature::{integrate, QuadratureError, DEFAULT_TOLERANCE};
+use crate::eval::EvalError;
+use crate::limits::Limits;
+use crate::parser::{BinOpKind, Expr, ExprVisitor};
+use crate::transformations::derivative::{try_partial_derivative, DerivativeError};
+use crate::transformations::simplify::Simplify;
+use std::fmt;
+
+/// An error that occurred while computing an arc length.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArcLengthError {
+    /// The derivative could not be computed.
+    Derivative(DerivativeError),
+    /// The integrand could not be compiled, e.g. because it contains an identifier other than the variable.
+    Eval(EvalError),
+    /// The integrand could not be integrated numerically.
+    Quadrature(QuadratureError),
+}
+
+impl fmt::Display for ArcLengthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArcLengthError::Derivative(err) => write!(f, "{}", err),
+            ArcLengthError::Eval(err) => write!(f, "{}", err),
+            ArcLengthError::Quadrature(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ArcLengthError {}
+
+impl From<DerivativeError> for ArcLengthError {
+    fn from(err: DerivativeError) -> Self {
+        ArcLengthError::Derivative(err)
+    }
+}
+
+impl From<EvalError> for ArcLengthError {
+    fn from(err: EvalError) -> Self {
+        ArcLengthError::Eval(err)
+    }
+}
+
+impl From<QuadratureError> for ArcLengthError {
+    fn from(err: QuadratureError) -> Self {
+        ArcLengthError::Quadrature(err)
+    }
+}
+
+/// An error that occurred while computing a tangent line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TangentError {
+    /// The derivative could not be computed.
+    Derivative(DerivativeError),
+    /// The function or its derivative could not be compiled, e.g. because it contains an identifier other than the
+    /// variable.
+    Eval(EvalError),
+    /// The function or its derivative is not finite at the value, e.g. `x ^ (1 / 3)` at `0` where the tangent is
+    /// vertical.
+    NotFinite(f64),
+}
+
+impl fmt::Display for TangentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TangentError::Derivative(err) => write!(f, "{}", err),
+            TangentError::Eval(err) => write!(f, "{}", err),
+            TangentError::NotFinite(a) => {
+                write!(f, "the function or its derivative is not finite at {}", a)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TangentError {}
+
+impl From<DerivativeError> for TangentError {
+    fn from(err: DerivativeError) -> Self {
+        TangentError::Derivative(err)
+    }
+}
+
+impl From<EvalError> for TangentError {
+    fn from(err: EvalError) -> Self {
+        TangentError::Eval(err)
+    }
+}
+
+/// Computes the curvature `f'' / (1 + f' ^ 2) ^ (3 / 2)` of the graph of `expr` as a function of `var`. The result is
+/// simplified.
+///
+/// The curvature is signed: it is positive where the graph is convex and negative where it is concave. `limits` apply
+/// to both derivatives.
+pub fn curvature(expr: &Expr, var: &str, limits: &Limits) -> Result<Expr, DerivativeError> {
+    let first = simplified_derivative(expr, var, limits)?;
+    let second = simplified_derivative(&first, var, limits)?;
+    // the simplifier keeps `0 / u` because `u` could be 0, but the denominator is at least 1 here
+    if second == Expr::Literal(0.0) {
+        return Ok(second);
+    }
+
+    let slope = Expr::Literal(1.0) + Expr::pow(first, Expr::Literal(2.0));
+    let exponent = Expr::binary(Expr::Literal(3.0), BinOpKind::Slash, Expr::Literal(2.0));
+    let mut res = Expr::binary(second, BinOpKind::Slash, Expr::pow(slope, exponent));
+    Simplify.visit(&mut res);
+    Ok(res)
+}
+
+/// Computes the arc length integrand `(1 + f' ^ 2) ^ (1 / 2)` of the graph of `expr` as a function of `var`, whose
+/// integral over `[a, b]` is the length of the graph between `a` and `b`. The result is simplified.
+pub fn arc_length_integrand(
+    expr: &Expr,
+    var: &str,
+    limits: &Limits,
+) -> Result<Expr, DerivativeError> {
+    let first = simplified_derivative(expr, var, limits)?;
+    let exponent = Expr::binary(Expr::Literal(1.0), BinOpKind::Slash, Expr::Literal(2.0));
+    let mut res = Expr::pow(
+        Expr::Literal(1.0) + Expr::pow(first, Expr::Literal(2.0)),
+        exponent,
+    );
+    Simplify.visit(&mut res);
+    Ok(res)
+}
+
+/// Computes the length of the graph of `expr` as a function of `var` between `a` and `b` by integrating
+/// [`arc_length_integrand`] numerically. The length is negative if `b < a`.
+pub fn arc_length(
+    expr: &Expr,
+    var: &str,
+    a: f64,
+    b: f64,
+    limits: &Limits,
+) -> Result<f64, ArcLengthError> {
+    let integrand = CompiledExpr::new(&arc_length_integrand(expr, var, limits)?, var)?;
+    Ok(integrate(|x| integrand.eval(x), a, b, DEFAULT_TOLERANCE)?)
+}
+
+/// The tangent line `y = f(a) + f'(a) * (x - a)` of the graph of a function at `x = a`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TangentLine {
+    pub a: f64,
+    /// The value `f(a)` of the function.
+    pub value: f64,
+    /// The value `f'(a)` of the derivative.
+    pub slope: f64,
+}
+
+impl TangentLine {
+    /// Evaluates the function `f` and its derivative `derivative` at `a`, e.g. to follow a point moving along a plot.
+    pub fn new(f: &CompiledExpr, derivative: &CompiledExpr, a: f64) -> Self {
+        Self {
+            a,
+            value: f.eval(a),
+            slope: derivative.eval(a),
+        }
+    }
+
+    /// Differentiates `expr` as a function of `var` and evaluates it and its derivative at `a`. Unlike [`Self::new`],
+    /// the line must be finite.
+    pub fn at(expr: &Expr, var: &str, a: f64, limits: &Limits) -> Result<Self, TangentError> {
+        let derivative = simplified_derivative(expr, var, limits)?;
+        let line = Self::new(
+            &CompiledExpr::new(expr, var)?,
+            &CompiledExpr::new(&derivative, var)?,
+            a,
+        );
+        if !line.value.is_finite() || !line.slope.is_finite() {
+            return Err(TangentError::NotFinite(a));
+        }
+        Ok(line)
+    }
+
+    /// Returns the value of the line at `x`.
+    pub fn eval(&self, x: f64) -> f64 {
+        self.value + self.slope * (x - self.a)
+    }
+
+    /// Returns the simplified equation of the line as a function of `var`, e.g. `(-1) + 2 * x`.
+    pub fn to_expr(&self, var: &str) -> Expr {
+        let intercept = self.value - self.slope * self.a;
+        let mut res = Expr::Literal(self.slope) * Expr::Identifier(var.to_string())
+            + Expr::Literal(intercept);
+        Simplify.visit(&mut res);
+        res
+    }
+}
+
+/// Computes the tangent line of the graph of `expr` as a function of `var` at `x = a`, which is also the linear
+/// approximation of the function near `a`, e.g. `(-1) + 2 * x` for `x ^ 2` at `1`. See [`TangentLine::to_expr`].
+pub fn tangent_line(expr: &Expr, var: &str, a: f64, limits: &Limits) -> Result<Expr, TangentError> {
+    Ok(TangentLine::at(expr, var, a, limits)?.to_expr(var))
+}
+
+/// Computes the derivative of `expr` with respect to `var` and simplifies it, so that the next derivative starts from
+/// a smaller expression.
+fn simplified_derivative(expr: &Expr, var: &str, limits: &Limits) -> Result<Expr, DerivativeError> {
+    let mut res = try_partial_derivative(expr, var, limits)?;
+    Simplify.visit(&mut res);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::eval;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+    use std::collections::HashMap;
+
+    fn parse(input: &str) -> Expr {
+        Parser::from(Token::lexer(input).spanned()).parse()
+    }
+
+    #[test]
+    fn test_curvature() {
+        let res = curvature(&parse("x ^ 2"), "x", &Limits::default()).unwrap();
+        assert_eq!(res.to_string(), "(2 / ((1 + ((2 * x) ^ 2)) ^ (3 / 2)))");
+        // the curvature of a line is 0
+        let res = curvature(&parse("3 * t + 1"), "t", &Limits::default()).unwrap();
+        assert_eq!(res.to_string(), "0");
+
+        // the curvature of a circle of radius 2 is 1 / 2
+        let res = curvature(&parse("-((4 - x ^ 2) ^ (1 / 2))"), "x", &Limits::default()).unwrap();
+        let bindings = HashMap::from([("x".to_string(), 1.0)]);
+        assert!((eval(&res, &bindings).unwrap() - 0.5).abs() < 1e-12);
+
+        assert!(matches!(
+            curvature(&parse("abs(x, 2)"), "x", &Limits::default()),
+            Err(DerivativeError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_tangent_line() {
+        let f = CompiledExpr::new(&parse("x ^ 2"), "x").unwrap();
+        let derivative = CompiledExpr::new(&parse("2 * x"), "x").unwrap();
+        let tangent = TangentLine::new(&f, &derivative, 1.0);
+        assert_eq!(
+            tangent,
+            TangentLine {
+                a: 1.0,
+                value: 1.0,
+                slope: 2.0
+            }
+        );
+        assert_eq!(tangent.eval(3.0), 5.0);
+        assert_eq!(tangent.to_expr("x").to_string(), "((-1) + (2 * x))");
+        assert_eq!(
+            TangentLine::new(&f, &derivative, 0.0)
+                .to_expr("x")
+                .to_string(),
+            "0"
+        );
+
+        let res = tangent_line(&parse("x ^ 3 - x"), "x", 2.0, &Limits::default()).unwrap();
+        assert_eq!(res.to_string(), "((-16) + (11 * x))");
+        let res = tangent_line(&parse("exp(t)"), "t", 0.0, &Limits::default()).unwrap();
+        assert_eq!(res.to_string(), "(1 + t)");
+        assert_eq!(
+            tangent_line(&parse("x ^ (1 / 3)"), "x", 0.0, &Limits::default()),
+            Err(TangentError::NotFinite(0.0))
+        );
+        assert_eq!(
+            tangent_line(&parse("a * x"), "x", 1.0, &Limits::default()),
+            Err(TangentError::Eval(EvalError::UnboundIdentifier(
+                "a".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_arc_length() {
+        let res = arc_length_integrand(&parse("x ^ 2"), "x", &Limits::default()).unwrap();
+        assert_eq!(res.to_string(), "((1 + ((2 * x) ^ 2)) ^ (1 / 2))");
+
+        // the length of a line is the distance between its ends
+        let res = arc_length(&parse("3 * t + 1"), "t", 0.0, 4.0, &Limits::default()).unwrap();
+        assert!((res - 4.0 * 10.0_f64.sqrt()).abs() < 1e-9);
+        let res = arc_length(&parse("x ^ 2"), "x", 0.0, 1.0, &Limits::default()).unwrap();
+        let expected = 5.0_f64.sqrt() / 2.0 + 2.0_f64.asinh() / 4.0;
+        assert!((res - expected).abs() < 1e-9);
+        // a third of the circumference of a circle of radius 2
+        let res = arc_length(
+            &parse("(4 - x ^ 2) ^ (1 / 2)"),
+            "x",
+            -1.0,
+            1.0,
+            &Limits::default(),
+        )
+        .unwrap();
+        assert!((res - 2.0 * std::f64::consts::PI / 3.0).abs() < 1e-9);
+
+        assert_eq!(
+            arc_length(&parse("a * x"), "x", 0.0, 1.0, &Limits::default()),
+            Err(ArcLengthError::Eval(EvalError::UnboundIdentifier(
+                "a".to_string()
+            )))
+        );
+        assert!(matches!(
+            arc_length(&parse("abs(x, 2)"), "x", 0.0, 1.0, &Limits::default()),
+            Err(ArcLengthError::Derivative(_))
+        ));
+    }
+}
@@ -0,0 +1,125 @@
+//! A history of the successive states of an expression with undo and redo, e.g. while rewriting it interactively.
+
+use crate::parser::Expr;
+
+/// A state of an [`ExprHistory`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub expr: Expr,
+    /// Describes how the expression was obtained, e.g. the name of the rule that was applied.
+    pub label: String,
+}
+
+/// The states of an expression, starting at the initial one. Recording a new state after undoing discards the states
+/// that could have been redone, like in a text editor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprHistory {
+    entries: Vec<HistoryEntry>,
+    /// The index of the current state in `entries`.
+    current: usize,
+}
+
+impl ExprHistory {
+    pub fn new(expr: Expr, label: impl Into<String>) -> Self {
+        Self {
+            entries: vec![HistoryEntry {
+                expr,
+                label: label.into(),
+            }],
+            current: 0,
+        }
+    }
+
+    /// Returns the current state.
+    pub fn current(&self) -> &HistoryEntry {
+        &self.entries[self.current]
+    }
+
+    /// Returns the states up to and including the current one, starting at the initial one.
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries[..=self.current]
+    }
+
+    /// Makes `expr` the current state. States that could have been redone are discarded.
+    pub fn push(&mut self, expr: Expr, label: impl Into<String>) {
+        self.entries.truncate(self.current + 1);
+        self.entries.push(HistoryEntry {
+            expr,
+            label: label.into(),
+        });
+        self.current += 1;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.current > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.current + 1 < self.entries.len()
+    }
+
+    /// Goes back to the previous state and returns it, or returns `None` if the current state is the initial one.
+    pub fn undo(&mut self) -> Option<&HistoryEntry> {
+        if !self.can_undo() {
+            return None;
+        }
+        self.current -= 1;
+        Some(self.current())
+    }
+
+    /// Goes forward to the state that was last undone and returns it, or returns `None` if there is none.
+    pub fn redo(&mut self) -> Option<&HistoryEntry> {
+        if !self.can_redo() {
+            return None;
+        }
+        self.current += 1;
+        Some(self.current())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+
+    fn parse(input: &str) -> Expr {
+        Parser::from(Token::lexer(input).spanned()).parse()
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut history = ExprHistory::new(parse("x * 1 + 0"), "input");
+        assert!(!history.can_undo());
+        assert_eq!(history.undo(), None);
+
+        history.push(parse("x * 1"), "addition of zero");
+        history.push(parse("x"), "multiplication by one");
+        assert_eq!(
+            history.undo().map(|entry| entry.expr.to_string()),
+            Some("(x * 1)".to_string())
+        );
+        assert_eq!(
+            history.undo().map(|entry| entry.label.as_str()),
+            Some("input")
+        );
+        assert!(history.can_redo());
+        assert_eq!(
+            history.redo().map(|entry| entry.label.as_str()),
+            Some("addition of zero")
+        );
+        assert_eq!(history.entries().len(), 2);
+
+        // recording a new state discards the states that could have been redone
+        history.push(parse("1 * x"), "commutativity");
+        assert!(!history.can_redo());
+        assert_eq!(history.redo(), None);
+        let labels: Vec<_> = history
+            .entries()
+            .iter()
+            .map(|entry| entry.label.as_str())
+            .collect();
+        assert_eq!(labels, ["input", "addition of zero", "commutativity"]);
+    }
+}
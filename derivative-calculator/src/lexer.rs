@@ -2,31 +2,90 @@ use logos::Logos;
 
 #[derive(Logos, Debug, PartialEq, Clone)]
 pub enum Token {
-    #[regex("[0-9.]+", |lex| lex.slice().parse())]
+    #[regex(r"[0-9][0-9_]*(\.[0-9_]*)?", |lex| parse_number(lex.slice()), priority = 3)]
+    #[regex(r"\.[0-9][0-9_]*", |lex| parse_number(lex.slice()), priority = 3)]
     Number(f64),
-    #[regex("[a-zA-Z]+", |lex| lex.slice().to_string())]
+    /// A sequence of digits and dots that is not a valid number, e.g. `1.2.3` or `.`.
+    #[regex("[0-9.][0-9._]*", priority = 1)]
+    MalformedNumber,
+    /// A name, which may end in primes and indices like the derivatives of undefined functions, e.g. `f'` or `f_1`.
+    #[regex("[a-zA-Z]+(_[0-9]+|')*", |lex| lex.slice().to_string())]
+    #[token("π", |_| "pi".to_string())]
     Identifier(String),
     #[token("+")]
     Plus,
+    // typographic operators are accepted as well since they are common in pasted expressions
     #[token("-")]
+    #[token("−")] // U+2212 MINUS SIGN
     Minus,
     #[token("*")]
+    #[token("×")]
     Asterisk,
     #[token("/")]
+    #[token("÷")]
     Slash,
     #[token("**")]
     #[token("^")]
     Exponent,
+    /// The radical sign, e.g. `√x`.
+    #[token("√")]
+    SquareRoot,
+    /// An exponent written with superscript digits, e.g. `²` in `x²`.
+    #[regex("⁻?[⁰¹²³⁴⁵⁶⁷⁸⁹]+", |lex| parse_superscript(lex.slice()))]
+    Superscript(f64),
     #[token("(")]
     OpenParen,
     #[token(")")]
     CloseParen,
+    #[token("[")]
+    OpenBracket,
+    #[token("]")]
+    CloseBracket,
+    #[token(",")]
+    Comma,
+    /// The bars around an absolute value, e.g. `|x|`.
+    #[token("|")]
+    Bar,
+    /// The `=` between the sides of an equation.
+    #[token("=")]
+    Equals,
     #[error]
     #[regex(r"[ \t\n\f]+", logos::skip)]
     Error,
     Eof,
 }
 
+/// Parses a number that may contain `_` as a digit separator, e.g. `1_000_000`.
+pub(crate) fn parse_number(slice: &str) -> Option<f64> {
+    slice.replace('_', "").parse().ok()
+}
+
+/// Parses a number written with superscript digits and an optional superscript minus sign.
+fn parse_superscript(slice: &str) -> Option<f64> {
+    superscript_to_ascii(slice).parse().ok()
+}
+
+/// Replaces the superscript digits and minus sign in `slice` with their ASCII counterparts, e.g. `⁻¹²` is `-12`.
+pub(crate) fn superscript_to_ascii(slice: &str) -> String {
+    slice
+        .chars()
+        .map(|c| match c {
+            '⁻' => '-',
+            '⁰' => '0',
+            '¹' => '1',
+            '²' => '2',
+            '³' => '3',
+            '⁴' => '4',
+            '⁵' => '5',
+            '⁶' => '6',
+            '⁷' => '7',
+            '⁸' => '8',
+            '⁹' => '9',
+            c => c,
+        })
+        .collect()
+}
+
 impl Token {
     /// Returns the binding power for the binary (infix) operator or `(-1, -1)` if not a valid operator.
     pub fn get_infix_bp(&self) -> (i32, i32) {
@@ -38,6 +97,15 @@ impl Token {
         }
     }
 
+    /// Returns the binding power for the postfix operator or `(-1, ())` if not a valid operator.
+    pub fn get_postfix_bp(&self) -> (i32, ()) {
+        match self {
+            // binds the same as `^`
+            Token::Superscript(_) => (6, ()),
+            _ => (-1, ()),
+        }
+    }
+
     /// Returns the binding power for the prefix operator or `((), -1)` if not a valid operator.
     pub fn get_prefix_bp(&self) -> ((), i32) {
         match self {
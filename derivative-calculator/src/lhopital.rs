@@ -0,0 +1,185 @@
+//! Limits of expressions at a point, e.g. `x / (x + 1)` as `x` goes to infinity.
+//!
+//! The limit is found by substituting the point. Quotients that are indeterminate there (`0 / 0` or `∞ / ∞`) are
+//! replaced by the quotient of the derivatives of the numerator and the denominator (L'Hôpital's rule) until they are
+//! not.
+//!
+//! Not to be confused with [`crate::limits`], the limits on the size of the expressions that are processed.
+
+use crate::eval::{eval, EvalError};
+use crate::limits::Limits;
+use crate::parser::{BinOpKind, Expr, ExprVisitor};
+use crate::transformations::derivative::{try_partial_derivative, DerivativeError};
+use crate::transformations::simplify::Simplify;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The most times [`limit`] applies L'Hôpital's rule, since the quotient of the derivatives can be indeterminate
+/// forever, e.g. for `x ^ 0.5 / (x + 1) ^ 0.5` at infinity.
+pub const MAX_LHOPITAL_STEPS: usize = 8;
+
+/// Values closer to 0 than this are treated as 0 when checking for `0 / 0`.
+const ZERO_TOLERANCE: f64 = 1e-12;
+
+/// An error that occurred while computing a limit.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitError {
+    /// The derivative of the numerator or the denominator could not be computed.
+    Derivative(DerivativeError),
+    /// The expression could not be evaluated at the point, e.g. because it contains an identifier other than the
+    /// variable.
+    Eval(EvalError),
+    /// The expression is still indeterminate after [`MAX_LHOPITAL_STEPS`] steps, or it is indeterminate and not a
+    /// quotient, e.g. `x ^ 2 - x` at infinity.
+    Indeterminate(Expr),
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::Derivative(err) => write!(f, "{}", err),
+            LimitError::Eval(err) => write!(f, "{}", err),
+            LimitError::Indeterminate(expr) => {
+                write!(f, "cannot resolve the indeterminate form `{}`", expr)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+impl From<DerivativeError> for LimitError {
+    fn from(err: DerivativeError) -> Self {
+        LimitError::Derivative(err)
+    }
+}
+
+impl From<EvalError> for LimitError {
+    fn from(err: EvalError) -> Self {
+        LimitError::Eval(err)
+    }
+}
+
+/// A limit computed by [`limit`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limit {
+    pub value: f64,
+    /// The simplified expression whose value was substituted, followed by the simplified quotient of the derivatives
+    /// after every application of L'Hôpital's rule.
+    pub steps: Vec<Expr>,
+}
+
+/// Writes the steps one per line, followed by the value, e.g. `(x / (x + 1))`, `= 1` and `= 1`.
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                write!(f, "\n= ")?;
+            }
+            write!(f, "{}", step)?;
+        }
+        write!(f, "\n= {}", Expr::Literal(self.value))
+    }
+}
+
+/// Computes the limit of `expr` as `var` goes to `point`, which can be infinite. `limits` apply to every derivative.
+///
+/// Only the value at the point is considered, so the limit of `1 / x` at `0` is infinity even though the limits from
+/// the left and from the right are different.
+pub fn limit(expr: &Expr, var: &str, point: f64, limits: &Limits) -> Result<Limit, LimitError> {
+    let bindings = HashMap::from([(var.to_string(), point)]);
+    let mut current = expr.clone();
+    Simplify.visit(&mut current);
+    let mut steps = vec![current.clone()];
+    loop {
+        let (numerator, denominator) = match &current {
+            Expr::Binary {
+                left,
+                op: BinOpKind::Slash,
+                right,
+            } => (left.as_ref(), right.as_ref()),
+            _ => {
+                let value = eval(&current, &bindings)?;
+                if value.is_nan() {
+                    return Err(LimitError::Indeterminate(current));
+                }
+                return Ok(Limit { value, steps });
+            }
+        };
+
+        let (top, bottom) = (eval(numerator, &bindings)?, eval(denominator, &bindings)?);
+        let indeterminate = (top.abs() < ZERO_TOLERANCE && bottom.abs() < ZERO_TOLERANCE)
+            || (top.is_infinite() && bottom.is_infinite());
+        if !indeterminate {
+            let value = top / bottom;
+            if value.is_nan() {
+                return Err(LimitError::Indeterminate(current));
+            }
+            return Ok(Limit { value, steps });
+        }
+        if steps.len() > MAX_LHOPITAL_STEPS {
+            return Err(LimitError::Indeterminate(current));
+        }
+
+        let mut next = Expr::binary(
+            try_partial_derivative(numerator, var, limits)?,
+            BinOpKind::Slash,
+            try_partial_derivative(denominator, var, limits)?,
+        );
+        Simplify.visit(&mut next);
+        steps.push(next.clone());
+        current = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+
+    fn limit_str(input: &str, point: f64) -> Result<Limit, LimitError> {
+        let expr = Parser::from(Token::lexer(input).spanned()).parse();
+        limit(&expr, "x", point, &Limits::default())
+    }
+
+    #[test]
+    fn test_limit() {
+        // no indeterminate form
+        let res = limit_str("x ^ 2 + 1", 2.0).unwrap();
+        assert_eq!((res.value, res.steps.len()), (5.0, 1));
+
+        // 0 / 0
+        let res = limit_str("(x ^ 2 - 1) / (x - 1)", 1.0).unwrap();
+        assert_eq!(res.value, 2.0);
+        let steps: Vec<_> = res.steps.iter().map(Expr::to_string).collect();
+        assert_eq!(steps, ["(((-1) + (x ^ 2)) / ((-1) + x))", "(2 * x)"]);
+
+        // 0 / 0 twice
+        let res = limit_str("(x ^ 3 - 3 * x + 2) / (x ^ 2 - 2 * x + 1)", 1.0).unwrap();
+        assert_eq!((res.value, res.steps.len()), (3.0, 3));
+
+        // ∞ / ∞
+        let res = limit_str("(2 * x ^ 2 + 1) / (x ^ 2 + x)", f64::INFINITY).unwrap();
+        assert_eq!(res.value, 2.0);
+        assert!(res.to_string().ends_with("\n= 2"));
+    }
+
+    #[test]
+    fn test_limit_errors() {
+        // ∞ / ∞ after every step
+        assert!(matches!(
+            limit_str("(x ^ 0.5) / ((x + 1) ^ 0.5)", f64::INFINITY),
+            Err(LimitError::Indeterminate(_))
+        ));
+        assert!(matches!(
+            limit_str("x ^ 2 - x", f64::INFINITY),
+            Err(LimitError::Indeterminate(_))
+        ));
+        assert!(matches!(
+            limit_str("(x - 1) / (x - a)", 1.0),
+            Err(LimitError::Eval(EvalError::UnboundIdentifier(_)))
+        ));
+    }
+}
@@ -1,6 +1,22 @@
 #![recursion_limit = "2048"]
 
+pub mod analysis;
+pub mod behavior;
+pub mod constants;
+pub mod diagnostic;
+pub mod equation;
+pub mod eval;
+pub mod examples;
+pub mod format;
+pub mod functions;
+pub mod geometry;
+pub mod history;
 pub mod lexer;
+pub mod lhopital;
+pub mod limits;
 pub mod parser;
+pub mod pipeline;
 pub mod rule;
+pub mod symbols;
+pub mod table;
 pub mod transformations;
@@ -0,0 +1,97 @@
+//! Limits on the size of the expressions that are processed.
+//!
+//! Pathological inputs (e.g. a huge paste) can make the transformation passes run for a very long time. The limits
+//! allow rejecting these inputs early with an [`ExprTooLarge`] error instead.
+
+use crate::parser::Expr;
+use std::fmt;
+
+/// Configurable limits on the size of the expressions that are processed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// The max number of tokens the parser accepts.
+    pub max_tokens: usize,
+    /// The max number of AST nodes in a parsed expression.
+    pub max_nodes: usize,
+    /// The max number of AST nodes that a transformation (e.g. computing a derivative) may produce.
+    pub max_output_nodes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_tokens: 1_000,
+            max_nodes: 1_000,
+            max_output_nodes: 100_000,
+        }
+    }
+}
+
+impl Limits {
+    /// Limits that never reject any expression.
+    pub fn unlimited() -> Self {
+        Self {
+            max_tokens: usize::MAX,
+            max_nodes: usize::MAX,
+            max_output_nodes: usize::MAX,
+        }
+    }
+
+    /// Returns an error if `expr` has more than `max_nodes` nodes.
+    pub fn check_nodes(&self, expr: &Expr) -> Result<(), ExprTooLarge> {
+        if expr.node_count() > self.max_nodes {
+            Err(ExprTooLarge {
+                kind: LimitKind::Nodes,
+                limit: self.max_nodes,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns an error if `expr` has more than `max_output_nodes` nodes.
+    pub fn check_output_nodes(&self, expr: &Expr) -> Result<(), ExprTooLarge> {
+        if expr.node_count() > self.max_output_nodes {
+            Err(ExprTooLarge {
+                kind: LimitKind::OutputNodes,
+                limit: self.max_output_nodes,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The limit that was exceeded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LimitKind {
+    Tokens,
+    Nodes,
+    OutputNodes,
+}
+
+/// Error returned when an expression exceeds one of the [`Limits`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ExprTooLarge {
+    /// The limit that was exceeded.
+    pub kind: LimitKind,
+    /// The value of the limit.
+    pub limit: usize,
+}
+
+impl fmt::Display for ExprTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self.kind {
+            LimitKind::Tokens => "tokens",
+            LimitKind::Nodes => "nodes",
+            LimitKind::OutputNodes => "nodes in the result",
+        };
+        write!(
+            f,
+            "expression too large, exceeded the limit of {} {}",
+            self.limit, what
+        )
+    }
+}
+
+impl std::error::Error for ExprTooLarge {}
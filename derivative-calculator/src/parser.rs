@@ -1,7 +1,15 @@
+use crate::constants::{self, Constant};
+use crate::diagnostic::{Diagnostic, DiagnosticCode, Diagnostics, Span};
+use crate::equation::Equation;
+use crate::format::ExprFormatter;
 use crate::lexer::Token;
-use std::{convert::TryFrom, convert::TryInto, fmt, iter::Peekable};
+use crate::limits::{ExprTooLarge, LimitKind, Limits};
+use crate::transformations::simplify::{contains_undefined, fold_literals};
+use std::collections::VecDeque;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::{convert::TryFrom, convert::TryInto, fmt};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum BinOpKind {
     Plus,
     Minus,
@@ -41,7 +49,7 @@ impl fmt::Display for BinOpKind {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum UnaryOpKind {
     Minus,
 }
@@ -69,43 +77,621 @@ impl fmt::Display for UnaryOpKind {
     }
 }
 
-/// Represents an expression. To print out the expression in a human readable format, use the [`fmt::Display`] trait.
+/// The location of a sub-expression as the indices of the children to follow from the root, see [`Expr::children`].
+/// For example, `[1, 0]` is the left operand of the right operand and `[]` is the root.
+pub type ExprPath = Vec<usize>;
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Expr {
+/// Represents an expression. To print out the expression in a human readable format, use the [`fmt::Display`] trait.
+/// The printed expression parses back to the same expression, as long as the literals are finite and the names are
+/// identifiers.
+///
+/// The literals are `f64` unless another type `T` is chosen. The parser and the transformations only work with `f64`,
+/// other types can be evaluated with [`crate::eval::eval_num`] after converting with [`Expr::map_literals`].
+#[derive(Debug, PartialEq)]
+pub enum Expr<T = f64> {
     // atoms
-    Literal(f64),
+    Literal(T),
     Identifier(String),
+    /// A built-in constant, e.g. `pi`. See [`crate::constants`].
+    Constant(Constant),
     // complex
     Binary {
-        left: Box<Expr>,
+        left: Box<Expr<T>>,
         op: BinOpKind,
-        right: Box<Expr>,
+        right: Box<Expr<T>>,
     },
     Unary {
         op: UnaryOpKind,
-        right: Box<Expr>,
+        right: Box<Expr<T>>,
+    },
+    /// A function call, e.g. `abs(x)`. See [`crate::functions`] for the built-in functions.
+    Call {
+        func: String,
+        args: Vec<Expr<T>>,
     },
+    /// An unevaluated integral of `integrand` with respect to `var`, written `int(integrand, var)`, or a definite
+    /// integral with the `(lower, upper)` bounds, written `int(integrand, var, lower, upper)`.
+    Integral {
+        integrand: Box<Expr<T>>,
+        var: String,
+        bounds: Option<Box<(Expr<T>, Expr<T>)>>,
+    },
+    /// A vector of components, written `[x ^ 2, abs(x), 1]`, e.g. the position on a parametric curve. Vectors are
+    /// evaluated and differentiated component-wise.
+    Vector(Vec<Expr<T>>),
+    /// The value of an operation that is not a number, e.g. `1 / 0`. See
+    /// [`crate::transformations::simplify::NonFinitePolicy`]. Written `undefined`.
+    Undefined,
     // used when filling in invalid syntax
     Error,
 }
 
+// The derived clone and drop glue are recursive and overflow the stack on deeply nested expressions, e.g.
+// `-(-(-...x))`. Cloning uses the explicit stack of `map_literals` instead.
+impl<T: Clone> Clone for Expr<T> {
+    fn clone(&self) -> Self {
+        self.map_literals(T::clone)
+    }
+}
+
+// The children are moved to an explicit stack, except for the atoms which can be dropped in place.
+impl<T> Drop for Expr<T> {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        take_children(self, &mut stack);
+        while let Some(mut expr) = stack.pop() {
+            take_children(&mut expr, &mut stack);
+        }
+    }
+}
+
+/// Moves the children of `expr` that have children of their own to `stack`, see the [`Drop`] impl of [`Expr`].
+fn take_children<T>(expr: &mut Expr<T>, stack: &mut Vec<Expr<T>>) {
+    let mut take = |child: &mut Expr<T>| {
+        let is_atom = matches!(
+            child,
+            Expr::Literal(_)
+                | Expr::Identifier(_)
+                | Expr::Constant(_)
+                | Expr::Undefined
+                | Expr::Error
+        );
+        if !is_atom {
+            stack.push(child.take());
+        }
+    };
+    match expr {
+        Expr::Binary { left, op: _, right } => {
+            take(left);
+            take(right);
+        }
+        Expr::Unary { op: _, right } => take(right),
+        Expr::Call { func: _, args } | Expr::Vector(args) => args.iter_mut().for_each(take),
+        Expr::Integral {
+            integrand, bounds, ..
+        } => {
+            take(integrand);
+            if let Some((lower, upper)) = bounds.as_deref_mut() {
+                take(lower);
+                take(upper);
+            }
+        }
+        Expr::Literal(_)
+        | Expr::Identifier(_)
+        | Expr::Constant(_)
+        | Expr::Undefined
+        | Expr::Error => {}
+    }
+}
+
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return self.fmt_indented_tree(f);
+        }
+
+        ExprFormatter::default().write(self, f)
+    }
+}
+
+/// Creates `left op right`, folding the literals like [`crate::transformations::simplify::Simplify`] does, e.g. `2 / 4`
+/// is the fraction `1 / 2` and `1 / 0` is kept as is.
+fn fold_binary(left: Expr, op: BinOpKind, right: Expr) -> Expr {
+    if let (Expr::Literal(a), Expr::Literal(b)) = (&left, &right) {
+        if let Some(folded) = fold_literals(*a, op, *b) {
+            return folded;
+        }
+    }
+    Expr::binary(left, op, right)
+}
+
+/// Returns whether `expr` can be dropped from an operation without hiding an error, i.e. it does not contain
+/// [`Expr::Error`], [`Expr::Undefined`] or an undefined operation such as `1 / 0`.
+fn can_drop(expr: &Expr) -> bool {
+    expr.find_paths(|expr| *expr == Expr::Error).is_empty() && !contains_undefined(expr)
+}
+
+/// Creates `left + right`, removing additions of `0` and folding literals, e.g. `Expr::add(x, 0)` is `x`. Like the other
+/// constructors, literals are folded like [`crate::transformations::simplify::Simplify`] does, i.e. exactly and only
+/// if the result is finite.
+impl Add for Expr {
+    type Output = Expr;
+
+    fn add(self, right: Expr) -> Expr {
+        match (self, right) {
+            (Expr::Literal(0.0), expr) | (expr, Expr::Literal(0.0)) => expr,
+            (left, right) => fold_binary(left, BinOpKind::Plus, right),
+        }
+    }
+}
+
+/// Creates `left - right`, removing subtractions of `0` and folding literals.
+impl Sub for Expr {
+    type Output = Expr;
+
+    fn sub(self, right: Expr) -> Expr {
+        match (self, right) {
+            (expr, Expr::Literal(0.0)) => expr,
+            (left, right) => fold_binary(left, BinOpKind::Minus, right),
+        }
+    }
+}
+
+/// Creates `left * right`, removing multiplications by `1`, turning multiplications by `0` into `0` and folding
+/// literals. A multiplication by `0` is kept if the other operand is an error or undefined, e.g. `0 * (1 / 0)`.
+impl Mul for Expr {
+    type Output = Expr;
+
+    fn mul(self, right: Expr) -> Expr {
+        match (self, right) {
+            (Expr::Literal(0.0), expr) | (expr, Expr::Literal(0.0)) if can_drop(&expr) => {
+                Expr::Literal(0.0)
+            }
+            (Expr::Literal(1.0), expr) | (expr, Expr::Literal(1.0)) => expr,
+            (left, right) => fold_binary(left, BinOpKind::Asterisk, right),
+        }
+    }
+}
+
+/// Creates `-right`, folding literals, e.g. `-Expr::Literal(2.0)` is the literal `-2`.
+impl Neg for Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Expr {
         match self {
-            Expr::Literal(num) => {
-                if *num >= 0.0 {
-                    write!(f, "{}", num)
-                } else {
-                    // print negative number in paren
-                    write!(f, "({})", num)
+            Expr::Literal(num) => Expr::Literal(-num),
+            right => Expr::Unary {
+                op: UnaryOpKind::Minus,
+                right: Box::new(right),
+            },
+        }
+    }
+}
+
+impl Expr {
+    /// Creates `base ^ exponent`, removing exponents of `0` and `1`, turning powers of `1` into `1` and folding
+    /// literals, e.g. `2 ^ -1` is the fraction `1 / 2` and `0 ^ -1` is kept as is. Like multiplications by `0`, the
+    /// dropped operand of `u ^ 0` or `1 ^ u` must not be an error or undefined.
+    pub fn pow(base: Expr, exponent: Expr) -> Expr {
+        match (base, exponent) {
+            (Expr::Literal(a), Expr::Literal(b)) => {
+                fold_binary(Expr::Literal(a), BinOpKind::Exponent, Expr::Literal(b))
+            }
+            (expr, Expr::Literal(0.0)) | (Expr::Literal(1.0), expr) if can_drop(&expr) => {
+                Expr::Literal(1.0)
+            }
+            (expr, Expr::Literal(1.0)) => expr,
+            (base, exponent) => Expr::binary(base, BinOpKind::Exponent, exponent),
+        }
+    }
+
+    pub(crate) fn binary(left: Expr, op: BinOpKind, right: Expr) -> Expr {
+        Expr::Binary {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+        }
+    }
+
+    /// Prints one node per line with the children indented below, e.g. `Binary +` / `  Literal 2` /
+    /// `  Identifier x`. Used by the alternate (`{:#}`) format.
+    fn fmt_indented_tree(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // (expr, depth)
+        let mut stack = vec![(self, 0)];
+        let mut first = true;
+        while let Some((expr, depth)) = stack.pop() {
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            write!(f, "{:indent$}", "", indent = depth * 2)?;
+            match expr {
+                Expr::Literal(num) => write!(f, "Literal {}", num)?,
+                Expr::Identifier(ident) => write!(f, "Identifier {}", ident)?,
+                Expr::Binary { left, op, right } => {
+                    write!(f, "Binary {}", op)?;
+                    stack.extend([(right.as_ref(), depth + 1), (left.as_ref(), depth + 1)]);
+                }
+                Expr::Unary { op, right } => {
+                    write!(f, "Unary {}", op)?;
+                    stack.push((right, depth + 1));
+                }
+                Expr::Call { func, args } => {
+                    write!(f, "Call {}", func)?;
+                    stack.extend(args.iter().rev().map(|arg| (arg, depth + 1)));
+                }
+                Expr::Integral { var, .. } => {
+                    write!(f, "Integral {}", var)?;
+                    stack.extend(
+                        expr.children()
+                            .into_iter()
+                            .rev()
+                            .map(|child| (child, depth + 1)),
+                    );
+                }
+                Expr::Vector(components) => {
+                    write!(f, "Vector")?;
+                    stack.extend(
+                        components
+                            .iter()
+                            .rev()
+                            .map(|component| (component, depth + 1)),
+                    );
+                }
+                Expr::Constant(constant) => write!(f, "Constant {}", constant)?,
+                Expr::Undefined => write!(f, "Undefined")?,
+                Expr::Error => write!(f, "Error")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T> Expr<T> {
+    /// Moves the expression out, leaving an [`Expr::Error`] in its place. Since `Expr` implements [`Drop`], matching
+    /// on an owned expression cannot move its fields out, e.g. `Expr::Binary { left, .. } => *left` does not compile.
+    pub fn take(&mut self) -> Expr<T> {
+        std::mem::replace(self, Expr::Error)
+    }
+
+    /// Returns the number of nodes in the expression tree.
+    pub fn node_count(&self) -> usize {
+        // Use an explicit stack instead of recursion to support deeply nested expressions.
+        let mut count = 0;
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            count += 1;
+            match expr {
+                Expr::Binary { left, op: _, right } => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+                Expr::Unary { op: _, right } => stack.push(right),
+                Expr::Call { func: _, args } | Expr::Vector(args) => stack.extend(args),
+                Expr::Integral { .. } => stack.extend(expr.children()),
+                Expr::Literal(_)
+                | Expr::Identifier(_)
+                | Expr::Constant(_)
+                | Expr::Undefined
+                | Expr::Error => {}
+            }
+        }
+        count
+    }
+
+    /// Returns the direct children of the expression in order: the operands of an operation, the arguments of a
+    /// call, the integrand and bounds of an integral or the components of a vector.
+    pub fn children(&self) -> Vec<&Expr<T>> {
+        match self {
+            Expr::Binary { left, op: _, right } => vec![left, right],
+            Expr::Unary { op: _, right } => vec![right],
+            Expr::Call { func: _, args } | Expr::Vector(args) => args.iter().collect(),
+            Expr::Integral {
+                integrand,
+                var: _,
+                bounds,
+            } => match bounds.as_deref() {
+                Some((lower, upper)) => vec![integrand, lower, upper],
+                None => vec![integrand],
+            },
+            Expr::Literal(_)
+            | Expr::Identifier(_)
+            | Expr::Constant(_)
+            | Expr::Undefined
+            | Expr::Error => Vec::new(),
+        }
+    }
+
+    pub(crate) fn children_mut(&mut self) -> Vec<&mut Expr<T>> {
+        match self {
+            Expr::Binary { left, op: _, right } => vec![left, right],
+            Expr::Unary { op: _, right } => vec![right],
+            Expr::Call { func: _, args } | Expr::Vector(args) => args.iter_mut().collect(),
+            Expr::Integral {
+                integrand,
+                var: _,
+                bounds,
+            } => match bounds.as_deref_mut() {
+                Some((lower, upper)) => vec![integrand, lower, upper],
+                None => vec![integrand],
+            },
+            Expr::Literal(_)
+            | Expr::Identifier(_)
+            | Expr::Constant(_)
+            | Expr::Undefined
+            | Expr::Error => Vec::new(),
+        }
+    }
+
+    /// Returns the sub-expression at `path`, or `None` if there is no such sub-expression.
+    pub fn get(&self, path: &[usize]) -> Option<&Expr<T>> {
+        path.iter()
+            .try_fold(self, |expr, &i| expr.children().get(i).copied())
+    }
+
+    /// Same as [`Expr::get`] but returns a mutable reference.
+    pub fn get_mut(&mut self, path: &[usize]) -> Option<&mut Expr<T>> {
+        path.iter()
+            .try_fold(self, |expr, &i| expr.children_mut().into_iter().nth(i))
+    }
+
+    /// Replaces the sub-expression at `path` with `new` and returns the old sub-expression. Unlike rewriting every
+    /// match of a pattern, identical sub-expressions elsewhere are left untouched. Returns `None` and leaves the
+    /// expression unchanged if there is no sub-expression at `path`.
+    pub fn replace_at(&mut self, path: &[usize], new: Expr<T>) -> Option<Expr<T>> {
+        self.get_mut(path).map(|expr| std::mem::replace(expr, new))
+    }
+
+    /// Returns the paths of all the sub-expressions for which `predicate` returns `true`, parents before their
+    /// children and children from left to right. The paths can be passed to [`Expr::replace_at`].
+    pub fn find_paths(&self, mut predicate: impl FnMut(&Expr<T>) -> bool) -> Vec<ExprPath> {
+        let mut paths = Vec::new();
+        let mut stack = vec![(self, ExprPath::new())];
+        while let Some((expr, path)) = stack.pop() {
+            if predicate(expr) {
+                paths.push(path.clone());
+            }
+            // pushed in reverse so that the first child is visited first
+            for (i, child) in expr.children().into_iter().enumerate().rev() {
+                let mut path = path.clone();
+                path.push(i);
+                stack.push((child, path));
+            }
+        }
+        paths
+    }
+
+    /// Returns the names of all the identifiers in the expression, sorted and without duplicates.
+    pub fn identifiers(&self) -> Vec<&str> {
+        let mut identifiers = Vec::new();
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            match expr {
+                Expr::Identifier(ident) => identifiers.push(ident.as_str()),
+                Expr::Binary { left, op: _, right } => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+                Expr::Unary { op: _, right } => stack.push(right),
+                Expr::Call { func: _, args } | Expr::Vector(args) => stack.extend(args),
+                Expr::Integral { .. } => stack.extend(expr.children()),
+                Expr::Literal(_) | Expr::Constant(_) | Expr::Undefined | Expr::Error => {}
+            }
+        }
+        identifiers.sort_unstable();
+        identifiers.dedup();
+        identifiers
+    }
+
+    /// Same as [`Expr::identifiers`] except that the variables of the definite integrals are left out inside of them,
+    /// where they are bound, e.g. only `x` for `int(t ^ 2, t, 0, x)`. The variable of an indefinite integral is free.
+    pub fn free_identifiers(&self) -> Vec<&str> {
+        let mut identifiers = Vec::new();
+        // the sub-expressions along with the variables that are bound around them
+        let mut stack = vec![(self, Vec::new())];
+        while let Some((expr, bound)) = stack.pop() {
+            match expr {
+                Expr::Identifier(ident) if !bound.contains(&ident.as_str()) => {
+                    identifiers.push(ident.as_str())
+                }
+                Expr::Integral {
+                    integrand,
+                    var,
+                    bounds: Some(bounds),
+                } => {
+                    stack.push((&bounds.0, bound.clone()));
+                    stack.push((&bounds.1, bound.clone()));
+                    let mut bound = bound;
+                    bound.push(var.as_str());
+                    stack.push((integrand, bound));
+                }
+                _ => stack.extend(
+                    expr.children()
+                        .into_iter()
+                        .map(|child| (child, bound.clone())),
+                ),
+            }
+        }
+        identifiers.sort_unstable();
+        identifiers.dedup();
+        identifiers
+    }
+
+    /// Converts the literals with `f`, e.g. to evaluate the expression with another number type.
+    pub fn map_literals<U>(&self, mut f: impl FnMut(&T) -> U) -> Expr<U> {
+        // sub-expressions are converted after their children, i.e. when they are visited for the second time
+        let mut stack = vec![(self, false)];
+        let mut results: Vec<Expr<U>> = Vec::new();
+        while let Some((expr, children_converted)) = stack.pop() {
+            if !children_converted {
+                stack.push((expr, true));
+                // pushed in reverse so that the first child ends up lowest on the result stack
+                stack.extend(
+                    expr.children()
+                        .into_iter()
+                        .rev()
+                        .map(|child| (child, false)),
+                );
+                continue;
+            }
+
+            let converted = match expr {
+                Expr::Literal(num) => Expr::Literal(f(num)),
+                Expr::Identifier(ident) => Expr::Identifier(ident.clone()),
+                Expr::Binary {
+                    left: _,
+                    op,
+                    right: _,
+                } => {
+                    let right = results.pop().expect("right operand should be converted");
+                    let left = results.pop().expect("left operand should be converted");
+                    Expr::Binary {
+                        left: Box::new(left),
+                        op: *op,
+                        right: Box::new(right),
+                    }
+                }
+                Expr::Unary { op, right: _ } => Expr::Unary {
+                    op: *op,
+                    right: Box::new(results.pop().expect("operand should be converted")),
+                },
+                Expr::Call { func, args } => Expr::Call {
+                    func: func.clone(),
+                    args: results.split_off(results.len() - args.len()),
+                },
+                Expr::Integral { var, bounds, .. } => {
+                    let bounds = bounds.as_ref().map(|_| {
+                        let upper = results.pop().expect("upper bound should be converted");
+                        let lower = results.pop().expect("lower bound should be converted");
+                        Box::new((lower, upper))
+                    });
+                    Expr::Integral {
+                        integrand: Box::new(results.pop().expect("integrand should be converted")),
+                        var: var.clone(),
+                        bounds,
+                    }
+                }
+                Expr::Vector(components) => {
+                    Expr::Vector(results.split_off(results.len() - components.len()))
                 }
+                Expr::Constant(constant) => Expr::Constant(*constant),
+                Expr::Undefined => Expr::Undefined,
+                Expr::Error => Expr::Error,
+            };
+            results.push(converted);
+        }
+        results
+            .pop()
+            .expect("the converted expression should be on the result stack")
+    }
+}
+
+impl Expr {
+    /// Returns a copy of the expression with the identifier `ident` replaced by `value`, except inside of the
+    /// integrals with respect to `ident` where it is a different variable.
+    pub fn substitute(&self, ident: &str, value: &Expr) -> Expr {
+        let mut res = self.clone();
+        let mut stack = vec![&mut res];
+        while let Some(expr) = stack.pop() {
+            match expr {
+                Expr::Identifier(name) if name == ident => *expr = value.clone(),
+                Expr::Integral {
+                    integrand,
+                    var,
+                    bounds,
+                } => {
+                    if var != ident {
+                        stack.push(integrand);
+                    }
+                    if let Some((lower, upper)) = bounds.as_deref_mut() {
+                        stack.extend([lower, upper]);
+                    }
+                }
+                _ => stack.extend(expr.children_mut()),
+            }
+        }
+        res
+    }
+
+    /// Renders the expression as a tree with one node per line, to show how the expression was parsed.
+    ///
+    /// ```text
+    /// +
+    /// ├── 1
+    /// └── *
+    ///     ├── 2
+    ///     └── x
+    /// ```
+    pub fn to_tree_string(&self) -> String {
+        let mut out = String::new();
+        // (expr, indentation of the line, connector to the parent)
+        let mut stack = vec![(self, String::new(), "")];
+        while let Some((expr, indent, connector)) = stack.pop() {
+            out += &indent;
+            out += connector;
+            let children: Vec<&Expr> = match expr {
+                Expr::Literal(num) => {
+                    out += &num.to_string();
+                    Vec::new()
+                }
+                Expr::Identifier(ident) => {
+                    out += ident;
+                    Vec::new()
+                }
+                Expr::Binary { left, op, right } => {
+                    out += &op.to_string();
+                    vec![left, right]
+                }
+                Expr::Unary { op, right } => {
+                    out += &op.to_string();
+                    vec![right]
+                }
+                Expr::Call { func, args } => {
+                    out += func;
+                    out += "()";
+                    args.iter().collect()
+                }
+                Expr::Integral { var, .. } => {
+                    out += "int d";
+                    out += var;
+                    expr.children()
+                }
+                Expr::Vector(components) => {
+                    out += "[]";
+                    components.iter().collect()
+                }
+                Expr::Constant(constant) => {
+                    out += constant.name();
+                    Vec::new()
+                }
+                Expr::Undefined => {
+                    out += "undefined";
+                    Vec::new()
+                }
+                Expr::Error => {
+                    out += "err";
+                    Vec::new()
+                }
+            };
+            out.push('\n');
+
+            let child_indent = indent
+                + match connector {
+                    "├── " => "│   ",
+                    "└── " => "    ",
+                    _ => "",
+                };
+            // pushed in reverse so that the first child is printed first
+            for (i, child) in children.iter().enumerate().rev() {
+                let connector = if i == children.len() - 1 {
+                    "└── "
+                } else {
+                    "├── "
+                };
+                stack.push((child, child_indent.clone(), connector));
             }
-            Expr::Identifier(ident) => write!(f, "{}", ident),
-            Expr::Binary { left, op, right } => write!(f, "({} {} {})", left, op, right),
-            Expr::Unary { op, right } => write!(f, "({}{})", op, right),
-            Expr::Error => write!(f, "err"),
         }
+        out
     }
 }
 
@@ -127,46 +713,261 @@ pub fn walk_expr(expr: &mut Expr, visitor: &mut impl ExprVisitor) {
         Expr::Unary { op: _, right } => {
             visitor.visit(right.as_mut());
         }
-        Expr::Error => {}
+        Expr::Call { func: _, args } | Expr::Vector(args) => {
+            for arg in args {
+                visitor.visit(arg);
+            }
+        }
+        Expr::Integral { .. } => {
+            for child in expr.children_mut() {
+                visitor.visit(child);
+            }
+        }
+        Expr::Constant(_) | Expr::Undefined | Expr::Error => {}
     }
 }
 
+/// How a parser handles the tokens left over after a complete expression, e.g. the `)` in `1 + 2)`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Trailing tokens are reported as an error.
+    #[default]
+    Strict,
+    /// Trailing tokens are skipped with a warning, so that the parsed prefix can still be used.
+    Lenient,
+}
+
 pub struct Parser<T>
 where
-    T: Iterator<Item = Token>,
+    T: Iterator<Item = (Token, Span)>,
 {
-    lexer: Peekable<T>,
+    lexer: T,
+    /// The tokens after `current_tok` that were already read from the lexer, see [`Parser::peek`].
+    lookahead: VecDeque<(Token, Span)>,
     current_tok: Token,
-    errors: Vec<String>,
+    /// The span of `current_tok`.
+    current_span: Span,
+    /// The span of the last token returned by `eat_tok`.
+    last_span: Span,
+    diagnostics: Diagnostics,
+    limits: Limits,
+    /// The number of tokens read from the lexer so far.
+    tokens_read: usize,
+    /// Set if the input exceeded one of the `limits`.
+    too_large: Option<ExprTooLarge>,
+    /// All the identifiers parsed so far, with their span.
+    identifiers: Vec<(String, Span)>,
+    /// The spans of the binary operations parsed so far, in the order they were created. See
+    /// [`Parser::operation_span`].
+    operation_spans: Vec<Span>,
+    /// The spans of the tokens the literals parsed so far were written as, in the order they were created, or `None`
+    /// for the literals that are not written out. See [`Parser::literal_spans`].
+    literal_spans: Vec<Option<Span>>,
+    /// See [`Parser::with_python_precedence`].
+    python_precedence: bool,
+    mode: ParseMode,
 }
 
 impl<T> From<T> for Parser<T>
 where
-    T: Iterator<Item = Token>,
+    T: Iterator<Item = (Token, Span)>,
 {
     fn from(lexer: T) -> Self {
-        let mut lexer = lexer.peekable();
-        let current_tok = lexer
+        Self::with_limits(lexer, Limits::unlimited())
+    }
+}
+
+impl<T> Parser<T>
+where
+    T: Iterator<Item = (Token, Span)>,
+{
+    /// Creates a new parser that rejects inputs exceeding `limits`.
+    pub fn with_limits(lexer: T, limits: Limits) -> Self {
+        let mut lexer = lexer;
+        let (current_tok, current_span) = lexer
             .next()
             .expect("there should be at least 1 element in lexer");
         Self {
             lexer,
+            lookahead: VecDeque::new(),
             current_tok,
-            errors: Vec::new(),
+            current_span: current_span.clone(),
+            last_span: current_span,
+            diagnostics: Diagnostics::new(),
+            limits,
+            tokens_read: 1,
+            too_large: None,
+            identifiers: Vec::new(),
+            operation_spans: Vec::new(),
+            literal_spans: Vec::new(),
+            python_precedence: false,
+            mode: ParseMode::default(),
         }
     }
-}
 
-impl<T> Parser<T>
-where
-    T: Iterator<Item = Token>,
-{
+    /// Makes prefix `-` bind less tightly than `^` like in Python, i.e. `-x ^ 2` is parsed as `-(x ^ 2)` instead of
+    /// `(-x) ^ 2`.
+    pub fn with_python_precedence(mut self) -> Self {
+        self.python_precedence = true;
+        self
+    }
+
+    /// Sets how tokens after a complete expression are handled. Defaults to [`ParseMode::Strict`].
+    pub fn with_mode(mut self, mode: ParseMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
     pub fn parse(&mut self) -> Expr {
         let expr = self.parse_expr();
-        if self.eat_tok() != Token::Eof {
-            self.unexpected();
+        if self.finish(&[&expr]) {
+            expr
+        } else {
+            Expr::Error
         }
-        expr
+    }
+
+    /// Parses an equation, e.g. `2 * x + 1 = 5`. A missing `=` is reported as an error and the right hand side is
+    /// then [`Expr::Error`].
+    pub fn parse_equation(&mut self) -> Equation {
+        let lhs = self.parse_expr();
+        let rhs = match self.eat_tok() {
+            Token::Equals => self.parse_expr(),
+            tok => self.unexpected_expected(tok, "a '=' token"),
+        };
+        if self.finish(&[&lhs, &rhs]) {
+            Equation { lhs, rhs }
+        } else {
+            Equation {
+                lhs: Expr::Error,
+                rhs: Expr::Error,
+            }
+        }
+    }
+
+    /// Handles the tokens after the parsed `exprs` according to the mode and checks the limits and the identifiers.
+    /// Returns `false` if the input exceeded the limits, in which case the expressions are incomplete.
+    fn finish(&mut self, exprs: &[&Expr]) -> bool {
+        match self.eat_tok() {
+            Token::Eof => {}
+            tok if self.mode == ParseMode::Strict => {
+                self.unexpected(tok);
+            }
+            _ => {
+                let start = self.last_span.start;
+                while self.eat_tok() != Token::Eof {}
+                self.diagnostics.push(Diagnostic::warning(
+                    DiagnosticCode::TrailingInput,
+                    "trailing input is ignored",
+                    start..self.last_span.end,
+                ));
+            }
+        }
+
+        if self.too_large.is_none() {
+            if let Err(err) = exprs
+                .iter()
+                .try_for_each(|expr| self.limits.check_nodes(expr))
+            {
+                self.too_large = Some(err);
+            }
+        }
+        if let Some(err) = self.too_large {
+            // the input was cut off, other diagnostics are meaningless
+            self.diagnostics.clear();
+            self.diagnostics.push(Diagnostic::error(
+                DiagnosticCode::ExprTooLarge,
+                err.to_string(),
+                self.last_span.clone(),
+            ));
+            return false;
+        }
+
+        self.check_adjacent_letters();
+        true
+    }
+
+    /// Warns about multi-letter identifiers whose letters are all used as identifiers on their own, e.g. `xy` in
+    /// `xy + x * y`. These are most likely meant to be a product and would otherwise silently be treated as a
+    /// different variable.
+    fn check_adjacent_letters(&mut self) {
+        let single_letters: Vec<&str> = self
+            .identifiers
+            .iter()
+            .map(|(ident, _)| ident.as_str())
+            .filter(|ident| ident.chars().count() == 1)
+            .collect();
+
+        for (ident, span) in &self.identifiers {
+            let letters: Vec<String> = ident.chars().map(String::from).collect();
+            if letters.len() > 1
+                && letters
+                    .iter()
+                    .all(|letter| single_letters.contains(&letter.as_str()))
+            {
+                let product = letters.join(" * ");
+                self.diagnostics.push(
+                    Diagnostic::warning(
+                        DiagnosticCode::AdjacentLetters,
+                        format!(
+                            "`{}` is a single variable, not a product. Did you mean `{}`?",
+                            ident, product
+                        ),
+                        span.clone(),
+                    )
+                    .with_suggestion(product),
+                );
+            }
+        }
+    }
+
+    /// Same as `parse` except that an error is returned if the input exceeds the limits of the parser.
+    /// Syntax errors are still reported through `diagnostics`.
+    pub fn parse_checked(&mut self) -> Result<Expr, ExprTooLarge> {
+        let expr = self.parse();
+        match self.too_large {
+            Some(err) => Err(err),
+            None => Ok(expr),
+        }
+    }
+
+    /// Returns the span of the binary operation at `path` in `expr`, the expression returned by this parser. Returns
+    /// `None` if there is no binary operation at `path` or if `expr` is not the parsed expression.
+    pub fn operation_span(&self, expr: &Expr, path: &[usize]) -> Option<Span> {
+        // the operations are created children before their parent and from left to right, i.e. in post-order
+        let mut operations = Vec::new();
+        let mut stack = vec![(expr, ExprPath::new(), false)];
+        while let Some((expr, path, children_visited)) = stack.pop() {
+            if children_visited {
+                if matches!(expr, Expr::Binary { .. }) {
+                    operations.push(path);
+                }
+                continue;
+            }
+            stack.push((expr, path.clone(), true));
+            for (i, child) in expr.children().into_iter().enumerate().rev() {
+                let mut path = path.clone();
+                path.push(i);
+                stack.push((child, path, false));
+            }
+        }
+        // operations discarded after a syntax error were still recorded
+        if operations.len() != self.operation_spans.len() {
+            return None;
+        }
+        let i = operations.iter().position(|operation| operation == path)?;
+        Some(self.operation_spans[i].clone())
+    }
+
+    /// Returns the spans of the source of the literals in `expr`, the expression returned by this parser, in the order
+    /// they are converted by [`Expr::map_literals`], e.g. to read the literals with more precision than `f64`. The span
+    /// is `None` for the literals that are not written out, i.e. the exponent `0.5` of a square root. Returns `None` if
+    /// `expr` is not the parsed expression.
+    pub fn literal_spans(&self, expr: &Expr) -> Option<&[Option<Span>]> {
+        // the literals are created from left to right, i.e. in the order of `map_literals`
+        let literals = expr.find_paths(|expr| matches!(expr, Expr::Literal(_)));
+        // literals discarded after a syntax error were still recorded
+        (literals.len() == self.literal_spans.len()).then_some(&self.literal_spans)
     }
 
     /// Alias for `self.parse_expr_bp(0)` to accept any expression.
@@ -176,41 +977,199 @@ where
 
     fn parse_atom(&mut self) -> Expr {
         match self.eat_tok() {
-            Token::Number(num) => Expr::Literal(num),
-            Token::Identifier(ident) => Expr::Identifier(ident),
+            Token::Number(num) => {
+                self.literal_spans.push(Some(self.last_span.clone()));
+                Expr::Literal(num)
+            }
+            Token::Identifier(ident) if ident == "int" && self.current_tok == Token::OpenParen => {
+                let start = self.last_span.start;
+                self.eat_tok(); // eat '('
+                self.integral(start)
+            }
+            Token::Identifier(ident) if self.current_tok == Token::OpenParen => {
+                let start = self.last_span.start;
+                self.eat_tok(); // eat '('
+                let mut args = self.parse_args();
+                // the square root is the power `0.5` so that it does not need rules of its own
+                if ident == "sqrt" && args.len() == 1 {
+                    self.operation_spans.push(start..self.last_span.end);
+                    self.literal_spans.push(None);
+                    return square_root(args.pop().unwrap());
+                }
+                Expr::Call { func: ident, args }
+            }
+            // the radicand binds tighter than `*` but includes powers, e.g. `√x ^ 2 * 3` is `sqrt(x ^ 2) * 3`
+            Token::SquareRoot => {
+                let start = self.last_span.start;
+                let radicand = self.parse_expr_bp(5);
+                self.operation_spans.push(start..self.last_span.end);
+                self.literal_spans.push(None);
+                square_root(radicand)
+            }
+            // `d/dx u` is a derivative request, same as `diff(u, x)`
+            Token::Identifier(ident)
+                if ident == "d"
+                    && self.current_tok == Token::Slash
+                    && self.next_is_differential() =>
+            {
+                self.eat_tok(); // eat '/'
+                let var = match self.eat_tok() {
+                    Token::Identifier(differential) => differential[1..].to_string(),
+                    _ => unreachable!("next token should be a differential"),
+                };
+                // the operand binds tighter than `*` but includes powers, e.g. `d/dx (x) ^ 3 * 2` is
+                // `diff(x ^ 3, x) * 2`
+                let operand = self.parse_expr_bp(5);
+                Expr::Call {
+                    func: "diff".to_string(),
+                    args: vec![operand, Expr::Identifier(var)],
+                }
+            }
+            Token::Identifier(ident) if ident == "undefined" => Expr::Undefined,
+            Token::Identifier(ident) => match constants::lookup(&ident) {
+                Some(constant) => Expr::Constant(constant),
+                None => {
+                    self.identifiers
+                        .push((ident.clone(), self.last_span.clone()));
+                    Expr::Identifier(ident)
+                }
+            },
             Token::OpenParen => {
                 let expr = self.parse_expr();
                 match self.eat_tok() {
                     Token::CloseParen => expr,
-                    _ => self.unexpected_expected("a '(' token"),
+                    tok => self.unexpected_expected(tok, "a '(' token"),
+                }
+            }
+            Token::OpenBracket => Expr::Vector(self.parse_list(Token::CloseBracket)),
+            // `|u|` is `abs(u)`, a bar in place of an operator closes the innermost one, e.g. `||x| - 1|`
+            Token::Bar => {
+                let arg = self.parse_expr();
+                match self.eat_tok() {
+                    Token::Bar => Expr::Call {
+                        func: "abs".to_string(),
+                        args: vec![arg],
+                    },
+                    tok => self.unexpected_expected(tok, "a '|' token"),
                 }
             }
-            _ => self.unexpected_expected("an expression"),
+            tok => self.unexpected_expected(tok, "an expression"),
         }
     }
 
+    /// Parses the arguments of `int(integrand, var)` or `int(integrand, var, lower, upper)`, starting at the byte offset
+    /// `start`. The opening paren should already have been eaten.
+    fn integral(&mut self, start: usize) -> Expr {
+        let mut args = self.parse_args();
+        match args.as_mut_slice() {
+            [integrand, Expr::Identifier(var)] => Expr::Integral {
+                integrand: Box::new(integrand.take()),
+                var: std::mem::take(var),
+                bounds: None,
+            },
+            [integrand, Expr::Identifier(var), lower, upper] => Expr::Integral {
+                integrand: Box::new(integrand.take()),
+                var: std::mem::take(var),
+                bounds: Some(Box::new((lower.take(), upper.take()))),
+            },
+            _ => {
+                self.diagnostics.push(Diagnostic::error(
+                    DiagnosticCode::UnexpectedToken,
+                    "`int` takes an integrand, a variable and optionally a lower and an upper bound",
+                    start..self.last_span.end,
+                ));
+                Expr::Error
+            }
+        }
+    }
+
+    /// Returns whether the token after the current one is a differential such as `dx` followed by a parenthesized
+    /// operand, e.g. in `d/dx (x ^ 2)`. Otherwise, e.g. `d/delta` is a division.
+    fn next_is_differential(&mut self) -> bool {
+        matches!(self.peek(0), Some(Token::Identifier(ident)) if is_differential(ident))
+            && self.peek(1) == Some(&Token::OpenParen)
+    }
+
+    /// Parses the comma separated arguments of a function call. The opening paren should already have been eaten.
+    fn parse_args(&mut self) -> Vec<Expr> {
+        self.parse_list(Token::CloseParen)
+    }
+
+    /// Parses comma separated expressions up to and including `close`, e.g. the arguments of a call or the components
+    /// of a vector. The opening token should already have been eaten.
+    fn parse_list(&mut self, close: Token) -> Vec<Expr> {
+        let mut list = Vec::new();
+        if self.current_tok == close {
+            self.eat_tok();
+            return list;
+        }
+        loop {
+            list.push(self.parse_expr());
+            match self.eat_tok() {
+                Token::Comma => {}
+                tok if tok == close => break,
+                tok => {
+                    let expected = match close {
+                        Token::CloseBracket => "a ',' or ']' token",
+                        _ => "a ',' or ')' token",
+                    };
+                    self.unexpected_expected(tok, expected);
+                    break;
+                }
+            }
+        }
+        list
+    }
+
     fn parse_expr_bp(&mut self, min_bp: i32) -> Expr {
+        let start = self.current_span.start;
         let mut left = match self.current_tok.get_prefix_bp() {
             ((), -1) => self.parse_atom(), // not prefix
             ((), right_bp) => {
+                // between `*` and `^`
+                let right_bp = if self.python_precedence { 5 } else { right_bp };
                 let prefix_op: UnaryOpKind = self
                     .eat_tok()
                     .try_into()
                     .expect("non negative bp should be valid unary op");
+                let is_number = matches!(self.current_tok, Token::Number(_));
                 let right = self.parse_expr_bp(right_bp);
-                if let Expr::Literal(num) = right {
-                    // fold unary literal in ast
-                    Expr::Literal(num * -1.0)
-                } else {
-                    Expr::Unary {
+                match right {
+                    // fold a negative number into the literal, but keep `-(2)` as written so that every expression
+                    // can be printed in a form that parses back to it
+                    Expr::Literal(num) if is_number => {
+                        if let Some(Some(span)) = self.literal_spans.last_mut() {
+                            span.start = start;
+                        }
+                        Expr::Literal(-num)
+                    }
+                    right => Expr::Unary {
                         op: prefix_op,
                         right: Box::new(right),
-                    }
+                    },
                 }
             }
         };
 
         loop {
+            let (left_bp, ()) = self.current_tok.get_postfix_bp();
+            if left_bp >= min_bp {
+                // superscript exponent, e.g. x²
+                left = match self.eat_tok() {
+                    Token::Superscript(exponent) => {
+                        self.literal_spans.push(Some(self.last_span.clone()));
+                        Expr::Binary {
+                            left: Box::new(left),
+                            op: BinOpKind::Exponent,
+                            right: Box::new(Expr::Literal(exponent)),
+                        }
+                    }
+                    _ => unreachable!("non negative bp should be valid postfix op"),
+                };
+                self.operation_spans.push(start..self.last_span.end);
+                continue;
+            }
+
             let (left_bp, right_bp) = self.current_tok.get_infix_bp();
 
             // stop parsing
@@ -222,6 +1181,7 @@ where
                 .try_into()
                 .expect("non negative bp should be valid binop");
             let right = self.parse_expr_bp(right_bp);
+            self.operation_spans.push(start..self.last_span.end);
             left = Expr::Binary {
                 left: Box::new(left),
                 op: bin_op,
@@ -235,27 +1195,99 @@ where
     // utils
 
     /// Returns the current token. Sets `self.current_tok` to the next [`Token`] in the lexer.
+    /// Once `max_tokens` tokens have been read, the next token is always [`Token::Eof`].
     fn eat_tok(&mut self) -> Token {
-        let res = self.current_tok.clone();
-        self.current_tok = self.lexer.next().unwrap_or(Token::Eof);
+        let res = std::mem::replace(&mut self.current_tok, Token::Eof);
+        // the span of eof is the empty span right after the last token
+        let eof_span = self.current_span.end..self.current_span.end;
+        self.last_span = std::mem::replace(&mut self.current_span, eof_span);
+        if let Some((next, span)) = self.lookahead.pop_front().or_else(|| self.lexer.next()) {
+            if self.tokens_read < self.limits.max_tokens {
+                self.tokens_read += 1;
+                self.current_tok = next;
+                self.current_span = span;
+            } else if self.too_large.is_none() {
+                self.too_large = Some(ExprTooLarge {
+                    kind: LimitKind::Tokens,
+                    limit: self.limits.max_tokens,
+                });
+            }
+        }
         res
     }
 
+    /// Reports `tok`, the last eaten token, as unexpected. [`Token::Error`] is reported as an invalid token and
+    /// [`Token::MalformedNumber`] as a malformed number instead.
+    fn report_unexpected(&mut self, tok: Token, message: String) {
+        let diagnostic = match tok {
+            Token::MalformedNumber => Diagnostic::error(
+                DiagnosticCode::MalformedNumber,
+                "malformed number literal",
+                self.last_span.clone(),
+            ),
+            Token::Error => Diagnostic::error(
+                DiagnosticCode::InvalidToken,
+                "unrecognized token",
+                self.last_span.clone(),
+            ),
+            _ => Diagnostic::error(
+                DiagnosticCode::UnexpectedToken,
+                message,
+                self.last_span.clone(),
+            ),
+        };
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Returns the token `n` tokens after the current one without eating it, e.g. `self.peek(0)` is the next token.
+    fn peek(&mut self, n: usize) -> Option<&Token> {
+        while self.lookahead.len() <= n {
+            let next = self.lexer.next()?;
+            self.lookahead.push_back(next);
+        }
+        self.lookahead.get(n).map(|(tok, _)| tok)
+    }
+
     /// Returns [`Expr::Error`].
-    fn unexpected(&mut self) -> Expr {
-        self.errors.push("unexpected token".to_string());
+    fn unexpected(&mut self, tok: Token) -> Expr {
+        self.report_unexpected(tok, "unexpected token".to_string());
         Expr::Error
     }
 
     /// Returns [`Expr::Error`].
-    fn unexpected_expected(&mut self, expected: &str) -> Expr {
-        self.errors
-            .push(format!("unexpected token, expected {}", expected));
+    fn unexpected_expected(&mut self, tok: Token, expected: &str) -> Expr {
+        self.report_unexpected(tok, format!("unexpected token, expected {}", expected));
         Expr::Error
     }
 
-    pub fn errors(&self) -> &Vec<String> {
-        &self.errors
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+}
+
+/// Returns whether `ident` is the differential of a single variable, i.e. `d` followed by a letter that is not a
+/// constant and an optional index, e.g. `dx` or `dx_1`.
+fn is_differential(ident: &str) -> bool {
+    let var = match ident.strip_prefix('d') {
+        Some(var) => var,
+        None => return false,
+    };
+    let mut chars = var.chars();
+    let letter = chars.next().is_some_and(|c| c.is_ascii_alphabetic());
+    let index = chars.as_str();
+    let valid_index = index.is_empty()
+        || index
+            .strip_prefix('_')
+            .is_some_and(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()));
+    letter && valid_index && constants::lookup(var).is_none()
+}
+
+/// Returns `radicand ^ 0.5`, the representation of `sqrt(radicand)`.
+fn square_root(radicand: Expr) -> Expr {
+    Expr::Binary {
+        left: Box::new(radicand),
+        op: BinOpKind::Exponent,
+        right: Box::new(Expr::Literal(0.5)),
     }
 }
 
@@ -267,13 +1299,16 @@ mod tests {
     use super::*;
 
     fn check(input: &str, expect: Expect) {
-        let lexer = Token::lexer(input);
+        let lexer = Token::lexer(input).spanned();
         let mut parser = Parser::from(lexer);
         let expr = parser.parse();
 
         let mut actual = expr.to_string();
-        for error in parser.errors() {
-            actual += &format!("\n[ERROR]: {}", error);
+        for diagnostic in parser.diagnostics() {
+            actual += &format!(
+                "\n[{}]: {} at {:?}",
+                diagnostic.severity, diagnostic.message, diagnostic.span
+            );
         }
         expect.assert_eq(&actual);
     }
@@ -304,6 +1339,261 @@ mod tests {
         check("1 / 2", expect![[r#"(1 / 2)"#]]);
         check("1 ^ 2", expect![[r#"(1 ^ 2)"#]]);
         check("1 ** 2", expect![[r#"(1 ^ 2)"#]]);
+
+        check("1 × 2", expect![[r#"(1 * 2)"#]]);
+        check("1 ÷ 2", expect![[r#"(1 / 2)"#]]);
+        check("1 − 2", expect![[r#"(1 - 2)"#]]);
+        check("−x", expect![[r#"(-x)"#]]);
+    }
+
+    #[test]
+    fn calls() {
+        check("abs(x)", expect![[r#"abs(x)"#]]);
+        check(
+            "2 * sign(x - 1) ^ 2",
+            expect![[r#"(2 * (sign(x - 1) ^ 2))"#]],
+        );
+        check("f(x, y + 1, 2)", expect![[r#"f(x, y + 1, 2)"#]]);
+        check("f()", expect![[r#"f()"#]]);
+        check(
+            "abs(x",
+            expect![[r#"
+            abs(x)
+            [ERROR]: unexpected token, expected a ',' or ')' token at 5..5"#]],
+        );
+        check(
+            "abs(x y)",
+            expect![[r#"
+            abs(x)
+            [ERROR]: unexpected token, expected a ',' or ')' token at 6..7
+            [ERROR]: unexpected token at 7..8"#]],
+        );
+    }
+
+    #[test]
+    fn identifiers() {
+        let expr = Parser::from(Token::lexer("y * x + 2 * x ^ z").spanned()).parse();
+        assert_eq!(expr.identifiers(), vec!["x", "y", "z"]);
+
+        let expr =
+            Parser::from(Token::lexer("y * int(t ^ 2, t, 0, x) + int(t, t)").spanned()).parse();
+        assert_eq!(expr.identifiers(), vec!["t", "x", "y"]);
+        assert_eq!(expr.free_identifiers(), vec!["t", "x", "y"]);
+        let expr =
+            Parser::from(Token::lexer("int(t * int(s, s, 0, t), t, 0, t)").spanned()).parse();
+        assert_eq!(expr.free_identifiers(), vec!["t"]);
+        let expr = Parser::from(Token::lexer("y * int(t ^ 2, t, 0, x)").spanned()).parse();
+        assert_eq!(expr.free_identifiers(), vec!["x", "y"]);
+    }
+
+    #[test]
+    fn replace_at() {
+        let mut expr = Parser::from(Token::lexer("abs(x) + 2 * abs(x)").spanned()).parse();
+        let target = Parser::from(Token::lexer("abs(x)").spanned()).parse();
+        let paths = expr.find_paths(|expr| *expr == target);
+        assert_eq!(paths, vec![vec![0], vec![1, 1]]);
+        assert_eq!(expr.get(&[1, 1]), Some(&target));
+        assert_eq!(expr.get(&[1, 2]), None);
+
+        // only the second occurrence is replaced
+        let old = expr.replace_at(&paths[1], Expr::Identifier("y".to_string()));
+        assert_eq!(old, Some(target));
+        assert_eq!(expr.to_string(), "(abs(x) + (2 * y))");
+
+        assert_eq!(expr.replace_at(&[0, 0, 0], Expr::Literal(1.0)), None);
+        assert_eq!(expr.to_string(), "(abs(x) + (2 * y))");
+        expr.replace_at(&[], Expr::Literal(1.0));
+        assert_eq!(expr, Expr::Literal(1.0));
+    }
+
+    #[test]
+    fn map_literals() {
+        let expr = Parser::from(Token::lexer("-2.5 * abs(x, 3) ^ 2").spanned()).parse();
+        let converted: Expr<i64> = expr.map_literals(|num| *num as i64);
+        assert_eq!(converted.node_count(), expr.node_count());
+        assert_eq!(converted.get(&[1, 0, 1]), Some(&Expr::Literal(3)));
+        assert_eq!(
+            converted.map_literals(|num| *num as f64).to_string(),
+            expr.to_string().replace("2.5", "2")
+        );
+    }
+
+    #[test]
+    fn smart_constructors() {
+        let x = || Expr::Identifier("x".to_string());
+        let lit = Expr::Literal;
+        assert_eq!(Expr::add(lit(0.0), x()), x());
+        assert_eq!(Expr::add(lit(1.0), lit(2.0)), lit(3.0));
+        assert_eq!(Expr::sub(x(), lit(0.0)), x());
+        assert_eq!(Expr::sub(lit(0.0), x()).to_string(), "(0 - x)");
+        assert_eq!(Expr::mul(x(), lit(1.0)), x());
+        assert_eq!(Expr::mul(lit(0.0), x()), lit(0.0));
+        assert_eq!(Expr::mul(lit(2.0), lit(3.0)), lit(6.0));
+        assert_eq!(Expr::mul(lit(2.0), x()).to_string(), "(2 * x)");
+        assert_eq!(Expr::pow(x(), lit(1.0)), x());
+        assert_eq!(Expr::pow(x(), lit(0.0)), lit(1.0));
+        assert_eq!(Expr::pow(lit(1.0), x()), lit(1.0));
+        assert_eq!(Expr::pow(lit(2.0), lit(3.0)), lit(8.0));
+        assert_eq!(Expr::pow(lit(0.0), lit(-1.0)).to_string(), "(0 ^ (-1))");
+        assert_eq!(Expr::pow(lit(2.0), lit(-1.0)).to_string(), "(1 / 2)");
+
+        let undefined = || Expr::binary(lit(1.0), BinOpKind::Slash, lit(0.0));
+        assert_eq!(
+            Expr::mul(lit(0.0), undefined()).to_string(),
+            "(0 * (1 / 0))"
+        );
+        assert_eq!(Expr::mul(Expr::Error, lit(0.0)).to_string(), "(err * 0)");
+        assert_eq!(
+            Expr::pow(undefined(), lit(0.0)).to_string(),
+            "((1 / 0) ^ 0)"
+        );
+        assert_eq!(
+            Expr::pow(lit(1.0), Expr::Undefined).to_string(),
+            "(1 ^ undefined)"
+        );
+        assert_eq!(-lit(2.0), lit(-2.0));
+        assert_eq!((-x()).to_string(), "(-x)");
+    }
+
+    #[test]
+    fn tree_string() {
+        let expr = Parser::from(Token::lexer("1 + 2 * -x ^ 2 - y").spanned()).parse();
+        expect![[r#"
+            -
+            ├── +
+            │   ├── 1
+            │   └── *
+            │       ├── 2
+            │       └── ^
+            │           ├── -
+            │           │   └── x
+            │           └── 2
+            └── y
+        "#]]
+        .assert_eq(&expr.to_tree_string());
+    }
+
+    #[test]
+    fn alternate_tree() {
+        let expr = Parser::from(Token::lexer("2 * x - abs(-y)").spanned()).parse();
+        expect![[r#"
+            Binary -
+              Binary *
+                Literal 2
+                Identifier x
+              Call abs
+                Unary -
+                  Identifier y"#]]
+        .assert_eq(&format!("{:#}", expr));
+    }
+
+    #[test]
+    fn derivative_request() {
+        check("d/dx (x ^ 2)", expect![[r#"diff(x ^ 2, x)"#]]);
+        check("d/dt (t * y) + 1", expect![[r#"(diff(t * y, t) + 1)"#]]);
+        check("d/dx (x)^3 * 2", expect![[r#"(diff(x ^ 3, x) * 2)"#]]);
+        check("d/dx_1 (x_1)", expect![[r#"diff(x_1, x_1)"#]]);
+        check("diff(x ^ 2, x)", expect![[r#"diff(x ^ 2, x)"#]]);
+        check("d / 2", expect![[r#"(d / 2)"#]]);
+        // only the differential of a single variable followed by a parenthesized operand
+        check("d/delta", expect!["((d) / delta)"]);
+        check("d/delta (x)", expect!["((d) / delta(x))"]);
+        check("d/dx", expect!["((d) / dx)"]);
+        check(
+            "d/dx x",
+            expect![[r#"
+            ((d) / dx)
+            [ERROR]: unexpected token at 5..6"#]],
+        );
+        check("d/de (x)", expect!["((d) / de(x))"]);
+    }
+
+    #[test]
+    fn digit_separators() {
+        check("1_000_000", expect![[r#"1000000"#]]);
+        check("1.234_567 * x", expect![[r#"(1.234567 * x)"#]]);
+    }
+
+    #[test]
+    fn superscript() {
+        check("x²", expect![[r#"(x ^ 2)"#]]);
+        check("2 * x¹⁰ + 1", expect![[r#"((2 * (x ^ 10)) + 1)"#]]);
+        check("x⁻¹", expect![[r#"(x ^ (-1))"#]]);
+        check("(x + 1)²", expect![[r#"((x + 1) ^ 2)"#]]);
+        check("x^2²", expect![[r#"(x ^ (2 ^ 2))"#]]);
+    }
+
+    #[test]
+    fn absolute_value() {
+        check("|x|", expect![[r#"abs(x)"#]]);
+        check("2 * |x - 1| ^ 2", expect![[r#"(2 * (abs(x - 1) ^ 2))"#]]);
+        check("||x| - 1|", expect![[r#"abs(abs(x) - 1)"#]]);
+        check("|-2|", expect![[r#"abs((-2))"#]]);
+        check(
+            "|x + 1",
+            expect![[r#"
+                err
+                [ERROR]: unexpected token, expected a '|' token at 6..6"#]],
+        );
+    }
+
+    #[test]
+    fn square_root() {
+        check("sqrt(x + 1)", expect![[r#"((x + 1) ^ 0.5)"#]]);
+        check("√x", expect![[r#"(x ^ 0.5)"#]]);
+        check("2 * √(x + 1)", expect![[r#"(2 * ((x + 1) ^ 0.5))"#]]);
+        check("√x ^ 2 * 3", expect![[r#"(((x ^ 2) ^ 0.5) * 3)"#]]);
+        check("√x²", expect![[r#"((x ^ 2) ^ 0.5)"#]]);
+        check("sqrt(x, 2)", expect![[r#"sqrt(x, 2)"#]]);
+    }
+
+    #[test]
+    fn operation_span() {
+        let input = "2 * (x + 1) ^ 3 - √y²";
+        let mut parser = Parser::from(Token::lexer(input).spanned());
+        let expr = parser.parse();
+        let span = |path: &[usize]| parser.operation_span(&expr, path).map(|span| &input[span]);
+        assert_eq!(span(&[]), Some(input));
+        assert_eq!(span(&[0, 1]), Some("(x + 1) ^ 3"));
+        assert_eq!(span(&[0, 1, 0]), Some("x + 1"));
+        assert_eq!(span(&[1]), Some("√y²"));
+        assert_eq!(span(&[1, 0]), Some("y²"));
+        assert_eq!(span(&[0, 0]), None);
+    }
+
+    #[test]
+    fn literal_spans() {
+        let input = "1_000 * x³ - √(- 0.25) + -2";
+        let mut parser = Parser::from(Token::lexer(input).spanned());
+        let expr = parser.parse();
+        let sources: Vec<Option<&str>> = parser
+            .literal_spans(&expr)
+            .unwrap()
+            .iter()
+            .map(|span| span.clone().map(|span| &input[span]))
+            .collect();
+        assert_eq!(
+            sources,
+            [Some("1_000"), Some("³"), Some("- 0.25"), None, Some("-2")]
+        );
+
+        // the parenthesized literal is discarded after the syntax error
+        let input = "1 + (2 3)";
+        let mut parser = Parser::from(Token::lexer(input).spanned());
+        let expr = parser.parse();
+        assert_eq!(parser.literal_spans(&expr), None);
+    }
+
+    #[test]
+    fn constants() {
+        check("2 * pi", expect![[r#"(2 * pi)"#]]);
+        check("π ^ e", expect![[r#"(pi ^ e)"#]]);
+        let expr = Parser::from(Token::lexer("e ^ x + pi * epsilon").spanned()).parse();
+        assert_eq!(expr.identifiers(), vec!["epsilon", "x"]);
+        assert!(matches!(
+            expr.get(&[0, 0]),
+            Some(Expr::Constant(Constant::E))
+        ));
     }
 
     #[test]
@@ -328,8 +1618,8 @@ mod tests {
         check(
             "1 $ 2",
             expect![[r#"
-            1
-            [ERROR]: unexpected token"#]],
+                1
+                [ERROR]: unrecognized token at 2..3"#]],
         );
     }
 
@@ -339,25 +1629,184 @@ mod tests {
             "(1",
             expect![[r#"
                 err
-                [ERROR]: unexpected token, expected a '(' token"#]],
+                [ERROR]: unexpected token, expected a '(' token at 2..2"#]],
         );
         check(
             "(1 + 2",
             expect![[r#"
                 err
-                [ERROR]: unexpected token, expected a '(' token"#]],
+                [ERROR]: unexpected token, expected a '(' token at 6..6"#]],
         );
         check(
             "1)",
             expect![[r#"
-            1
-            [ERROR]: unexpected token"#]],
+                1
+                [ERROR]: unexpected token at 1..2"#]],
         );
         check(
             "1 + 2)",
             expect![[r#"
-            (1 + 2)
-            [ERROR]: unexpected token"#]],
+                (1 + 2)
+                [ERROR]: unexpected token at 5..6"#]],
+        );
+    }
+
+    #[test]
+    fn integrals() {
+        check("int(x ^ 2, x)", expect![[r#"int(x ^ 2, x)"#]]);
+        check(
+            "2 * int(t, t, 0, x + 1)",
+            expect![[r#"(2 * int(t, t, 0, x + 1))"#]],
+        );
+        check(
+            "int(x, 2)",
+            expect![[r#"
+                err
+                [ERROR]: `int` takes an integrand, a variable and optionally a lower and an upper bound at 0..9"#]],
+        );
+        check(
+            "int(x, x, 0)",
+            expect![[r#"
+                err
+                [ERROR]: `int` takes an integrand, a variable and optionally a lower and an upper bound at 0..12"#]],
+        );
+    }
+
+    #[test]
+    fn vectors() {
+        check("[x ^ 2, abs(x), 1]", expect![[r#"[x ^ 2, abs(x), 1]"#]]);
+        check("2 * [t, -t] + []", expect![[r#"((2 * [t, (-t)]) + [])"#]]);
+        check(
+            "[x, 1)",
+            expect![[r#"
+                [x, 1]
+                [ERROR]: unexpected token, expected a ',' or ']' token at 5..6"#]],
+        );
+    }
+
+    #[test]
+    fn equations() {
+        let parse = |input| {
+            let mut parser = Parser::from(Token::lexer(input).spanned());
+            let equation = parser.parse_equation();
+            (equation.to_string(), parser.diagnostics().len())
+        };
+        assert_eq!(parse("2 * x + 1 = y"), ("((2 * x) + 1) = y".to_string(), 0));
+        assert_eq!(parse("x + 1"), ("(x + 1) = err".to_string(), 1));
+        assert_eq!(parse("x = 1 = 2"), ("x = 1".to_string(), 1));
+        check(
+            "x = 1",
+            expect![[r#"
+                x
+                [ERROR]: unexpected token at 2..3"#]],
+        );
+    }
+
+    #[test]
+    fn leading_dot() {
+        check(".5 * x", expect![[r#"(0.5 * x)"#]]);
+        check("2. + 1", expect![[r#"(2 + 1)"#]]);
+    }
+
+    #[test]
+    fn error_malformed_number() {
+        check(
+            "1.2.3 + x",
+            expect![[r#"
+                (err + x)
+                [ERROR]: malformed number literal at 0..5"#]],
+        );
+        check(
+            "x * .",
+            expect![[r#"
+                (x * err)
+                [ERROR]: malformed number literal at 4..5"#]],
+        );
+    }
+
+    #[test]
+    fn lenient_mode() {
+        let mut parser =
+            Parser::from(Token::lexer("1 + 2) * 3").spanned()).with_mode(ParseMode::Lenient);
+        assert_eq!(parser.parse().to_string(), "(1 + 2)");
+        assert_eq!(
+            parser.diagnostics().iter().collect::<Vec<_>>(),
+            vec![&Diagnostic::warning(
+                DiagnosticCode::TrailingInput,
+                "trailing input is ignored",
+                5..10
+            )]
+        );
+        assert!(!parser.diagnostics().has_errors());
+    }
+
+    #[test]
+    fn display_deeply_nested() {
+        let depth = 100_000;
+        let mut expr = Expr::Identifier("x".to_string());
+        for _ in 0..depth {
+            expr = Expr::Unary {
+                op: UnaryOpKind::Minus,
+                right: Box::new(expr),
+            };
+        }
+
+        let expected = "(-".repeat(depth) + "x" + &")".repeat(depth);
+        assert!(expr.to_string() == expected);
+        // cloning and dropping must not overflow the stack either
+        assert!(expr.clone().node_count() == depth + 1);
+    }
+
+    #[test]
+    fn error_too_large() {
+        let limits = Limits {
+            max_tokens: 5,
+            max_nodes: 3,
+            ..Limits::default()
+        };
+        let parse = |input: &str| {
+            let mut parser = Parser::with_limits(Token::lexer(input).spanned(), limits);
+            let res = parser.parse_checked();
+            let messages: Vec<_> = parser
+                .diagnostics()
+                .iter()
+                .map(|diagnostic| diagnostic.message.clone())
+                .collect();
+            (res, messages)
+        };
+
+        assert_eq!(
+            parse("1 + 2 + 3 + 4"),
+            (
+                Err(ExprTooLarge {
+                    kind: LimitKind::Tokens,
+                    limit: 5
+                }),
+                vec!["expression too large, exceeded the limit of 5 tokens".to_string()]
+            )
+        );
+        assert_eq!(
+            parse("x + -y"),
+            (
+                Err(ExprTooLarge {
+                    kind: LimitKind::Nodes,
+                    limit: 3
+                }),
+                vec!["expression too large, exceeded the limit of 3 nodes".to_string()]
+            )
+        );
+        assert!(parse("(1 + 2)").0.is_ok());
+    }
+
+    #[test]
+    fn warning_adjacent_letters() {
+        check(
+            "xy + x * y",
+            expect![[r#"
+                (xy + (x * y))
+                [WARNING]: `xy` is a single variable, not a product. Did you mean `x * y`? at 0..2"#]],
         );
+        // no warning if the letters are not used on their own
+        check("ab * x", expect![[r#"(ab * x)"#]]);
     }
 }
@@ -0,0 +1,780 @@
+//! The full pipeline from an input string to its simplified derivative, shared by the frontends.
+
+use crate::analysis::Assumptions;
+use crate::diagnostic::{Diagnostic, DiagnosticCode, Diagnostics, Span};
+use crate::eval::{with_angle_mode, AngleMode};
+use crate::lexer::Token;
+use crate::limits::{ExprTooLarge, Limits};
+use crate::parser::{Expr, ExprPath, ExprVisitor, Parser};
+use crate::transformations::cleanup::DerivativeCleanup;
+use crate::transformations::derivative::{
+    try_partial_derivative, try_partial_derivative_cached, CacheStats, DerivativeCache,
+    DerivativeError, EvaluateDerivatives,
+};
+use crate::transformations::logarithm::ExpandLogarithms;
+use crate::transformations::prettify::Prettify;
+use crate::transformations::profile::{profile_rules, RuleProfile};
+use crate::transformations::simplify::{
+    is_undefined_operation, FoldUndefined, NonFinitePolicy, Simplify, SimplifyWith,
+};
+use crate::transformations::RuleTransformSet;
+use logos::Logos;
+use std::fmt;
+
+/// A step of the pipeline that is timed separately.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Phase {
+    Parse,
+    SimplifyInput,
+    PrettifyInput,
+    Derive,
+    Cleanup,
+    SimplifyDerivative,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Phase::Parse => "Parsed input",
+            Phase::SimplifyInput => "Simplify input",
+            Phase::PrettifyInput => "Prettify input",
+            Phase::Derive => "Compute derivative",
+            Phase::Cleanup => "Clean up derivative",
+            Phase::SimplifyDerivative => "Simplify and prettify derivative",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The duration of a [`Phase`] and the size of the expression it produced.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PhaseReport {
+    pub phase: Phase,
+    /// The duration in milliseconds, as measured by the clock passed to [`run_pipeline`].
+    pub millis: f64,
+    /// The number of nodes in the expression after the phase.
+    pub nodes: usize,
+}
+
+/// An error that stopped the pipeline.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineError {
+    /// The input does not contain any tokens.
+    NoInput,
+    /// The input exceeded the limits of the parser.
+    TooLarge(ExprTooLarge),
+    /// The derivative could not be computed.
+    Derivative(DerivativeError),
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::NoInput => write!(f, "no input found, skipping"),
+            PipelineError::TooLarge(err) => write!(f, "{}", err),
+            PipelineError::Derivative(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+/// Everything produced by [`run_pipeline`]. The expressions of the phases that did not run because of an earlier
+/// `error` are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PipelineReport {
+    /// The expression exactly as parsed.
+    pub parsed: Option<Expr>,
+    /// The diagnostics emitted by the parser, and the undefined operations that are kept as written with
+    /// [`NonFinitePolicy::Keep`].
+    pub diagnostics: Diagnostics,
+    /// The simplified input, which is the expression that is differentiated.
+    pub simplified: Option<Expr>,
+    /// The simplified input in a more readable form, for display.
+    pub prettified: Option<Expr>,
+    /// The variable that the input is differentiated with respect to, which may differ from
+    /// [`PipelineOptions::with_variable`] if a picker is set with [`PipelineOptions::with_variable_picker`].
+    pub variable: Option<String>,
+    /// The simplified and prettified derivative.
+    pub derivative: Option<Expr>,
+    /// The error that stopped the pipeline, if any.
+    pub error: Option<PipelineError>,
+    /// The phases that ran, in order.
+    pub phases: Vec<PhaseReport>,
+    /// The statistics of the derivative cache if it is enabled with [`PipelineOptions::with_derivative_cache`].
+    pub derivative_cache: Option<CacheStats>,
+    /// The statistics of the rules applied by all the phases if profiling is enabled with
+    /// [`PipelineOptions::with_rule_profile`].
+    pub rule_profile: Option<RuleProfile>,
+}
+
+impl PipelineReport {
+    /// Returns the sum of the durations of all the phases in milliseconds.
+    pub fn total_millis(&self) -> f64 {
+        self.phases.iter().map(|phase| phase.millis).sum()
+    }
+}
+
+/// Options for [`run_pipeline_with_options`].
+#[derive(Clone)]
+pub struct PipelineOptions<'a> {
+    limits: Limits,
+    rules: Option<&'a RuleTransformSet<'a>>,
+    variable: String,
+    variable_picker: Option<fn(&Expr, &str) -> String>,
+    prettify: bool,
+    derivative_cache: bool,
+    non_finite: NonFinitePolicy,
+    rule_clock: Option<fn() -> f64>,
+    expand_logarithms: bool,
+    assumptions: Assumptions,
+    angle_mode: AngleMode,
+}
+
+impl Default for PipelineOptions<'_> {
+    fn default() -> Self {
+        Self {
+            limits: Limits::default(),
+            rules: None,
+            variable: "x".to_string(),
+            variable_picker: None,
+            prettify: true,
+            derivative_cache: false,
+            non_finite: NonFinitePolicy::default(),
+            rule_clock: None,
+            expand_logarithms: false,
+            assumptions: Assumptions::new(),
+            angle_mode: AngleMode::default(),
+        }
+    }
+}
+
+impl<'a> PipelineOptions<'a> {
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets extra rules that are applied whenever the expressions are simplified, e.g. rules defined by the user with
+    /// [`crate::transformations::custom::parse_rules`].
+    pub fn with_rules(mut self, rules: &'a RuleTransformSet<'a>) -> Self {
+        self.rules = Some(rules);
+        self
+    }
+
+    /// Sets the variable that the input is differentiated with respect to. Defaults to `x`.
+    pub fn with_variable(mut self, variable: impl Into<String>) -> Self {
+        self.variable = variable.into();
+        self
+    }
+
+    /// Sets a function that picks the variable from the simplified input and the variable set with
+    /// [`Self::with_variable`], e.g. [`crate::transformations::derivative::pick_variable`]. Defaults to always using
+    /// the variable set with [`Self::with_variable`].
+    pub fn with_variable_picker(mut self, picker: Option<fn(&Expr, &str) -> String>) -> Self {
+        self.variable_picker = picker;
+        self
+    }
+
+    /// Sets whether the input and the derivative are prettified for display. Defaults to `true`.
+    pub fn with_prettify(mut self, prettify: bool) -> Self {
+        self.prettify = prettify;
+        self
+    }
+
+    /// Sets whether repeated sub-expressions are only differentiated once, see [`DerivativeCache`]. Defaults to
+    /// `false`.
+    pub fn with_derivative_cache(mut self, derivative_cache: bool) -> Self {
+        self.derivative_cache = derivative_cache;
+        self
+    }
+
+    /// Sets what to do with undefined operations such as `1 / 0`. Defaults to [`NonFinitePolicy::Keep`].
+    pub fn with_non_finite_policy(mut self, non_finite: NonFinitePolicy) -> Self {
+        self.non_finite = non_finite;
+        self
+    }
+
+    /// Sets the clock used to time every rule that is applied, see [`profile_rules`]. This slows down the pipeline, so
+    /// it is disabled by default.
+    pub fn with_rule_profile(mut self, clock: Option<fn() -> f64>) -> Self {
+        self.rule_clock = clock;
+        self
+    }
+
+    /// Sets whether the logarithms of products, quotients and powers are expanded whenever the expressions are
+    /// simplified, see [`ExpandLogarithms`]. The expansions assume that the arguments of the logarithms are positive,
+    /// so this defaults to `false`.
+    pub fn with_expand_logarithms(mut self, expand_logarithms: bool) -> Self {
+        self.expand_logarithms = expand_logarithms;
+        self
+    }
+
+    /// Sets the facts about the identifiers that the transformations may rely on, e.g. that `x` is positive so that
+    /// [`ExpandLogarithms`] rewrites `exp(ln(x))` to `x`. Defaults to no assumptions.
+    pub fn with_assumptions(mut self, assumptions: Assumptions) -> Self {
+        self.assumptions = assumptions;
+        self
+    }
+
+    /// Sets the unit of the angles of the trigonometric functions of the input, see [`with_angle_mode`]. The input is
+    /// converted before it is simplified, so the derivative is in the same unit, e.g. `cos(pi / 180 * x) * (pi / 180)`
+    /// for `sin(x)` in degrees. Defaults to [`AngleMode::Radians`].
+    pub fn with_angle_mode(mut self, angle_mode: AngleMode) -> Self {
+        self.angle_mode = angle_mode;
+        self
+    }
+}
+
+/// Parses `input`, simplifies it and computes its derivative.
+///
+/// `now` returns the current time in milliseconds and is used to time the phases. It is passed in because there is
+/// no portable clock, e.g. the web frontend uses `performance.now()`.
+pub fn run_pipeline(input: &str, limits: Limits, now: impl FnMut() -> f64) -> PipelineReport {
+    run_pipeline_with_options(input, &PipelineOptions::default().with_limits(limits), now)
+}
+
+/// Same as [`run_pipeline`] with custom `options`.
+pub fn run_pipeline_with_options(
+    input: &str,
+    options: &PipelineOptions,
+    now: impl FnMut() -> f64,
+) -> PipelineReport {
+    match options.rule_clock {
+        Some(clock) => {
+            let (mut report, profile) = profile_rules(clock, || run_phases(input, options, now));
+            report.rule_profile = Some(profile);
+            report
+        }
+        None => run_phases(input, options, now),
+    }
+}
+
+/// Returns the span of the innermost operation of `parsed` that is the undefined `operation` or simplifies to it, e.g.
+/// `1 / (2 - 2)` for `1 / 0`.
+fn undefined_operation_span<T>(
+    parser: &Parser<T>,
+    parsed: &Expr,
+    operation: &Expr,
+    simplify: impl Fn(&mut Expr),
+) -> Option<Span>
+where
+    T: Iterator<Item = (Token, Span)>,
+{
+    // reversed so that children come before their parent
+    let paths: Vec<_> = parsed
+        .find_paths(|expr| matches!(expr, Expr::Binary { .. }))
+        .into_iter()
+        .rev()
+        .collect();
+    let get = |path: &ExprPath| parsed.get(path).expect("path was just found");
+    let path = paths
+        .iter()
+        .find(|path| get(path) == operation)
+        .or_else(|| {
+            paths.iter().find(|path| {
+                let mut simplified = get(path).clone();
+                simplify(&mut simplified);
+                simplified == *operation
+            })
+        })?;
+    parser.operation_span(parsed, path)
+}
+
+fn run_phases(
+    input: &str,
+    options: &PipelineOptions,
+    mut now: impl FnMut() -> f64,
+) -> PipelineReport {
+    let limits = options.limits;
+    let simplify = |expr: &mut Expr| {
+        let simplify = |expr: &mut Expr| match options.rules {
+            Some(rules) => SimplifyWith(rules).visit(expr),
+            None => Simplify.visit(expr),
+        };
+        simplify(expr);
+        if options.expand_logarithms {
+            // the rewritten sub-expressions can simplify with their neighbours, e.g. `x * exp(ln(x))` is `x ^ 2` if `x`
+            // is positive
+            ExpandLogarithms(&options.assumptions).visit(expr);
+            simplify(expr);
+        }
+    };
+    let mut report = PipelineReport::default();
+    let mut start = now();
+    let mut record = |report: &mut PipelineReport, phase: Phase, expr: &Expr| {
+        let end = now();
+        report.phases.push(PhaseReport {
+            phase,
+            millis: end - start,
+            nodes: expr.node_count(),
+        });
+        start = end;
+    };
+
+    let tokens = Token::lexer(input);
+    if tokens.clone().next().is_none() {
+        report.error = Some(PipelineError::NoInput);
+        return report;
+    }
+
+    let mut parser = Parser::with_limits(tokens.spanned(), limits);
+    let parsed = parser.parse_checked();
+    report.diagnostics = parser.diagnostics().clone();
+    let ast = match parsed {
+        Ok(ast) => ast,
+        Err(err) => {
+            report.error = Some(PipelineError::TooLarge(err));
+            return report;
+        }
+    };
+    record(&mut report, Phase::Parse, &ast);
+    report.parsed = Some(ast.clone());
+
+    // the undefined operations are handled before simplifying, which could remove them, e.g. `0 * (1 / 0)`, and after,
+    // which could create them, e.g. `1 / (1 - 1)`
+    let handle_undefined = |report: &mut PipelineReport, expr: &mut Expr| match options.non_finite {
+        NonFinitePolicy::Keep => {
+            for path in expr.find_paths(is_undefined_operation) {
+                let operation = expr.get(&path).expect("path was just found");
+                let message = format!("`{}` is undefined", operation);
+                if report
+                    .diagnostics
+                    .iter()
+                    .all(|diagnostic| diagnostic.message != message)
+                {
+                    let parsed = report.parsed.as_ref().expect("input should be parsed");
+                    // the operations in the derivative are not in the input
+                    let span = undefined_operation_span(&parser, parsed, operation, simplify)
+                        .unwrap_or(0..input.len());
+                    report.diagnostics.push(Diagnostic::warning(
+                        DiagnosticCode::UndefinedOperation,
+                        message,
+                        span,
+                    ));
+                }
+            }
+        }
+        NonFinitePolicy::Undefined => FoldUndefined.visit(expr),
+    };
+
+    // converted before the derivative requests are evaluated so that they are in the same unit
+    let mut ast = with_angle_mode(&ast, options.angle_mode);
+
+    // evaluate derivative requests such as `diff(x * y, y)` typed in the input
+    let mut evaluate = EvaluateDerivatives::new(&limits);
+    evaluate.visit(&mut ast);
+    if let Some(err) = evaluate.error {
+        report.error = Some(PipelineError::Derivative(err));
+        return report;
+    }
+    handle_undefined(&mut report, &mut ast);
+    simplify(&mut ast);
+    handle_undefined(&mut report, &mut ast);
+    record(&mut report, Phase::SimplifyInput, &ast);
+
+    // do not prettify expr used for derivative, and do not simplify it again since that would undo some of the readable
+    // forms, e.g. `x - 3` is simplified to `(-3) + x`
+    let mut prettified = ast.clone();
+    if options.prettify {
+        Prettify.visit(&mut prettified);
+        record(&mut report, Phase::PrettifyInput, &prettified);
+    }
+    report.prettified = Some(prettified);
+
+    let variable = match options.variable_picker {
+        Some(picker) => picker(&ast, &options.variable),
+        None => options.variable.clone(),
+    };
+    let derivative = if options.derivative_cache {
+        let mut cache = DerivativeCache::new();
+        let derivative = try_partial_derivative_cached(&ast, &variable, &limits, &mut cache);
+        report.derivative_cache = Some(cache.stats());
+        derivative
+    } else {
+        try_partial_derivative(&ast, &variable, &limits)
+    };
+    report.variable = Some(variable);
+    report.simplified = Some(ast);
+    let mut derivative = match derivative {
+        Ok(derivative) => derivative,
+        Err(err) => {
+            report.error = Some(PipelineError::Derivative(err));
+            return report;
+        }
+    };
+    record(&mut report, Phase::Derive, &derivative);
+
+    DerivativeCleanup.visit(&mut derivative);
+    record(&mut report, Phase::Cleanup, &derivative);
+
+    simplify(&mut derivative);
+    handle_undefined(&mut report, &mut derivative);
+    if options.prettify {
+        Prettify.visit(&mut derivative);
+    }
+    record(&mut report, Phase::SimplifyDerivative, &derivative);
+    report.derivative = Some(derivative);
+
+    report
+}
+
+/// The result of one line of the input of [`run_batch`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchRow<'a> {
+    /// The line number, starting at 1.
+    pub line: usize,
+    pub input: &'a str,
+    pub report: PipelineReport,
+}
+
+/// Runs [`run_pipeline_with_options`] on every non-empty line of `input`, e.g. for a list of exercises pasted at once.
+pub fn run_batch<'a>(
+    input: &'a str,
+    options: &PipelineOptions,
+    mut now: impl FnMut() -> f64,
+) -> Vec<BatchRow<'a>> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| BatchRow {
+            line: i + 1,
+            input: line,
+            report: run_pipeline_with_options(line, options, &mut now),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transformations::custom::parse_rules;
+    use crate::transformations::derivative::pick_variable;
+
+    /// A clock that advances by 1ms every time it is read.
+    fn ticks() -> impl FnMut() -> f64 {
+        let mut time = 0.0;
+        move || {
+            time += 1.0;
+            time
+        }
+    }
+
+    #[test]
+    fn test_run_pipeline() {
+        let report = run_pipeline("x ^ 2 + 3 * x", Limits::default(), ticks());
+        assert_eq!(report.error, None);
+        assert_eq!(
+            report.prettified.as_ref().unwrap().to_string(),
+            "((x ^ 2) + (3 * x))"
+        );
+        assert_eq!(
+            report.derivative.as_ref().unwrap().to_string(),
+            "(3 + (2 * x))"
+        );
+        let phases: Vec<_> = report.phases.iter().map(|phase| phase.phase).collect();
+        assert_eq!(
+            phases,
+            [
+                Phase::Parse,
+                Phase::SimplifyInput,
+                Phase::PrettifyInput,
+                Phase::Derive,
+                Phase::Cleanup,
+                Phase::SimplifyDerivative
+            ]
+        );
+        assert_eq!(report.phases[0].nodes, 7);
+        assert_eq!(report.total_millis(), 6.0);
+    }
+
+    #[test]
+    fn test_run_pipeline_square_root() {
+        for input in ["sqrt(x)", "√x", "x ^ 0.5"] {
+            let report = run_pipeline(input, Limits::default(), ticks());
+            assert_eq!(report.prettified.unwrap().to_string(), "sqrt(x)");
+            assert_eq!(
+                report.derivative.unwrap().to_string(),
+                "(1 / (2 * sqrt(x)))"
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_pipeline_negative_literals() {
+        let cases = [
+            ("x - 3", "(x - 3)", "1"),
+            ("-x ^ 2", "((-x) ^ 2)", "(2 * x)"),
+            ("-(x ^ 2)", "(-(x ^ 2))", "(-(2 * x))"),
+            ("5 - 2 * x ^ 3", "(5 - (2 * (x ^ 3)))", "(-(6 * (x ^ 2)))"),
+            ("1 / x", "(1 / x)", "(-(1 / (x ^ 2)))"),
+        ];
+        for (input, prettified, derivative) in cases {
+            let report = run_pipeline(input, Limits::default(), ticks());
+            assert_eq!(
+                report.prettified.unwrap().to_string(),
+                prettified,
+                "input: {}",
+                input
+            );
+            assert_eq!(
+                report.derivative.unwrap().to_string(),
+                derivative,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_pipeline_small_constants() {
+        // constants close to the identities of the rules are kept as written
+        let cases = [
+            ("0.0000000001 * x", "0.0000000001"),
+            ("x * 1.0000000001", "1.0000000001"),
+            ("x / 0.0000000001", "10000000000"),
+            ("x ^ 2.0000000001", "(2.0000000001 * (x ^ 1.0000000001))"),
+        ];
+        for (input, derivative) in cases {
+            let report = run_pipeline(input, Limits::default(), ticks());
+            assert_eq!(
+                report.derivative.unwrap().to_string(),
+                derivative,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_pipeline_negative_exponents() {
+        let report = run_pipeline("x ^ -1", Limits::default(), ticks());
+        assert_eq!(report.prettified.unwrap().to_string(), "(1 / x)");
+        assert_eq!(report.derivative.unwrap().to_string(), "(-(1 / (x ^ 2)))");
+        let report = run_pipeline("1 / x ^ 2", Limits::default(), ticks());
+        assert_eq!(report.prettified.unwrap().to_string(), "(1 / (x ^ 2))");
+        assert_eq!(
+            report.derivative.unwrap().to_string(),
+            "((-(2 * x)) / (x ^ 4))"
+        );
+    }
+
+    #[test]
+    fn test_run_pipeline_logarithms() {
+        let report = run_pipeline(
+            "ln(e ^ x) * exp(0) + ln(exp(x ^ 2))",
+            Limits::default(),
+            ticks(),
+        );
+        assert_eq!(report.simplified.unwrap().to_string(), "(x + (x ^ 2))");
+        assert_eq!(report.derivative.unwrap().to_string(), "(1 + (2 * x))");
+
+        // only holds for positive arguments, see `ExpandLogarithms`
+        let report = run_pipeline("e ^ ln(x)", Limits::default(), ticks());
+        assert_eq!(report.simplified.unwrap().to_string(), "(e ^ ln(x))");
+    }
+
+    #[test]
+    fn test_run_pipeline_expand_logarithms() {
+        let options = PipelineOptions::default()
+            .with_expand_logarithms(true)
+            .with_prettify(false);
+        let report = run_pipeline_with_options("ln(x ^ 3 * y)", &options, ticks());
+        assert_eq!(
+            report.simplified.unwrap().to_string(),
+            "((3 * ln(x)) + ln(y))"
+        );
+        assert_eq!(report.derivative.unwrap().to_string(), "(3 / x)");
+
+        // undefined for `x <= 0` unless `x` is assumed positive
+        let report = run_pipeline_with_options("x * exp(ln(x))", &options, ticks());
+        assert_eq!(report.simplified.unwrap().to_string(), "(x * exp(ln(x)))");
+        let options = options.with_assumptions(Assumptions::new().with_positive("x"));
+        let report = run_pipeline_with_options("x * exp(ln(x))", &options, ticks());
+        assert_eq!(report.simplified.unwrap().to_string(), "(x ^ 2)");
+    }
+
+    #[test]
+    fn test_run_pipeline_angle_mode() {
+        let options = PipelineOptions::default().with_prettify(false);
+        let report = run_pipeline_with_options("sin(x)", &options, ticks());
+        assert_eq!(report.derivative.unwrap().to_string(), "cos(x)");
+
+        let options = options.with_angle_mode(AngleMode::Degrees);
+        let report = run_pipeline_with_options("sin(x)", &options, ticks());
+        assert_eq!(report.parsed.unwrap().to_string(), "sin(x)");
+        assert_eq!(
+            report.simplified.unwrap().to_string(),
+            "sin((pi / 180) * x)"
+        );
+        assert_eq!(
+            report.derivative.unwrap().to_string(),
+            "(cos((pi / 180) * x) * (pi / 180))"
+        );
+        let report = run_pipeline_with_options("diff(sin(x), x)", &options, ticks());
+        assert_eq!(
+            report.simplified.unwrap().to_string(),
+            "(cos((pi / 180) * x) * (pi / 180))"
+        );
+    }
+
+    #[test]
+    fn test_run_pipeline_errors() {
+        let report = run_pipeline("  ", Limits::default(), ticks());
+        assert_eq!(report.error, Some(PipelineError::NoInput));
+        assert!(report.phases.is_empty());
+
+        let report = run_pipeline("abs(x, 2)", Limits::default(), ticks());
+        assert!(matches!(
+            report.error,
+            Some(PipelineError::Derivative(DerivativeError::Unsupported(_)))
+        ));
+        assert!(report.prettified.is_some());
+        assert_eq!(report.derivative, None);
+        assert_eq!(report.phases.len(), 3);
+
+        let report = run_pipeline("d/dx (abs(x, 2))", Limits::default(), ticks());
+        assert!(matches!(
+            report.error,
+            Some(PipelineError::Derivative(DerivativeError::Unsupported(_)))
+        ));
+        assert_eq!(report.phases.len(), 1);
+    }
+
+    #[test]
+    fn test_run_pipeline_with_derivative_cache() {
+        let input = "abs(x ^ 2 + 1) / (x ^ 2 + 1)";
+        let report = run_pipeline(input, Limits::default(), ticks());
+        assert_eq!(report.derivative_cache, None);
+
+        let options = PipelineOptions::default().with_derivative_cache(true);
+        let cached = run_pipeline_with_options(input, &options, ticks());
+        assert!(cached.derivative_cache.unwrap().hits > 0);
+        assert_eq!(cached.derivative, report.derivative);
+    }
+
+    #[test]
+    fn test_run_pipeline_with_rule_profile() {
+        let report = run_pipeline("x * 1 + 0", Limits::default(), ticks());
+        assert_eq!(report.rule_profile, None);
+
+        let options = PipelineOptions::default().with_rule_profile(Some(|| 0.0));
+        let profiled = run_pipeline_with_options("x * 1 + 0", &options, ticks());
+        assert!(profiled.rule_profile.unwrap().total_fired() > 0);
+        assert_eq!(profiled.derivative, report.derivative);
+    }
+
+    #[test]
+    fn test_run_pipeline_with_rules() {
+        let rules = parse_rules("abs(_1 ^ 2) => _1 ^ 2").unwrap();
+        let options = PipelineOptions::default().with_rules(&rules);
+        let report = run_pipeline_with_options("abs(x ^ 2)", &options, ticks());
+        assert_eq!(report.simplified.as_ref().unwrap().to_string(), "(x ^ 2)");
+        assert_eq!(report.derivative.as_ref().unwrap().to_string(), "(2 * x)");
+    }
+
+    #[test]
+    fn test_run_pipeline_options() {
+        let options = PipelineOptions::default()
+            .with_variable("y")
+            .with_prettify(false);
+        let report = run_pipeline_with_options("x * y ^ 2 - y", &options, ticks());
+        assert_eq!(
+            report.derivative.as_ref().unwrap().to_string(),
+            "((-1) + ((2 * y) * x))"
+        );
+        assert_eq!(report.prettified, report.simplified);
+        assert!(report
+            .phases
+            .iter()
+            .all(|phase| phase.phase != Phase::PrettifyInput));
+
+        let options = PipelineOptions::default().with_variable_picker(Some(pick_variable));
+        let report = run_pipeline_with_options("t ^ 2", &options, ticks());
+        assert_eq!(report.variable.as_deref(), Some("t"));
+        assert_eq!(report.derivative.as_ref().unwrap().to_string(), "(2 * t)");
+        let report = run_pipeline_with_options("x * t", &options, ticks());
+        assert_eq!(report.variable.as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn test_run_pipeline_non_finite_policy() {
+        let input = "x + 1 / (2 - 2) + 0 ^ -1";
+        let report = run_pipeline(input, Limits::default(), ticks());
+        let messages: Vec<_> = report
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (
+                    DiagnosticCode::UndefinedOperation,
+                    "`(0 ^ (-1))` is undefined"
+                ),
+                (DiagnosticCode::UndefinedOperation, "`(1 / 0)` is undefined"),
+                // the derivative of `1 / 0` by the quotient rule
+                (DiagnosticCode::UndefinedOperation, "`(0 / 0)` is undefined"),
+                // the derivative of `0 ^ -1` by the power rule
+                (
+                    DiagnosticCode::UndefinedOperation,
+                    "`(0 ^ (-2))` is undefined"
+                ),
+            ]
+        );
+        assert_eq!(
+            report.simplified.as_ref().unwrap().to_string(),
+            "((x + (1 / 0)) + (0 ^ (-1)))"
+        );
+        // the operations in the input, even if they are only undefined once simplified
+        let spans: Vec<_> = report
+            .diagnostics
+            .iter()
+            .map(|diagnostic| &input[diagnostic.span.clone()])
+            .collect();
+        assert_eq!(spans, ["0 ^ -1", "1 / (2 - 2)", input, input]);
+        let report = run_pipeline("sqrt(-4) * x", Limits::default(), ticks());
+        assert_eq!(report.diagnostics.iter().next().unwrap().span, 0..8);
+
+        let options = PipelineOptions::default().with_non_finite_policy(NonFinitePolicy::Undefined);
+        let report = run_pipeline_with_options(input, &options, ticks());
+        assert!(report.diagnostics.is_empty());
+        assert_eq!(report.simplified, Some(Expr::Undefined));
+        assert_eq!(report.derivative, Some(Expr::Undefined));
+        let report = run_pipeline_with_options("x ^ 2 + 0 * (1 / 0)", &options, ticks());
+        assert_eq!(report.simplified, Some(Expr::Undefined));
+    }
+
+    #[test]
+    fn test_run_batch() {
+        let rows = run_batch(
+            "x ^ 2\n\n  \nabs(x, 2)\n2 * x",
+            &PipelineOptions::default(),
+            ticks(),
+        );
+        let summary: Vec<_> = rows
+            .iter()
+            .map(|row| {
+                let derivative = match &row.report.derivative {
+                    Some(derivative) => derivative.to_string(),
+                    None => row.report.error.as_ref().unwrap().to_string(),
+                };
+                (row.line, row.input, derivative)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1, "x ^ 2", "(2 * x)".to_string()),
+                (
+                    4,
+                    "abs(x, 2)",
+                    "cannot compute the derivative of `abs(x, 2)`".to_string()
+                ),
+                (5, "2 * x", "2".to_string()),
+            ]
+        );
+    }
+}
@@ -3,10 +3,63 @@
 pub mod lexer;
 pub mod parser;
 
+use crate::diagnostic::{Diagnostic, DiagnosticCode, Diagnostics};
+use crate::eval::eval;
 use crate::parser::{Expr, UnaryOpKind};
 use logos::Logos;
-use parser::RuleExpr;
-use std::collections::BTreeMap;
+use parser::{RuleExpr, RuleFunc};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// How literals are compared when matching patterns.
+///
+/// Patterns match literals exactly by default, e.g. `_1 * 1` does not match `x * 1.0000000001` since the user wrote
+/// that constant on purpose. Folding constants introduces rounding errors (e.g. `0.1 * 3 * 10 = 3.0000000000000004`),
+/// so folded values are rounded with [`Tolerance::FOLDING`] instead, see [`Tolerance::round`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tolerance {
+    /// The max difference between two literals that are considered equal, relative to the larger magnitude of the
+    /// two. Nothing but `0` itself is equal to `0`.
+    pub epsilon: f64,
+}
+
+impl Tolerance {
+    /// Only literals that are exactly equal match.
+    pub const EXACT: Tolerance = Tolerance { epsilon: 0.0 };
+
+    /// The rounding error expected from folding a few literals, i.e. a few units in the last place. Larger differences
+    /// are real fractions, e.g. `0.5 + 1000000000000` is not an integer.
+    pub const FOLDING: Tolerance = Tolerance {
+        epsilon: 4.0 * f64::EPSILON,
+    };
+
+    /// Returns `true` if `a` and `b` are equal up to the tolerance.
+    pub fn eq(&self, a: f64, b: f64) -> bool {
+        #[allow(clippy::float_cmp)] // exactly equal values, including infinities
+        let exactly_equal = a == b;
+        exactly_equal || (a - b).abs() <= self.epsilon * a.abs().max(b.abs())
+    }
+
+    /// Returns `true` if `num` is an integer up to the tolerance.
+    pub fn is_integer(&self, num: f64) -> bool {
+        self.eq(num, num.round())
+    }
+
+    /// Returns the integer that `num` is equal to up to the tolerance, or `num` itself if there is none, e.g. a folded
+    /// `1.0000000000000002` is `1`.
+    pub fn round(&self, num: f64) -> f64 {
+        if self.is_integer(num) {
+            num.round()
+        } else {
+            num
+        }
+    }
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self::EXACT
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct MatchResult<'a> {
@@ -18,24 +71,134 @@ pub struct MatchResult<'a> {
     /// A failed match does not necessarily mean `matched_exprs` is empty. For instance, if a wildcard is successfully matched, then a fail occurs, the wildcard result will still be kept.
     /// All wildcard ids should be unique.
     pub matched_exprs: BTreeMap<i32, &'a Expr>,
+    /// The names of the functions matched by function wildcards (e.g. `_f1`), by wildcard id.
+    pub matched_funcs: BTreeMap<i32, &'a str>,
+    /// The literal wildcards inside a computed pattern, which are not in the input, by wildcard id, e.g. `_lit1` is
+    /// `2` if `{-_lit1}` matched `-2`.
+    pub computed_literals: BTreeMap<i32, f64>,
 }
 
 impl RuleExpr {
     pub fn new_rule_from_str(pattern: &str) -> Self {
-        let tokens = lexer::RuleToken::lexer(pattern);
+        let tokens = lexer::RuleToken::lexer(pattern).spanned();
         let mut parser = parser::RuleParser::from(tokens);
         parser.parse()
     }
 
+    /// Same as [`RuleExpr::new_rule_from_str`] except that syntax errors are returned instead of being filled in with
+    /// [`RuleExpr::Error`], for rules that are not hard-coded (e.g. entered by a user).
+    pub fn try_from_str(pattern: &str) -> Result<Self, Diagnostics> {
+        let mut tokens = lexer::RuleToken::lexer(pattern).spanned().peekable();
+        if tokens.peek().is_none() {
+            let mut diagnostics = Diagnostics::new();
+            diagnostics.push(Diagnostic::error(
+                DiagnosticCode::UnexpectedToken,
+                "unexpected end of input, expected a rule expression",
+                pattern.len()..pattern.len(),
+            ));
+            return Err(diagnostics);
+        }
+        let mut parser = parser::RuleParser::from(tokens);
+        let expr = parser.parse();
+        if parser.diagnostics().has_errors() {
+            Err(parser.diagnostics().clone())
+        } else {
+            Ok(expr)
+        }
+    }
+
+    /// Adds the ids of the sub-expression wildcards (e.g. `_1` or `_lit2`) to `exprs` and the ids of the function
+    /// wildcards to `funcs`.
+    pub(crate) fn collect_wildcards(&self, exprs: &mut BTreeSet<i32>, funcs: &mut BTreeSet<i32>) {
+        match self {
+            RuleExpr::AnySubExpr(id)
+            | RuleExpr::AnyLiteral(id)
+            | RuleExpr::AnyInteger(id)
+            | RuleExpr::AnyNonLiteral(id) => {
+                exprs.insert(*id);
+            }
+            RuleExpr::Binary { left, op: _, right } => {
+                left.collect_wildcards(exprs, funcs);
+                right.collect_wildcards(exprs, funcs);
+            }
+            RuleExpr::Unary { op: _, right } | RuleExpr::Computed(right) => {
+                right.collect_wildcards(exprs, funcs)
+            }
+            RuleExpr::Call { func, args } => {
+                if let RuleFunc::Any(id) = func {
+                    funcs.insert(*id);
+                }
+                for arg in args {
+                    arg.collect_wildcards(exprs, funcs);
+                }
+            }
+            RuleExpr::Literal(_) | RuleExpr::Constant(_) | RuleExpr::Error => {}
+        }
+    }
+
+    /// Adds the ids of the wildcards that only match literals (e.g. `_lit1` or `_int2`) to `ids`.
+    pub(crate) fn collect_literal_wildcards(&self, ids: &mut BTreeSet<i32>) {
+        match self {
+            RuleExpr::AnyLiteral(id) | RuleExpr::AnyInteger(id) => {
+                ids.insert(*id);
+            }
+            RuleExpr::Binary { left, op: _, right } => {
+                left.collect_literal_wildcards(ids);
+                right.collect_literal_wildcards(ids);
+            }
+            RuleExpr::Unary { op: _, right } | RuleExpr::Computed(right) => {
+                right.collect_literal_wildcards(ids)
+            }
+            RuleExpr::Call { func: _, args } => {
+                for arg in args {
+                    arg.collect_literal_wildcards(ids);
+                }
+            }
+            RuleExpr::AnySubExpr(_)
+            | RuleExpr::AnyNonLiteral(_)
+            | RuleExpr::Literal(_)
+            | RuleExpr::Constant(_)
+            | RuleExpr::Error => {}
+        }
+    }
+
+    /// Adds the computed expressions (e.g. `{_lit1 + 1}`) to `computed`.
+    pub(crate) fn collect_computed<'a>(&'a self, computed: &mut Vec<&'a RuleExpr>) {
+        match self {
+            RuleExpr::Computed(_) => computed.push(self),
+            RuleExpr::Binary { left, op: _, right } => {
+                left.collect_computed(computed);
+                right.collect_computed(computed);
+            }
+            RuleExpr::Unary { op: _, right } => right.collect_computed(computed),
+            RuleExpr::Call { func: _, args } => {
+                for arg in args {
+                    arg.collect_computed(computed);
+                }
+            }
+            RuleExpr::AnySubExpr(_)
+            | RuleExpr::AnyLiteral(_)
+            | RuleExpr::AnyInteger(_)
+            | RuleExpr::AnyNonLiteral(_)
+            | RuleExpr::Literal(_)
+            | RuleExpr::Constant(_)
+            | RuleExpr::Error => {}
+        }
+    }
+
     /// Tries to match a [`RuleExpr`] pattern on an [`Expr`].
     /// When encountering a wildcard rule, will append the matched [`Expr`] onto the `matched_exprs` argument.
     /// # Params
     /// * `expr` - The expression to try to match.
     /// * `matched_exprs` - A list of matched expressions from wildcards.
+    /// * `computed_literals` - The literal wildcards matched inside computed patterns.
     fn match_expr_inner<'a>(
         &self,
         expr: &'a Expr,
         matched_exprs: &mut BTreeMap<i32, &'a Expr>,
+        matched_funcs: &mut BTreeMap<i32, &'a str>,
+        computed_literals: &mut BTreeMap<i32, f64>,
+        tolerance: Tolerance,
     ) -> bool {
         // Returns true if wildcard is successful match (no match with same id yet, or already matched same Expr).
         // Else returns false.
@@ -50,13 +213,21 @@ impl RuleExpr {
         };
 
         match self {
-            #[allow(clippy::float_cmp)] // FIXME
-            RuleExpr::Literal(num_rule) => matches!(expr, Expr::Literal(num) if num == num_rule),
+            RuleExpr::Literal(num_rule) => {
+                matches!(expr, Expr::Literal(num) if tolerance.eq(*num, *num_rule))
+            }
+            RuleExpr::Constant(constant_rule) => {
+                matches!(expr, Expr::Constant(constant) if constant == constant_rule)
+            }
             RuleExpr::AnySubExpr(id) => insert_added_match(*id, expr),
             RuleExpr::AnyLiteral(id) => match expr {
                 Expr::Literal(_) => insert_added_match(*id, expr),
                 _ => false,
             },
+            RuleExpr::AnyInteger(id) => match expr {
+                Expr::Literal(num) if tolerance.is_integer(*num) => insert_added_match(*id, expr),
+                _ => false,
+            },
             RuleExpr::AnyNonLiteral(id) => match expr {
                 Expr::Literal(_) => false,
                 _ => insert_added_match(*id, expr),
@@ -71,37 +242,142 @@ impl RuleExpr {
                     left,
                     op,
                     right
-                } if op == op_rule && left_rule.match_expr_inner(left, matched_exprs) && right_rule.match_expr_inner(right, matched_exprs)
+                } if op == op_rule && left_rule.match_expr_inner(left, matched_exprs, matched_funcs, computed_literals, tolerance) && right_rule.match_expr_inner(right, matched_exprs, matched_funcs, computed_literals, tolerance)
             ),
             RuleExpr::Unary {
                 op: op_rule,
                 right: right_rule,
             } => {
-                matches!(expr, Expr::Unary {op, right} if op == op_rule && right_rule.match_expr_inner(right, matched_exprs))
+                matches!(expr, Expr::Unary {op, right} if op == op_rule && right_rule.match_expr_inner(right, matched_exprs, matched_funcs, computed_literals, tolerance))
             }
+            RuleExpr::Call {
+                func: func_rule,
+                args: args_rule,
+            } => match expr {
+                Expr::Call { func, args } if args.len() == args_rule.len() => {
+                    let func_matches = match func_rule {
+                        RuleFunc::Named(name) => name == func,
+                        RuleFunc::Any(id) => match matched_funcs.get(id) {
+                            Some(existing) => existing == func,
+                            None => {
+                                matched_funcs.insert(*id, func);
+                                true
+                            }
+                        },
+                    };
+                    func_matches
+                        && args_rule.iter().zip(args).all(|(arg_rule, arg)| {
+                            arg_rule.match_expr_inner(
+                                arg,
+                                matched_exprs,
+                                matched_funcs,
+                                computed_literals,
+                                tolerance,
+                            )
+                        })
+                }
+                _ => false,
+            },
+            // only a negated literal wildcard can be solved for, e.g. `{-_lit1}` matches `-2` with `_lit1 = 2`
+            RuleExpr::Computed(computed) => match (&**computed, expr) {
+                (
+                    RuleExpr::Unary {
+                        op: UnaryOpKind::Minus,
+                        right,
+                    },
+                    Expr::Literal(num),
+                ) => {
+                    let id = match **right {
+                        RuleExpr::AnyLiteral(id) => id,
+                        RuleExpr::AnyInteger(id) if tolerance.is_integer(*num) => id,
+                        _ => return false,
+                    };
+                    match computed_literals.get(&id) {
+                        Some(existing) => tolerance.eq(*existing, -num),
+                        None => {
+                            computed_literals.insert(id, -num);
+                            true
+                        }
+                    }
+                }
+                _ => false,
+            },
             RuleExpr::Error => false,
         }
     }
 
-    /// Tries to match a [`RuleExpr`] pattern on an [`Expr`].
+    /// Tries to match a [`RuleExpr`] pattern on an [`Expr`], comparing literals exactly.
     /// # Panics
     /// This method panics if two wildcard matches have the same id.
     pub fn match_expr<'a>(&self, expr: &'a Expr) -> MatchResult<'a> {
+        self.match_expr_with_tolerance(expr, Tolerance::EXACT)
+    }
+
+    /// Same as [`RuleExpr::match_expr`] except that literals are compared with `tolerance`.
+    pub fn match_expr_with_tolerance<'a>(
+        &self,
+        expr: &'a Expr,
+        tolerance: Tolerance,
+    ) -> MatchResult<'a> {
         let mut matched_exprs = BTreeMap::new();
-        let matches = self.match_expr_inner(expr, &mut matched_exprs);
+        let mut matched_funcs = BTreeMap::new();
+        let mut computed_literals = BTreeMap::new();
+        let matches = self.match_expr_inner(
+            expr,
+            &mut matched_exprs,
+            &mut matched_funcs,
+            &mut computed_literals,
+            tolerance,
+        ) && computed_literals
+            .iter()
+            .all(|(id, num)| match matched_exprs.get(id) {
+                Some(Expr::Literal(matched)) => tolerance.eq(*matched, *num),
+                Some(_) => false,
+                None => true,
+            });
         MatchResult {
             matches,
             source_expr: expr,
             matched_exprs,
+            matched_funcs,
+            computed_literals,
         }
     }
 
+    /// Same as [`RuleExpr::write_expr`] except that function wildcards and computed literals are filled in as well.
+    pub fn write_match(&self, match_res: &MatchResult) -> Option<Expr> {
+        if match_res.computed_literals.is_empty() {
+            return self.write_expr_inner(&match_res.matched_exprs, &match_res.matched_funcs);
+        }
+        let computed: BTreeMap<_, _> = match_res
+            .computed_literals
+            .iter()
+            .map(|(id, num)| (*id, Expr::Literal(*num)))
+            .collect();
+        let mut matched_exprs = match_res.matched_exprs.clone();
+        matched_exprs.extend(computed.iter().map(|(id, expr)| (*id, expr)));
+        self.write_expr_inner(&matched_exprs, &match_res.matched_funcs)
+    }
+
     /// Fills in the wildcards of a [`RuleExpr`] with results of `match_res`.
+    ///
+    /// Returns `None` if a computed expression (e.g. `{_lit1 + 1}`) cannot be evaluated to a finite number, e.g.
+    /// because it contains something other than literals, in which case the rule should not be applied.
     /// # Panics
     /// This method panics if a wildcard id is not found in `matched_exprs`. This method also panics if the wildcard type does not match.
-    pub fn write_expr(&self, matched_exprs: &BTreeMap<i32, &Expr>) -> Expr {
-        match self {
+    /// Function wildcards are never found, use [`RuleExpr::write_match`] instead.
+    pub fn write_expr(&self, matched_exprs: &BTreeMap<i32, &Expr>) -> Option<Expr> {
+        self.write_expr_inner(matched_exprs, &BTreeMap::new())
+    }
+
+    fn write_expr_inner(
+        &self,
+        matched_exprs: &BTreeMap<i32, &Expr>,
+        matched_funcs: &BTreeMap<i32, &str>,
+    ) -> Option<Expr> {
+        let expr = match self {
             RuleExpr::Literal(num) => Expr::Literal(*num),
+            RuleExpr::Constant(constant) => Expr::Constant(*constant),
             RuleExpr::AnySubExpr(id) => (*matched_exprs
                 .get(id)
                 .unwrap_or_else(|| panic!("wildcard _{} not found", id)))
@@ -110,6 +386,10 @@ impl RuleExpr {
                 .get(id)
                 .unwrap_or_else(|| panic!("wildcard _lit{} not found", id)))
             .clone(),
+            RuleExpr::AnyInteger(id) => (*matched_exprs
+                .get(id)
+                .unwrap_or_else(|| panic!("wildcard _int{} not found", id)))
+            .clone(),
             RuleExpr::AnyNonLiteral(id) => (*matched_exprs
                 .get(id)
                 .unwrap_or_else(|| panic!("wildcard _nonlit{} not found", id)))
@@ -119,9 +399,9 @@ impl RuleExpr {
                 op,
                 right: right_rule,
             } => Expr::Binary {
-                left: Box::new(left_rule.write_expr(matched_exprs)),
+                left: Box::new(left_rule.write_expr_inner(matched_exprs, matched_funcs)?),
                 op: *op,
-                right: Box::new(right_rule.write_expr(matched_exprs)),
+                right: Box::new(right_rule.write_expr_inner(matched_exprs, matched_funcs)?),
             },
             RuleExpr::Unary {
                 op,
@@ -131,25 +411,55 @@ impl RuleExpr {
                     match **right_rule {
                         // if literal or literal wildcard, fold directly in emitted ast
                         RuleExpr::Literal(num) => Expr::Literal(-num),
-                        RuleExpr::AnyLiteral(id) => match *matched_exprs
+                        RuleExpr::AnyLiteral(id) | RuleExpr::AnyInteger(id) => match *matched_exprs
                             .get(&id)
-                            .unwrap_or_else(|| panic!("wildcard _lit{} not found", id))
+                            .unwrap_or_else(|| panic!("literal wildcard {} not found", id))
                         {
                             Expr::Literal(num) => Expr::Literal(-num),
                             _ => unreachable!(),
                         },
                         _ => Expr::Unary {
                             op: *op,
-                            right: Box::new(right_rule.write_expr(matched_exprs)),
+                            right: Box::new(
+                                right_rule.write_expr_inner(matched_exprs, matched_funcs)?,
+                            ),
                         },
                     }
                 } else {
                     // emit right ast as is
-                    right_rule.write_expr(matched_exprs)
+                    right_rule.write_expr_inner(matched_exprs, matched_funcs)?
+                }
+            }
+            RuleExpr::Call { func, args } => Expr::Call {
+                func: match func {
+                    RuleFunc::Named(name) => name.clone(),
+                    RuleFunc::Any(id) => matched_funcs
+                        .get(id)
+                        .unwrap_or_else(|| panic!("wildcard _f{} not found", id))
+                        .to_string(),
+                },
+                args: args
+                    .iter()
+                    .map(|arg| arg.write_expr_inner(matched_exprs, matched_funcs))
+                    .collect::<Option<_>>()?,
+            },
+            RuleExpr::Computed(expr) => {
+                let expr = expr.write_expr_inner(matched_exprs, matched_funcs)?;
+                match eval(&expr, &HashMap::new()) {
+                    Ok(num) if num.is_finite() => Expr::Literal(num),
+                    Ok(num) => {
+                        log::debug!("not applying rule, {} is {}", expr, num);
+                        return None;
+                    }
+                    Err(err) => {
+                        log::debug!("not applying rule, cannot compute {}: {}", expr, err);
+                        return None;
+                    }
                 }
             }
             RuleExpr::Error => Expr::Error,
-        }
+        };
+        Some(expr)
     }
 }
 
@@ -160,21 +470,21 @@ mod tests {
     use crate::parser::Parser;
 
     fn expr_matches_rule(expr: &str, rule: &str) -> bool {
-        let expr: Expr = Parser::from(Token::lexer(expr)).parse();
+        let expr: Expr = Parser::from(Token::lexer(expr).spanned()).parse();
         let rule = RuleExpr::new_rule_from_str(rule);
         rule.match_expr(&expr).matches
     }
 
     fn rule_transform_expr(expr: &str, rule: &str, out: &str, expected: &str) {
-        let expr: Expr = Parser::from(Token::lexer(expr)).parse();
+        let expr: Expr = Parser::from(Token::lexer(expr).spanned()).parse();
         let rule = RuleExpr::new_rule_from_str(rule);
         let out = RuleExpr::new_rule_from_str(out);
-        let expected: Expr = Parser::from(Token::lexer(expected)).parse();
+        let expected: Expr = Parser::from(Token::lexer(expected).spanned()).parse();
 
         let match_res = rule.match_expr(&expr);
         assert!(match_res.matches);
 
-        let out_expr = out.write_expr(&match_res.matched_exprs);
+        let out_expr = out.write_match(&match_res).unwrap();
         assert_eq!(out_expr, expected);
     }
 
@@ -193,6 +503,43 @@ mod tests {
         assert!(!expr_matches_rule("(2 * x) + x", "_lit1 + _2"));
     }
 
+    #[test]
+    fn test_match_expr_any_integer() {
+        assert!(expr_matches_rule("x ^ 2", "_1 ^ _int2"));
+        assert!(expr_matches_rule("x ^ -3", "_1 ^ _int2"));
+        assert!(!expr_matches_rule("x ^ 2.0000000001", "_1 ^ _int2"));
+        assert!(!expr_matches_rule("x ^ 0.5", "_1 ^ _int2"));
+        assert!(!expr_matches_rule("x ^ y", "_1 ^ _int2"));
+    }
+
+    #[test]
+    fn test_match_expr_tolerance() {
+        // user constants are matched exactly
+        assert!(!expr_matches_rule("x * 1.0000000001", "_1 * 1"));
+        assert!(!expr_matches_rule("x * 0.0000000001", "_1 * 0"));
+
+        let matches = |expr: &str, rule: &str| {
+            let expr = Parser::from(Token::lexer(expr).spanned()).parse();
+            let rule = RuleExpr::new_rule_from_str(rule);
+            rule.match_expr_with_tolerance(&expr, Tolerance::FOLDING)
+                .matches
+        };
+        assert!(matches("x * 1.0000000000000002", "_1 * 1"));
+        assert!(matches("x ^ 2.0000000000000004", "_1 ^ _int2"));
+        assert!(!matches("x ^ 2.0000000000001", "_1 ^ _int2"));
+        assert!(!matches("x * 1.001", "_1 * 1"));
+        // never compared absolutely
+        assert!(!matches("x * 0.0000000000001", "_1 * 0"));
+    }
+
+    #[test]
+    fn test_tolerance_round() {
+        assert_eq!(Tolerance::FOLDING.round(0.1 * 3.0 * 10.0), 3.0);
+        assert_eq!(Tolerance::FOLDING.round(1.0000000001), 1.0000000001);
+        assert_eq!(Tolerance::FOLDING.round(1e-17), 1e-17);
+        assert_eq!(Tolerance::FOLDING.round(1e12 + 0.5), 1e12 + 0.5);
+    }
+
     #[test]
     fn test_write_expr() {
         rule_transform_expr("0 + x", "0 + _1", "_1", "x");
@@ -204,5 +551,50 @@ mod tests {
             "x ^ (2 * 3)",
         );
         rule_transform_expr("1 / x", "1 / _1", "_1 ^ -1", "x ^ -1");
+        rule_transform_expr("x ^ 3", "_1 ^ _lit2", "_1 ^ {_lit2 - 1}", "x ^ 2");
+        rule_transform_expr("2 * 3", "_lit1 * _lit2", "{_lit1 * _lit2}", "6");
+        rule_transform_expr("abs(abs(x))", "abs(abs(_1))", "abs(_1)", "abs(x)");
+        rule_transform_expr("f(x) + f(y)", "_f(_1) + _f(_2)", "_f(_1 + _2)", "f(x + y)");
+    }
+
+    #[test]
+    fn test_match_expr_computed() {
+        rule_transform_expr("x ^ -2", "_1 ^ {-_lit2}", "1 / _1 ^ _lit2", "1 / x ^ 2");
+        rule_transform_expr("x ^ 3", "_1 ^ {-_int2}", "_1 ^ _int2", "x ^ -3");
+        assert!(!expr_matches_rule("x ^ -0.5", "_1 ^ {-_int2}"));
+        assert!(!expr_matches_rule("x ^ y", "_1 ^ {-_lit2}"));
+        assert!(expr_matches_rule("2 * x ^ -2", "_lit2 * _1 ^ {-_lit2}"));
+        assert!(!expr_matches_rule("2 * x ^ 2", "_lit2 * _1 ^ {-_lit2}"));
+        assert!(!expr_matches_rule("x ^ 2", "_1 ^ {_lit2 - 1}"));
+    }
+
+    #[test]
+    fn test_write_expr_computed_errors() {
+        let write = |expr: &str, rule: &str, out: &str| {
+            let expr = Parser::from(Token::lexer(expr).spanned()).parse();
+            let rule = RuleExpr::new_rule_from_str(rule);
+            let match_res = rule.match_expr(&expr);
+            assert!(match_res.matches);
+            RuleExpr::new_rule_from_str(out).write_match(&match_res)
+        };
+        // not a literal
+        assert_eq!(write("x + 1", "_1 + 1", "{_1 + 1}"), None);
+        // not finite
+        assert_eq!(write("2 / 0", "_lit1 / _lit2", "{_lit1 / _lit2}"), None);
+        assert_eq!(
+            write("2 / 4", "_lit1 / _lit2", "{_lit1 / _lit2}"),
+            Some(Expr::Literal(0.5))
+        );
+    }
+
+    #[test]
+    fn test_match_expr_call() {
+        assert!(expr_matches_rule("abs(x + 1)", "abs(_1)"));
+        assert!(!expr_matches_rule("sign(x)", "abs(_1)"));
+        assert!(expr_matches_rule("sign(x)", "_f(_1)"));
+        assert!(!expr_matches_rule("f(x, y)", "_f(_1)"));
+        assert!(expr_matches_rule("f(x) * f(y)", "_f1(_1) * _f1(_2)"));
+        assert!(!expr_matches_rule("f(x) * g(y)", "_f1(_1) * _f1(_2)"));
+        assert!(expr_matches_rule("f(x) * g(y)", "_f1(_1) * _f2(_2)"));
     }
 }
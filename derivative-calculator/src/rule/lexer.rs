@@ -1,19 +1,31 @@
 //! Lexer for rules in string format.
 
+use crate::lexer::parse_number;
 use crate::parser::{BinOpKind, UnaryOpKind};
 use logos::Logos;
 use std::convert::TryFrom;
 
 #[derive(Logos, Debug, PartialEq, Clone)]
 pub enum RuleToken {
-    #[regex("[0-9.]+", |lex| lex.slice().parse())]
+    #[regex("[0-9.][0-9._]*", |lex| parse_number(lex.slice()))]
     Literal(f64),
     #[regex("_[0-9.]+", |lex| lex.slice()[1..].parse())]
     AnySubExpr(i32),
     #[regex("_lit[0-9.]+", |lex| lex.slice()[4..].parse())]
     AnyLiteral(i32),
+    #[regex("_int[0-9.]+", |lex| lex.slice()[4..].parse())]
+    AnyInteger(i32),
     #[regex("_nonlit[0-9.]+", |lex| lex.slice()[7..].parse())]
     AnyNonLiteral(i32),
+    /// The name of a function, e.g. `abs` in `abs(_1)`.
+    #[regex("[a-zA-Z]+", |lex| lex.slice().to_string())]
+    Function(String),
+    /// A wildcard matching the name of any function, e.g. `_f` or `_f1`. The id defaults to `0` if omitted.
+    #[regex("_f[0-9]*", |lex| match &lex.slice()[2..] {
+        "" => Ok(0),
+        id => id.parse(),
+    })]
+    AnyFunction(i32),
     // operators
     #[token("+")]
     Plus,
@@ -30,6 +42,12 @@ pub enum RuleToken {
     OpenParen,
     #[token(")")]
     CloseParen,
+    #[token("{")]
+    OpenBrace,
+    #[token("}")]
+    CloseBrace,
+    #[token(",")]
+    Comma,
     #[error]
     #[regex(r"[ \t\n\f]+", logos::skip)]
     Error,
@@ -93,5 +111,32 @@ mod tests {
 
         let tokens: Vec<_> = RuleToken::lexer("_lit1 + _lit2").collect();
         assert_eq!(tokens, vec![AnyLiteral(1), Plus, AnyLiteral(2)]);
+
+        let tokens: Vec<_> = RuleToken::lexer("_1 ^ _int2").collect();
+        assert_eq!(tokens, vec![AnySubExpr(1), Exponent, AnyInteger(2)]);
+
+        let tokens: Vec<_> = RuleToken::lexer("abs(_1) + _f(_2) + _f3(_4)").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Function("abs".to_string()),
+                OpenParen,
+                AnySubExpr(1),
+                CloseParen,
+                Plus,
+                AnyFunction(0),
+                OpenParen,
+                AnySubExpr(2),
+                CloseParen,
+                Plus,
+                AnyFunction(3),
+                OpenParen,
+                AnySubExpr(4),
+                CloseParen
+            ]
+        );
+
+        let tokens: Vec<_> = RuleToken::lexer("1_000 * 1.234_567").collect();
+        assert_eq!(tokens, vec![Literal(1000.0), Asterisk, Literal(1.234567)]);
     }
 }
@@ -1,6 +1,8 @@
 //! Parsing for rules in string format.
 
-use crate::parser::{BinOpKind, UnaryOpKind};
+use crate::constants::{self, Constant};
+use crate::diagnostic::{Diagnostic, DiagnosticCode, Diagnostics, Span};
+use crate::parser::{BinOpKind, ParseMode, UnaryOpKind};
 use crate::rule::lexer::RuleToken;
 use std::{convert::TryInto, fmt, iter::Peekable};
 
@@ -11,7 +13,11 @@ pub enum RuleExpr {
     Literal(f64),
     AnySubExpr(i32),
     AnyLiteral(i32),
+    /// Matches literals that are integers, up to rounding errors.
+    AnyInteger(i32),
     AnyNonLiteral(i32),
+    /// A built-in constant, e.g. `pi` in `sin(pi)`. Only matches the same constant.
+    Constant(Constant),
     // complex
     Binary {
         left: Box<RuleExpr>,
@@ -22,63 +28,218 @@ pub enum RuleExpr {
         op: UnaryOpKind,
         right: Box<RuleExpr>,
     },
+    Call {
+        func: RuleFunc,
+        args: Vec<RuleExpr>,
+    },
+    /// An expression over matched literals that is evaluated when writing the output, e.g. `{_lit1 + 1}`. In a
+    /// pattern, only a negated literal wildcard matches, e.g. `{-_lit1}` matches `-2` with `_lit1 = 2`.
+    Computed(Box<RuleExpr>),
     // used when filling in invalid syntax
     Error,
 }
 
-impl fmt::Display for RuleExpr {
+/// The function of a [`RuleExpr::Call`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleFunc {
+    /// Matches only the function with this name.
+    Named(String),
+    /// Matches any function. All the wildcards with the same id must match the same function.
+    Any(i32),
+}
+
+impl fmt::Display for RuleFunc {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            RuleExpr::Literal(num) => {
-                if *num >= 0.0 {
-                    write!(f, "{}", num)
-                } else {
-                    // print negative number in paren
-                    write!(f, "({})", num)
+            RuleFunc::Named(name) => write!(f, "{}", name),
+            RuleFunc::Any(id) => write!(f, "_f{}", id),
+        }
+    }
+}
+
+/// An item on the work stack used when printing a [`RuleExpr`].
+enum DisplayItem<'a> {
+    Expr(&'a RuleExpr),
+    Text(&'static str),
+    BinOp(BinOpKind),
+    UnaryOp(UnaryOpKind),
+}
+
+impl fmt::Display for RuleExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return self.fmt_indented_tree(f);
+        }
+
+        // Use an explicit stack instead of recursion to not overflow the call stack on deeply nested expressions.
+        // Items are pushed in reverse order of printing.
+        let mut stack = vec![DisplayItem::Expr(self)];
+        while let Some(item) = stack.pop() {
+            match item {
+                DisplayItem::Expr(expr) => match expr {
+                    RuleExpr::Literal(num) => {
+                        if *num >= 0.0 {
+                            write!(f, "{}", num)?
+                        } else {
+                            // print negative number in paren
+                            write!(f, "({})", num)?
+                        }
+                    }
+                    RuleExpr::AnySubExpr(id) => write!(f, "_{}", id)?,
+                    RuleExpr::AnyLiteral(id) => write!(f, "_lit{}", id)?,
+                    RuleExpr::AnyInteger(id) => write!(f, "_int{}", id)?,
+                    RuleExpr::AnyNonLiteral(id) => write!(f, "_nonlit{}", id)?,
+                    RuleExpr::Constant(constant) => write!(f, "{}", constant)?,
+                    RuleExpr::Binary { left, op, right } => stack.extend([
+                        DisplayItem::Text(")"),
+                        DisplayItem::Expr(right),
+                        DisplayItem::BinOp(*op),
+                        DisplayItem::Expr(left),
+                        DisplayItem::Text("("),
+                    ]),
+                    RuleExpr::Unary { op, right } => stack.extend([
+                        DisplayItem::Text(")"),
+                        DisplayItem::Expr(right),
+                        DisplayItem::UnaryOp(*op),
+                        DisplayItem::Text("("),
+                    ]),
+                    RuleExpr::Call { func, args } => {
+                        write!(f, "{}(", func)?;
+                        stack.push(DisplayItem::Text(")"));
+                        for (i, arg) in args.iter().enumerate().rev() {
+                            stack.push(DisplayItem::Expr(arg));
+                            if i > 0 {
+                                stack.push(DisplayItem::Text(", "));
+                            }
+                        }
+                    }
+                    RuleExpr::Computed(expr) => {
+                        write!(f, "{{")?;
+                        stack.extend([DisplayItem::Text("}"), DisplayItem::Expr(expr)]);
+                    }
+                    RuleExpr::Error => write!(f, "err")?,
+                },
+                DisplayItem::Text(text) => write!(f, "{}", text)?,
+                DisplayItem::BinOp(op) => write!(f, " {} ", op)?,
+                DisplayItem::UnaryOp(op) => write!(f, "{}", op)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl RuleExpr {
+    /// Prints one node per line with the children indented below, e.g. `Binary +` / `  AnyLiteral 1` /
+    /// `  AnySubExpr 2`. Used by the alternate (`{:#}`) format.
+    fn fmt_indented_tree(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // (expr, depth)
+        let mut stack = vec![(self, 0)];
+        let mut first = true;
+        while let Some((expr, depth)) = stack.pop() {
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            write!(f, "{:indent$}", "", indent = depth * 2)?;
+            match expr {
+                RuleExpr::Literal(num) => write!(f, "Literal {}", num)?,
+                RuleExpr::AnySubExpr(id) => write!(f, "AnySubExpr {}", id)?,
+                RuleExpr::AnyLiteral(id) => write!(f, "AnyLiteral {}", id)?,
+                RuleExpr::AnyInteger(id) => write!(f, "AnyInteger {}", id)?,
+                RuleExpr::AnyNonLiteral(id) => write!(f, "AnyNonLiteral {}", id)?,
+                RuleExpr::Constant(constant) => write!(f, "Constant {}", constant)?,
+                RuleExpr::Binary { left, op, right } => {
+                    write!(f, "Binary {}", op)?;
+                    stack.extend([(right.as_ref(), depth + 1), (left.as_ref(), depth + 1)]);
+                }
+                RuleExpr::Unary { op, right } => {
+                    write!(f, "Unary {}", op)?;
+                    stack.push((right, depth + 1));
+                }
+                RuleExpr::Call { func, args } => {
+                    write!(f, "Call {}", func)?;
+                    stack.extend(args.iter().rev().map(|arg| (arg, depth + 1)));
+                }
+                RuleExpr::Computed(expr) => {
+                    write!(f, "Computed")?;
+                    stack.push((expr, depth + 1));
                 }
+                RuleExpr::Error => write!(f, "Error")?,
             }
-            RuleExpr::AnySubExpr(id) => write!(f, "_{}", id),
-            RuleExpr::AnyLiteral(id) => write!(f, "_lit{}", id),
-            RuleExpr::AnyNonLiteral(id) => write!(f, "_nonlit{}", id),
-            RuleExpr::Binary { left, op, right } => write!(f, "({} {} {})", left, op, right),
-            RuleExpr::Unary { op, right } => write!(f, "({}{})", op, right),
-            RuleExpr::Error => write!(f, "err"),
         }
+        Ok(())
     }
 }
 
 pub struct RuleParser<T>
 where
-    T: Iterator<Item = RuleToken>,
+    T: Iterator<Item = (RuleToken, Span)>,
 {
     lexer: Peekable<T>,
     current_tok: RuleToken,
-    errors: Vec<String>,
+    /// The span of `current_tok`.
+    current_span: Span,
+    /// The span of the last token returned by `eat_tok`.
+    last_span: Span,
+    diagnostics: Diagnostics,
+    mode: ParseMode,
 }
 
 impl<T> From<T> for RuleParser<T>
 where
-    T: Iterator<Item = RuleToken>,
+    T: Iterator<Item = (RuleToken, Span)>,
 {
     fn from(lexer: T) -> Self {
         let mut lexer = lexer.peekable();
-        let current_tok = lexer
+        let (current_tok, current_span) = lexer
             .next()
             .expect("there should be at least 1 element in lexer");
         Self {
             lexer,
             current_tok,
-            errors: Vec::new(),
+            current_span: current_span.clone(),
+            last_span: current_span,
+            diagnostics: Diagnostics::new(),
+            mode: ParseMode::default(),
         }
     }
 }
 
 impl<T> RuleParser<T>
 where
-    T: Iterator<Item = RuleToken>,
+    T: Iterator<Item = (RuleToken, Span)>,
 {
+    /// Sets how tokens after a complete rule expression are handled. Defaults to [`ParseMode::Strict`].
+    pub fn with_mode(mut self, mode: ParseMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
     pub fn parse(&mut self) -> RuleExpr {
-        self.parse_expr()
+        let expr = self.parse_expr();
+        // the end of the input is the empty span right after the last token, the tokens after a syntax error are
+        // usually the consequence of that error so they are not reported again
+        if expr != RuleExpr::Error && !self.current_span.is_empty() {
+            match self.mode {
+                ParseMode::Strict => {
+                    self.eat_tok();
+                    return self.unexpected("the end of the rule");
+                }
+                ParseMode::Lenient => {
+                    let start = self.current_span.start;
+                    while !self.current_span.is_empty() {
+                        self.eat_tok();
+                    }
+                    self.diagnostics.push(Diagnostic::warning(
+                        DiagnosticCode::TrailingInput,
+                        "trailing input is ignored",
+                        start..self.last_span.end,
+                    ));
+                }
+            }
+        }
+        expr
     }
 
     /// Alias for `self.parse_expr_bp(0)` to accept any expression.
@@ -91,7 +252,23 @@ where
             RuleToken::Literal(num) => RuleExpr::Literal(num),
             RuleToken::AnySubExpr(id) => RuleExpr::AnySubExpr(id),
             RuleToken::AnyLiteral(id) => RuleExpr::AnyLiteral(id),
+            RuleToken::AnyInteger(id) => RuleExpr::AnyInteger(id),
             RuleToken::AnyNonLiteral(id) => RuleExpr::AnyNonLiteral(id),
+            RuleToken::Function(name) => match constants::lookup(&name) {
+                // a constant is a name that is not called, e.g. `pi` in `sin(pi)`
+                Some(constant) if self.current_tok != RuleToken::OpenParen => {
+                    RuleExpr::Constant(constant)
+                }
+                _ => self.parse_call(RuleFunc::Named(name)),
+            },
+            RuleToken::AnyFunction(id) => self.parse_call(RuleFunc::Any(id)),
+            RuleToken::OpenBrace => {
+                let expr = self.parse_expr();
+                match self.eat_tok() {
+                    RuleToken::CloseBrace => RuleExpr::Computed(Box::new(expr)),
+                    _ => self.unexpected("a '}' token"),
+                }
+            }
             RuleToken::OpenParen => {
                 let expr = self.parse_expr();
                 match self.eat_tok() {
@@ -103,6 +280,23 @@ where
         }
     }
 
+    /// Parses the arguments of a call to `func`. The function name should already have been eaten.
+    fn parse_call(&mut self, func: RuleFunc) -> RuleExpr {
+        if self.eat_tok() != RuleToken::OpenParen {
+            return self.unexpected("a '(' token after the function name");
+        }
+        let mut args = Vec::new();
+        loop {
+            args.push(self.parse_expr());
+            match self.eat_tok() {
+                RuleToken::Comma => {}
+                RuleToken::CloseParen => break,
+                _ => return self.unexpected("a ',' or ')' token"),
+            }
+        }
+        RuleExpr::Call { func, args }
+    }
+
     fn parse_expr_bp(&mut self, min_bp: i32) -> RuleExpr {
         let mut left = match self.current_tok.get_prefix_bp() {
             ((), -1) => self.parse_atom(), // not prefix
@@ -114,7 +308,7 @@ where
                 let right = self.parse_expr_bp(right_bp);
                 if let RuleExpr::Literal(num) = right {
                     // fold unary literal in ast
-                    RuleExpr::Literal(num * -1.0)
+                    RuleExpr::Literal(-num)
                 } else {
                     RuleExpr::Unary {
                         op: prefix_op,
@@ -150,17 +344,26 @@ where
 
     /// Returns the current token. Sets `self.current_tok` to the next [`RuleToken`] in the lexer.
     fn eat_tok(&mut self) -> RuleToken {
-        let res = self.current_tok.clone();
-        self.current_tok = self.lexer.next().unwrap_or(RuleToken::Error);
-        res
+        // the end of the input is the empty span right after the last token
+        let eof_span = self.current_span.end..self.current_span.end;
+        let (next_tok, next_span) = self.lexer.next().unwrap_or((RuleToken::Error, eof_span));
+        self.last_span = std::mem::replace(&mut self.current_span, next_span);
+        std::mem::replace(&mut self.current_tok, next_tok)
     }
 
     /// Returns [`crate::parser::Expr::Error`].
     fn unexpected(&mut self, expected: &str) -> RuleExpr {
-        self.errors
-            .push(format!("unexpected token, expected {}", expected));
+        self.diagnostics.push(Diagnostic::error(
+            DiagnosticCode::UnexpectedToken,
+            format!("unexpected token, expected {}", expected),
+            self.last_span.clone(),
+        ));
         RuleExpr::Error
     }
+
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
 }
 
 #[cfg(test)]
@@ -170,7 +373,7 @@ mod tests {
 
     #[test]
     fn test_parser() {
-        let tokens = RuleToken::lexer("0 + _1");
+        let tokens = RuleToken::lexer("0 + _1").spanned();
         let mut parser = RuleParser::from(tokens);
         assert_eq!(
             parser.parse(),
@@ -181,7 +384,7 @@ mod tests {
             }
         );
 
-        let tokens = RuleToken::lexer("_lit1 + _lit2");
+        let tokens = RuleToken::lexer("_lit1 + _lit2").spanned();
         let mut parser = RuleParser::from(tokens);
         assert_eq!(
             parser.parse(),
@@ -192,7 +395,7 @@ mod tests {
             }
         );
 
-        let tokens = RuleToken::lexer("(_lit1 + _lit2)");
+        let tokens = RuleToken::lexer("(_lit1 + _lit2)").spanned();
         let mut parser = RuleParser::from(tokens);
         assert_eq!(
             parser.parse(),
@@ -203,4 +406,104 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_parser_calls() {
+        let tokens = RuleToken::lexer("_f(abs(_1), 2)").spanned();
+        let mut parser = RuleParser::from(tokens);
+        let expr = parser.parse();
+        assert_eq!(
+            expr,
+            RuleExpr::Call {
+                func: RuleFunc::Any(0),
+                args: vec![
+                    RuleExpr::Call {
+                        func: RuleFunc::Named("abs".to_string()),
+                        args: vec![RuleExpr::AnySubExpr(1)]
+                    },
+                    RuleExpr::Literal(2.0)
+                ]
+            }
+        );
+        assert_eq!(expr.to_string(), "_f0(abs(_1), 2)");
+
+        let tokens = RuleToken::lexer("abs + 1").spanned();
+        let mut parser = RuleParser::from(tokens);
+        assert_eq!(parser.parse(), RuleExpr::Error);
+        assert_eq!(parser.diagnostics().len(), 1);
+    }
+
+    #[test]
+    fn test_parser_computed() {
+        let tokens = RuleToken::lexer("_1 ^ {_lit2 - 1}").spanned();
+        let mut parser = RuleParser::from(tokens);
+        let expr = parser.parse();
+        assert_eq!(
+            expr,
+            RuleExpr::Binary {
+                left: Box::new(RuleExpr::AnySubExpr(1)),
+                op: BinOpKind::Exponent,
+                right: Box::new(RuleExpr::Computed(Box::new(RuleExpr::Binary {
+                    left: Box::new(RuleExpr::AnyLiteral(2)),
+                    op: BinOpKind::Minus,
+                    right: Box::new(RuleExpr::Literal(1.0))
+                })))
+            }
+        );
+        assert_eq!(expr.to_string(), "(_1 ^ {(_lit2 - 1)})");
+    }
+
+    #[test]
+    fn test_alternate_tree() {
+        let expr = RuleParser::from(RuleToken::lexer("_f1(_1) ^ {_lit2 - 1}").spanned()).parse();
+        assert_eq!(
+            format!("{:#}", expr),
+            "Binary ^\n  Call _f1\n    AnySubExpr 1\n  Computed\n    Binary -\n      AnyLiteral 2\n      Literal 1"
+        );
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        let mut parser = RuleParser::from(RuleToken::lexer("_1 + 2").spanned());
+        assert_eq!(parser.parse().to_string(), "(_1 + 2)");
+        assert!(parser.diagnostics().is_empty());
+
+        let mut parser = RuleParser::from(RuleToken::lexer("_1 + 2) * 3").spanned())
+            .with_mode(ParseMode::Lenient);
+        assert_eq!(parser.parse().to_string(), "(_1 + 2)");
+        assert_eq!(
+            parser.diagnostics().iter().collect::<Vec<_>>(),
+            vec![&Diagnostic::warning(
+                DiagnosticCode::TrailingInput,
+                "trailing input is ignored",
+                6..11
+            )]
+        );
+
+        let mut parser = RuleParser::from(RuleToken::lexer("_1 + 2)").spanned());
+        assert_eq!(parser.parse(), RuleExpr::Error);
+        assert_eq!(
+            parser.diagnostics().iter().collect::<Vec<_>>(),
+            vec![&Diagnostic::error(
+                DiagnosticCode::UnexpectedToken,
+                "unexpected token, expected the end of the rule",
+                6..7
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parser_diagnostics() {
+        let tokens = RuleToken::lexer("(_1 + 2").spanned();
+        let mut parser = RuleParser::from(tokens);
+        assert_eq!(parser.parse(), RuleExpr::Error);
+        assert_eq!(
+            parser.diagnostics().iter().collect::<Vec<_>>(),
+            vec![&Diagnostic::error(
+                DiagnosticCode::UnexpectedToken,
+                "unexpected token, expected a '(' token",
+                7..7
+            )]
+        );
+    }
 }
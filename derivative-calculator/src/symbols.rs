@@ -0,0 +1,160 @@
+//! The names that can be used in an input, for suggesting completions while typing.
+
+use crate::functions::FUNCTIONS;
+use crate::parser::Expr;
+use std::collections::BTreeSet;
+
+/// Functions that are not evaluated but handled by the transformation passes.
+const SPECIAL_FUNCTIONS: &[&str] = &["diff"];
+
+/// What a [`Symbol`] refers to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Identifier,
+}
+
+/// A name that can be used in an input.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Symbol<'a> {
+    pub name: &'a str,
+    pub kind: SymbolKind,
+}
+
+/// The known function names and the identifiers used in previous inputs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolRegistry {
+    identifiers: BTreeSet<String>,
+}
+
+impl SymbolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remembers the identifiers of `expr`, e.g. after it was entered by the user.
+    pub fn add_identifiers(&mut self, expr: &Expr) {
+        self.identifiers
+            .extend(expr.identifiers().into_iter().map(str::to_string));
+    }
+
+    /// Returns all the symbols starting with `prefix`. Functions come first, then identifiers, each sorted by name.
+    pub fn suggestions(&self, prefix: &str) -> Vec<Symbol<'_>> {
+        let functions = FUNCTIONS
+            .iter()
+            .map(|func| func.name)
+            .chain(SPECIAL_FUNCTIONS.iter().copied())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|name| Symbol {
+                name,
+                kind: SymbolKind::Function,
+            });
+        let identifiers = self.identifiers.iter().map(|name| Symbol {
+            name,
+            kind: SymbolKind::Identifier,
+        });
+        functions
+            .chain(identifiers)
+            .filter(|symbol| symbol.name.starts_with(prefix) && symbol.name != prefix)
+            .collect()
+    }
+
+    /// Completes the name that `input` ends with, e.g. `2 * ab` becomes `2 * abs(`. If several symbols match, the name
+    /// is only completed up to their longest common prefix. Returns `None` if there is nothing to complete.
+    pub fn complete(&self, input: &str) -> Option<String> {
+        let prefix = last_word(input);
+        if prefix.is_empty() {
+            return None;
+        }
+        let suggestions = self.suggestions(prefix);
+        let completion = match suggestions.as_slice() {
+            [] => return None,
+            [symbol] => match symbol.kind {
+                SymbolKind::Function => format!("{}(", symbol.name),
+                SymbolKind::Identifier => symbol.name.to_string(),
+            },
+            [first, rest @ ..] => {
+                let common = rest.iter().fold(first.name, |common, symbol| {
+                    let len = common
+                        .chars()
+                        .zip(symbol.name.chars())
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    &common[..len]
+                });
+                if common == prefix {
+                    return None;
+                }
+                common.to_string()
+            }
+        };
+        Some(format!(
+            "{}{}",
+            &input[..input.len() - prefix.len()],
+            completion
+        ))
+    }
+}
+
+/// Returns the name that is being typed at the end of `input`, which may be empty.
+pub fn last_word(input: &str) -> &str {
+    let start = input
+        .rfind(|c: char| !c.is_ascii_alphabetic())
+        .map_or(0, |i| i + 1);
+    &input[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+
+    fn registry(inputs: &[&str]) -> SymbolRegistry {
+        let mut registry = SymbolRegistry::new();
+        for input in inputs {
+            registry.add_identifiers(&Parser::from(Token::lexer(input).spanned()).parse());
+        }
+        registry
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let registry = registry(&["alpha * x + delta", "a + x"]);
+        let names = |prefix| {
+            registry
+                .suggestions(prefix)
+                .iter()
+                .map(|symbol| symbol.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("a"), ["abs", "acos", "asin", "atan", "alpha"]);
+        assert_eq!(names("d"), ["diff", "delta"]);
+        assert_eq!(names("x"), Vec::<&str>::new());
+        assert_eq!(names("q"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_complete() {
+        let registry = registry(&["signal + alpha"]);
+        assert_eq!(registry.complete("2 * ab"), Some("2 * abs(".to_string()));
+        assert_eq!(registry.complete("x + alp"), Some("x + alpha".to_string()));
+        // `sign` and `signal`
+        assert_eq!(registry.complete("sig"), Some("sign".to_string()));
+        // `sign`, `signal` and `sin`
+        assert_eq!(registry.complete("si"), None);
+        assert_eq!(registry.complete("sign"), Some("signal".to_string()));
+        assert_eq!(registry.complete("x + "), None);
+        assert_eq!(registry.complete("q"), None);
+    }
+
+    #[test]
+    fn test_last_word() {
+        assert_eq!(last_word("2 * abs"), "abs");
+        assert_eq!(last_word("2x"), "x");
+        assert_eq!(last_word("x + "), "");
+        assert_eq!(last_word(""), "");
+    }
+}
@@ -0,0 +1,157 @@
+//! Tables of the values of a function and its derivative, e.g. to export them to a spreadsheet.
+
+use crate::eval::compiled::CompiledExpr;
+use crate::parser::Expr;
+use std::fmt;
+
+/// The most rows [`sample`] returns, to keep a mistyped step from freezing the app.
+pub const MAX_SAMPLES: usize = 10_000;
+
+/// The values of the variable to sample: `start`, `start + step`, `start + 2 * step`, ... up to and including `end`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SampleRange {
+    pub start: f64,
+    pub end: f64,
+    pub step: f64,
+}
+
+/// An error that occurred while sampling.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SampleError {
+    /// The range or the step is not finite, or the step is not positive.
+    InvalidRange,
+    /// The range contains more than [`MAX_SAMPLES`] values.
+    TooManySamples,
+}
+
+impl fmt::Display for SampleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SampleError::InvalidRange => write!(
+                f,
+                "the range must be finite and the step must be greater than 0"
+            ),
+            SampleError::TooManySamples => write!(
+                f,
+                "too many samples, the limit is {}. Use a larger step",
+                MAX_SAMPLES
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SampleError {}
+
+/// A row of the table returned by [`sample`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SampleRow {
+    /// The value of the variable.
+    pub x: f64,
+    /// The value of the function, or `None` if it could not be evaluated.
+    pub value: Option<f64>,
+    /// The value of the derivative, or `None` if it could not be evaluated.
+    pub derivative: Option<f64>,
+}
+
+/// Evaluates `f` and its `derivative` at every value of `variable` in `range`. An expression that cannot be
+/// evaluated, e.g. because it contains another identifier, leaves its column empty instead of failing.
+pub fn sample(
+    f: &Expr,
+    derivative: &Expr,
+    variable: &str,
+    range: &SampleRange,
+) -> Result<Vec<SampleRow>, SampleError> {
+    let SampleRange { start, end, step } = *range;
+    if !(start.is_finite() && end.is_finite() && step.is_finite() && step > 0.0) {
+        return Err(SampleError::InvalidRange);
+    }
+    if end < start {
+        return Ok(Vec::new());
+    }
+    // the last value may be slightly past `end` because of rounding errors
+    let steps = ((end - start) / step * (1.0 + f64::EPSILON)).floor();
+    if steps >= MAX_SAMPLES as f64 {
+        return Err(SampleError::TooManySamples);
+    }
+
+    // multiplying instead of adding up the steps keeps the rounding errors from accumulating
+    let xs: Vec<f64> = (0..=steps as usize)
+        .map(|i| start + i as f64 * step)
+        .collect();
+    // evaluation only fails because of the shape of the expression, e.g. an unknown identifier, so it fails for
+    // every value or for none
+    let column = |expr: &Expr| {
+        CompiledExpr::new(expr, variable).ok().map(|compiled| {
+            let mut values = vec![0.0; xs.len()];
+            compiled.eval_many(&xs, &mut values);
+            values
+        })
+    };
+    let (values, derivatives) = (column(f), column(derivative));
+    Ok(xs
+        .iter()
+        .enumerate()
+        .map(|(i, x)| SampleRow {
+            x: *x,
+            value: values.as_ref().map(|values| values[i]),
+            derivative: derivatives.as_ref().map(|derivatives| derivatives[i]),
+        })
+        .collect())
+}
+
+/// Formats `rows` as CSV with a header row, e.g. `x,f(x),f'(x)` for the `variable` `x`. Values that could not be
+/// evaluated are left empty.
+pub fn to_csv(rows: &[SampleRow], variable: &str) -> String {
+    let cell = |value: Option<f64>| value.map(|value| value.to_string()).unwrap_or_default();
+    let mut csv = format!("{0},f({0}),f'({0})\n", variable);
+    for row in rows {
+        csv += &format!("{},{},{}\n", row.x, cell(row.value), cell(row.derivative));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+
+    fn parse(input: &str) -> Expr {
+        Parser::from(Token::lexer(input).spanned()).parse()
+    }
+
+    #[test]
+    fn test_sample() {
+        let range = SampleRange {
+            start: 0.0,
+            end: 0.3,
+            step: 0.1,
+        };
+        let rows = sample(&parse("1 / x"), &parse("-1 / x ^ 2 + y"), "x", &range).unwrap();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0].value, Some(f64::INFINITY));
+        assert_eq!(rows[2].value, Some(5.0));
+        assert_eq!(rows[2].derivative, None);
+        assert_eq!(
+            to_csv(&rows[1..2], "x"),
+            "x,f(x),f'(x)\n0.1,10,\n".to_string()
+        );
+
+        let invalid = |start, end, step| {
+            sample(
+                &parse("x"),
+                &parse("1"),
+                "x",
+                &SampleRange { start, end, step },
+            )
+        };
+        assert_eq!(invalid(0.0, 1.0, 0.0), Err(SampleError::InvalidRange));
+        assert_eq!(
+            invalid(0.0, f64::INFINITY, 1.0),
+            Err(SampleError::InvalidRange)
+        );
+        assert_eq!(invalid(0.0, 1e9, 1.0), Err(SampleError::TooManySamples));
+        assert_eq!(invalid(1.0, 0.0, 1.0), Ok(Vec::new()));
+    }
+}
@@ -1,15 +1,63 @@
 //! AST transformations.
 
+pub mod cleanup;
+pub mod custom;
 pub mod derivative;
+pub mod identities;
+pub mod integrate;
+pub mod lint;
+pub mod logarithm;
 pub mod prettify;
+pub mod profile;
 pub mod simplify;
 
-use crate::parser::Expr;
+use crate::eval::{equivalent, eval, Equivalence};
+use crate::parser::{BinOpKind, Expr, ExprPath, UnaryOpKind};
 use crate::rule::parser::RuleExpr;
-use crate::rule::MatchResult;
+use crate::rule::{MatchResult, Tolerance};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
 
-/// The max number of iterations per apply. Exceeding this amount will cause an error.
-pub const MAX_ITERATIONS_PER_APPLY: i32 = 500;
+/// The default max number of iterations per apply, see [`RuleTransformSet::with_max_iterations`].
+pub const MAX_ITERATIONS_PER_APPLY: usize = 500;
+
+/// What happens when applying the rules of a [`RuleTransformSet`] exceeds its max number of iterations, which usually
+/// means that some rules rewrite each other's output in a loop.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum IterationLimitPolicy {
+    /// Log a warning and return the expression of the last iteration.
+    #[default]
+    BestSoFar,
+    /// Return an [`IterationLimitExceeded`] error from [`RuleTransformSet::apply_rules_checked`] and
+    /// [`RuleTransformSet::apply_rules_incremental_checked`]. The other methods cannot fail and log a warning with the
+    /// error and return the expression of the last iteration.
+    Error,
+    /// Panic in debug builds to catch looping rules early, and behave as with [`IterationLimitPolicy::BestSoFar`] in
+    /// release builds.
+    DebugPanic,
+}
+
+/// Applying the rules exceeded the max number of iterations with [`IterationLimitPolicy::Error`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct IterationLimitExceeded {
+    /// The max number of iterations.
+    pub limit: usize,
+    /// The expression of the last iteration.
+    pub best: Expr,
+}
+
+impl fmt::Display for IterationLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "applying the rules exceeded {} iterations, the rules may be looping",
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for IterationLimitExceeded {}
 
 pub enum TransformOut<'a> {
     OutPattern(RuleExpr),
@@ -17,14 +65,343 @@ pub enum TransformOut<'a> {
     OutHandler(&'a (dyn Fn(&MatchResult) -> Option<Expr> + Sync)),
 }
 
+/// How the two sides of a [`Guard`] are compared.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// Returns `true` if `left` and `right` compare this way. Equality is up to `tolerance`.
+    pub fn compare(self, left: f64, right: f64, tolerance: Tolerance) -> bool {
+        match self {
+            Comparison::Equal => tolerance.eq(left, right),
+            Comparison::NotEqual => !tolerance.eq(left, right),
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right || tolerance.eq(left, right),
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right || tolerance.eq(left, right),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// A condition on the matched literals that must hold for a rule to apply, e.g. `_lit1 > 0`. Both sides are evaluated
+/// like computed expressions (e.g. `{_lit1 + 1}`) once the pattern matched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Guard {
+    pub left: RuleExpr,
+    pub op: Comparison,
+    pub right: RuleExpr,
+}
+
+impl Guard {
+    /// Returns `true` if the guard holds for the wildcards of `match_res`. A guard whose sides cannot be evaluated,
+    /// e.g. because a wildcard matched an identifier, does not hold.
+    pub fn holds(&self, match_res: &MatchResult, tolerance: Tolerance) -> bool {
+        let value = |side: &RuleExpr| {
+            side.write_match(match_res)
+                .and_then(|side| eval(&side, &HashMap::new()).ok())
+        };
+        match (value(&self.left), value(&self.right)) {
+            (Some(left), Some(right)) => self.op.compare(left, right, tolerance),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Guard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.left, self.op, self.right)
+    }
+}
+
 pub struct Transformation<'a> {
     pattern: RuleExpr,
     out: TransformOut<'a>,
+    /// A human readable name for the rule, e.g. "power rule".
+    name: Option<Cow<'a, str>>,
+    /// Rules with a higher priority are tried first.
+    priority: i32,
+    /// The rule only applies if the guard holds.
+    guard: Option<Guard>,
+}
+
+impl<'a> Transformation<'a> {
+    pub fn pattern(&self) -> &RuleExpr {
+        &self.pattern
+    }
+
+    /// Returns the human readable name of the rule, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn guard(&self) -> Option<&Guard> {
+        self.guard.as_ref()
+    }
+
+    /// Matches the pattern on `expr` and checks the guard. The match fails if the guard does not hold.
+    fn match_expr<'e>(&self, expr: &'e Expr, tolerance: Tolerance) -> MatchResult<'e> {
+        let mut match_res = self.pattern.match_expr_with_tolerance(expr, tolerance);
+        if let (true, Some(guard)) = (match_res.matches, &self.guard) {
+            match_res.matches = guard.holds(&match_res, tolerance);
+        }
+        match_res
+    }
+
+    /// Returns the priority of the rule, `0` unless set with [`RuleTransformSet::with_priorities`].
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+}
+
+impl fmt::Display for Transformation<'_> {
+    /// Displays the name of the rule or its pattern if the rule is unnamed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{}", self.pattern),
+        }
+    }
+}
+
+/// An error returned by [`RuleTransformSet::apply_rule_at`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApplyRuleError {
+    /// The expression has no sub-expression at the path.
+    InvalidPath(ExprPath),
+    /// No rule of the set has the name.
+    UnknownRule(String),
+    /// The rule does not match the sub-expression at the path.
+    NoMatch,
+}
+
+impl fmt::Display for ApplyRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApplyRuleError::InvalidPath(path) => write!(f, "no sub-expression at {:?}", path),
+            ApplyRuleError::UnknownRule(rule) => write!(f, "unknown rule `{}`", rule),
+            ApplyRuleError::NoMatch => write!(f, "the rule does not apply here"),
+        }
+    }
+}
+
+impl std::error::Error for ApplyRuleError {}
+
+/// The kind of the root node of an [`Expr`]. Used to index rules by the nodes they can possibly match.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum NodeShape {
+    Literal,
+    Identifier,
+    Constant,
+    Binary(BinOpKind),
+    Unary(UnaryOpKind),
+    Call,
+    Integral,
+    Vector,
+    Undefined,
+    Error,
+}
+
+impl NodeShape {
+    fn of_expr(expr: &Expr) -> Self {
+        match expr {
+            Expr::Literal(_) => NodeShape::Literal,
+            Expr::Identifier(_) => NodeShape::Identifier,
+            Expr::Constant(_) => NodeShape::Constant,
+            Expr::Binary { op, .. } => NodeShape::Binary(*op),
+            Expr::Unary { op, .. } => NodeShape::Unary(*op),
+            Expr::Call { .. } => NodeShape::Call,
+            Expr::Integral { .. } => NodeShape::Integral,
+            Expr::Vector(_) => NodeShape::Vector,
+            Expr::Undefined => NodeShape::Undefined,
+            Expr::Error => NodeShape::Error,
+        }
+    }
+
+    /// Returns the shape of the nodes that `pattern` can match or `None` if the pattern can match any node.
+    fn of_pattern(pattern: &RuleExpr) -> Option<Self> {
+        match pattern {
+            RuleExpr::Literal(_) | RuleExpr::AnyLiteral(_) | RuleExpr::AnyInteger(_) => {
+                Some(NodeShape::Literal)
+            }
+            RuleExpr::Constant(_) => Some(NodeShape::Constant),
+            RuleExpr::Binary { op, .. } => Some(NodeShape::Binary(*op)),
+            RuleExpr::Unary { op, .. } => Some(NodeShape::Unary(*op)),
+            RuleExpr::Call { .. } => Some(NodeShape::Call),
+            // `_nonlit` matches every shape except literals. Treat it as a catch all and let the matcher reject literals.
+            RuleExpr::AnySubExpr(_) | RuleExpr::AnyNonLiteral(_) => None,
+            // never matches anything
+            RuleExpr::Computed(_) | RuleExpr::Error => Some(NodeShape::Error),
+        }
+    }
+}
+
+/// Indices of the rules in a [`RuleTransformSet`] grouped by the shape of the root node they can match.
+/// Every list is sorted in application order.
+struct RuleIndex {
+    by_shape: HashMap<NodeShape, Vec<usize>>,
+    /// Rules whose pattern can match any node.
+    any: Vec<usize>,
+}
+
+impl RuleIndex {
+    fn new(rules: &[Transformation]) -> Self {
+        let mut by_shape: HashMap<NodeShape, Vec<usize>> = HashMap::new();
+        let mut any = Vec::new();
+        for (i, transform) in rules.iter().enumerate() {
+            match NodeShape::of_pattern(&transform.pattern) {
+                Some(shape) => by_shape.entry(shape).or_default().push(i),
+                None => any.push(i),
+            }
+        }
+        // catch all rules are candidates for every shape
+        for indices in by_shape.values_mut() {
+            indices.extend_from_slice(&any);
+            indices.sort_unstable();
+        }
+
+        Self { by_shape, any }
+    }
+
+    /// Returns the indices of the rules that can possibly match `expr`, in application order.
+    fn candidates(&self, expr: &Expr) -> &[usize] {
+        self.by_shape
+            .get(&NodeShape::of_expr(expr))
+            .unwrap_or(&self.any)
+    }
 }
 
 /// Utility to keep on applying transformations until no more matches.
+///
+/// The rules are tried in application order: by decreasing priority (see [`RuleTransformSet::with_priorities`]) and
+/// then in the order they were added to the set. Whenever a rule rewrites the expression, the following rules are
+/// tried on the rewritten expression.
 pub struct RuleTransformSet<'a> {
-    pub rules: Vec<Transformation<'a>>,
+    rules: Vec<Transformation<'a>>,
+    index: RuleIndex,
+    /// How literals in the patterns are compared.
+    tolerance: Tolerance,
+    max_iterations: usize,
+    on_iteration_limit: IterationLimitPolicy,
+    /// Whether every rewrite is checked numerically, see [`RuleTransformSet::with_soundness_checks`].
+    soundness_checks: bool,
+}
+
+/// Adds the paths of the wildcards of `rule` (e.g. `_1` or `_lit2`) below `path` to `paths`, by wildcard id.
+/// Wildcards inside computed expressions are skipped since they are not copied as is.
+fn wildcard_paths(rule: &RuleExpr, path: &mut ExprPath, paths: &mut Vec<(i32, ExprPath)>) {
+    match rule {
+        RuleExpr::AnySubExpr(id)
+        | RuleExpr::AnyLiteral(id)
+        | RuleExpr::AnyInteger(id)
+        | RuleExpr::AnyNonLiteral(id) => paths.push((*id, path.clone())),
+        RuleExpr::Binary { left, op: _, right } => {
+            for (i, child) in [left, right].into_iter().enumerate() {
+                path.push(i);
+                wildcard_paths(child, path, paths);
+                path.pop();
+            }
+        }
+        // a negated literal wildcard is written as a single literal, see `RuleExpr::write_expr`
+        RuleExpr::Unary { op: _, right }
+            if matches!(**right, RuleExpr::AnyLiteral(_) | RuleExpr::AnyInteger(_)) => {}
+        RuleExpr::Unary { op: _, right } => {
+            path.push(0);
+            wildcard_paths(right, path, paths);
+            path.pop();
+        }
+        RuleExpr::Call { func: _, args } => {
+            for (i, arg) in args.iter().enumerate() {
+                path.push(i);
+                wildcard_paths(arg, path, paths);
+                path.pop();
+            }
+        }
+        RuleExpr::Literal(_) | RuleExpr::Constant(_) | RuleExpr::Computed(_) | RuleExpr::Error => {}
+    }
+}
+
+/// Returns whether the sub-expression at `path` is in one of the `clean` sub-expressions.
+fn is_clean(clean: &[ExprPath], path: &[usize]) -> bool {
+    clean.iter().any(|clean| path.starts_with(clean))
+}
+
+/// Returns the paths of the clean sub-expressions, i.e. the ones that are already transformed, of the output of
+/// `transform` given the `clean` paths of its input. These are the wildcards of the output that captured a clean
+/// sub-expression. The output of a handler is never clean.
+fn carry_clean(transform: &Transformation, clean: &[ExprPath]) -> Vec<ExprPath> {
+    let out = match &transform.out {
+        TransformOut::OutPattern(out) => out,
+        TransformOut::OutHandler(_) => return Vec::new(),
+    };
+    let mut captured = Vec::new();
+    wildcard_paths(&transform.pattern, &mut ExprPath::new(), &mut captured);
+    let mut written = Vec::new();
+    wildcard_paths(out, &mut ExprPath::new(), &mut written);
+    written
+        .into_iter()
+        .filter(|(id, _)| {
+            captured
+                .iter()
+                .any(|(captured_id, path)| captured_id == id && is_clean(clean, path))
+        })
+        .map(|(_, path)| path)
+        .collect()
+}
+
+/// Returns the paths of the children of `expr`.
+fn child_paths(expr: &Expr) -> Vec<ExprPath> {
+    (0..expr.children().len()).map(|i| vec![i]).collect()
+}
+
+/// Evaluates `before` and `after` at a few points and logs an error if `transform` rewrote `before` into an expression
+/// with a different value. Returns `false` if the values differ. Expressions that cannot be evaluated, e.g. because
+/// they contain undefined functions, are not checked.
+fn check_soundness(transform: &Transformation, before: &Expr, after: &Expr) -> bool {
+    match equivalent(before, after) {
+        Ok(Equivalence::Different { point, left, right }) => {
+            log::error!(
+                "unsound rule {}: rewrote {} = {} into {} = {} at {:?}",
+                transform,
+                before,
+                left,
+                after,
+                right,
+                point
+            );
+            false
+        }
+        Ok(Equivalence::Equivalent) | Err(_) => true,
+    }
+}
+
+/// The direction in which an identity `(left, right)` is turned into a rule.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    /// Rewrite `left` into `right`.
+    LeftToRight,
+    /// Rewrite `right` into `left`.
+    RightToLeft,
 }
 
 pub type RuleTransformHandler<'a> = &'a [(
@@ -32,16 +409,42 @@ pub type RuleTransformHandler<'a> = &'a [(
     &'a (dyn for<'r, 's> Fn(&'r MatchResult<'s>) -> Option<Expr> + Sync + 'a),
 )];
 
+/// Splits the output of a hard-coded rule from its guard, e.g. `_1 - _lit2 if _lit2 > 0`. The guard is written like in
+/// [`custom`] rules.
+fn split_guard(out: &str) -> (&str, Option<&str>) {
+    match out.split_once(custom::GUARD_SEPARATOR) {
+        Some((out, guard)) => (out, Some(guard)),
+        None => (out, None),
+    }
+}
+
+/// Parses the guard of a hard-coded rule with `pattern`.
+///
+/// # Panics
+/// Panics if the guard is invalid.
+fn parse_guard(guard: &str, pattern: &RuleExpr) -> Guard {
+    custom::parse_guard(guard, pattern).unwrap_or_else(|_| panic!("invalid guard `{}`", guard))
+}
+
 impl<'a> RuleTransformSet<'a> {
+    /// Creates a rule set from `(pattern, out)` pairs followed by `(pattern, handler)` pairs. The output of a pattern
+    /// can be followed by a guard, e.g. `("_1 + {-_lit2}", "_1 - _lit2 if _lit2 > 0")`.
+    ///
+    /// # Panics
+    /// Panics if a guard is invalid.
     pub fn new_from_str(patterns: &[(&str, &str)], handlers: RuleTransformHandler<'a>) -> Self {
         let mut transformations: Vec<_> = patterns
             .iter()
             .map(|(pattern, out)| {
+                let (out, guard) = split_guard(out);
                 let pattern = RuleExpr::new_rule_from_str(pattern);
                 let out = RuleExpr::new_rule_from_str(out);
                 Transformation {
+                    guard: guard.map(|guard| parse_guard(guard, &pattern)),
                     pattern,
                     out: TransformOut::OutPattern(out),
+                    name: None,
+                    priority: 0,
                 }
             })
             .collect();
@@ -51,74 +454,645 @@ impl<'a> RuleTransformSet<'a> {
             Transformation {
                 pattern,
                 out: TransformOut::OutHandler(handler),
+                name: None,
+                priority: 0,
+                guard: None,
             }
         }));
 
+        Self::from_rules(transformations)
+    }
+
+    fn from_rules(mut rules: Vec<Transformation<'a>>) -> Self {
+        // the sort is stable, so rules with the same priority stay in insertion order
+        rules.sort_by_key(|transform| std::cmp::Reverse(transform.priority));
         Self {
-            rules: transformations,
+            index: RuleIndex::new(&rules),
+            rules,
+            tolerance: Tolerance::default(),
+            max_iterations: MAX_ITERATIONS_PER_APPLY,
+            on_iteration_limit: IterationLimitPolicy::default(),
+            soundness_checks: cfg!(feature = "soundness-checks"),
+        }
+    }
+
+    /// Replaces the rules of the set, keeping its settings.
+    fn with_replaced_rules(self, rules: Vec<Transformation<'a>>) -> Self {
+        Self {
+            tolerance: self.tolerance,
+            max_iterations: self.max_iterations,
+            on_iteration_limit: self.on_iteration_limit,
+            soundness_checks: self.soundness_checks,
+            ..Self::from_rules(rules)
+        }
+    }
+
+    /// Sets how literals in the patterns are compared with the literals in the expressions. Defaults to
+    /// [`Tolerance::EXACT`].
+    pub fn with_tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Sets the max number of times the rules are applied to an expression in [`RuleTransformSet::apply_rules`] and
+    /// what happens when it is exceeded. Defaults to [`MAX_ITERATIONS_PER_APPLY`] and
+    /// [`IterationLimitPolicy::BestSoFar`].
+    pub fn with_max_iterations(
+        mut self,
+        max_iterations: usize,
+        policy: IterationLimitPolicy,
+    ) -> Self {
+        self.max_iterations = max_iterations;
+        self.on_iteration_limit = policy;
+        self
+    }
+
+    /// Sets whether every rewrite is checked by evaluating the expression before and after at a few points, logging
+    /// an error with the rule and the counterexample if they differ. This catches unsound rules during development but
+    /// makes applying rules much slower. Defaults to `true` if the `soundness-checks` feature is enabled, which also
+    /// checks the built-in rule sets.
+    pub fn with_soundness_checks(mut self, soundness_checks: bool) -> Self {
+        self.soundness_checks = soundness_checks;
+        self
+    }
+
+    /// Checks the rewrite of `before` into `after` by `transform` if soundness checks are enabled. Returns `after`.
+    fn checked(&self, transform: &Transformation, before: &Expr, after: Expr) -> Expr {
+        if self.soundness_checks {
+            check_soundness(transform, before, &after);
         }
+        after
+    }
+
+    /// Appends a rule for every identity `(left, right)`, rewriting in `direction`. This allows declaring an identity
+    /// once and sharing it between rule sets that rewrite in opposite directions (e.g. simplify and prettify).
+    ///
+    /// Both sides of an identity should use the same wildcards. The right side can be followed by a guard like in
+    /// [`RuleTransformSet::new_from_str`], which applies in both directions, e.g.
+    /// `("_1 ^ {-_lit2}", "1 / _1 ^ _lit2 if _lit2 > 0")`.
+    ///
+    /// # Panics
+    /// Panics if a guard is invalid.
+    pub fn with_identities(mut self, identities: &[(&str, &str)], direction: Direction) -> Self {
+        let mut rules = std::mem::take(&mut self.rules);
+        rules.extend(identities.iter().map(|(left, right)| {
+            let (right, guard) = split_guard(right);
+            let (pattern, out) = match direction {
+                Direction::LeftToRight => (*left, right),
+                Direction::RightToLeft => (right, *left),
+            };
+            let pattern = RuleExpr::new_rule_from_str(pattern);
+            let guard = guard.map(|guard| parse_guard(guard, &pattern));
+            Transformation {
+                pattern,
+                out: TransformOut::OutPattern(RuleExpr::new_rule_from_str(out)),
+                name: None,
+                priority: 0,
+                guard,
+            }
+        }));
+        self.with_replaced_rules(rules)
+    }
+
+    /// Appends a rule rewriting `pattern` into `out` for every `(pattern, out)` pair, e.g. for rules parsed at runtime
+    /// with [`RuleExpr::try_from_str`].
+    pub fn with_rules(mut self, rules: Vec<(RuleExpr, RuleExpr)>) -> Self {
+        let mut transformations = std::mem::take(&mut self.rules);
+        transformations.extend(rules.into_iter().map(|(pattern, out)| Transformation {
+            pattern,
+            out: TransformOut::OutPattern(out),
+            name: None,
+            priority: 0,
+            guard: None,
+        }));
+        self.with_replaced_rules(transformations)
+    }
+
+    /// Attaches human readable names to the rules. Every `(pattern, name)` pair names all the rules whose pattern is
+    /// `pattern`.
+    ///
+    /// # Panics
+    /// Panics if no rule has the pattern, to catch typos in the patterns.
+    pub fn with_names(mut self, names: &[(&str, &'a str)]) -> Self {
+        for (pattern, name) in names {
+            let pattern = RuleExpr::new_rule_from_str(pattern);
+            let mut found = false;
+            for transform in &mut self.rules {
+                if transform.pattern == pattern {
+                    transform.name = Some(Cow::Borrowed(name));
+                    found = true;
+                }
+            }
+            assert!(found, "no rule with pattern {}", pattern);
+        }
+        self
+    }
+
+    /// Sets the priorities of the rules. Every `(pattern, priority)` pair sets the priority of all the rules whose
+    /// pattern is `pattern`. Rules that are not listed keep their priority, which is `0` by default.
+    ///
+    /// # Panics
+    /// Panics if no rule has the pattern, to catch typos in the patterns.
+    pub fn with_priorities(mut self, priorities: &[(&str, i32)]) -> Self {
+        let mut rules = std::mem::take(&mut self.rules);
+        for (pattern, priority) in priorities {
+            let pattern = RuleExpr::new_rule_from_str(pattern);
+            let mut found = false;
+            for transform in &mut rules {
+                if transform.pattern == pattern {
+                    transform.priority = *priority;
+                    found = true;
+                }
+            }
+            assert!(found, "no rule with pattern {}", pattern);
+        }
+        self.with_replaced_rules(rules)
+    }
+
+    /// Returns the rules in the set, in application order.
+    pub fn rules(&self) -> &[Transformation<'a>] {
+        &self.rules
+    }
+
+    /// Returns the rules that can possibly match `expr` and that come at or after `start` in application order.
+    fn candidate_rules(
+        &self,
+        expr: &Expr,
+        start: usize,
+    ) -> impl Iterator<Item = (usize, &Transformation<'a>)> {
+        let candidates = self.index.candidates(expr);
+        let first = candidates.partition_point(|&i| i < start);
+        candidates[first..]
+            .iter()
+            .map(move |&i| (i, &self.rules[i]))
     }
 
     pub fn apply_rules(&self, expr: &Expr) -> Expr {
-        let mut expr = expr.clone();
+        self.try_apply_rules(expr).unwrap_or_else(|| expr.clone())
+    }
+
+    /// Same as `apply_rules` except that an error is returned if the max number of iterations is exceeded and the
+    /// policy is [`IterationLimitPolicy::Error`].
+    pub fn apply_rules_checked(&self, expr: &Expr) -> Result<Expr, IterationLimitExceeded> {
+        Ok(self
+            .apply_rules_until_fixpoint(expr, None)?
+            .unwrap_or_else(|| expr.clone()))
+    }
+
+    /// Same as `apply_rules` except that `None` is returned if no rule modified the expression.
+    pub fn try_apply_rules(&self, expr: &Expr) -> Option<Expr> {
+        self.apply_rules_until_fixpoint(expr, None)
+            .unwrap_or_else(|err| {
+                log::warn!("{}, exiting immediately", err);
+                Some(err.best)
+            })
+    }
+
+    /// Applies the rules until none of them matches. Returns `None` if no rule modified the expression.
+    ///
+    /// If `clean` is set, it holds the paths of the sub-expressions that are already transformed and is updated after
+    /// every rewrite, see [`RuleTransformSet::apply_rules_incremental`].
+    fn apply_rules_until_fixpoint(
+        &self,
+        expr: &Expr,
+        mut clean: Option<&mut Vec<ExprPath>>,
+    ) -> Result<Option<Expr>, IterationLimitExceeded> {
+        let mut transformed: Option<Expr> = None;
         let mut i = 0;
         loop {
             let mut last_iter_transformed = false;
 
-            for transform in &self.rules {
-                // match pattern
-                let match_res = transform.pattern.match_expr(&expr);
-                if match_res.matches {
-                    last_iter_transformed = true;
+            // The candidates depend on the shape of the expression which changes after every rewrite.
+            let mut next_rule = 0;
+            loop {
+                let current = transformed.as_ref().unwrap_or(expr);
+                let (rule_i, transform) = match self.candidate_rules(current, next_rule).next() {
+                    Some(candidate) => candidate,
+                    None => break,
+                };
+                next_rule = rule_i + 1;
+
+                let (matched, res) = profile::timed(transform, || {
+                    // match pattern
+                    let match_res = transform.match_expr(current, self.tolerance);
+                    if !match_res.matches {
+                        return (false, None);
+                    }
+                    log::trace!("applying {} to {}", transform, current);
 
                     // write output
-                    match &transform.out {
-                        TransformOut::OutPattern(out) => {
-                            expr = out.write_expr(&match_res.matched_exprs)
+                    let res = match &transform.out {
+                        TransformOut::OutPattern(out) => out.write_match(&match_res),
+                        TransformOut::OutHandler(handler) => handler(&match_res),
+                    };
+                    (true, res.map(|res| self.checked(transform, current, res)))
+                });
+                if matched {
+                    // if handler returned `None`, no change happened
+                    last_iter_transformed = res.is_some();
+                    if let Some(res) = res {
+                        transformed = Some(res);
+                        if let Some(clean) = clean.as_deref_mut() {
+                            *clean = carry_clean(transform, clean);
                         }
-                        TransformOut::OutHandler(handler) => match handler(&match_res) {
-                            Some(res) => expr = res,
-                            None => last_iter_transformed = false, // if handler returned `None`, no change happened
-                        },
                     }
                 }
             }
 
             if !last_iter_transformed {
-                break expr;
-            } else if i > MAX_ITERATIONS_PER_APPLY {
-                log::warn!("Exceeded MAX_ITERATIONS_PER_APPLY, exiting immediately");
-                break expr;
+                break Ok(transformed);
+            } else if i > self.max_iterations {
+                let best =
+                    transformed.expect("the expression was transformed in the last iteration");
+                self.check_iteration_limit(expr, &best)?;
+                break Ok(Some(best));
             }
 
             i += 1;
         }
     }
 
+    /// Handles exceeding the max number of iterations while applying the rules to `expr` according to the
+    /// [`IterationLimitPolicy`]. `best` is the expression of the last iteration.
+    fn check_iteration_limit(
+        &self,
+        expr: &Expr,
+        best: &Expr,
+    ) -> Result<(), IterationLimitExceeded> {
+        match self.on_iteration_limit {
+            IterationLimitPolicy::Error => Err(IterationLimitExceeded {
+                limit: self.max_iterations,
+                best: best.clone(),
+            }),
+            IterationLimitPolicy::DebugPanic if cfg!(debug_assertions) => {
+                panic!(
+                    "exceeded {} iterations while applying rules to {}",
+                    self.max_iterations, expr
+                )
+            }
+            IterationLimitPolicy::BestSoFar | IterationLimitPolicy::DebugPanic => {
+                log::warn!(
+                    "Exceeded {} iterations per apply, exiting immediately",
+                    self.max_iterations
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Applies the rules on the root of `expr`. The children of `expr` should already have been transformed.
+    ///
+    /// If the root is rewritten, only the newly created nodes are transformed again, bottom up. Subtrees that were
+    /// carried over from the original expression through a wildcard are already transformed and are not walked again.
+    /// The root is then transformed again if one of its children changed, e.g. `(x ^ 0.5) ^ 2` is rewritten to
+    /// `x ^ (0.5 * 2)` which is `x ^ 1` once the exponent is folded, and then `x`.
+    pub fn apply_rules_incremental(&self, expr: &mut Expr) {
+        if let Err(err) = self.apply_rules_incremental_changed(expr) {
+            log::warn!("{}, exiting immediately", err);
+        }
+    }
+
+    /// Same as `apply_rules_incremental` except that an error is returned if the max number of iterations is exceeded
+    /// and the policy is [`IterationLimitPolicy::Error`]. `expr` is then left as the expression of the last iteration.
+    pub fn apply_rules_incremental_checked(
+        &self,
+        expr: &mut Expr,
+    ) -> Result<(), IterationLimitExceeded> {
+        match self.apply_rules_incremental_changed(expr) {
+            Ok(_) => Ok(()),
+            // the error of a sub-expression only holds the sub-expression
+            Err(err) => Err(IterationLimitExceeded {
+                best: expr.clone(),
+                ..err
+            }),
+        }
+    }
+
+    /// Same as [`RuleTransformSet::apply_rules_incremental_checked`] but returns whether `expr` changed.
+    fn apply_rules_incremental_changed(
+        &self,
+        expr: &mut Expr,
+    ) -> Result<bool, IterationLimitExceeded> {
+        let mut changed = false;
+        for _ in 0..=self.max_iterations {
+            let mut clean = child_paths(expr);
+            let transformed = match self.apply_rules_until_fixpoint(expr, Some(&mut clean)) {
+                Ok(transformed) => transformed,
+                Err(err) => {
+                    *expr = err.best.clone();
+                    return Err(err);
+                }
+            };
+            match transformed {
+                Some(transformed) => *expr = transformed,
+                None => return Ok(changed),
+            }
+            changed = true;
+            if !self.reapply_children(expr, &mut ExprPath::new(), &clean)? {
+                return Ok(true);
+            }
+        }
+        self.check_iteration_limit(expr, expr)?;
+        Ok(changed)
+    }
+
+    /// Transforms the children of the expression at `path` that are not `clean`. Returns whether one of them changed.
+    fn reapply_children(
+        &self,
+        expr: &mut Expr,
+        path: &mut ExprPath,
+        clean: &[ExprPath],
+    ) -> Result<bool, IterationLimitExceeded> {
+        let mut changed = false;
+        for (i, child) in expr.children_mut().into_iter().enumerate() {
+            path.push(i);
+            if !is_clean(clean, path) {
+                // bottom up, so that the rules see the transformed children
+                changed |= self.reapply_children(child, path, clean)?;
+                changed |= self.apply_rules_incremental_changed(child)?;
+            }
+            path.pop();
+        }
+        Ok(changed)
+    }
+
+    /// Applies the rule called `rule` exactly once to the sub-expression of `expr` at `path`, e.g. to let the user
+    /// simplify an expression step by step. The name of a rule is its [`fmt::Display`], i.e. its human readable name
+    /// or its pattern if it is unnamed. If several rules have the name, the first one that applies is used.
+    pub fn apply_rule_at(
+        &self,
+        expr: &Expr,
+        rule: &str,
+        path: &[usize],
+    ) -> Result<Expr, ApplyRuleError> {
+        let target = expr
+            .get(path)
+            .ok_or_else(|| ApplyRuleError::InvalidPath(path.to_vec()))?;
+        let mut rules = self
+            .rules
+            .iter()
+            .filter(|transform| transform.to_string() == rule)
+            .peekable();
+        if rules.peek().is_none() {
+            return Err(ApplyRuleError::UnknownRule(rule.to_string()));
+        }
+
+        let rewritten = rules
+            .find_map(|transform| {
+                let match_res = transform.match_expr(target, self.tolerance);
+                if !match_res.matches {
+                    return None;
+                }
+                let res = match &transform.out {
+                    TransformOut::OutPattern(out) => out.write_match(&match_res)?,
+                    TransformOut::OutHandler(handler) => handler(&match_res)?,
+                };
+                Some(self.checked(transform, target, res))
+            })
+            .ok_or(ApplyRuleError::NoMatch)?;
+        let mut res = expr.clone();
+        res.replace_at(path, rewritten);
+        Ok(res)
+    }
+
     /// Same as `apply_rules` except every rule can be applied at most 1 time.
     /// Once a match is found, exits immediately. If no match is found, returns `None`.
     /// If a handler returns `None`, it is the same as no match.
     pub fn apply_rules_once(&self, expr: &Expr) -> Option<Expr> {
-        let mut expr = expr.clone();
-        for transform in &self.rules {
+        for (_, transform) in self.candidate_rules(expr, 0) {
             // match pattern
-            let match_res = transform.pattern.match_expr(&expr);
+            let match_res = transform.match_expr(expr, self.tolerance);
             if match_res.matches {
                 // write output
-                match &transform.out {
-                    TransformOut::OutPattern(out) => {
-                        expr = out.write_expr(&match_res.matched_exprs)
-                    }
-                    TransformOut::OutHandler(handler) => match handler(&match_res) {
-                        Some(res) => expr = res,
-                        None => continue, // if handler returned `None`, no change happened
-                    },
-                }
+                let res = match &transform.out {
+                    TransformOut::OutPattern(out) => out.write_match(&match_res),
+                    TransformOut::OutHandler(handler) => handler(&match_res),
+                };
+                let res = match res {
+                    Some(res) => res,
+                    None => continue, // if nothing was written, no change happened
+                };
 
-                return Some(expr);
+                return Some(self.checked(transform, expr, res));
             }
         }
 
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+
+    fn parse(input: &str) -> Expr {
+        Parser::from(Token::lexer(input).spanned()).parse()
+    }
+
+    #[test]
+    fn test_rule_index_candidates() {
+        let transforms = RuleTransformSet::new_from_str(
+            &[
+                ("_1 + 0", "_1"),
+                ("_1 * 1", "_1"),
+                ("_nonlit1", "_nonlit1"),
+                ("_lit1", "_lit1"),
+                ("_1 + _1", "2 * _1"),
+            ],
+            &[],
+        );
+
+        let candidates = |input: &str| -> Vec<usize> {
+            transforms
+                .candidate_rules(&parse(input), 0)
+                .map(|(i, _)| i)
+                .collect()
+        };
+        assert_eq!(candidates("x + 1"), vec![0, 2, 4]);
+        assert_eq!(candidates("x * 1"), vec![1, 2]);
+        assert_eq!(candidates("2"), vec![2, 3]);
+        assert_eq!(candidates("x"), vec![2]);
+    }
+
+    #[test]
+    fn test_rule_names() {
+        let transforms = RuleTransformSet::new_from_str(&[("_1 + 0", "_1"), ("_1 * 1", "_1")], &[])
+            .with_names(&[("_1 * 1", "multiplication by one")]);
+        let names: Vec<String> = transforms
+            .rules()
+            .iter()
+            .map(|rule| rule.to_string())
+            .collect();
+        assert_eq!(names, vec!["(_1 + 0)", "multiplication by one"]);
+        assert_eq!(transforms.rules()[0].name(), None);
+    }
+
+    #[test]
+    fn test_identities() {
+        let identities = [("_1 ^ -1", "1 / _1")];
+        let forward = RuleTransformSet::new_from_str(&[], &[])
+            .with_identities(&identities, Direction::LeftToRight);
+        let backward = RuleTransformSet::new_from_str(&[], &[])
+            .with_identities(&identities, Direction::RightToLeft);
+        assert_eq!(forward.apply_rules(&parse("x ^ -1")), parse("1 / x"));
+        assert_eq!(backward.apply_rules(&parse("1 / x")), parse("x ^ -1"));
+        assert_eq!(forward.apply_rules(&parse("1 / x")), parse("1 / x"));
+
+        let identities = [("_1 ^ {-_lit2}", "1 / _1 ^ _lit2 if _lit2 > 0")];
+        let forward = RuleTransformSet::new_from_str(&[], &[])
+            .with_identities(&identities, Direction::LeftToRight);
+        let backward = RuleTransformSet::new_from_str(&[], &[])
+            .with_identities(&identities, Direction::RightToLeft);
+        assert_eq!(forward.apply_rules(&parse("x ^ -2")), parse("1 / x ^ 2"));
+        assert_eq!(forward.apply_rules(&parse("x ^ 2")), parse("x ^ 2"));
+        assert_eq!(backward.apply_rules(&parse("1 / x ^ 2")), parse("x ^ -2"));
+        assert_eq!(
+            backward.apply_rules(&parse("1 / x ^ -2")),
+            parse("1 / x ^ -2")
+        );
+    }
+
+    #[test]
+    fn test_apply_rules_incremental() {
+        let transforms = RuleTransformSet::new_from_str(
+            &[
+                ("(_1 ^ _2) ^ _3", "_1 ^ (_2 * _3)"),
+                ("0.5 * 2", "1"),
+                ("_1 ^ 1", "_1"),
+            ],
+            &[],
+        );
+        // the root is transformed again once its new child is
+        let mut expr = parse("(x ^ 0.5) ^ 2");
+        transforms.apply_rules_incremental(&mut expr);
+        assert_eq!(expr, parse("x"));
+        // the children are assumed to be transformed already
+        let mut expr = parse("f((x ^ 0.5) ^ 2, y ^ 1)");
+        transforms.apply_rules_incremental(&mut expr);
+        assert_eq!(expr, parse("f((x ^ 0.5) ^ 2, y ^ 1)"));
+    }
+
+    #[test]
+    fn test_apply_rules_with_index() {
+        let transforms = RuleTransformSet::new_from_str(&[("_1 + 0", "_1"), ("_1 * 1", "_1")], &[]);
+        assert_eq!(transforms.apply_rules(&parse("(x * 1) + 0")), parse("x"));
+    }
+
+    #[test]
+    fn test_priorities() {
+        let transforms = RuleTransformSet::new_from_str(
+            &[("_1 * 1", "_1"), ("_1 * _2", "_2 * _1"), ("_1 + 0", "_1")],
+            &[],
+        );
+        assert_eq!(
+            transforms.apply_rules_once(&parse("x * 1")),
+            Some(parse("x"))
+        );
+
+        let transforms = transforms.with_priorities(&[("_1 * _2", 1)]);
+        assert_eq!(
+            transforms.apply_rules_once(&parse("x * 1")),
+            Some(parse("1 * x"))
+        );
+        let patterns: Vec<String> = transforms
+            .rules()
+            .iter()
+            .map(|rule| rule.to_string())
+            .collect();
+        assert_eq!(patterns, vec!["(_1 * _2)", "(_1 * 1)", "(_1 + 0)"]);
+        assert_eq!(transforms.rules()[0].priority(), 1);
+    }
+
+    #[test]
+    fn test_check_soundness() {
+        let transforms =
+            RuleTransformSet::new_from_str(&[("_1 * _1", "2 * _1"), ("_1 + _1", "2 * _1")], &[])
+                .with_soundness_checks(true);
+        let check = |rule: usize, before: &str| {
+            let before = parse(before);
+            let after = transforms.apply_rules_once(&before).unwrap();
+            check_soundness(&transforms.rules()[rule], &before, &after)
+        };
+        assert!(!check(0, "x * x"));
+        assert!(check(1, "x + x"));
+        // undefined functions cannot be evaluated
+        assert!(check(0, "f(x) * f(x)"));
+    }
+
+    /// Rules that rewrite each other's output forever.
+    fn looping_rules() -> RuleTransformSet<'static> {
+        RuleTransformSet::new_from_str(&[("_1 + 1", "1 + _1"), ("1 + _1", "_1 + 1")], &[])
+    }
+
+    #[test]
+    fn test_iteration_limit() {
+        let expr = parse("x + 1");
+        let best_so_far = looping_rules().with_max_iterations(10, IterationLimitPolicy::BestSoFar);
+        assert_eq!(best_so_far.apply_rules_checked(&expr), Ok(parse("x + 1")));
+
+        let error = looping_rules().with_max_iterations(10, IterationLimitPolicy::Error);
+        let err = error.apply_rules_checked(&expr).unwrap_err();
+        assert_eq!(err.limit, 10);
+        assert_eq!(err.best, parse("x + 1"));
+        // infallible methods return the best result so far
+        assert_eq!(error.apply_rules(&expr), parse("x + 1"));
+
+        // settings are kept when rules are added
+        let error = error.with_identities(&[("_1 * 0", "0")], Direction::LeftToRight);
+        assert!(error.apply_rules_checked(&expr).is_err());
+
+        // the rewritten root has a new child that loops
+        let error = error.with_identities(&[("_1 * 3", "(_1 + 1) * 2")], Direction::LeftToRight);
+        let mut expr = parse("x * 3");
+        let err = error
+            .apply_rules_incremental_checked(&mut expr)
+            .unwrap_err();
+        assert_eq!(err.limit, 10);
+        assert_eq!(err.best, parse("(x + 1) * 2"));
+        assert_eq!(expr, parse("(x + 1) * 2"));
+        let mut expr = parse("x + 1");
+        best_so_far
+            .apply_rules_incremental_checked(&mut expr)
+            .unwrap();
+        assert_eq!(expr, parse("x + 1"));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "exceeded 10 iterations")]
+    fn test_iteration_limit_debug_panic() {
+        looping_rules()
+            .with_max_iterations(10, IterationLimitPolicy::DebugPanic)
+            .apply_rules(&parse("x + 1"));
+    }
+
+    #[test]
+    fn test_apply_rule_at() {
+        let transforms = RuleTransformSet::new_from_str(&[("_1 + 0", "_1"), ("_1 * 1", "_1")], &[])
+            .with_names(&[("_1 * 1", "multiplication by one")]);
+        let expr = parse("(x * 1) + (y * 1)");
+        assert_eq!(
+            transforms.apply_rule_at(&expr, "multiplication by one", &[1]),
+            Ok(parse("(x * 1) + y"))
+        );
+        assert_eq!(
+            transforms.apply_rule_at(&parse("x + 0"), "(_1 + 0)", &[]),
+            Ok(parse("x"))
+        );
+        assert_eq!(
+            transforms.apply_rule_at(&expr, "multiplication by one", &[]),
+            Err(ApplyRuleError::NoMatch)
+        );
+        assert_eq!(
+            transforms.apply_rule_at(&expr, "power rule", &[]),
+            Err(ApplyRuleError::UnknownRule("power rule".to_string()))
+        );
+        assert_eq!(
+            transforms.apply_rule_at(&expr, "multiplication by one", &[2]),
+            Err(ApplyRuleError::InvalidPath(vec![2]))
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! Cheap cleanup of the shapes produced by [`crate::transformations::derivative`]. Running this before
+//! [`crate::transformations::simplify::Simplify`] removes most of the trivial nodes (e.g. `1 * u'` or `0 + v`) with a
+//! small rule set so that the general simplifier has less work to do.
+
+use crate::parser::{walk_expr, Expr, ExprVisitor};
+use crate::transformations::simplify::{cancel, contains_undefined};
+use crate::transformations::RuleTransformSet;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref CLEANUP_TRANSFORMS: RuleTransformSet<'static> = RuleTransformSet::new_from_str(&[
+        // derivatives of constants and of the variable itself
+        ("0 + _1", "_1"),
+        ("_1 + 0", "_1"),
+        ("_1 - 0", "_1"),
+        ("0 - _1", "-_1"),
+        ("1 * _1", "_1"),
+        ("_1 * 1", "_1"),
+        ("-0", "0"),
+        // power rule, e.g. x ^ (1 - 1)
+        ("_1 ^ 1", "_1"),
+        // exponent of the power rule, e.g. 3 - 1
+        ("_lit1 - _lit2", "{_lit1 - _lit2}"),
+    ], &[
+        // unless the dropped operand is undefined, e.g. `0 * (1 / 0)` is undefined
+        ("0 * _1", &|res| cancel(res, Expr::Literal(0.0))),
+        ("_1 * 0", &|res| cancel(res, Expr::Literal(0.0))),
+        ("_1 ^ 0", &|res| cancel(res, Expr::Literal(1.0))),
+        // unless the denominator is 0 or undefined, e.g. `0 / 0` is undefined
+        ("0 / _1", &|res| match res.matched_exprs[&1] {
+            Expr::Literal(num) if *num == 0.0 => None,
+            denominator => (!contains_undefined(denominator)).then_some(Expr::Literal(0.0)),
+        }),
+    ]).with_names(&[
+        ("0 + _1", "derivative of a constant term"),
+        ("_1 + 0", "derivative of a constant term"),
+        ("_1 - 0", "derivative of a constant term"),
+        ("0 - _1", "derivative of a constant term"),
+        ("0 * _1", "derivative of a constant factor"),
+        ("_1 * 0", "derivative of a constant factor"),
+        ("1 * _1", "derivative of the variable"),
+        ("_1 * 1", "derivative of the variable"),
+        ("_1 ^ 0", "power rule"),
+        ("_1 ^ 1", "power rule"),
+        ("_lit1 - _lit2", "power rule"),
+    ]);
+}
+
+pub struct DerivativeCleanup;
+
+impl ExprVisitor for DerivativeCleanup {
+    fn visit(&mut self, expr: &mut Expr) {
+        walk_expr(expr, self);
+
+        // only the newly created ast nodes need to be visited again
+        CLEANUP_TRANSFORMS.apply_rules_incremental(expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use crate::transformations::derivative::derivative;
+    use expect_test::expect;
+    use logos::Logos;
+
+    #[test]
+    fn test_cleanup_derivative() {
+        let expr = Parser::from(Token::lexer("x ^ 3 + 2 * x - 5").spanned()).parse();
+        let mut res = derivative(&expr, "x");
+        DerivativeCleanup.visit(&mut res);
+        expect![[r#"((3 * (x ^ 2)) + 2)"#]].assert_eq(&res.to_string());
+
+        let expr = Parser::from(Token::lexer("x * y").spanned()).parse();
+        let mut res = derivative(&expr, "x");
+        DerivativeCleanup.visit(&mut res);
+        expect![[r#"y"#]].assert_eq(&res.to_string());
+    }
+
+    #[test]
+    fn test_cleanup_zero_numerator() {
+        let cleanup = |input: &str| {
+            let mut expr = Parser::from(Token::lexer(input).spanned()).parse();
+            DerivativeCleanup.visit(&mut expr);
+            expr.to_string()
+        };
+        assert_eq!(cleanup("0 / x"), "0");
+        assert_eq!(cleanup("0 / 0"), "(0 / 0)");
+        assert_eq!(cleanup("0 / (3 - 3)"), "(0 / 0)");
+        assert_eq!(cleanup("0 / (1 / 0)"), "(0 / (1 / 0))");
+        assert_eq!(cleanup("(1 / 0) * 0"), "((1 / 0) * 0)");
+    }
+}
@@ -0,0 +1,454 @@
+//! Rewrite rules that are defined at runtime, e.g. by the user of a frontend or in a rule file, instead of being
+//! hard-coded.
+//!
+//! Rules are written one per line as `pattern => output` in the same syntax as the built-in rules, e.g.
+//! `_1 * _1 => _1 ^ 2`. A rule can be preceded by a name and followed by a guard, and `#` starts a comment:
+//!
+//! ```text
+//! # rules for squares
+//! square: _1 * _1 => _1 ^ 2
+//! positive power: _1 ^ _lit2 => _1 * _1 ^ {_lit2 - 1} if _lit2 > 1
+//! ```
+//!
+//! A guard compares two expressions over the literal wildcards of the pattern with `==`, `!=`, `<`, `<=`, `>` or
+//! `>=`, see [`Guard`].
+
+use crate::diagnostic::{Diagnostic, DiagnosticCode, Diagnostics, Span};
+use crate::rule::parser::RuleExpr;
+use crate::transformations::{Comparison, Guard, RuleTransformSet, TransformOut, Transformation};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+
+/// Separates the pattern from the output of a rule.
+pub const RULE_SEPARATOR: &str = "=>";
+/// Separates the name of a rule from the rule.
+pub const NAME_SEPARATOR: char = ':';
+/// Separates the output of a rule from its guard.
+pub const GUARD_SEPARATOR: &str = " if ";
+/// Starts a comment that runs to the end of the line.
+pub const COMMENT: char = '#';
+
+/// The comparison operators of a guard. Operators that are a prefix of another operator come last.
+const COMPARISONS: &[(&str, Comparison)] = &[
+    ("==", Comparison::Equal),
+    ("!=", Comparison::NotEqual),
+    ("<=", Comparison::LessOrEqual),
+    (">=", Comparison::GreaterOrEqual),
+    ("<", Comparison::Less),
+    (">", Comparison::Greater),
+];
+
+/// An invalid rule in the input of [`parse_rules`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleError {
+    /// The line of the rule, starting at 1.
+    pub line: usize,
+    /// The errors in the rule. The spans are relative to the start of the line.
+    pub diagnostics: Diagnostics,
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<_> = self
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect();
+        write!(
+            f,
+            "invalid rule on line {}: {}",
+            self.line,
+            messages.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+/// An error while loading a rule file with [`RuleTransformSet::from_file`].
+#[derive(Debug)]
+pub enum RuleFileError {
+    /// The file could not be read.
+    Io(std::io::Error),
+    /// The file contains an invalid rule.
+    Rule(RuleError),
+}
+
+impl fmt::Display for RuleFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleFileError::Io(err) => write!(f, "cannot read rule file: {}", err),
+            RuleFileError::Rule(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for RuleFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RuleFileError::Io(err) => Some(err),
+            RuleFileError::Rule(err) => Some(err),
+        }
+    }
+}
+
+impl From<std::io::Error> for RuleFileError {
+    fn from(err: std::io::Error) -> Self {
+        RuleFileError::Io(err)
+    }
+}
+
+impl From<RuleError> for RuleFileError {
+    fn from(err: RuleError) -> Self {
+        RuleFileError::Rule(err)
+    }
+}
+
+impl RuleTransformSet<'static> {
+    /// Parses a rule document in the format described in the [module documentation](self). The rules are applied in
+    /// the order they are written.
+    pub fn from_str_document(text: &str) -> Result<Self, RuleError> {
+        let mut rules = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let rule = parse_line(line).map_err(|diagnostics| RuleError {
+                line: i + 1,
+                diagnostics,
+            })?;
+            rules.extend(rule);
+        }
+        Ok(RuleTransformSet::new_from_str(&[], &[]).with_replaced_rules(rules))
+    }
+
+    /// Reads and parses the rule document at `path`, see [`RuleTransformSet::from_str_document`].
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, RuleFileError> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(Self::from_str_document(&text)?)
+    }
+}
+
+/// Parses the rules in `text`, one per line. Empty lines and comments are ignored. Same as
+/// [`RuleTransformSet::from_str_document`].
+pub fn parse_rules(text: &str) -> Result<RuleTransformSet<'static>, RuleError> {
+    RuleTransformSet::from_str_document(text)
+}
+
+/// Parses a line of a rule document. Returns `None` if the line does not contain a rule.
+fn parse_line(line: &str) -> Result<Option<Transformation<'static>>, Diagnostics> {
+    let line = match line.find(COMMENT) {
+        Some(comment) => &line[..comment],
+        None => line,
+    };
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let (name, rule_start) = match line.find(NAME_SEPARATOR) {
+        Some(separator) => {
+            let name = line[..separator].trim();
+            if name.is_empty() {
+                let mut diagnostics = Diagnostics::new();
+                diagnostics.push(Diagnostic::error(
+                    DiagnosticCode::UnexpectedToken,
+                    "expected a rule name before `:`",
+                    0..separator + 1,
+                ));
+                return Err(diagnostics);
+            }
+            (Some(name.to_string()), separator + 1)
+        }
+        None => (None, 0),
+    };
+    let (rule, guard) = match line[rule_start..].find(GUARD_SEPARATOR) {
+        Some(separator) => {
+            let guard_start = rule_start + separator + GUARD_SEPARATOR.len();
+            (
+                &line[rule_start..rule_start + separator],
+                Some((guard_start, &line[guard_start..])),
+            )
+        }
+        None => (&line[rule_start..], None),
+    };
+
+    let (pattern, out) =
+        parse_rule(rule).map_err(|diagnostics| offset_spans(&diagnostics, rule_start))?;
+    let guard = match guard {
+        Some((guard_start, guard)) => Some(
+            parse_guard(guard, &pattern)
+                .map_err(|diagnostics| offset_spans(&diagnostics, guard_start))?,
+        ),
+        None => None,
+    };
+    Ok(Some(Transformation {
+        pattern,
+        out: TransformOut::OutPattern(out),
+        name: name.map(Cow::Owned),
+        priority: 0,
+        guard,
+    }))
+}
+
+/// Parses the guard `left op right` of a rule with `pattern`.
+pub(super) fn parse_guard(guard: &str, pattern: &RuleExpr) -> Result<Guard, Diagnostics> {
+    let comparison = COMPARISONS
+        .iter()
+        .find_map(|(op, comparison)| guard.find(op).map(|i| (i, op.len(), *comparison)));
+    let (op_start, op_len, op) = match comparison {
+        Some(comparison) => comparison,
+        None => {
+            let mut diagnostics = Diagnostics::new();
+            diagnostics.push(Diagnostic::error(
+                DiagnosticCode::UnexpectedToken,
+                "expected a comparison such as `_lit1 > 0`",
+                0..guard.len(),
+            ));
+            return Err(diagnostics);
+        }
+    };
+    let right_start = op_start + op_len;
+
+    let left = RuleExpr::try_from_str(&guard[..op_start])?;
+    let right = RuleExpr::try_from_str(&guard[right_start..])
+        .map_err(|diagnostics| offset_spans(&diagnostics, right_start))?;
+    let mut diagnostics = unbound_wildcards(pattern, &left, 0..op_start);
+    for diagnostic in &unbound_wildcards(pattern, &right, right_start..guard.len()) {
+        diagnostics.push(diagnostic.clone());
+    }
+    if diagnostics.is_empty() {
+        Ok(Guard { left, op, right })
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// Parses a single rule `pattern => output` into the pattern and the output.
+pub fn parse_rule(rule: &str) -> Result<(RuleExpr, RuleExpr), Diagnostics> {
+    let separator = match rule.find(RULE_SEPARATOR) {
+        Some(separator) => separator,
+        None => {
+            let mut diagnostics = Diagnostics::new();
+            diagnostics.push(Diagnostic::error(
+                DiagnosticCode::UnexpectedToken,
+                format!("expected `pattern {} output`", RULE_SEPARATOR),
+                0..rule.len(),
+            ));
+            return Err(diagnostics);
+        }
+    };
+    let out_start = separator + RULE_SEPARATOR.len();
+
+    let pattern = RuleExpr::try_from_str(&rule[..separator])?;
+    let out = RuleExpr::try_from_str(&rule[out_start..])
+        .map_err(|diagnostics| offset_spans(&diagnostics, out_start))?;
+
+    let mut diagnostics = unbound_wildcards(&pattern, &out, out_start..rule.len());
+    for diagnostic in &non_literal_computations(&pattern, &out, out_start..rule.len()) {
+        diagnostics.push(diagnostic.clone());
+    }
+    if diagnostics.is_empty() {
+        Ok((pattern, out))
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// Reports the wildcards of `expr` that are not in `pattern`, at `span`. An output or a guard can only be written if
+/// all its wildcards are filled in by the pattern.
+fn unbound_wildcards(pattern: &RuleExpr, expr: &RuleExpr, span: Span) -> Diagnostics {
+    let (mut bound_exprs, mut bound_funcs) = (BTreeSet::new(), BTreeSet::new());
+    pattern.collect_wildcards(&mut bound_exprs, &mut bound_funcs);
+    let (mut exprs, mut funcs) = (BTreeSet::new(), BTreeSet::new());
+    expr.collect_wildcards(&mut exprs, &mut funcs);
+    let mut diagnostics = Diagnostics::new();
+    for id in exprs.difference(&bound_exprs) {
+        diagnostics.push(Diagnostic::error(
+            DiagnosticCode::UnboundWildcard,
+            format!("wildcard {} is not in the pattern", id),
+            span.clone(),
+        ));
+    }
+    for id in funcs.difference(&bound_funcs) {
+        diagnostics.push(Diagnostic::error(
+            DiagnosticCode::UnboundWildcard,
+            format!("wildcard _f{} is not in the pattern", id),
+            span.clone(),
+        ));
+    }
+    diagnostics
+}
+
+/// Reports the wildcards inside the computed expressions of `out` (e.g. `{_lit1 + 1}`) that do not match literals in
+/// `pattern`, at `span`. Computed expressions are evaluated when the rule is applied, so they can only use literals.
+fn non_literal_computations(pattern: &RuleExpr, out: &RuleExpr, span: Span) -> Diagnostics {
+    let mut literals = BTreeSet::new();
+    pattern.collect_literal_wildcards(&mut literals);
+    let mut computed = Vec::new();
+    out.collect_computed(&mut computed);
+    let (mut exprs, mut funcs) = (BTreeSet::new(), BTreeSet::new());
+    for expr in computed {
+        expr.collect_wildcards(&mut exprs, &mut funcs);
+    }
+    let mut diagnostics = Diagnostics::new();
+    for id in exprs.difference(&literals) {
+        diagnostics.push(Diagnostic::error(
+            DiagnosticCode::NonLiteralComputation,
+            format!(
+                "wildcard {} is computed but does not match a literal in the pattern",
+                id
+            ),
+            span.clone(),
+        ));
+    }
+    for id in funcs {
+        diagnostics.push(Diagnostic::error(
+            DiagnosticCode::NonLiteralComputation,
+            format!("wildcard _f{} is computed but is a function", id),
+            span.clone(),
+        ));
+    }
+    diagnostics
+}
+
+/// Moves the spans of `diagnostics` by `offset` bytes.
+fn offset_spans(diagnostics: &Diagnostics, offset: usize) -> Diagnostics {
+    let mut res = Diagnostics::new();
+    for diagnostic in diagnostics {
+        let mut diagnostic = diagnostic.clone();
+        diagnostic.span = diagnostic.span.start + offset..diagnostic.span.end + offset;
+        res.push(diagnostic);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::{ExprVisitor, Parser};
+    use crate::transformations::simplify::SimplifyWith;
+    use logos::Logos;
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = parse_rules("_1 * _1 => _1 ^ 2\n\nabs(abs(_1)) => abs(_1)").unwrap();
+        assert_eq!(rules.rules().len(), 2);
+
+        let mut expr = Parser::from(Token::lexer("(x + 1) * (x + 1)").spanned()).parse();
+        SimplifyWith(&rules).visit(&mut expr);
+        assert_eq!(expr.to_string(), "((1 + x) ^ 2)");
+    }
+
+    #[test]
+    fn test_parse_rule_errors() {
+        let err = parse_rules("_1 => _1\n_1 + => _1").err().unwrap();
+        assert_eq!(err.line, 2);
+        assert_eq!(
+            err.to_string(),
+            "invalid rule on line 2: unexpected token, expected a rule expression"
+        );
+
+        let messages = |rule| {
+            parse_rule(rule)
+                .unwrap_err()
+                .iter()
+                .map(|diagnostic| (diagnostic.message.clone(), diagnostic.span.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            messages("_1 + _1"),
+            [("expected `pattern => output`".to_string(), 0..7)]
+        );
+        assert_eq!(
+            messages("_1 => _1 )"),
+            [(
+                "unexpected token, expected the end of the rule".to_string(),
+                9..10
+            )]
+        );
+        assert_eq!(
+            messages("_1 * _2 => _f(_3)"),
+            [
+                ("wildcard 3 is not in the pattern".to_string(), 10..17),
+                ("wildcard _f0 is not in the pattern".to_string(), 10..17)
+            ]
+        );
+        assert_eq!(
+            messages("_1 + 1 => {_1 + 1}"),
+            [(
+                "wildcard 1 is computed but does not match a literal in the pattern".to_string(),
+                9..18
+            )]
+        );
+        assert!(parse_rule("_1 ^ {-_lit2} => 1 / _1 ^ {_lit2 + 0}").is_ok());
+    }
+
+    #[test]
+    fn test_rule_document() {
+        let document = "\
+# rules for squares
+square: _1 * _1 => _1 ^ 2   # the name is shown in the rule list
+
+positive power: _1 ^ _int2 => _1 * _1 ^ {_int2 - 1} if _int2 > 2
+";
+        let rules = RuleTransformSet::from_str_document(document).unwrap();
+        let names: Vec<_> = rules.rules().iter().map(|rule| rule.name()).collect();
+        assert_eq!(names, [Some("square"), Some("positive power")]);
+        assert_eq!(
+            rules.rules()[1].guard().map(|guard| guard.to_string()),
+            Some("_int2 > 2".to_string())
+        );
+
+        let parse = |input| Parser::from(Token::lexer(input).spanned()).parse();
+        assert_eq!(rules.apply_rules(&parse("x * x")), parse("x ^ 2"));
+        // the guard does not hold
+        assert_eq!(rules.apply_rules(&parse("y ^ 2")), parse("y ^ 2"));
+        assert_eq!(
+            rules.apply_rules_once(&parse("y ^ 3")),
+            Some(parse("y * y ^ 2"))
+        );
+    }
+
+    #[test]
+    fn test_rule_document_errors() {
+        let err = |document| {
+            let err = RuleTransformSet::from_str_document(document).err().unwrap();
+            let diagnostic = err.diagnostics.iter().next().unwrap();
+            (
+                err.line,
+                diagnostic.message.clone(),
+                diagnostic.span.clone(),
+            )
+        };
+        assert_eq!(
+            err(": _1 => _1"),
+            (1, "expected a rule name before `:`".to_string(), 0..1)
+        );
+        assert_eq!(
+            err("# comment\nname: _1 => _1 if _1"),
+            (
+                2,
+                "expected a comparison such as `_lit1 > 0`".to_string(),
+                18..20
+            )
+        );
+        assert_eq!(
+            err("name: _1 => _1 if _lit2 > 0"),
+            (1, "wildcard 2 is not in the pattern".to_string(), 18..24)
+        );
+    }
+
+    #[test]
+    fn test_rule_file() {
+        let path = std::env::temp_dir().join("derivative-calculator-test.rules");
+        std::fs::write(&path, "double negation: -(-_1) => _1\n").unwrap();
+        let rules = RuleTransformSet::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(rules.rules()[0].to_string(), "double negation");
+
+        assert!(matches!(
+            RuleTransformSet::from_file(&path),
+            Err(RuleFileError::Io(_))
+        ));
+    }
+}
@@ -1,110 +1,1771 @@
-//! Make expression more readable. For best result, pass expression through [`crate::transformations::Simplify`] before and after.
+//! Compute the derivative of an expression.
 
-use crate::parser::{BinOpKind, Expr, UnaryOpKind};
-use crate::{rule::MatchResult, transformations::RuleTransformSet};
+use crate::functions;
+use crate::limits::{ExprTooLarge, LimitKind, Limits};
+use crate::parser::{walk_expr, BinOpKind, Expr, ExprVisitor};
+use crate::transformations::simplify::Simplify;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::{Hash, Hasher};
 
+/// An error that occurred while computing a derivative.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DerivativeError {
+    /// The derivative of the sub-expression is not supported, e.g. `abs(x, 2)`.
+    Unsupported(Expr),
+    /// The derivative exceeded the limits.
+    TooLarge(ExprTooLarge),
+    /// The direction of a directional derivative does not have one component per variable.
+    DimensionMismatch { vars: usize, components: usize },
+}
+
+impl fmt::Display for DerivativeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DerivativeError::Unsupported(expr) => {
+                write!(f, "cannot compute the derivative of `{}`", expr)
+            }
+            DerivativeError::TooLarge(err) => write!(f, "{}", err),
+            DerivativeError::DimensionMismatch { vars, components } => write!(
+                f,
+                "the direction has {} component(s) but there are {} variable(s)",
+                components, vars
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DerivativeError {}
+
+impl From<ExprTooLarge> for DerivativeError {
+    fn from(err: ExprTooLarge) -> Self {
+        DerivativeError::TooLarge(err)
+    }
+}
+
+/// The number of lookups in a [`DerivativeCache`] that found a derivative (`hits`) and that did not (`misses`).
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+/// A derivative stored in a [`DerivativeCache`].
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    expr: Expr,
+    var: String,
+    derivative: Expr,
+}
+
+/// The derivatives of the sub-expressions that have already been differentiated, so that repeated sub-expressions
+/// (which are common in the output of the product and quotient rules) are only differentiated once. The cache can be
+/// reused across calls to [`try_partial_derivative_cached`].
+///
+/// Every sub-expression is stored along with its derivative, so the cache uses more memory than the expressions.
+#[derive(Debug, Clone, Default)]
+pub struct DerivativeCache {
+    /// The entries by the canonical hash of their expression. Entries with the same hash are compared structurally.
+    entries: HashMap<u64, Vec<CacheEntry>>,
+    stats: CacheStats,
+}
+
+impl DerivativeCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Removes all the derivatives and resets the statistics.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn get(&mut self, hash: u64, expr: &Expr, var: &str) -> Option<&Expr> {
+        let entry = self
+            .entries
+            .get(&hash)
+            .and_then(|entries| {
+                entries
+                    .iter()
+                    .find(|entry| entry.var == var && entry.expr == *expr)
+            })
+            .map(|entry| &entry.derivative);
+        match entry {
+            Some(_) => self.stats.hits += 1,
+            None => self.stats.misses += 1,
+        }
+        entry
+    }
+
+    fn insert(&mut self, hash: u64, expr: &Expr, var: &str, derivative: &Expr) {
+        self.entries.entry(hash).or_default().push(CacheEntry {
+            expr: expr.clone(),
+            var: var.to_string(),
+            derivative: derivative.clone(),
+        });
+    }
+}
+
+/// Computes the canonical hash of every sub-expression of `expr`, by address. Structurally equal expressions have the
+/// same hash. `0` and `-0` are considered equal as they are by [`PartialEq`].
+fn canonical_hashes(expr: &Expr) -> HashMap<*const Expr, u64> {
+    let mut hashes: HashMap<*const Expr, u64> = HashMap::new();
+    // sub-expressions are hashed after their children, i.e. when they are visited for the second time
+    let mut stack = vec![(expr, false)];
+    while let Some((expr, children_hashed)) = stack.pop() {
+        if !children_hashed {
+            stack.push((expr, true));
+            stack.extend(expr.children().into_iter().map(|child| (child, false)));
+            continue;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        match expr {
+            Expr::Literal(num) => {
+                0u8.hash(&mut hasher);
+                // normalize `-0` to `0`
+                (num + 0.0).to_bits().hash(&mut hasher);
+            }
+            Expr::Identifier(id) => {
+                1u8.hash(&mut hasher);
+                id.hash(&mut hasher);
+            }
+            Expr::Binary { op, .. } => {
+                2u8.hash(&mut hasher);
+                op.hash(&mut hasher);
+            }
+            Expr::Unary { op, .. } => {
+                3u8.hash(&mut hasher);
+                op.hash(&mut hasher);
+            }
+            Expr::Call { func, .. } => {
+                4u8.hash(&mut hasher);
+                func.hash(&mut hasher);
+            }
+            Expr::Error => 5u8.hash(&mut hasher),
+            Expr::Undefined => 6u8.hash(&mut hasher),
+            Expr::Integral { var, .. } => {
+                7u8.hash(&mut hasher);
+                var.hash(&mut hasher);
+            }
+            Expr::Vector(components) => {
+                8u8.hash(&mut hasher);
+                components.len().hash(&mut hasher);
+            }
+            Expr::Constant(constant) => {
+                9u8.hash(&mut hasher);
+                constant.hash(&mut hasher);
+            }
+        }
+        for child in expr.children() {
+            hashes[&(child as *const Expr)].hash(&mut hasher);
+        }
+        hashes.insert(expr as *const Expr, hasher.finish());
+    }
+    hashes
+}
+
+/// A pending step when computing a derivative.
+enum Task<'a> {
+    /// Compute the derivative of the expression and push it onto the result stack.
+    Differentiate(&'a Expr),
+    /// Same as `Differentiate` except that the cache is not looked up, because it does not contain the expression.
+    DifferentiateUncached(&'a Expr),
+    /// Pop the derivatives of the operands of the expression off the result stack and combine them into the
+    /// derivative of the expression.
+    Combine(&'a Expr),
+    /// Store the derivative on top of the result stack in the cache as the derivative of the expression.
+    Store(&'a Expr),
+}
+
+/// Creates the nodes of a derivative while keeping track of the number of nodes in the output. The count is an upper
+/// bound since the smart constructors (e.g. [`Expr::pow`]) may drop nodes.
+struct Builder {
+    nodes: usize,
+}
+
+impl Builder {
+    fn literal(&mut self, num: f64) -> Expr {
+        self.nodes += 1;
+        Expr::Literal(num)
+    }
+
+    fn binary(&mut self, left: Expr, op: BinOpKind, right: Expr) -> Expr {
+        self.nodes += 1;
+        Expr::Binary {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+        }
+    }
+
+    fn sum(&mut self, left: Expr, right: Expr) -> Expr {
+        self.nodes += 1;
+        left + right
+    }
+
+    fn difference(&mut self, left: Expr, right: Expr) -> Expr {
+        self.nodes += 1;
+        left - right
+    }
+
+    fn product(&mut self, left: Expr, right: Expr) -> Expr {
+        self.nodes += 1;
+        left * right
+    }
+
+    fn power(&mut self, base: Expr, exponent: Expr) -> Expr {
+        self.nodes += 1;
+        Expr::pow(base, exponent)
+    }
+
+    fn negation(&mut self, right: Expr) -> Expr {
+        self.nodes += 1;
+        -right
+    }
+
+    fn call(&mut self, func: &str, args: Vec<Expr>) -> Expr {
+        self.nodes += 1;
+        Expr::Call {
+            func: func.to_string(),
+            args,
+        }
+    }
+
+    fn integral(&mut self, integrand: Expr, var: &str, bounds: Option<(Expr, Expr)>) -> Expr {
+        self.nodes += 1;
+        Expr::Integral {
+            integrand: Box::new(integrand),
+            var: var.to_string(),
+            bounds: bounds.map(Box::new),
+        }
+    }
+
+    fn vector(&mut self, components: Vec<Expr>) -> Expr {
+        self.nodes += 1;
+        Expr::Vector(components)
+    }
+
+    fn error(&mut self) -> Expr {
+        self.nodes += 1;
+        Expr::Error
+    }
+
+    fn undefined(&mut self) -> Expr {
+        self.nodes += 1;
+        Expr::Undefined
+    }
+
+    /// Copies an operand of the original expression into the output.
+    fn copy(&mut self, expr: &Expr) -> Expr {
+        self.nodes += expr.node_count();
+        expr.clone()
+    }
+
+    /// Copies an operand of the original expression into the output with `ident` replaced by `value`.
+    fn substitute(&mut self, expr: &Expr, ident: &str, value: &Expr) -> Expr {
+        let res = expr.substitute(ident, value);
+        self.nodes += res.node_count();
+        res
+    }
+}
+
+/// Computes the derivative of `expr` with respect to `var`. All other identifiers are treated as constants.
+///
+/// The expression tree is traversed with an explicit work stack rather than recursion so that deeply nested
+/// expressions do not overflow the (small) wasm stack. Unsupported sub-expressions differentiate to [`Expr::Error`].
+#[must_use]
+pub fn derivative(expr: &Expr, var: &str) -> Expr {
+    partial_derivative(expr, var)
+}
+
+/// Same as [`derivative`] except that an error is returned for unsupported sub-expressions and as soon as the
+/// derivative exceeds `limits.max_output_nodes` nodes.
+pub fn derivative_with_limits(
+    expr: &Expr,
+    var: &str,
+    limits: &Limits,
+) -> Result<Expr, DerivativeError> {
+    try_partial_derivative(expr, var, limits)
+}
+
+/// Computes the `n`-th derivative of `expr` with respect to `var`, e.g. `6 * x` for the 2nd derivative of `x ^ 3`. The
+/// 0-th derivative is `expr` itself. Unlike [`derivative`], the result is simplified.
+///
+/// Returns [`Expr::Error`] if a derivative cannot be computed or exceeds the default [`Limits`], see
+/// [`derivative_n_with_limits`].
 #[must_use]
-pub fn derivative(expr: &Expr) -> Expr {
-    let transforms = RuleTransformSet::new_from_str(
-        &[("_lit1", "0")],
-        &[
+pub fn derivative_n(expr: &Expr, var: &str, n: u32) -> Expr {
+    derivative_n_with_limits(expr, var, n, &Limits::default()).unwrap_or(Expr::Error)
+}
+
+/// Same as [`derivative_n`] except that an error is returned for unsupported sub-expressions and as soon as a
+/// derivative exceeds `limits.max_output_nodes` nodes.
+///
+/// Every derivative is simplified before it is differentiated again, since the product and quotient rules would
+/// otherwise make the size grow exponentially with `n`. The derivatives stop being computed once they repeat, e.g.
+/// from the 1st derivative of `exp(x)` or once a polynomial reaches `0`, so that large `n` are cheap in that case.
+pub fn derivative_n_with_limits(
+    expr: &Expr,
+    var: &str,
+    n: u32,
+    limits: &Limits,
+) -> Result<Expr, DerivativeError> {
+    let n = n as usize;
+    let mut history = vec![expr.clone()];
+    while history.len() <= n {
+        let mut next = try_partial_derivative(history.last().unwrap(), var, limits)?;
+        Simplify.visit(&mut next);
+        limits.check_output_nodes(&next)?;
+        // the derivatives from `start` on repeat with a period of `history.len() - start`
+        if let Some(start) = history.iter().position(|prev| *prev == next) {
+            let period = history.len() - start;
+            return Ok(history.swap_remove(start + (n - start) % period));
+        }
+        history.push(next);
+    }
+    Ok(history.pop().expect("the history contains at least `expr`"))
+}
+
+/// Picks the variable to differentiate `expr` with respect to: `preferred` if `expr` contains it, and otherwise the
+/// only identifier of `expr`, e.g. `t` for `t ^ 2` when `x` is preferred. `preferred` is kept if `expr` has no
+/// identifiers or several, since the choice would be arbitrary.
+///
+/// This can be passed to [`crate::pipeline::PipelineOptions::with_variable_picker`].
+pub fn pick_variable(expr: &Expr, preferred: &str) -> String {
+    match expr.identifiers().as_slice() {
+        [only] if *only != preferred => only.to_string(),
+        _ => preferred.to_string(),
+    }
+}
+
+/// Computes the partial derivative of `expr` with respect to `var`. All other identifiers are treated as constants.
+#[must_use]
+pub fn partial_derivative(expr: &Expr, var: &str) -> Expr {
+    match differentiate(expr, var, &Limits::unlimited(), false, None) {
+        Ok(res) => res,
+        Err(err) => unreachable!("lenient derivative without limits cannot fail: {}", err),
+    }
+}
+
+/// Same as [`partial_derivative`] except that an error is returned for unsupported sub-expressions and as soon as the
+/// derivative exceeds `limits.max_output_nodes` nodes.
+pub fn try_partial_derivative(
+    expr: &Expr,
+    var: &str,
+    limits: &Limits,
+) -> Result<Expr, DerivativeError> {
+    differentiate(expr, var, limits, true, None)
+}
+
+/// Same as [`try_partial_derivative`] except that the derivatives of the sub-expressions are looked up in and added to
+/// `cache`.
+pub fn try_partial_derivative_cached(
+    expr: &Expr,
+    var: &str,
+    limits: &Limits,
+    cache: &mut DerivativeCache,
+) -> Result<Expr, DerivativeError> {
+    differentiate(expr, var, limits, true, Some(cache))
+}
+
+/// Computes the directional derivative `∇f · d` of `expr` along `direction`, where `vars[i]` is the variable for the
+/// `i`-th component of the direction. The result is simplified.
+///
+/// The direction is used as is and is not normalized. Returns [`DerivativeError::DimensionMismatch`] if `vars` and
+/// `direction` do not have the same length.
+pub fn directional_derivative(
+    expr: &Expr,
+    vars: &[&str],
+    direction: &[f64],
+    limits: &Limits,
+) -> Result<Expr, DerivativeError> {
+    if vars.len() != direction.len() {
+        return Err(DerivativeError::DimensionMismatch {
+            vars: vars.len(),
+            components: direction.len(),
+        });
+    }
+
+    let gradient = gradient(expr, limits)?;
+    let mut res = vars
+        .iter()
+        .zip(direction)
+        // the variables that do not appear in `expr` have no partial derivative
+        .filter_map(|(var, component)| Some((gradient.get(*var)?, component)))
+        .map(|(partial, component)| Expr::Binary {
+            left: Box::new(Expr::Literal(*component)),
+            op: BinOpKind::Asterisk,
+            right: Box::new(partial.clone()),
+        })
+        .reduce(|sum, term| Expr::Binary {
+            left: Box::new(sum),
+            op: BinOpKind::Plus,
+            right: Box::new(term),
+        })
+        .unwrap_or(Expr::Literal(0.0));
+    Simplify.visit(&mut res);
+    Ok(res)
+}
+
+/// Computes the total differential `∂f/∂x dx + ∂f/∂y dy + ...` of `expr` over all the identifiers in `expr`. The
+/// differentials `dx`, `dy`, ... are represented as identifiers. The result is simplified.
+///
+/// The partial derivatives are computed with [`try_partial_derivative`], so an error is returned for unsupported
+/// sub-expressions and as soon as a partial derivative exceeds `limits.max_output_nodes` nodes.
+pub fn total_differential(expr: &Expr, limits: &Limits) -> Result<Expr, DerivativeError> {
+    let mut terms = Vec::new();
+    for var in expr.identifiers() {
+        terms.push(Expr::Binary {
+            left: Box::new(try_partial_derivative(expr, var, limits)?),
+            op: BinOpKind::Asterisk,
+            right: Box::new(Expr::Identifier(format!("d{}", var))),
+        });
+    }
+    let mut res = terms
+        .into_iter()
+        .reduce(|sum, term| Expr::Binary {
+            left: Box::new(sum),
+            op: BinOpKind::Plus,
+            right: Box::new(term),
+        })
+        .unwrap_or(Expr::Literal(0.0));
+    Simplify.visit(&mut res);
+    Ok(res)
+}
+
+/// The derivative of the input of [`gradient`] with respect to one of its sub-expressions, i.e. its adjoint. An adjoint
+/// refers to the adjoint of the parent sub-expression by index rather than containing a copy of it, so that every
+/// adjoint is built once and shared by all the sub-expressions below it.
+enum Adjoint {
+    /// The derivative of the input with respect to itself.
+    One,
+    /// A step of the chain rule applied to the adjoint at the index, e.g. `adjoint * v` for `u` in `u * v`, see
+    /// [`chain_rule`].
+    Step(usize, ChainStep),
+}
+
+/// The adjoints built by [`gradient`], by index.
+struct Tape {
+    adjoints: Vec<Adjoint>,
+}
+
+impl Tape {
+    fn push(&mut self, adjoint: Adjoint) -> usize {
+        self.adjoints.push(adjoint);
+        self.adjoints.len() - 1
+    }
+
+    /// Applies the `steps` to the adjoint at the index `adjoint` and returns the index of the result.
+    fn apply(&mut self, adjoint: usize, steps: impl IntoIterator<Item = ChainStep>) -> usize {
+        steps.into_iter().fold(adjoint, |adjoint, step| {
+            self.push(Adjoint::Step(adjoint, step))
+        })
+    }
+
+    /// Writes out the sums of the adjoints in `terms` as expressions, by key. Every adjoint is written out once and
+    /// moved into the last expression that uses it, so only the adjoints that are used several times are copied.
+    fn write(
+        self,
+        terms: BTreeMap<String, Vec<usize>>,
+        b: &mut Builder,
+        limits: &Limits,
+    ) -> Result<BTreeMap<String, Expr>, DerivativeError> {
+        // an adjoint is only written out if it is used by a term, directly or through the adjoints below it, which
+        // always come after it
+        let mut uses = vec![0; self.adjoints.len()];
+        for adjoint in terms.values().flatten() {
+            uses[*adjoint] += 1;
+        }
+        for i in (0..self.adjoints.len()).rev() {
+            if let (true, Adjoint::Step(parent, _)) = (uses[i] > 0, &self.adjoints[i]) {
+                uses[*parent] += 1;
+            }
+        }
+
+        let mut written: Vec<Option<Expr>> = Vec::with_capacity(self.adjoints.len());
+        let take =
+            |written: &mut Vec<Option<Expr>>, uses: &mut [usize], b: &mut Builder, i: usize| {
+                uses[i] -= 1;
+                if uses[i] == 0 {
+                    written[i].take().expect("the adjoint was written out")
+                } else {
+                    b.copy(written[i].as_ref().expect("the adjoint was written out"))
+                }
+            };
+        for (i, adjoint) in self.adjoints.into_iter().enumerate() {
+            let expr = match adjoint {
+                _ if uses[i] == 0 => None,
+                Adjoint::One => Some(b.literal(1.0)),
+                Adjoint::Step(adjoint, step) => {
+                    let adjoint = take(&mut written, &mut uses, b, adjoint);
+                    Some(step.apply(adjoint, b))
+                }
+            };
+            written.push(expr);
+            check_output_nodes(b, limits)?;
+        }
+
+        let mut res = BTreeMap::new();
+        for (id, adjoints) in terms {
+            let mut sum: Option<Expr> = None;
+            for adjoint in adjoints {
+                let term = take(&mut written, &mut uses, b, adjoint);
+                sum = Some(match sum {
+                    Some(sum) => b.binary(sum, BinOpKind::Plus, term),
+                    None => term,
+                });
+            }
+            res.insert(id, sum.expect("a term was added for every key"));
+            check_output_nodes(b, limits)?;
+        }
+        Ok(res)
+    }
+}
+
+/// Returns an error if `b` created more than `limits.max_output_nodes` nodes.
+fn check_output_nodes(b: &Builder, limits: &Limits) -> Result<(), DerivativeError> {
+    if b.nodes > limits.max_output_nodes {
+        return Err(ExprTooLarge {
+            kind: LimitKind::OutputNodes,
+            limit: limits.max_output_nodes,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Computes the partial derivatives of `expr` with respect to all the identifiers in `expr` in a single traversal,
+/// which is faster than calling [`try_partial_derivative`] once per identifier for larger expressions. The variables
+/// of definite integrals are bound inside of them, so there is no partial derivative with respect to them, see
+/// [`Expr::free_identifiers`]. The results are simplified.
+///
+/// The derivatives are accumulated from the root down (like reverse-mode automatic differentiation) with the same
+/// rules as [`try_partial_derivative`]: the derivative of `expr` with respect to every sub-expression is computed once
+/// and shared by all the identifiers below it, see [`Adjoint`]. The sub-expressions that are not scalars, i.e. vectors
+/// and integrals, are differentiated with [`try_partial_derivative`] instead. Derivative requests such as
+/// `diff(x * y, x)` are evaluated first, see [`EvaluateDerivatives`].
+pub fn gradient(expr: &Expr, limits: &Limits) -> Result<BTreeMap<String, Expr>, DerivativeError> {
+    let is_request = |expr: &Expr| matches!(expr, Expr::Call { func, .. } if func == "diff");
+    let evaluated;
+    let expr = if expr.find_paths(is_request).is_empty() {
+        expr
+    } else {
+        let mut expr = expr.clone();
+        let mut evaluate = EvaluateDerivatives::new(limits);
+        evaluate.visit(&mut expr);
+        if let Some(err) = evaluate.error {
+            return Err(err);
+        }
+        evaluated = expr;
+        &evaluated
+    };
+
+    let mut b = Builder { nodes: 0 };
+    let mut tape = Tape {
+        adjoints: vec![Adjoint::One],
+    };
+    // the adjoints that make up the partial derivative with respect to each identifier
+    let mut terms: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    // an undefined value makes every partial derivative undefined, like in `differentiate`
+    let mut undefined = false;
+    // the sub-expressions along with the index of their adjoint
+    let mut stack = vec![(expr, 0)];
+    // every identifier is differentiated for, so only the sub-expressions without identifiers are constant
+    let depends = |expr: &Expr| !expr.free_identifiers().is_empty();
+
+    while let Some((expr, adjoint)) = stack.pop() {
+        match expr {
+            Expr::Literal(_) | Expr::Constant(_) => {}
+            Expr::Undefined => undefined = true,
+            Expr::Identifier(id) => terms.entry(id.clone()).or_default().push(adjoint),
+            Expr::Integral { .. } | Expr::Vector(_) => {
+                for id in expr.free_identifiers() {
+                    let partial = try_partial_derivative(expr, id, limits)?;
+                    if partial != Expr::Literal(0.0) {
+                        b.nodes += partial.node_count();
+                        let step = ChainStep::Apply(BinOpKind::Asterisk, partial);
+                        let d_id = tape.apply(adjoint, [step]);
+                        terms.entry(id.to_string()).or_default().push(d_id);
+                    }
+                }
+            }
+            _ => {
+                let rule = chain_rule(expr, &depends, &mut b)
+                    .ok_or_else(|| DerivativeError::Unsupported(expr.clone()))?;
+                // the steps only scale the derivatives, so the outer steps can be applied to the adjoint first and
+                // shared by all the operands
+                let adjoint = tape.apply(adjoint, rule.outer);
+                for (operand, steps) in rule.terms {
+                    stack.push((operand, tape.apply(adjoint, steps)));
+                }
+            }
+        }
+
+        check_output_nodes(&b, limits)?;
+    }
+
+    let mut res = tape.write(terms, &mut b, limits)?;
+    // identifiers that only appear in constant sub-expressions such as `sign(y)`
+    for id in expr.free_identifiers() {
+        res.entry(id.to_string()).or_insert(Expr::Literal(0.0));
+    }
+    for partial in res.values_mut() {
+        if undefined {
+            *partial = Expr::binary(partial.take(), BinOpKind::Plus, Expr::Undefined);
+        }
+        Simplify.visit(partial);
+    }
+    Ok(res)
+}
+
+/// Computes the Hessian matrix of `expr`, i.e. the second partial derivative with respect to `vars[i]` and then
+/// `vars[j]` in row `i` and column `j`. The results are simplified.
+///
+/// Only the upper triangle is computed and the lower triangle is a copy of it, since the mixed partials of the
+/// functions that can be differentiated are equal wherever they are continuous (Schwarz's theorem).
+pub fn hessian(
+    expr: &Expr,
+    vars: &[&str],
+    limits: &Limits,
+) -> Result<Vec<Vec<Expr>>, DerivativeError> {
+    let mut res: Vec<Vec<Expr>> = vec![Vec::with_capacity(vars.len()); vars.len()];
+    for (i, var) in vars.iter().enumerate() {
+        let mut first = try_partial_derivative(expr, var, limits)?;
+        Simplify.visit(&mut first);
+        for j in 0..vars.len() {
+            let second = if j < i {
+                res[j][i].clone()
+            } else {
+                let mut second = try_partial_derivative(&first, vars[j], limits)?;
+                Simplify.visit(&mut second);
+                second
+            };
+            res[i].push(second);
+        }
+    }
+    Ok(res)
+}
+
+/// Replaces the derivative requests `diff(u, x)` (also written `d/dx (u)`) with the partial derivative of `u` with
+/// respect to `x`. Nested requests are evaluated from the inside out.
+///
+/// The derivatives are computed with [`try_partial_derivative`]. The first error is kept in `error`, and the requests
+/// after it are left as written.
+pub struct EvaluateDerivatives<'a> {
+    limits: &'a Limits,
+    pub error: Option<DerivativeError>,
+}
+
+impl<'a> EvaluateDerivatives<'a> {
+    pub fn new(limits: &'a Limits) -> Self {
+        Self {
+            limits,
+            error: None,
+        }
+    }
+}
+
+impl ExprVisitor for EvaluateDerivatives<'_> {
+    fn visit(&mut self, expr: &mut Expr) {
+        walk_expr(expr, self);
+        if self.error.is_some() {
+            return;
+        }
+        if let Expr::Call { func, args } = expr {
+            if let ("diff", [operand, Expr::Identifier(var)]) = (func.as_str(), args.as_slice()) {
+                match try_partial_derivative(operand, var, self.limits) {
+                    Ok(derivative) => *expr = derivative,
+                    Err(err) => self.error = Some(err),
+                }
+            }
+        }
+    }
+}
+
+/// Returns `true` if `func` is a call that is differentiated with the chain rule without knowing the function, e.g.
+/// `f(x ^ 2)`.
+fn is_undefined_function(func: &str, args: &[Expr]) -> bool {
+    !args.is_empty() && functions::lookup(func).is_none() && func != "diff"
+}
+
+/// Returns `true` if `func` is one of the built-in trigonometric or inverse trigonometric functions.
+fn is_trigonometric(func: &str) -> bool {
+    matches!(func, "sin" | "cos" | "tan" | "asin" | "acos" | "atan")
+}
+
+/// A step of the chain rule that is applied to the derivative `d` of an operand, see [`ChainRule`].
+enum ChainStep {
+    /// `factor * d`, e.g. `sign(u) * d` for `u` in `abs(u)`
+    Scale(Expr),
+    /// `d op factor`, e.g. `d * v` for `u` in `u * v` or `d / u` for `u` in `ln(u)`
+    Apply(BinOpKind, Expr),
+    /// `-d`
+    Negate,
+}
+
+impl ChainStep {
+    fn apply(self, d: Expr, b: &mut Builder) -> Expr {
+        match self {
+            ChainStep::Scale(factor) => b.product(factor, d),
+            ChainStep::Apply(BinOpKind::Asterisk, factor) => b.product(d, factor),
+            ChainStep::Apply(op, factor) => b.binary(d, op, factor),
+            ChainStep::Negate => b.negation(d),
+        }
+    }
+}
+
+/// The derivative of an expression in terms of the derivatives of its operands, see [`chain_rule`]. It is the sum of
+/// the `terms`, each of which applies its steps to the derivative of its operand, followed by the `outer` steps, e.g.
+/// `(u' * v - v' * u) / v ^ 2` for `u / v`. A term that ends with [`ChainStep::Negate`] is subtracted from the terms
+/// before it rather than added.
+struct ChainRule<'a> {
+    terms: Vec<(&'a Expr, Vec<ChainStep>)>,
+    outer: Vec<ChainStep>,
+}
+
+/// Returns the operands of `expr` whose derivatives make up the derivative of `expr`, in order, e.g. only `u` for
+/// `u ^ n` if `depends` is `false` for `n`. `depends` returns whether an operand depends on the variables that are
+/// differentiated for. Returns `None` if `expr` is not differentiated with [`chain_rule`], i.e. for atoms, vectors,
+/// integrals and unsupported calls.
+fn chain_operands<'a>(expr: &'a Expr, depends: &impl Fn(&Expr) -> bool) -> Option<Vec<&'a Expr>> {
+    match expr {
+        Expr::Unary { op: _, right } => Some(vec![right]),
+        // chain rule only requires the derivative of the base if the exponent is constant, e.g. `x ^ (1 / 3)`, and
+        // exponentials only require the derivative of the exponent, e.g. `2 ^ (x ^ 2)`
+        Expr::Binary {
+            left,
+            op: BinOpKind::Exponent,
+            right,
+        } if !depends(right) => Some(vec![left]),
+        Expr::Binary {
+            left,
+            op: BinOpKind::Exponent,
+            right,
+        } if !depends(left) => Some(vec![right]),
+        Expr::Binary { left, op: _, right } => Some(vec![left, right]),
+        Expr::Call { func, args }
+            if (matches!(func.as_str(), "abs" | "sqrt" | "ln" | "log" | "exp")
+                || is_trigonometric(func))
+                && args.len() == 1 =>
+        {
+            Some(vec![&args[0]])
+        }
+        // sign is constant everywhere except at 0 where it is not differentiable
+        Expr::Call { func, args } if func == "sign" && args.len() == 1 => Some(Vec::new()),
+        // the derivative of an undefined function is left unevaluated, e.g. `f'(x)`
+        Expr::Call { func, args } if is_undefined_function(func, args) => {
+            Some(args.iter().collect())
+        }
+        _ => None,
+    }
+}
+
+/// Returns the derivative of `expr` in terms of the derivatives of its [`chain_operands`]. This is the table of rules
+/// shared by [`try_partial_derivative`] and [`gradient`].
+fn chain_rule<'a>(
+    expr: &'a Expr,
+    depends: &impl Fn(&Expr) -> bool,
+    b: &mut Builder,
+) -> Option<ChainRule<'a>> {
+    let operands = chain_operands(expr, depends)?;
+    let mut outer = Vec::new();
+    let steps: Vec<Vec<ChainStep>> = match (expr, operands.as_slice()) {
+        // (-u)' = -u'
+        (Expr::Unary { .. }, _) => vec![vec![ChainStep::Negate]],
+        // logarithmic differentiation: ln(u ^ v)' = (v * ln(u))', so (u ^ v)' = u ^ v * (v' * ln(u) + v * u' / u)
+        (
+            Expr::Binary {
+                left,
+                op: BinOpKind::Exponent,
+                right,
+            },
+            [_, _],
+        ) => {
+            let pow = {
+                let (base, exponent) = (b.copy(left), b.copy(right));
+                b.power(base, exponent)
+            };
+            outer.push(ChainStep::Scale(pow));
+            let (v, u) = (b.copy(right), b.copy(left));
+            let ln = {
+                let base = b.copy(left);
+                b.call("ln", vec![base])
+            };
+            vec![
+                vec![ChainStep::Scale(v), ChainStep::Apply(BinOpKind::Slash, u)],
+                vec![ChainStep::Apply(BinOpKind::Asterisk, ln)],
+            ]
+        }
+        // (u ^ n)' = n * u ^ (n - 1) * u'
+        (
+            Expr::Binary {
+                left,
+                op: BinOpKind::Exponent,
+                right,
+            },
+            [operand],
+        ) if std::ptr::eq(*operand, &**left) => {
+            let n_minus_1 = {
+                let (n, one) = (b.copy(right), b.literal(1.0));
+                b.difference(n, one)
+            };
+            let pow = {
+                let base = b.copy(left);
+                b.power(base, n_minus_1)
+            };
+            let n = b.copy(right);
+            vec![vec![ChainStep::Scale(b.product(n, pow))]]
+        }
+        // (a ^ u)' = a ^ u * ln(a) * u'
+        (
+            Expr::Binary {
+                left,
+                op: BinOpKind::Exponent,
+                right,
+            },
+            _,
+        ) => {
+            let pow = {
+                let (base, exponent) = (b.copy(left), b.copy(right));
+                b.power(base, exponent)
+            };
+            let ln = {
+                let base = b.copy(left);
+                b.call("ln", vec![base])
+            };
+            vec![vec![ChainStep::Scale(b.product(pow, ln))]]
+        }
+        (Expr::Binary { left, op, right }, _) => match op {
+            BinOpKind::Plus => vec![Vec::new(), Vec::new()],
+            BinOpKind::Minus => vec![Vec::new(), vec![ChainStep::Negate]],
+            // (u * v)' = u' * v + v' * u
+            BinOpKind::Asterisk => {
+                let (left, right) = (b.copy(left), b.copy(right));
+                vec![
+                    vec![ChainStep::Apply(BinOpKind::Asterisk, right)],
+                    vec![ChainStep::Apply(BinOpKind::Asterisk, left)],
+                ]
+            }
+            // (u / v)' = (u' * v - v' * u) / v ^ 2
+            BinOpKind::Slash => {
+                let right_squared = {
+                    let (right, two) = (b.copy(right), b.literal(2.0));
+                    b.power(right, two)
+                };
+                outer.push(ChainStep::Apply(BinOpKind::Slash, right_squared));
+                let (left, right) = (b.copy(left), b.copy(right));
+                vec![
+                    vec![ChainStep::Apply(BinOpKind::Asterisk, right)],
+                    vec![
+                        ChainStep::Apply(BinOpKind::Asterisk, left),
+                        ChainStep::Negate,
+                    ],
+                ]
+            }
+            BinOpKind::Exponent => unreachable!("handled above"),
+        },
+        // |u|' = sign(u) * u'
+        (Expr::Call { func, args }, _) if func == "abs" => {
+            let arg = b.copy(&args[0]);
+            vec![vec![ChainStep::Scale(b.call("sign", vec![arg]))]]
+        }
+        // sqrt(u)' = u' / (2 * sqrt(u))
+        (Expr::Call { func, args }, _) if func == "sqrt" => {
+            let twice_sqrt = {
+                let arg = b.copy(&args[0]);
+                let (two, sqrt) = (b.literal(2.0), b.call("sqrt", vec![arg]));
+                b.product(two, sqrt)
+            };
+            vec![vec![ChainStep::Apply(BinOpKind::Slash, twice_sqrt)]]
+        }
+        // ln(u)' = u' / u
+        (Expr::Call { func, args }, _) if func == "ln" || func == "log" => {
+            let mut arg = b.copy(&args[0]);
+            // log(u)' = u' / (u * ln(10))
+            if func == "log" {
+                let ln_10 = {
+                    let ten = b.literal(10.0);
+                    b.call("ln", vec![ten])
+                };
+                arg = b.product(arg, ln_10);
+            }
+            vec![vec![ChainStep::Apply(BinOpKind::Slash, arg)]]
+        }
+        // exp(u)' = exp(u) * u'
+        (Expr::Call { func, args }, _) if func == "exp" => {
+            let arg = b.copy(&args[0]);
+            vec![vec![ChainStep::Scale(b.call("exp", vec![arg]))]]
+        }
+        // sin(u)' = cos(u) * u', asin(u)' = u' / (1 - u ^ 2) ^ (1 / 2), ...
+        (Expr::Call { func, args }, _) if is_trigonometric(func) => {
+            vec![trigonometric_steps(func, &args[0], b)]
+        }
+        (Expr::Call { func, args: _ }, _) if func == "sign" => Vec::new(),
+        // f(u, v)' = f_1(u, v) * u' + f_2(u, v) * v'
+        (Expr::Call { func, args }, _) => (0..args.len())
+            .map(|i| {
+                let copies = args.iter().map(|arg| b.copy(arg)).collect();
+                vec![ChainStep::Scale(
+                    b.call(&partial_name(func, i, args.len()), copies),
+                )]
+            })
+            .collect(),
+        _ => unreachable!("only operations have chain operands"),
+    };
+    let mut terms: Vec<_> = operands.into_iter().zip(steps).collect();
+    // the derivative of the exponent comes first, e.g. `u ^ v * (v' * ln(u) + v * u' / u)`
+    if matches!(
+        expr,
+        Expr::Binary {
+            op: BinOpKind::Exponent,
+            ..
+        }
+    ) && terms.len() == 2
+    {
+        terms.reverse();
+    }
+    Some(ChainRule { terms, outer })
+}
+
+/// Returns the steps of the chain rule for the trigonometric function `func` of `arg`. The derivative is a divisor
+/// rather than a fraction when possible, e.g. `d / cos(u) ^ 2` for `tan(u)`.
+fn trigonometric_steps(func: &str, arg: &Expr, b: &mut Builder) -> Vec<ChainStep> {
+    // 1 - u ^ 2 or 1 + u ^ 2
+    let one_and_square = |b: &mut Builder, op: BinOpKind| {
+        let square = {
+            let (arg, two) = (b.copy(arg), b.literal(2.0));
+            b.power(arg, two)
+        };
+        let one = b.literal(1.0);
+        b.binary(one, op, square)
+    };
+    match func {
+        // sin(u)' = cos(u) * u'
+        "sin" => {
+            let arg = b.copy(arg);
+            vec![ChainStep::Scale(b.call("cos", vec![arg]))]
+        }
+        // cos(u)' = -(sin(u) * u')
+        "cos" => {
+            let arg = b.copy(arg);
+            vec![
+                ChainStep::Scale(b.call("sin", vec![arg])),
+                ChainStep::Negate,
+            ]
+        }
+        // tan(u)' = u' / cos(u) ^ 2
+        "tan" => {
+            let cos_squared = {
+                let arg = b.copy(arg);
+                let cos = b.call("cos", vec![arg]);
+                let two = b.literal(2.0);
+                b.power(cos, two)
+            };
+            vec![ChainStep::Apply(BinOpKind::Slash, cos_squared)]
+        }
+        // asin(u)' = u' / (1 - u ^ 2) ^ (1 / 2) and acos(u)' = -asin(u)'
+        "asin" | "acos" => {
+            let root = {
+                let radicand = one_and_square(b, BinOpKind::Minus);
+                let half = {
+                    let (one, two) = (b.literal(1.0), b.literal(2.0));
+                    b.binary(one, BinOpKind::Slash, two)
+                };
+                b.power(radicand, half)
+            };
+            let mut steps = vec![ChainStep::Apply(BinOpKind::Slash, root)];
+            if func == "acos" {
+                steps.push(ChainStep::Negate);
+            }
+            steps
+        }
+        // atan(u)' = u' / (1 + u ^ 2)
+        "atan" => vec![ChainStep::Apply(
+            BinOpKind::Slash,
+            one_and_square(b, BinOpKind::Plus),
+        )],
+        _ => unreachable!("not a trigonometric function: {}", func),
+    }
+}
+
+/// Returns the name of the derivative of the undefined function `func` with respect to its argument at `index`:
+/// `f'` if it takes a single argument, and `f_1`, `f_2`, ... otherwise.
+fn partial_name(func: &str, index: usize, arity: usize) -> String {
+    if arity == 1 {
+        format!("{}'", func)
+    } else {
+        format!("{}_{}", func, index + 1)
+    }
+}
+
+/// Computes the derivative of `expr` with respect to `var`. If `strict` is set, unsupported sub-expressions return an
+/// error instead of differentiating to [`Expr::Error`]. Only strict derivatives are cached, so that a cached
+/// [`Expr::Error`] cannot hide an error.
+fn differentiate(
+    expr: &Expr,
+    var: &str,
+    limits: &Limits,
+    strict: bool,
+    mut cache: Option<&mut DerivativeCache>,
+) -> Result<Expr, DerivativeError> {
+    debug_assert!(
+        strict || cache.is_none(),
+        "lenient derivatives are not cached"
+    );
+    let hashes = match cache {
+        Some(_) => canonical_hashes(expr),
+        None => HashMap::new(),
+    };
+    let mut tasks = vec![Task::Differentiate(expr)];
+    // Derivatives of the sub-expressions that have been processed so far.
+    let mut results: Vec<Expr> = Vec::new();
+    let mut builder = Builder { nodes: 0 };
+
+    while let Some(task) = tasks.pop() {
+        match task {
+            // atoms are cheaper to differentiate than to look up
+            Task::Differentiate(
+                expr @ (Expr::Binary { .. } | Expr::Unary { .. } | Expr::Call { .. }),
+            ) if cache.is_some() => {
+                let hash = hashes[&(expr as *const Expr)];
+                let cache = cache.as_deref_mut().unwrap();
+                match cache.get(hash, expr, var) {
+                    Some(derivative) => results.push(builder.copy(derivative)),
+                    None => {
+                        tasks.push(Task::Store(expr));
+                        tasks.push(Task::DifferentiateUncached(expr));
+                    }
+                }
+            }
+            Task::Differentiate(expr) | Task::DifferentiateUncached(expr) => match expr {
+                Expr::Literal(_) | Expr::Constant(_) => results.push(builder.literal(0.0)),
+                // an undefined value stays undefined, unlike a constant
+                Expr::Undefined => results.push(builder.undefined()),
+                Expr::Identifier(id) if id == var => results.push(builder.literal(1.0)),
+                // Treat all other identifiers as constant.
+                Expr::Identifier(_id) => results.push(builder.literal(0.0)),
+                Expr::Binary { .. } | Expr::Unary { .. } | Expr::Call { .. } | Expr::Error => {
+                    let depends = |expr: &Expr| expr.identifiers().contains(&var);
+                    match chain_operands(expr, &depends) {
+                        Some(operands) => {
+                            tasks.push(Task::Combine(expr));
+                            // the first operand is differentiated first and thus ends up at the bottom of the result
+                            // stack
+                            tasks.extend(operands.into_iter().rev().map(Task::Differentiate));
+                        }
+                        None if strict => return Err(DerivativeError::Unsupported(expr.clone())),
+                        None => {
+                            log::warn!("derivative not yet implemented for {}", expr);
+                            results.push(builder.error());
+                        }
+                    }
+                }
+                // vectors are differentiated component-wise, e.g. the velocity on a parametric curve
+                Expr::Vector(components) => {
+                    tasks.push(Task::Combine(expr));
+                    tasks.extend(components.iter().rev().map(Task::Differentiate));
+                }
+                // fundamental theorem of calculus
+                Expr::Integral {
+                    integrand,
+                    var: int_var,
+                    bounds: None,
+                } if int_var == var => results.push(builder.copy(integrand)),
+                Expr::Integral {
+                    integrand,
+                    var: int_var,
+                    bounds,
+                } => {
+                    tasks.push(Task::Combine(expr));
+                    if let Some((lower, upper)) = bounds.as_deref() {
+                        tasks.push(Task::Differentiate(upper));
+                        tasks.push(Task::Differentiate(lower));
+                    }
+                    // the variable of the integral is a different identifier inside of it
+                    if int_var != var {
+                        tasks.push(Task::Differentiate(integrand));
+                    }
+                }
+            },
+            Task::Combine(expr) => {
+                let res = combine(expr, var, &mut results, &mut builder);
+                results.push(res);
+            }
+            Task::Store(expr) => {
+                let derivative = results
+                    .last()
+                    .expect("derivative of the expression should be on the result stack");
+                let cache = cache.as_deref_mut().expect("only stored with a cache");
+                cache.insert(hashes[&(expr as *const Expr)], expr, var, derivative);
+            }
+        }
+
+        if builder.nodes > limits.max_output_nodes {
+            return Err(ExprTooLarge {
+                kind: LimitKind::OutputNodes,
+                limit: limits.max_output_nodes,
+            }
+            .into());
+        }
+    }
+
+    Ok(results
+        .pop()
+        .expect("derivative of the input should be on the result stack"))
+}
+
+/// Computes the derivative of `expr` with respect to `var` from the derivatives of its operands on top of the
+/// `results` stack.
+fn combine(expr: &Expr, var: &str, results: &mut Vec<Expr>, b: &mut Builder) -> Expr {
+    let mut pop = || {
+        results
+            .pop()
+            .expect("derivative of operand should be on the result stack")
+    };
+
+    match expr {
+        Expr::Vector(components) => {
+            let mut d_components: Vec<_> = components.iter().map(|_| pop()).collect();
+            d_components.reverse();
+            b.vector(d_components)
+        }
+        // Leibniz integral rule: int(f, t, a, b)' = f(b) * b' - f(a) * a' + int(f', t, a, b)
+        Expr::Integral {
+            integrand,
+            var: int_var,
+            bounds,
+        } => {
+            let boundary = match bounds.as_deref() {
+                Some((lower, upper)) => {
+                    let (d_upper, d_lower) = (pop(), pop());
+                    let at_upper = {
+                        let integrand = b.substitute(integrand, int_var, upper);
+                        b.product(integrand, d_upper)
+                    };
+                    let at_lower = {
+                        let integrand = b.substitute(integrand, int_var, lower);
+                        b.product(integrand, d_lower)
+                    };
+                    b.difference(at_upper, at_lower)
+                }
+                None => b.literal(0.0),
+            };
+            if int_var == var {
+                return boundary;
+            }
+            match pop() {
+                Expr::Literal(0.0) => boundary,
+                d_integrand => {
+                    let bounds = bounds
+                        .as_deref()
+                        .map(|(lower, upper)| (b.copy(lower), b.copy(upper)));
+                    let integral = b.integral(d_integrand, int_var, bounds);
+                    b.sum(boundary, integral)
+                }
+            }
+        }
+        _ => {
+            let depends = |expr: &Expr| expr.identifiers().contains(&var);
+            let operands = chain_operands(expr, &depends).expect("only operations are combined");
+            // the last operand is on top of the result stack
+            let mut d_operands: Vec<_> = operands
+                .into_iter()
+                .rev()
+                .map(|operand| (operand, pop()))
+                .collect();
+            // the derivative of a function of a constant is 0 rather than e.g. `0 / u` for `ln(u)`, but undefined
+            // operations are kept, e.g. `1 / 0`
+            let is_function = matches!(expr, Expr::Call { func, .. } if matches!(func.as_str(), "sqrt" | "ln" | "log") || is_trigonometric(func));
+            if let (true, [(_, d @ Expr::Literal(0.0))]) = (is_function, d_operands.as_mut_slice())
+            {
+                return d.take();
+            }
+
+            let rule = chain_rule(expr, &depends, b).expect("operations have a chain rule");
+            let mut res: Option<Expr> = None;
+            for (operand, mut steps) in rule.terms {
+                let i = d_operands
+                    .iter()
+                    .position(|(other, _)| std::ptr::eq(*other, operand))
+                    .expect("every operand was differentiated");
+                let (_, d) = d_operands.swap_remove(i);
+                // subtracted rather than added, e.g. `u' - v'` rather than `u' + (-v')`
+                let subtract = res.is_some() && matches!(steps.last(), Some(ChainStep::Negate));
+                if subtract {
+                    steps.pop();
+                }
+                let term = steps.into_iter().fold(d, |d, step| step.apply(d, b));
+                res = Some(match res {
+                    Some(sum) if subtract => b.difference(sum, term),
+                    Some(sum) => b.sum(sum, term),
+                    None => term,
+                });
+            }
+            let res = res.unwrap_or_else(|| b.literal(0.0));
+            rule.outer.into_iter().fold(res, |d, step| step.apply(d, b))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use expect_test::expect;
+    use logos::Logos;
+
+    #[test]
+    fn test_derivative_deeply_nested() {
+        let depth = 100_000;
+        let mut expr = Expr::Identifier("x".to_string());
+        for _ in 0..depth {
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                op: BinOpKind::Plus,
+                right: Box::new(Expr::Identifier("x".to_string())),
+            };
+        }
+
+        // the derivatives of the terms are folded while differentiating
+        let res = derivative(&expr, "x");
+        assert_eq!(res, Expr::Literal(depth as f64 + 1.0));
+    }
+
+    #[test]
+    fn test_derivative_product() {
+        let expr = Parser::from(Token::lexer("x * y").spanned()).parse();
+        let expected = Parser::from(Token::lexer("y").spanned()).parse();
+        assert_eq!(derivative(&expr, "x"), expected);
+        let expected = Parser::from(Token::lexer("x").spanned()).parse();
+        assert_eq!(derivative(&expr, "y"), expected);
+    }
+
+    #[test]
+    fn test_derivative_n() {
+        let parse = |input: &str| Parser::from(Token::lexer(input).spanned()).parse();
+        let cases = [
+            ("x ^ 3 + y * x", 0, "((x ^ 3) + (y * x))"),
+            ("x ^ 3 + y * x", 2, "(6 * x)"),
+            ("x ^ 3 + y * x", 3, "6"),
+            ("x ^ 3 + y * x", 1_000_000, "0"),
+            ("exp(2 * x)", 3, "(8 * exp(2 * x))"),
+            ("x * exp(x)", 2, "(exp(x) + ((exp(x) * x) + exp(x)))"),
+        ];
+        for (input, n, expected) in cases {
+            let res = derivative_n(&parse(input), "x", n);
+            assert_eq!(res.to_string(), expected, "input: {}, n: {}", input, n);
+        }
+
+        assert_eq!(
+            derivative_n(&parse("exp(x)"), "x", u32::MAX),
+            parse("exp(x)")
+        );
+        // -6 / x ^ 4
+        let res = derivative_n(&parse("1 / x"), "x", 3);
+        let bindings = HashMap::from([("x".to_string(), 2.0)]);
+        assert_eq!(crate::eval::eval(&res, &bindings), Ok(-0.375));
+        assert_eq!(derivative_n(&parse("abs(x, 2)"), "x", 2), Expr::Error);
+        let limits = Limits {
+            max_output_nodes: 50,
+            ..Limits::default()
+        };
+        assert!(matches!(
+            derivative_n_with_limits(&parse("1 / x"), "x", 1_000, &limits),
+            Err(DerivativeError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_pick_variable() {
+        let parse = |input: &str| Parser::from(Token::lexer(input).spanned()).parse();
+        assert_eq!(pick_variable(&parse("t ^ 2 + 1"), "x"), "t");
+        assert_eq!(pick_variable(&parse("x * y"), "x"), "x");
+        assert_eq!(pick_variable(&parse("a * t"), "x"), "x");
+        assert_eq!(pick_variable(&parse("2"), "x"), "x");
+        assert_eq!(pick_variable(&parse("pi * e ^ t"), "x"), "t");
+    }
+
+    #[test]
+    fn test_derivative_constant_exponent() {
+        let expr = Parser::from(Token::lexer("x ^ (1 / 3)").spanned()).parse();
+        let expected =
+            Parser::from(Token::lexer("(1 / 3) * (x ^ ((1 / 3) - 1))").spanned()).parse();
+        assert_eq!(derivative(&expr, "x"), expected);
+    }
+
+    #[test]
+    fn test_derivative_variable_exponent() {
+        let cases = [
+            ("x ^ x", "((x ^ x) * (1 + ln(x)))"),
             (
-                "_1",
-                &|res: &MatchResult| match res.matched_exprs.get(&1).unwrap() {
-                        Expr::Identifier(id) if id == "x" /* TODO */ => Some(Expr::Literal(1.0)),
-                        // Treat all other identifiers as constant.
-                        Expr::Identifier(_id) => Some(Expr::Literal(0.0)),
-                        _ => None,
-                    },
+                "(2 * x) ^ (x + 1)",
+                "(((2 * x) ^ (1 + x)) * (ln(2 * x) + ((2 * (1 + x)) / (2 * x))))",
             ),
-            // unary minus
-            ("-_1", &|res: &MatchResult| {
-                Some(Expr::Unary {
-                    op: UnaryOpKind::Minus,
-                    right: Box::new(derivative(res.matched_exprs.get(&1).unwrap())),
-                })
-            }),
-            ("_1 + _2", &|res: &MatchResult| {
-                Some(Expr::Binary {
-                    left: Box::new(derivative(res.matched_exprs.get(&1).unwrap())),
-                    op: BinOpKind::Plus,
-                    right: Box::new(derivative(res.matched_exprs.get(&2).unwrap())),
-                })
-            }),
-            ("_1 - _2", &|res: &MatchResult| {
-                Some(Expr::Binary {
-                    left: Box::new(derivative(res.matched_exprs.get(&1).unwrap())),
-                    op: BinOpKind::Minus,
-                    right: Box::new(derivative(res.matched_exprs.get(&2).unwrap())),
-                })
-            }),
-            ("_1 * _2", &|res: &MatchResult| {
-                Some(Expr::Binary {
-                    left: Box::new(Expr::Binary {
-                        left: Box::new(derivative(res.matched_exprs.get(&1).unwrap())),
-                        op: BinOpKind::Asterisk,
-                        right: Box::new((*res.matched_exprs.get(&2).unwrap()).clone()),
-                    }),
-                    op: BinOpKind::Plus,
-                    right: Box::new(Expr::Binary {
-                        left: Box::new(derivative(res.matched_exprs.get(&2).unwrap())),
-                        op: BinOpKind::Asterisk,
-                        right: Box::new((*res.matched_exprs.get(&1).unwrap()).clone()),
-                    }),
-                })
-            }),
-            ("_1 / _2", &|res: &MatchResult| {
-                Some(Expr::Binary {
-                    left: Box::new(Expr::Binary {
-                        left: Box::new(Expr::Binary {
-                            left: Box::new(derivative(res.matched_exprs.get(&1).unwrap())),
-                            op: BinOpKind::Asterisk,
-                            right: Box::new((*res.matched_exprs.get(&2).unwrap()).clone()),
-                        }),
-                        op: BinOpKind::Minus,
-                        right: Box::new(Expr::Binary {
-                            left: Box::new(derivative(res.matched_exprs.get(&2).unwrap())),
-                            op: BinOpKind::Asterisk,
-                            right: Box::new((*res.matched_exprs.get(&1).unwrap()).clone()),
-                        }),
-                    }),
-                    op: BinOpKind::Slash,
-                    right: Box::new(Expr::Binary {
-                        left: Box::new((*res.matched_exprs.get(&2).unwrap()).clone()),
-                        op: BinOpKind::Exponent,
-                        right: Box::new(Expr::Literal(2.0)),
-                    }),
-                })
-            }),
-            // use chain rule g(x) ^ n => n * g(x) ^ (n - 1) * g'(x)
-            ("_1 ^ _lit2", &|res: &MatchResult| {
-                Some(Expr::Binary {
-                    left: Box::new(Expr::Binary {
-                        left: Box::new((*res.matched_exprs.get(&2).unwrap()).clone()),
-                        op: BinOpKind::Asterisk,
-                        right: Box::new(Expr::Binary {
-                            left: Box::new((*res.matched_exprs.get(&1).unwrap()).clone()),
-                            op: BinOpKind::Exponent,
-                            right: Box::new(Expr::Binary {
-                                left: Box::new((*res.matched_exprs.get(&2).unwrap()).clone()),
-                                op: BinOpKind::Minus,
-                                right: Box::new(Expr::Literal(1.0)),
-                            }),
-                        }),
-                    }),
-                    op: BinOpKind::Asterisk,
-                    right: Box::new(derivative(res.matched_exprs.get(&1).unwrap())),
-                })
-            }),
-            // catch all
-            ("_1", &|res| {
-                log::warn!("derivative not yet implemented for {}", res.source_expr);
-                Some(Expr::Error)
-            }),
-        ],
-    );
+            ("x ^ (2 * y)", "((2 * y) * (x ^ ((-1) + (2 * y))))"),
+        ];
+        for (input, expected) in cases {
+            let mut res = derivative(&Parser::from(Token::lexer(input).spanned()).parse(), "x");
+            Simplify.visit(&mut res);
+            assert_eq!(res.to_string(), expected, "input: {}", input);
+        }
+
+        let expr = Parser::from(Token::lexer("x ^ y").spanned()).parse();
+        let res = gradient(&expr, &Limits::default()).unwrap();
+        // both the base and the exponent depend on an identifier, so the rule for `x ^ x` is used
+        assert_eq!(res["x"].to_string(), "((y * (x ^ y)) / x)");
+        assert_eq!(res["y"].to_string(), "((x ^ y) * ln(x))");
+    }
+
+    #[test]
+    fn test_derivative_logarithm_exponential() {
+        let cases = [
+            ("ln(x ^ 2)", "((2 * x) / (x ^ 2))"),
+            ("log(x)", "(1 / (x * ln(10)))"),
+            ("exp(2 * x)", "(2 * exp(2 * x))"),
+            ("2 ^ x", "((2 ^ x) * ln(2))"),
+            ("a ^ (x ^ 2)", "(((a ^ (x ^ 2)) * ln(a)) * (2 * x))"),
+        ];
+        for (input, expected) in cases {
+            let mut res = derivative(&Parser::from(Token::lexer(input).spanned()).parse(), "x");
+            Simplify.visit(&mut res);
+            assert_eq!(res.to_string(), expected, "input: {}", input);
+        }
+
+        let expr = Parser::from(Token::lexer("2 ^ (x * y) + ln(y)").spanned()).parse();
+        let res = gradient(&expr, &Limits::default()).unwrap();
+        for (var, partial) in &res {
+            let mut expected = partial_derivative(&expr, var);
+            Simplify.visit(&mut expected);
+            assert!(
+                matches!(
+                    crate::eval::equivalent(partial, &expected),
+                    Ok(crate::eval::Equivalence::Equivalent)
+                ),
+                "partial derivative with respect to {}: {}",
+                var,
+                partial
+            );
+        }
+    }
+
+    #[test]
+    fn test_derivative_trigonometric() {
+        let cases = [
+            ("sin(x)", "cos(x)"),
+            ("cos(2 * x)", "(-(2 * sin(2 * x)))"),
+            ("tan(x ^ 2)", "((2 * x) / (cos(x ^ 2) ^ 2))"),
+            ("asin(x)", "(1 / ((1 - (x ^ 2)) ^ (1 / 2)))"),
+            ("acos(3 * x)", "(-(3 / ((1 - ((3 * x) ^ 2)) ^ (1 / 2))))"),
+            ("atan(x)", "(1 / (1 + (x ^ 2)))"),
+            ("sin(a)", "0"),
+        ];
+        for (input, expected) in cases {
+            let mut res = derivative(&Parser::from(Token::lexer(input).spanned()).parse(), "x");
+            Simplify.visit(&mut res);
+            assert_eq!(res.to_string(), expected, "input: {}", input);
+        }
+
+        let expr = Parser::from(Token::lexer("sin(x * y) + atan(y) - acos(x)").spanned()).parse();
+        let res = gradient(&expr, &Limits::default()).unwrap();
+        for (var, partial) in &res {
+            let mut expected = partial_derivative(&expr, var);
+            Simplify.visit(&mut expected);
+            assert!(
+                matches!(
+                    crate::eval::equivalent(partial, &expected),
+                    Ok(crate::eval::Equivalence::Equivalent)
+                ),
+                "partial derivative with respect to {}: {}",
+                var,
+                partial
+            );
+        }
+    }
+
+    #[test]
+    fn test_derivative_square_root() {
+        // `sqrt(u)` is parsed as `u ^ 0.5`, calls are only written by `Prettify`
+        let radicand = Parser::from(Token::lexer("x ^ 2 + 1").spanned()).parse();
+        let expr = Expr::Call {
+            func: "sqrt".to_string(),
+            args: vec![radicand],
+        };
+        let mut res = derivative(&expr, "x");
+        Simplify.visit(&mut res);
+        assert_eq!(res.to_string(), "((2 * x) / (2 * sqrt(1 + (x ^ 2))))");
+
+        let power = Parser::from(Token::lexer("sqrt(x ^ 2 + 1)").spanned()).parse();
+        let res = gradient(&expr, &Limits::default()).unwrap();
+        assert_eq!(
+            crate::eval::equivalent(&res["x"], &derivative(&power, "x")),
+            Ok(crate::eval::Equivalence::Equivalent)
+        );
+    }
+
+    #[test]
+    fn test_derivative_abs() {
+        let expected = Parser::from(Token::lexer("sign(x ^ 2) * (2 * x)").spanned()).parse();
+        for input in ["abs(x ^ 2)", "|x ^ 2|"] {
+            let expr = Parser::from(Token::lexer(input).spanned()).parse();
+            assert_eq!(derivative(&expr, "x"), expected, "input: {}", input);
+        }
+
+        let expr = Parser::from(Token::lexer("sign(x)").spanned()).parse();
+        assert_eq!(derivative(&expr, "x"), Expr::Literal(0.0));
+    }
+
+    #[test]
+    fn test_derivative_constants() {
+        let cases = [
+            ("pi * x ^ 2", "((2 * x) * pi)"),
+            ("e ^ x", "((e ^ x) * ln(e))"),
+            ("x ^ e", "(e * (x ^ (e - 1)))"),
+            ("sin(pi * e)", "0"),
+        ];
+        for (input, expected) in cases {
+            let expr = Parser::from(Token::lexer(input).spanned()).parse();
+            assert_eq!(
+                derivative(&expr, "x").to_string(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_partial_derivative() {
+        let expr = Parser::from(Token::lexer("x * y").spanned()).parse();
+        let expected = Parser::from(Token::lexer("x").spanned()).parse();
+        assert_eq!(partial_derivative(&expr, "y"), expected);
+    }
+
+    #[test]
+    fn test_directional_derivative() {
+        let expr = Parser::from(Token::lexer("x * y + z").spanned()).parse();
+        let limits = Limits::default();
+        let res = directional_derivative(&expr, &["x", "y", "z"], &[1.0, 2.0, 0.0], &limits);
+        expect![[r#"(y + (2 * x))"#]].assert_eq(&res.unwrap().to_string());
+
+        let res = directional_derivative(&expr, &["x", "w"], &[1.0, 2.0], &limits);
+        assert_eq!(res.unwrap().to_string(), "y");
+        let expr = Parser::from(Token::lexer("int(t, t, 0, x)").spanned()).parse();
+        let res = directional_derivative(&expr, &["x", "y"], &[3.0, 1.0], &limits);
+        assert_eq!(res.unwrap().to_string(), "(3 * x)");
+        assert_eq!(
+            directional_derivative(&expr, &[], &[], &limits),
+            Ok(Expr::Literal(0.0))
+        );
+        assert_eq!(
+            directional_derivative(&expr, &["x", "y"], &[1.0], &limits),
+            Err(DerivativeError::DimensionMismatch {
+                vars: 2,
+                components: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_total_differential() {
+        let limits = Limits::default();
+        let expr = Parser::from(Token::lexer("x * y + 3").spanned()).parse();
+        expect![[r#"((y * dx) + (x * dy))"#]]
+            .assert_eq(&total_differential(&expr, &limits).unwrap().to_string());
+
+        let expr = Parser::from(Token::lexer("3").spanned()).parse();
+        assert_eq!(total_differential(&expr, &limits), Ok(Expr::Literal(0.0)));
+
+        let expr = Parser::from(Token::lexer("x * abs(y, 2)").spanned()).parse();
+        assert!(matches!(
+            total_differential(&expr, &limits),
+            Err(DerivativeError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_derivative_too_large() {
+        let expr = Parser::from(Token::lexer("x * x * x * x * x * x").spanned()).parse();
+        let limits = Limits {
+            max_output_nodes: 20,
+            ..Limits::default()
+        };
+        assert_eq!(
+            derivative_with_limits(&expr, "x", &limits),
+            Err(DerivativeError::TooLarge(ExprTooLarge {
+                kind: LimitKind::OutputNodes,
+                limit: 20
+            }))
+        );
+        assert_eq!(
+            derivative_with_limits(&expr, "x", &Limits::default()).unwrap(),
+            derivative(&expr, "x")
+        );
+    }
+
+    #[test]
+    fn test_derivative_cached() {
+        let expr = Parser::from(Token::lexer("(x * y) * (x * y) + (x * y) * -0").spanned()).parse();
+        let mut cache = DerivativeCache::new();
+        let res = try_partial_derivative_cached(&expr, "x", &Limits::default(), &mut cache);
+        assert_eq!(res.as_ref(), Ok(&partial_derivative(&expr, "x")));
+        // `x * y` is differentiated once
+        assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 4 });
+
+        // the derivative with respect to another variable is not cached
+        let res = try_partial_derivative_cached(&expr, "y", &Limits::default(), &mut cache);
+        assert_eq!(res, Ok(partial_derivative(&expr, "y")));
+        assert_eq!(cache.stats(), CacheStats { hits: 4, misses: 8 });
+
+        // the whole expression is cached
+        let res = try_partial_derivative_cached(&expr, "x", &Limits::default(), &mut cache);
+        assert_eq!(res, Ok(partial_derivative(&expr, "x")));
+        assert_eq!(cache.stats(), CacheStats { hits: 5, misses: 8 });
+
+        let expr = Parser::from(Token::lexer("abs(x, 2)").spanned()).parse();
+        assert!(matches!(
+            try_partial_derivative_cached(&expr, "x", &Limits::default(), &mut cache),
+            Err(DerivativeError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_gradient() {
+        let expr = Parser::from(Token::lexer("x ^ 2 * y + abs(y) / x - sign(z)").spanned()).parse();
+        let res = gradient(&expr, &Limits::default()).unwrap();
+        assert_eq!(res.keys().collect::<Vec<_>>(), ["x", "y", "z"]);
+        for (var, partial) in &res {
+            let mut expected = partial_derivative(&expr, var);
+            Simplify.visit(&mut expected);
+            assert!(
+                matches!(
+                    crate::eval::equivalent(partial, &expected),
+                    Ok(crate::eval::Equivalence::Equivalent)
+                ),
+                "partial derivative with respect to {}: {}",
+                var,
+                partial
+            );
+        }
+        expect![[r#"0"#]].assert_eq(&res["z"].to_string());
+
+        let expr = Parser::from(Token::lexer("f(x * y, x)").spanned()).parse();
+        let res = gradient(&expr, &Limits::default()).unwrap();
+        assert_eq!(
+            res["x"].to_string(),
+            "(f_2(x * y, x) + (f_1(x * y, x) * y))"
+        );
+        assert_eq!(res["y"].to_string(), "(f_1(x * y, x) * x)");
+
+        // the same sub-expressions as `differentiate`
+        let gradient_strings = |input: &str| {
+            let expr = Parser::from(Token::lexer(input).spanned()).parse();
+            gradient(&expr, &Limits::default()).map(|res| {
+                res.into_iter()
+                    .map(|(var, partial)| (var, partial.to_string()))
+                    .collect::<Vec<_>>()
+            })
+        };
+        let owned = |partials: &[(&str, &str)]| {
+            partials
+                .iter()
+                .map(|(var, partial)| (var.to_string(), partial.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            gradient_strings("y * int(t ^ 2, t, 0, x)"),
+            // `t` is bound by the integral
+            Ok(owned(&[
+                ("x", "(y * (x ^ 2))"),
+                ("y", "int(t ^ 2, t, 0, x)")
+            ]))
+        );
+        assert_eq!(
+            gradient_strings("diff(x ^ 2 * y, x) + y"),
+            Ok(owned(&[("x", "(2 * y)"), ("y", "(1 + (2 * x))")]))
+        );
+        assert_eq!(
+            gradient_strings("[x * y, x]"),
+            Ok(owned(&[("x", "[y, 1]"), ("y", "[x, 0]")]))
+        );
+        assert_eq!(
+            gradient_strings("x * y + undefined"),
+            Ok(owned(&[("x", "undefined"), ("y", "undefined")]))
+        );
+
+        let expr = Parser::from(Token::lexer("abs(x, y)").spanned()).parse();
+        assert_eq!(
+            gradient(&expr, &Limits::default()),
+            Err(DerivativeError::Unsupported(expr))
+        );
+        let limits = Limits {
+            max_output_nodes: 20,
+            ..Limits::default()
+        };
+        let expr = Parser::from(Token::lexer("sin(x * y * z) ^ 3").spanned()).parse();
+        assert!(matches!(
+            gradient(&expr, &limits),
+            Err(DerivativeError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_hessian() {
+        let to_strings = |matrix: Vec<Vec<Expr>>| -> Vec<Vec<String>> {
+            matrix
+                .iter()
+                .map(|row| row.iter().map(Expr::to_string).collect())
+                .collect()
+        };
+        let expr = Parser::from(Token::lexer("x ^ 2 * y + y ^ 3 - a * x * z").spanned()).parse();
+        let res = hessian(&expr, &["x", "y", "z"], &Limits::default()).unwrap();
+        assert_eq!(
+            to_strings(res),
+            [
+                ["(2 * y)", "(2 * x)", "(0 - a)"],
+                ["(2 * x)", "(6 * y)", "0"],
+                ["(0 - a)", "0", "0"],
+            ]
+        );
+
+        let res = hessian(&expr, &[], &Limits::default()).unwrap();
+        assert!(res.is_empty());
+
+        let expr = Parser::from(Token::lexer("x * abs(y, 2)").spanned()).parse();
+        assert!(matches!(
+            hessian(&expr, &["x", "y"], &Limits::default()),
+            Err(DerivativeError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_derivative_unsupported() {
+        let expr = Parser::from(Token::lexer("2 * x + abs(x, 2)").spanned()).parse();
+        let err = derivative_with_limits(&expr, "x", &Limits::default()).unwrap_err();
+        assert_eq!(
+            err,
+            DerivativeError::Unsupported(Parser::from(Token::lexer("abs(x, 2)").spanned()).parse())
+        );
+        assert_eq!(
+            err.to_string(),
+            "cannot compute the derivative of `abs(x, 2)`"
+        );
+        assert_eq!(derivative(&expr, "x").to_string(), "(2 + err)");
+    }
+
+    #[test]
+    fn test_evaluate_derivatives() {
+        let unlimited = Limits::unlimited();
+        let cases = [
+            ("diff(x ^ 2 * y, y) + 1", "(1 + (x ^ 2))"),
+            ("d/dt (t ^ 3)", "(3 * (t ^ 2))"),
+            ("d/dx (x^3)", "(3 * (x ^ 2))"),
+            ("diff(diff(x ^ 3, x), x)", "(6 * x)"),
+            ("d/dx (d/dx (x ^ 3))", "(6 * x)"),
+            ("diff(diff(x ^ 2 * y ^ 2, x), y)", "((2 * y) * (2 * x))"),
+            ("diff(x ^ 2, x) + x", "(3 * x)"),
+            ("diff(diff(x ^ 2, x) * x, x)", "(4 * x)"),
+            ("diff(x, 2)", "diff(x, 2)"),
+        ];
+        for (input, expected) in cases {
+            let mut expr = Parser::from(Token::lexer(input).spanned()).parse();
+            let mut evaluate = EvaluateDerivatives::new(&unlimited);
+            evaluate.visit(&mut expr);
+            assert_eq!(evaluate.error, None, "input: {}", input);
+            Simplify.visit(&mut expr);
+            assert_eq!(expr.to_string(), expected, "input: {}", input);
+        }
+
+        let mut expr =
+            Parser::from(Token::lexer("d/dx (abs(x, 2)) + diff(x, x)").spanned()).parse();
+        let mut evaluate = EvaluateDerivatives::new(&unlimited);
+        evaluate.visit(&mut expr);
+        assert!(matches!(
+            evaluate.error,
+            Some(DerivativeError::Unsupported(_))
+        ));
+
+        let limits = Limits {
+            max_output_nodes: 10,
+            ..unlimited
+        };
+        let mut expr = Parser::from(Token::lexer("d/dx (x ^ 2 + 1) ^ 10 * x").spanned()).parse();
+        let mut evaluate = EvaluateDerivatives::new(&limits);
+        evaluate.visit(&mut expr);
+        assert!(matches!(evaluate.error, Some(DerivativeError::TooLarge(_))));
+    }
+
+    #[test]
+    fn test_derivative_undefined_function() {
+        let cases = [
+            ("f(x)", "f'(x)"),
+            ("f(x ^ 2)", "(f'(x ^ 2) * (2 * x))"),
+            ("g(f(x))", "(g'(f(x)) * f'(x))"),
+            ("f(y)", "0"),
+            ("f(x, y)", "f_1(x, y)"),
+            ("f(x ^ 2, x)", "((f_1(x ^ 2, x) * (2 * x)) + f_2(x ^ 2, x))"),
+            ("f'(x) + f_1(x, 2)", "(f''(x) + f_1_1(x, 2))"),
+        ];
+        for (input, expected) in cases {
+            let expr = Parser::from(Token::lexer(input).spanned()).parse();
+            let mut res = derivative(&expr, "x");
+            Simplify.visit(&mut res);
+            assert_eq!(res.to_string(), expected, "input: {}", input);
+            // the names of the derivatives can be parsed back
+            assert_eq!(Parser::from(Token::lexer(expected).spanned()).parse(), res);
+        }
+
+        // higher order derivatives
+        let expr = Parser::from(Token::lexer("f(x)").spanned()).parse();
+        let mut res = derivative(&derivative(&expr, "x"), "x");
+        Simplify.visit(&mut res);
+        assert_eq!(res.to_string(), "f''(x)");
+    }
+
+    #[test]
+    fn test_derivative_vector() {
+        let cases = [
+            ("[x ^ 2, abs(x), 1]", "[2 * x, sign(x), 0]"),
+            ("[]", "[]"),
+            ("[f(x), [x, y]]", "[f'(x), [1, 0]]"),
+        ];
+        for (input, expected) in cases {
+            let expr = Parser::from(Token::lexer(input).spanned()).parse();
+            let mut res = derivative(&expr, "x");
+            Simplify.visit(&mut res);
+            assert_eq!(res.to_string(), expected, "input: {}", input);
+        }
+
+        // the acceleration on a parametric curve
+        let expr = Parser::from(Token::lexer("[x ^ 3, 2 * x]").spanned()).parse();
+        let mut res = partial_derivative(&partial_derivative(&expr, "x"), "x");
+        Simplify.visit(&mut res);
+        assert_eq!(res.to_string(), "[6 * x, 0]");
+    }
 
-    transforms
-        .apply_rules_once(expr)
-        .unwrap_or_else(|| panic!("derivative not yet implemented for {}", expr))
+    #[test]
+    fn test_derivative_integral() {
+        let cases = [
+            ("int(x ^ 2, x)", "(x ^ 2)"),
+            ("int(t ^ 2, t, 0, x)", "(x ^ 2)"),
+            ("int(t ^ 2, t, x, 2 * x)", "((2 * ((2 * x) ^ 2)) - (x ^ 2))"),
+            ("int(t, t, 0, 1)", "0"),
+            // the variable of the integral is a different identifier
+            ("int(x * t, t, 0, 1)", "int(t, t, 0, 1)"),
+            ("int(t * x, t)", "int(t, t)"),
+            ("int(t, t, 0, int(x, x))", "(int(x, x) * x)"),
+        ];
+        for (input, expected) in cases {
+            let expr = Parser::from(Token::lexer(input).spanned()).parse();
+            let mut res = derivative(&expr, "x");
+            Simplify.visit(&mut res);
+            assert_eq!(res.to_string(), expected, "input: {}", input);
+        }
+    }
 }
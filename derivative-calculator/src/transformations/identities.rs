@@ -0,0 +1,12 @@
+//! Identities shared between rule sets that rewrite in opposite directions. See
+//! [`crate::transformations::RuleTransformSet::with_identities`].
+//!
+//! The left side of an identity is the form that is easier to compute with and the right side is the form that is
+//! easier to read. [`crate::transformations::prettify`] rewrites the identities left to right.
+
+/// Pairs of `(computable form, readable form)`.
+pub const IDENTITIES: &[(&str, &str)] = &[
+    ("0.5", "1 / 2"),
+    // negative exponents, e.g. x ^ -2 = 1 / x ^ 2
+    ("_1 ^ {-_lit2}", "1 / _1 ^ _lit2 if _lit2 > 0"),
+];
@@ -0,0 +1,269 @@
+//! Compute the antiderivative of an expression.
+//!
+//! Only sums of constant multiples of a few basic forms are supported: powers of the variable (including `1 / x`),
+//! exponentials and logarithms, as well as these forms composed with a linear function of the variable, e.g.
+//! `(2 * x + 1) ^ 3` or `exp(-x)`. Everything else, e.g. `x * exp(x)`, would require integration by parts or a
+//! substitution and is rejected with an error.
+
+use crate::limits::Limits;
+use crate::parser::{BinOpKind, Expr, ExprVisitor, UnaryOpKind};
+use crate::transformations::derivative::try_partial_derivative;
+use crate::transformations::simplify::Simplify;
+use std::fmt;
+
+/// An error that occurred while computing an antiderivative.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntegrationError {
+    /// The antiderivative of the term is not supported, e.g. `x * exp(x)`.
+    Unsupported(Expr),
+}
+
+impl fmt::Display for IntegrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrationError::Unsupported(expr) => {
+                write!(f, "cannot compute the antiderivative of `{}`", expr)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IntegrationError {}
+
+/// Computes an antiderivative of `expr` with respect to `var`, without the constant of integration. All other
+/// identifiers are treated as constants. The input is simplified first, e.g. so that `x * x` is integrated as `x ^ 2`,
+/// and the result is simplified.
+///
+/// The antiderivative of `1 / u` is `ln(abs(u))` so that it is valid on both sides of the pole.
+pub fn integrate(expr: &Expr, var: &str) -> Result<Expr, IntegrationError> {
+    let mut expr = expr.clone();
+    Simplify.visit(&mut expr);
+
+    // the terms along with the constant factor that they are multiplied by
+    let mut stack = vec![(&expr, Expr::Literal(1.0))];
+    let mut res: Option<Expr> = None;
+    while let Some((term, factor)) = stack.pop() {
+        let is_constant = |expr: &Expr| !expr.identifiers().contains(&var);
+        match term {
+            Expr::Binary {
+                left,
+                op: op @ (BinOpKind::Plus | BinOpKind::Minus),
+                right,
+            } => {
+                let right_factor = match op {
+                    BinOpKind::Minus => -factor.clone(),
+                    _ => factor.clone(),
+                };
+                stack.push((left, factor));
+                stack.push((right, right_factor));
+            }
+            Expr::Unary {
+                op: UnaryOpKind::Minus,
+                right,
+            } => stack.push((right, -factor)),
+            Expr::Binary {
+                left,
+                op: BinOpKind::Asterisk,
+                right,
+            } if is_constant(left) => stack.push((right, factor * left.as_ref().clone())),
+            Expr::Binary {
+                left,
+                op: BinOpKind::Asterisk,
+                right,
+            } if is_constant(right) => stack.push((left, factor * right.as_ref().clone())),
+            Expr::Binary {
+                left,
+                op: BinOpKind::Slash,
+                right,
+            } if is_constant(right) && !is_constant(left) => {
+                stack.push((left, Expr::binary(factor, BinOpKind::Slash, *right.clone())))
+            }
+            _ => {
+                let (coefficient, antiderivative) = integrate_term(term, var)
+                    .ok_or_else(|| IntegrationError::Unsupported(term.clone()))?;
+                // the constant factors are simplified on their own so that the numbers are folded, e.g. `2 * (1 / 2)`
+                let mut factor = factor * coefficient;
+                Simplify.visit(&mut factor);
+                let term = match factor {
+                    Expr::Literal(1.0) => antiderivative,
+                    factor => factor * antiderivative,
+                };
+                res = Some(match res {
+                    Some(sum) => sum + term,
+                    None => term,
+                });
+            }
+        }
+    }
+
+    let mut res = res.expect("the input is at least one term");
+    Simplify.visit(&mut res);
+    Ok(res)
+}
+
+/// Returns the antiderivative of a term that is not a sum or a constant multiple as a constant coefficient and the
+/// expression that it multiplies, or `None` if it is not supported.
+fn integrate_term(term: &Expr, var: &str) -> Option<(Expr, Expr)> {
+    if !term.identifiers().contains(&var) {
+        return Some((term.clone(), Expr::Identifier(var.to_string())));
+    }
+    match term {
+        Expr::Identifier(_) => power_antiderivative(term, Expr::Literal(1.0), var),
+        // u ^ n, where n is constant
+        Expr::Binary {
+            left,
+            op: BinOpKind::Exponent,
+            right,
+        } if !right.identifiers().contains(&var) => {
+            power_antiderivative(left, right.as_ref().clone(), var)
+        }
+        // a ^ u = exp(u * ln(a)), where a is constant
+        Expr::Binary {
+            left,
+            op: BinOpKind::Exponent,
+            right,
+        } if !left.identifiers().contains(&var) => {
+            let slope = linear_slope(right, var)?;
+            let ln = Expr::Call {
+                func: "ln".to_string(),
+                args: vec![left.as_ref().clone()],
+            };
+            Some((reciprocal(ln * slope), term.clone()))
+        }
+        // c / u ^ n = c * u ^ -n, where c and n are constant
+        Expr::Binary {
+            left,
+            op: BinOpKind::Slash,
+            right,
+        } if !left.identifiers().contains(&var) => {
+            let (base, exponent) = match right.as_ref() {
+                Expr::Binary {
+                    left: base,
+                    op: BinOpKind::Exponent,
+                    right: exponent,
+                } if !exponent.identifiers().contains(&var) => {
+                    (base.as_ref(), -exponent.as_ref().clone())
+                }
+                _ => (right.as_ref(), Expr::Literal(-1.0)),
+            };
+            let (coefficient, antiderivative) = power_antiderivative(base, exponent, var)?;
+            Some((left.as_ref().clone() * coefficient, antiderivative))
+        }
+        Expr::Call { func, args } if args.len() == 1 => {
+            let slope = linear_slope(&args[0], var)?;
+            let antiderivative = match func.as_str() {
+                "exp" => term.clone(),
+                // ln(u) = u * ln(u) - u
+                "ln" => args[0].clone() * term.clone() - args[0].clone(),
+                _ => return None,
+            };
+            Some((reciprocal(slope), antiderivative))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the antiderivative of `base ^ exponent`, where `exponent` is constant and `base` is linear in `var`:
+/// `u ^ (n + 1) / ((n + 1) * u')`, or `ln(abs(u)) / u'` if `n = -1`.
+fn power_antiderivative(base: &Expr, exponent: Expr, var: &str) -> Option<(Expr, Expr)> {
+    let slope = linear_slope(base, var)?;
+    let mut n_plus_1 = exponent + Expr::Literal(1.0);
+    Simplify.visit(&mut n_plus_1);
+    if n_plus_1 == Expr::Literal(0.0) {
+        let abs = Expr::Call {
+            func: "abs".to_string(),
+            args: vec![base.clone()],
+        };
+        let ln = Expr::Call {
+            func: "ln".to_string(),
+            args: vec![abs],
+        };
+        Some((reciprocal(slope), ln))
+    } else {
+        let pow = Expr::pow(base.clone(), n_plus_1.clone());
+        Some((reciprocal(n_plus_1 * slope), pow))
+    }
+}
+
+/// Returns the derivative of `inner` with respect to `var` if it is a constant other than `0`, i.e. if `inner` is a
+/// linear function of `var`. Terms composed with a linear function are integrated by dividing by its slope.
+fn linear_slope(inner: &Expr, var: &str) -> Option<Expr> {
+    let mut slope = try_partial_derivative(inner, var, &Limits::default()).ok()?;
+    Simplify.visit(&mut slope);
+    (!slope.identifiers().contains(&var) && slope != Expr::Literal(0.0)).then_some(slope)
+}
+
+/// Returns `1 / expr`.
+fn reciprocal(expr: Expr) -> Expr {
+    Expr::binary(Expr::Literal(1.0), BinOpKind::Slash, expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{verify_antiderivative, Equivalence};
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+
+    fn parse(input: &str) -> Expr {
+        Parser::from(Token::lexer(input).spanned()).parse()
+    }
+
+    #[test]
+    fn test_integrate() {
+        let cases = [
+            ("3", "(3 * x)"),
+            ("x", "((1 / 2) * (x ^ 2))"),
+            (
+                "x ^ 3 + 2 * x - 5",
+                "(((x ^ 2) + ((1 / 4) * (x ^ 4))) + ((-5) * x))",
+            ),
+            (
+                "a * x ^ 2 / 3 - x / 2",
+                "((((-1) / 4) * (x ^ 2)) + ((((1 / 3) * a) * (1 / 3)) * (x ^ 3)))",
+            ),
+            ("1 / x", "ln(abs(x))"),
+            ("3 / x ^ 2", "((-3) * (x ^ (-1)))"),
+            ("x ^ (1 / 2)", "((2 / 3) * (x ^ (3 / 2)))"),
+            ("(2 * x + 1) ^ 3", "((1 / 8) * ((1 + (2 * x)) ^ 4))"),
+            ("-exp(-x)", "exp((-x))"),
+            ("2 ^ x", "((1 / ln(2)) * (2 ^ x))"),
+            ("ln(x)", "((x * ln(x)) - x)"),
+        ];
+        for (input, expected) in cases {
+            let f = parse(input);
+            let res = integrate(&f, "x").unwrap();
+            assert_eq!(res.to_string(), expected, "input: {}", input);
+            assert_eq!(
+                verify_antiderivative(&f, &res, "x"),
+                Ok(Equivalence::Equivalent),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_integrate_unsupported() {
+        let cases = [
+            (
+                "x * exp(x)",
+                "cannot compute the antiderivative of `(x * exp(x))`",
+            ),
+            (
+                "2 * x + exp(x ^ 2)",
+                "cannot compute the antiderivative of `exp(x ^ 2)`",
+            ),
+            (
+                "1 / (x ^ 2 + 1)",
+                "cannot compute the antiderivative of `(1 / (1 + (x ^ 2)))`",
+            ),
+            ("x ^ x", "cannot compute the antiderivative of `(x ^ x)`"),
+        ];
+        for (input, expected) in cases {
+            let err = integrate(&parse(input), "x").unwrap_err();
+            assert_eq!(err.to_string(), expected, "input: {}", input);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Finds likely mistakes in a [`RuleTransformSet`], e.g. rules that can never fire or that loop.
+//!
+//! The checks compare the patterns with each other rather than running the rules, so they are conservative: rules
+//! with a handler or a guard are only reported when that does not depend on what the handler returns or whether the
+//! guard holds.
+
+use crate::rule::parser::{RuleExpr, RuleFunc};
+use crate::transformations::{RuleTransformSet, TransformOut};
+use std::collections::BTreeMap;
+
+/// A likely mistake in a [`RuleTransformSet`]. Rules are identified by their index in [`RuleTransformSet::rules`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// Every expression matched by `rule` is matched by the earlier rule `by`, so `rule` can never fire.
+    Shadowed { rule: usize, by: usize },
+    /// The output of `first` is matched by the pattern of `second` and the other way around, so the rules may rewrite
+    /// each other's output forever. `first` and `second` are the same for a rule that matches its own output.
+    Loop { first: usize, second: usize },
+}
+
+impl Lint {
+    /// Returns a human readable description of the lint, naming the rules of `rules`.
+    pub fn message(&self, rules: &RuleTransformSet) -> String {
+        let rules = rules.rules();
+        match *self {
+            Lint::Shadowed { rule, by } => format!(
+                "rule `{}` can never fire because `{}` matches first",
+                rules[rule], rules[by]
+            ),
+            Lint::Loop { first, second } if first == second => {
+                format!("rule `{}` matches its own output", rules[first])
+            }
+            Lint::Loop { first, second } => format!(
+                "rules `{}` and `{}` rewrite each other's output",
+                rules[first], rules[second]
+            ),
+        }
+    }
+}
+
+/// Returns the lints of `rules`, shadowed rules first, each in application order.
+pub fn lint(rules: &RuleTransformSet) -> Vec<Lint> {
+    let rules = rules.rules();
+    let mut lints = Vec::new();
+
+    for (i, rule) in rules.iter().enumerate() {
+        // a handler or a guard can decline to rewrite, in which case the following rules are tried
+        let by = rules[..i].iter().position(|earlier| {
+            matches!(earlier.out, TransformOut::OutPattern(_))
+                && earlier.guard.is_none()
+                && subsumes(&earlier.pattern, &rule.pattern)
+        });
+        if let Some(by) = by {
+            lints.push(Lint::Shadowed { rule: i, by });
+        }
+    }
+
+    // the output of a handler is unknown and a guard may stop the loop
+    let outputs: Vec<_> = rules
+        .iter()
+        .enumerate()
+        .filter_map(|(i, rule)| match &rule.out {
+            TransformOut::OutPattern(out) if rule.guard.is_none() => Some((i, &rule.pattern, out)),
+            _ => None,
+        })
+        .collect();
+    for (n, (first, first_pattern, first_out)) in outputs.iter().enumerate() {
+        for (second, second_pattern, second_out) in &outputs[n..] {
+            if subsumes(second_pattern, first_out) && subsumes(first_pattern, second_out) {
+                lints.push(Lint::Loop {
+                    first: *first,
+                    second: *second,
+                });
+            }
+        }
+    }
+
+    lints
+}
+
+/// The sub-patterns and functions bound to the wildcards of the general pattern in [`subsumes`].
+#[derive(Default)]
+struct Bindings<'a> {
+    exprs: BTreeMap<i32, &'a RuleExpr>,
+    funcs: BTreeMap<i32, &'a RuleFunc>,
+}
+
+/// Returns `true` if every expression matched by `specific` is also matched by `general`. The wildcards of `specific`
+/// stand for any expression they can match and [`RuleExpr::Computed`] stands for any literal, so that `specific` can
+/// also be the output of a rule.
+fn subsumes(general: &RuleExpr, specific: &RuleExpr) -> bool {
+    subsumes_inner(general, specific, &mut Bindings::default())
+}
+
+fn subsumes_inner<'a>(
+    general: &RuleExpr,
+    specific: &'a RuleExpr,
+    bindings: &mut Bindings<'a>,
+) -> bool {
+    let mut bind = |id: i32, specific: &'a RuleExpr| match bindings.exprs.get(&id) {
+        Some(existing) => *existing == specific,
+        None => {
+            bindings.exprs.insert(id, specific);
+            true
+        }
+    };
+
+    match general {
+        RuleExpr::Literal(num) => matches!(specific, RuleExpr::Literal(other) if other == num),
+        RuleExpr::Constant(constant) => {
+            matches!(specific, RuleExpr::Constant(other) if other == constant)
+        }
+        RuleExpr::AnySubExpr(id) => bind(*id, specific),
+        RuleExpr::AnyLiteral(id) => match specific {
+            RuleExpr::Literal(_)
+            | RuleExpr::AnyLiteral(_)
+            | RuleExpr::AnyInteger(_)
+            | RuleExpr::Computed(_) => bind(*id, specific),
+            _ => false,
+        },
+        RuleExpr::AnyInteger(id) => match specific {
+            RuleExpr::Literal(num) if num.fract() == 0.0 => bind(*id, specific),
+            RuleExpr::AnyInteger(_) => bind(*id, specific),
+            _ => false,
+        },
+        RuleExpr::AnyNonLiteral(id) => match specific {
+            RuleExpr::AnyNonLiteral(_)
+            | RuleExpr::Constant(_)
+            | RuleExpr::Binary { .. }
+            | RuleExpr::Unary { .. }
+            | RuleExpr::Call { .. } => bind(*id, specific),
+            _ => false,
+        },
+        RuleExpr::Binary { left, op, right } => match specific {
+            RuleExpr::Binary {
+                left: specific_left,
+                op: specific_op,
+                right: specific_right,
+            } => {
+                op == specific_op
+                    && subsumes_inner(left, specific_left, bindings)
+                    && subsumes_inner(right, specific_right, bindings)
+            }
+            _ => false,
+        },
+        RuleExpr::Unary { op, right } => match specific {
+            RuleExpr::Unary {
+                op: specific_op,
+                right: specific_right,
+            } => op == specific_op && subsumes_inner(right, specific_right, bindings),
+            _ => false,
+        },
+        RuleExpr::Call { func, args } => match specific {
+            RuleExpr::Call {
+                func: specific_func,
+                args: specific_args,
+            } if args.len() == specific_args.len() => {
+                let func_matches = match func {
+                    RuleFunc::Named(_) => func == specific_func,
+                    RuleFunc::Any(id) => match bindings.funcs.get(id) {
+                        Some(existing) => *existing == specific_func,
+                        None => {
+                            bindings.funcs.insert(*id, specific_func);
+                            true
+                        }
+                    },
+                };
+                func_matches
+                    && args
+                        .iter()
+                        .zip(specific_args)
+                        .all(|(arg, specific_arg)| subsumes_inner(arg, specific_arg, bindings))
+            }
+            _ => false,
+        },
+        RuleExpr::Computed(_) | RuleExpr::Error => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(rules: &RuleTransformSet) -> Vec<String> {
+        lint(rules).iter().map(|lint| lint.message(rules)).collect()
+    }
+
+    #[test]
+    fn test_shadowed() {
+        let rules = RuleTransformSet::new_from_str(
+            &[
+                ("_1 * _lit2", "_lit2 * _1"),
+                ("_1 * 1", "_1"),
+                ("_1 * _1", "_1 ^ 2"),
+                ("_f1(_1) + _f1(_1)", "2 * _f1(_1)"),
+                ("abs(_1) + abs(_1)", "2 * abs(_1)"),
+            ],
+            &[],
+        );
+        assert_eq!(
+            lint(&rules),
+            [
+                Lint::Shadowed { rule: 1, by: 0 },
+                Lint::Shadowed { rule: 4, by: 3 }
+            ]
+        );
+        assert_eq!(
+            messages(&rules)[0],
+            "rule `(_1 * 1)` can never fire because `(_1 * _lit2)` matches first"
+        );
+    }
+
+    #[test]
+    fn test_loops() {
+        let rules = RuleTransformSet::new_from_str(
+            &[
+                ("_1 ^ -1", "1 / _1"),
+                ("1 / _1", "_1 ^ -1"),
+                ("_1 + _2", "_2 + _1"),
+                ("_lit1 * _lit2", "{_lit1 * _lit2}"),
+            ],
+            &[],
+        );
+        assert_eq!(
+            messages(&rules),
+            [
+                "rules `(_1 ^ (-1))` and `(1 / _1)` rewrite each other's output",
+                "rule `(_1 + _2)` matches its own output"
+            ]
+        );
+    }
+}
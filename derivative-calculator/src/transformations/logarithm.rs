@@ -0,0 +1,169 @@
+//! Rules for the natural logarithm `ln` and the exponential `exp`, e.g. to make derivatives of logarithms readable.
+//!
+//! The [`identities`] are also applied by [`crate::transformations::simplify::Simplify`]. The [`expansions`] are not,
+//! apply them with [`ExpandLogarithms`] instead, e.g. with [`crate::pipeline::PipelineOptions::with_expand_logarithms`].
+
+use crate::analysis::Assumptions;
+use crate::constants::Constant;
+use crate::parser::{walk_expr, BinOpKind, Expr, ExprVisitor};
+use crate::transformations::RuleTransformSet;
+use lazy_static::lazy_static;
+
+/// The rules of the [`identities`], shared with the simplification rules.
+pub(super) const IDENTITY_RULES: &[(&str, &str)] = &[
+    ("ln(exp(_1))", "_1"),
+    ("ln(1)", "0"),
+    ("exp(0)", "1"),
+    ("ln(e)", "1"),
+    ("ln(e ^ _1)", "_1"),
+];
+
+/// The names of the [`IDENTITY_RULES`].
+pub(super) const IDENTITY_NAMES: &[(&str, &str)] = &[
+    ("ln(exp(_1))", "logarithm of exponential"),
+    ("ln(1)", "logarithm of one"),
+    ("exp(0)", "exponential of zero"),
+    ("ln(e)", "logarithm of e"),
+    ("ln(e ^ _1)", "logarithm of a power of e"),
+];
+
+lazy_static! {
+    static ref IDENTITIES: RuleTransformSet<'static> =
+        RuleTransformSet::new_from_str(IDENTITY_RULES, &[]).with_names(IDENTITY_NAMES);
+
+    // only valid for positive arguments, e.g. `ln(x * y)` is defined for `x = y = -1` but `ln(x) + ln(y)` is not
+    static ref EXPANSIONS: RuleTransformSet<'static> = RuleTransformSet::new_from_str(&[
+        ("ln(_1 * _2)", "ln(_1) + ln(_2)"),
+        ("ln(_1 / _2)", "ln(_1) - ln(_2)"),
+        ("ln(_1 ^ _2)", "_2 * ln(_1)"),
+    ], &[])
+    .with_names(&[
+        ("ln(_1 * _2)", "logarithm of product"),
+        ("ln(_1 / _2)", "logarithm of quotient"),
+        ("ln(_1 ^ _2)", "logarithm of power"),
+    ]);
+}
+
+/// Returns the identities that hold for every argument, e.g. `ln(exp(x)) = x`.
+pub fn identities() -> &'static RuleTransformSet<'static> {
+    &IDENTITIES
+}
+
+/// Returns the rules that expand logarithms of products, quotients and powers, e.g. `ln(x ^ 2) = 2 * ln(x)`. They
+/// assume that the arguments of the logarithms are positive, so they are opt in.
+pub fn expansions() -> &'static RuleTransformSet<'static> {
+    &EXPANSIONS
+}
+
+/// Applies the [`identities`].
+pub struct SimplifyLogarithms;
+
+impl ExprVisitor for SimplifyLogarithms {
+    fn visit(&mut self, expr: &mut Expr) {
+        walk_expr(expr, self);
+        IDENTITIES.apply_rules_incremental(expr);
+    }
+}
+
+/// Applies the [`identities`] and the [`expansions`]. `exp(ln(u))` and `e ^ ln(u)` are also rewritten to `u`, but only
+/// if `u` is positive under the assumptions since they are undefined otherwise.
+pub struct ExpandLogarithms<'a>(pub &'a Assumptions);
+
+impl ExprVisitor for ExpandLogarithms<'_> {
+    fn visit(&mut self, expr: &mut Expr) {
+        walk_expr(expr, self);
+        IDENTITIES.apply_rules_incremental(expr);
+        if let Some(arg) = exp_of_logarithm(expr) {
+            if self.0.is_positive(arg) {
+                *expr = arg.clone();
+                return;
+            }
+        }
+        EXPANSIONS.apply_rules_incremental(expr);
+    }
+}
+
+/// Returns `u` if `expr` is `exp(ln(u))` or `e ^ ln(u)`.
+fn exp_of_logarithm(expr: &Expr) -> Option<&Expr> {
+    let exponent = match expr {
+        Expr::Call { func, args } if func == "exp" && args.len() == 1 => &args[0],
+        Expr::Binary {
+            left,
+            op: BinOpKind::Exponent,
+            right,
+        } if **left == Expr::Constant(Constant::E) => right,
+        _ => return None,
+    };
+    match exponent {
+        Expr::Call { func, args } if func == "ln" && args.len() == 1 => Some(&args[0]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use crate::transformations::simplify::Simplify;
+    use logos::Logos;
+
+    fn simplify_with(input: &str, mut visitor: impl ExprVisitor) -> String {
+        let mut expr = Parser::from(Token::lexer(input).spanned()).parse();
+        visitor.visit(&mut expr);
+        Simplify.visit(&mut expr);
+        expr.to_string()
+    }
+
+    #[test]
+    fn test_identities() {
+        assert_eq!(
+            simplify_with("ln(exp(x + 1))", SimplifyLogarithms),
+            "(1 + x)"
+        );
+        assert_eq!(simplify_with("2 * exp(0) + ln(1)", SimplifyLogarithms), "2");
+        assert_eq!(
+            simplify_with("ln(e) + ln(e ^ x)", SimplifyLogarithms),
+            "(1 + x)"
+        );
+        // not valid for every argument
+        assert_eq!(
+            simplify_with("exp(ln(x))", SimplifyLogarithms),
+            "exp(ln(x))"
+        );
+        assert_eq!(simplify_with("ln(x * y)", SimplifyLogarithms), "ln(x * y)");
+    }
+
+    #[test]
+    fn test_expansions() {
+        let none = Assumptions::new();
+        assert_eq!(
+            simplify_with("ln(x ^ 2 / y)", ExpandLogarithms(&none)),
+            "((2 * ln(x)) - ln(y))"
+        );
+        assert_eq!(
+            simplify_with("ln(2 * x)", ExpandLogarithms(&none)),
+            "(ln(2) + ln(x))"
+        );
+    }
+
+    #[test]
+    fn test_exp_of_logarithm() {
+        let none = Assumptions::new();
+        let positive = Assumptions::new().with_positive("x");
+        // undefined for `x <= 0`
+        assert_eq!(
+            simplify_with("exp(ln(x))", ExpandLogarithms(&none)),
+            "exp(ln(x))"
+        );
+        assert_eq!(
+            simplify_with("exp(ln(x))", ExpandLogarithms(&positive)),
+            "x"
+        );
+        assert_eq!(simplify_with("e ^ ln(x)", ExpandLogarithms(&positive)), "x");
+        assert_eq!(
+            simplify_with("exp(ln(y ^ 2 + 1))", ExpandLogarithms(&none)),
+            "(1 + (y ^ 2))"
+        );
+    }
+}
@@ -1,32 +1,46 @@
-//! Make expression more readable. For best result, pass expression through [`crate::transformations::Simplify`] before and after.
+//! Make expression more readable. For best result, pass expression through [`crate::transformations::Simplify`] before.
+//! `Simplify` should not run after, since it rewrites some of the readable forms back, e.g. `x - 3` to `(-3) + x`.
 
-use crate::transformations::RuleTransformSet;
-use crate::{
-    parser::{walk_expr, Expr, ExprVisitor},
-    rule::parser::RuleExpr,
-};
+use crate::parser::{walk_expr, Expr, ExprVisitor};
+use crate::transformations::identities::IDENTITIES;
+use crate::transformations::simplify::{literal, reduce_fraction};
+use crate::transformations::{Direction, RuleTransformSet};
 use lazy_static::lazy_static;
 
 lazy_static! {
     static ref PRETTIFY_TRANSFORMS: RuleTransformSet<'static> = RuleTransformSet::new_from_str(
-        &[("0.5", "1 / 2"),],
         &[
-            // change negative exponent to division
-            ("_1 ^ _lit2",
-            &|res| match res.matched_exprs.get(&2).unwrap() {
-                Expr::Literal(num) =>
-                    if *num < 0.0 {
-                        Some(
-                            RuleExpr::new_rule_from_str("1 / _1 ^ -_lit2")
-                                .write_expr(&res.matched_exprs),
-                        )
-                    } else {
-                        None
-                    },
-                _ => unreachable!(),
-            })
+            // move the sign of a fraction to the numerator
+            ("-(_int1 / _int2)", "{-_int1} / _int2"),
+            // write negations with a unary minus, e.g. 0 - x = -x
+            ("0 - _1", "-_1"),
+            ("-1 * _1", "-_1"),
+            ("(-_1) * _2", "-(_1 * _2)"),
+            ("_1 * (-_2)", "-(_1 * _2)"),
+            ("(-_1) + _2", "_2 - _1"),
+            ("_1 + (-_2)", "_1 - _2"),
+            ("_1 - (-_2)", "_1 + _2"),
+            ("-(-_1)", "_1"),
+            // the same with negative literals, e.g. (-3) + x = x - 3 and (-2) * x = -(2 * x)
+            ("{-_lit1} * _2", "-(_lit1 * _2) if _lit1 > 0"),
+            ("{-_lit1} / _nonlit2", "-(_lit1 / _nonlit2) if _lit1 > 0"),
+            ("{-_lit1} + _2", "_2 - _lit1 if _lit1 > 0"),
+            ("_1 + {-_lit2}", "_1 - _lit2 if _lit2 > 0"),
+            ("_1 - {-_lit2}", "_1 + _lit2 if _lit2 > 0"),
+            // write the power `1 / 2` as a square root, `0.5` is rewritten to `1 / 2` by the identities first
+            ("_1 ^ (1 / 2)", "sqrt(_1)"),
+            // the power of one left by a negative exponent, e.g. x ^ -1 = 1 / x ^ 1
+            ("_1 ^ 1", "_1"),
+            // write the reciprocals left by negative exponents as a single fraction, e.g. 2 * (1 / x) = 2 / x
+            ("_1 * (1 / _2)", "_1 / _2"),
+            ("(_1 / _2) / _3", "_1 / (_2 * _3)"),
+        ],
+        &[
+            // reduce fractions, e.g. 6 / 4 = 3 / 2
+            ("_int1 / _int2", &|res| reduce_fraction(literal(res, 1), literal(res, 2))),
         ]
-    );
+    )
+    .with_identities(IDENTITIES, Direction::LeftToRight);
 }
 
 pub struct Prettify;
@@ -35,9 +49,69 @@ impl ExprVisitor for Prettify {
     fn visit(&mut self, expr: &mut Expr) {
         walk_expr(expr, self);
 
-        *expr = PRETTIFY_TRANSFORMS.apply_rules(expr);
+        // only the newly created ast nodes need to be visited again
+        PRETTIFY_TRANSFORMS.apply_rules_incremental(expr);
+    }
+}
 
-        // simplify any newly created ast nodes
-        walk_expr(expr, self);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::Parser;
+    use logos::Logos;
+
+    fn prettify_str(input: &str) -> String {
+        let mut expr = Parser::from(Token::lexer(input).spanned()).parse();
+        Prettify.visit(&mut expr);
+        expr.to_string()
+    }
+
+    #[test]
+    fn test_negations() {
+        assert_eq!(prettify_str("-1 * x ^ 2"), "(-(x ^ 2))");
+        assert_eq!(prettify_str("-2 * x"), "(-(2 * x))");
+        assert_eq!(prettify_str("-2 * -x"), "(2 * x)");
+        assert_eq!(prettify_str("x * -y"), "(-(x * y))");
+        assert_eq!(prettify_str("-3 + x"), "(x - 3)");
+        assert_eq!(prettify_str("x + -3"), "(x - 3)");
+        assert_eq!(prettify_str("x - -3"), "(x + 3)");
+        assert_eq!(prettify_str("-1.5 + x ^ 2"), "((x ^ 2) - 1.5)");
+        assert_eq!(prettify_str("-3"), "(-3)");
+        assert_eq!(prettify_str("-1 / x"), "(-(1 / x))");
+        assert_eq!(prettify_str("-1 / 3"), "((-1) / 3)");
+        assert_eq!(prettify_str("0 - 4 * x"), "(-(4 * x))");
+        assert_eq!(prettify_str("(-x) * y"), "(-(x * y))");
+        assert_eq!(prettify_str("(-x) + y"), "(y - x)");
+        assert_eq!(prettify_str("y + (-1) * x"), "(y - x)");
+        assert_eq!(prettify_str("2 * x"), "(2 * x)");
+    }
+
+    #[test]
+    fn test_reduce_fractions() {
+        assert_eq!(prettify_str("6 / 4"), "(3 / 2)");
+        assert_eq!(prettify_str("6 / -4 * x"), "(((-3) / 2) * x)");
+        assert_eq!(prettify_str("-(2 / 6)"), "((-1) / 3)");
+        assert_eq!(prettify_str("-(3 / 4)"), "((-3) / 4)");
+        assert_eq!(prettify_str("8 / 4"), "2");
+        assert_eq!(prettify_str("x / 4"), "(x / 4)");
+    }
+
+    #[test]
+    fn test_square_roots() {
+        assert_eq!(prettify_str("x ^ 0.5"), "sqrt(x)");
+        assert_eq!(prettify_str("√(x + 1)"), "sqrt(x + 1)");
+        assert_eq!(prettify_str("x ^ (1 / 2) * 2"), "(sqrt(x) * 2)");
+        assert_eq!(prettify_str("x ^ -0.5"), "(1 / sqrt(x))");
+        assert_eq!(prettify_str("x ^ 1.5"), "(x ^ 1.5)");
+    }
+
+    #[test]
+    fn test_negative_exponents() {
+        assert_eq!(prettify_str("x ^ -1"), "(1 / x)");
+        assert_eq!(prettify_str("2 * x ^ -3"), "(2 / (x ^ 3))");
+        assert_eq!(prettify_str("-2 * x ^ -3"), "(-(2 / (x ^ 3)))");
+        assert_eq!(prettify_str("(x + 1) ^ -2.5"), "(1 / ((x + 1) ^ 2.5))");
+        assert_eq!(prettify_str("x ^ 2"), "(x ^ 2)");
     }
 }
@@ -0,0 +1,161 @@
+//! Profiling of the rules applied by [`RuleTransformSet`](super::RuleTransformSet), e.g. to find the rules that make
+//! simplifying slow.
+//!
+//! The rule sets are shared statics, so the statistics are collected in a thread local while [`profile_rules`] runs
+//! instead of in the sets themselves.
+
+use super::Transformation;
+use crate::parser::Expr;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The statistics of the rules with the same name, see [`Transformation::name`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleStats {
+    /// The human readable name of the rule, or its pattern if it is unnamed.
+    pub name: String,
+    /// The number of times the pattern was matched against an expression.
+    pub attempts: usize,
+    /// The number of times the rule rewrote an expression.
+    pub fired: usize,
+    /// The time spent matching and rewriting in milliseconds, including the rules applied by its handler.
+    pub millis: f64,
+}
+
+/// The statistics of every rule that was tried while [`profile_rules`] ran, slowest first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleProfile {
+    pub rules: Vec<RuleStats>,
+}
+
+impl RuleProfile {
+    /// Returns the statistics of the rule called `name`, if it was tried.
+    pub fn get(&self, name: &str) -> Option<&RuleStats> {
+        self.rules.iter().find(|stats| stats.name == name)
+    }
+
+    /// Returns the number of rewrites by all the rules.
+    pub fn total_fired(&self) -> usize {
+        self.rules.iter().map(|stats| stats.fired).sum()
+    }
+}
+
+/// Writes the statistics as a table with aligned columns, one rule per line.
+impl fmt::Display for RuleProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .rules
+            .iter()
+            .map(|stats| stats.name.chars().count())
+            .chain(["rule".len()])
+            .max()
+            .unwrap_or(0);
+        write!(
+            f,
+            "{:width$}  {:>8}  {:>8}  {:>8}",
+            "rule", "tried", "fired", "ms"
+        )?;
+        for stats in &self.rules {
+            write!(
+                f,
+                "\n{:width$}  {:>8}  {:>8}  {:>8.3}",
+                stats.name, stats.attempts, stats.fired, stats.millis
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct Profiler {
+    clock: fn() -> f64,
+    stats: HashMap<String, RuleStats>,
+}
+
+thread_local! {
+    static PROFILER: RefCell<Option<Profiler>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` and returns its result along with the statistics of the rules it applied. `clock` returns the current
+/// time in milliseconds, like the clock of [`crate::pipeline::run_pipeline`].
+///
+/// Reading the clock around every rule makes applying rules noticeably slower, so this is meant for debugging.
+/// Nested calls profile the rules of the inner call only.
+pub fn profile_rules<R>(clock: fn() -> f64, f: impl FnOnce() -> R) -> (R, RuleProfile) {
+    let profiler = Profiler {
+        clock,
+        stats: HashMap::new(),
+    };
+    let outer = PROFILER.with(|cell| cell.replace(Some(profiler)));
+    let res = f();
+    let profiler = PROFILER
+        .with(|cell| cell.replace(outer))
+        .expect("the profiler was set above");
+
+    let mut rules: Vec<_> = profiler.stats.into_values().collect();
+    rules.sort_by(|a, b| {
+        b.millis
+            .total_cmp(&a.millis)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    (res, RuleProfile { rules })
+}
+
+/// Runs `f`, which tries `transform` and returns whether its pattern matched along with the rewritten expression, and
+/// records it if rules are being profiled. A match without a rewrite, i.e. a handler returning `None`, did not fire.
+pub(crate) fn timed(
+    transform: &Transformation,
+    f: impl FnOnce() -> (bool, Option<Expr>),
+) -> (bool, Option<Expr>) {
+    let clock = match PROFILER.with(|cell| cell.borrow().as_ref().map(|profiler| profiler.clock)) {
+        Some(clock) => clock,
+        None => return f(),
+    };
+    let start = clock();
+    let (matched, res) = f();
+    let millis = clock() - start;
+    PROFILER.with(|cell| {
+        if let Some(profiler) = cell.borrow_mut().as_mut() {
+            let name = transform.to_string();
+            let stats = profiler
+                .stats
+                .entry(name.clone())
+                .or_insert_with(|| RuleStats {
+                    name,
+                    attempts: 0,
+                    fired: 0,
+                    millis: 0.0,
+                });
+            stats.attempts += 1;
+            stats.fired += usize::from(res.is_some());
+            stats.millis += millis;
+        }
+    });
+    (matched, res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::{ExprVisitor, Parser};
+    use crate::transformations::simplify::Simplify;
+    use logos::Logos;
+
+    #[test]
+    fn test_profile_rules() {
+        let mut expr = Parser::from(Token::lexer("0 + x * 1").spanned()).parse();
+        let ((), profile) = profile_rules(|| 0.0, || Simplify.visit(&mut expr));
+        assert_eq!(expr.to_string(), "x");
+        assert!(profile.total_fired() >= 2);
+        assert!(profile
+            .rules
+            .iter()
+            .all(|stats| stats.fired <= stats.attempts && stats.millis == 0.0));
+        assert!(profile.to_string().starts_with("rule"));
+
+        // nothing is recorded if no rule is applied
+        let ((), profile) = profile_rules(|| 0.0, || {});
+        assert_eq!(profile, RuleProfile::default());
+    }
+}
@@ -1,42 +1,236 @@
 //! Fold constants.
+//!
+//! Operations whose result is not a finite number, e.g. `1 / 0` or `0 ^ -1`, are never folded. See [`NonFinitePolicy`]
+//! for what to do with them instead.
 
-use crate::parser::{walk_expr, Expr, ExprVisitor};
-use crate::transformations::RuleTransformSet;
+use crate::analysis::{is_even, Assumptions};
+use crate::eval::eval;
+use crate::parser::{walk_expr, BinOpKind, Expr, ExprVisitor};
+use crate::rule::parser::RuleExpr;
+use crate::rule::{MatchResult, Tolerance};
+use crate::transformations::logarithm;
+use crate::transformations::{ApplyRuleError, Direction, RuleTransformSet, Transformation};
 use lazy_static::lazy_static;
+use std::collections::HashMap;
+
+/// Every integer up to this magnitude can be represented exactly by a `f64`.
+const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_992.0; // 2 ^ 53
+
+/// Returns the literal matched by the wildcard `id`.
+pub(super) fn literal(res: &MatchResult, id: i32) -> f64 {
+    match res.matched_exprs.get(&id) {
+        Some(Expr::Literal(num)) => *num,
+        _ => unreachable!("wildcard should match a literal"),
+    }
+}
+
+/// Returns `num` as an integer if it is an integer that is represented exactly.
+fn exact_integer(num: f64) -> Option<i128> {
+    if Tolerance::EXACT.is_integer(num) && num.abs() <= MAX_EXACT_INTEGER {
+        Some(num as i128)
+    } else {
+        None
+    }
+}
+
+/// Folds two literals. If both literals are exact integers, `int_op` is used and the result is only folded if it is
+/// an exact integer as well. Otherwise the literals are folded with `float_op` if the result is finite, see
+/// [`fold_float`].
+fn fold(
+    num1: f64,
+    num2: f64,
+    int_op: fn(i128, i128) -> Option<i128>,
+    float_op: fn(f64, f64) -> f64,
+) -> Option<Expr> {
+    match (exact_integer(num1), exact_integer(num2)) {
+        (Some(int1), Some(int2)) => int_op(int1, int2)
+            .filter(|int| int.unsigned_abs() <= MAX_EXACT_INTEGER as u128)
+            .map(|int| Expr::Literal(int as f64)),
+        _ => fold_float(float_op(num1, num2)),
+    }
+}
+
+/// Returns the folded value `num` if it is finite. Its rounding error is removed if it is close to an integer, so that
+/// e.g. `0.1 * 3 * 10` is `3` and still matches the identity rules, see [`Tolerance::FOLDING`].
+fn fold_float(num: f64) -> Option<Expr> {
+    Some(num)
+        .filter(|num| num.is_finite())
+        .map(|num| Expr::Literal(Tolerance::FOLDING.round(num)))
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+/// Returns the quotient of two integer literals as a fraction in lowest terms with a positive denominator, or as an
+/// integer if it divides evenly, e.g. `6 / -4 = (-3) / 2`. Returns `None` if the quotient is already normalized, one
+/// of the literals is not an exact integer or the denominator is 0.
+pub(super) fn reduce_fraction(num1: f64, num2: f64) -> Option<Expr> {
+    match (exact_integer(num1), exact_integer(num2)) {
+        (Some(int1), Some(int2)) if int2 != 0 => {
+            let divisor = gcd(int1, int2) * int2.signum();
+            let (numerator, denominator) = (int1 / divisor, int2 / divisor);
+            if denominator == 1 {
+                Some(Expr::Literal(numerator as f64))
+            } else if (numerator, denominator) == (int1, int2) {
+                // already in lowest terms
+                None
+            } else {
+                Some(Expr::Binary {
+                    left: Box::new(Expr::Literal(numerator as f64)),
+                    op: BinOpKind::Slash,
+                    right: Box::new(Expr::Literal(denominator as f64)),
+                })
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Folds the division of two literals. The quotient of two integers is kept as a fraction, see [`reduce_fraction`].
+fn fold_division(num1: f64, num2: f64) -> Option<Expr> {
+    match (exact_integer(num1), exact_integer(num2)) {
+        (Some(_), Some(int2)) if int2 != 0 => reduce_fraction(num1, num2),
+        _ => fold_float(num1 / num2),
+    }
+}
+
+/// Returns whether `expr` contains [`Expr::Undefined`] or an undefined operation, see [`is_undefined_operation`].
+pub(crate) fn contains_undefined(expr: &Expr) -> bool {
+    let undefined = |expr: &Expr| *expr == Expr::Undefined || is_undefined_operation(expr);
+    !expr.find_paths(undefined).is_empty()
+}
+
+/// Returns `out` if the operand `_1` does not contain anything undefined, see [`contains_undefined`].
+pub(super) fn cancel(res: &MatchResult, out: Expr) -> Option<Expr> {
+    (!contains_undefined(res.matched_exprs[&1])).then_some(out)
+}
+
+/// Folds the power of two literals. An integer to a negative integer power is kept as the fraction
+/// `1 / int1 ^ abs(int2)`, e.g. `2 ^ -3 = 1 / 8`, see [`reduce_fraction`].
+fn fold_power(num1: f64, num2: f64) -> Option<Expr> {
+    match (exact_integer(num1), exact_integer(num2)) {
+        (Some(int1), Some(int2)) if int2 < 0 => {
+            let denominator = u32::try_from(int2.unsigned_abs())
+                .ok()
+                .and_then(|int2| int1.checked_pow(int2))
+                .filter(|int| *int != 0 && int.unsigned_abs() <= MAX_EXACT_INTEGER as u128)?;
+            Some(reduce_fraction(1.0, denominator as f64).unwrap_or_else(|| {
+                Expr::binary(
+                    Expr::Literal(1.0),
+                    BinOpKind::Slash,
+                    Expr::Literal(denominator as f64),
+                )
+            }))
+        }
+        _ => fold(
+            num1,
+            num2,
+            |int1, int2| {
+                u32::try_from(int2)
+                    .ok()
+                    .and_then(|int2| int1.checked_pow(int2))
+            },
+            f64::powf,
+        ),
+    }
+}
+
+/// Folds the operation `num1 op num2` like [`Simplify`] does, e.g. `2 ^ -1` is the fraction `1 / 2`. Returns `None` if
+/// the operation should be kept as written, e.g. because its result is not finite or it is a fraction in lowest terms.
+pub(crate) fn fold_literals(num1: f64, op: BinOpKind, num2: f64) -> Option<Expr> {
+    match op {
+        BinOpKind::Plus => fold(num1, num2, i128::checked_add, |num1, num2| num1 + num2),
+        BinOpKind::Minus => fold(num1, num2, i128::checked_sub, |num1, num2| num1 - num2),
+        BinOpKind::Asterisk => fold(num1, num2, i128::checked_mul, |num1, num2| num1 * num2),
+        BinOpKind::Slash => fold_division(num1, num2),
+        BinOpKind::Exponent => fold_power(num1, num2),
+    }
+}
+
+/// Folds the literals `_lit1` and `_lit2` of the operation `_lit1 op _lit2`, see [`fold_literals`].
+fn fold_matched(res: &MatchResult, op: BinOpKind) -> Option<Expr> {
+    fold_literals(literal(res, 1), op, literal(res, 2))
+}
+
+/// Rewrites `(u ^ n) ^ p` to `abs(u) ^ (n * p)` for an even `n` and a `p` that is not an integer, e.g. `(x ^ 2) ^ 0.5`
+/// is `abs(x)` rather than `x`. `u ^ n` is `abs(u) ^ n` for an even `n`, so this never drops the sign of `u`. Integer
+/// `p` are left to the power of a power rule, e.g. `(x ^ 2) ^ 3` is `x ^ 6`.
+fn even_power_of_power(res: &MatchResult) -> Option<Expr> {
+    let n = literal(res, 2);
+    if !is_even(n) {
+        return None;
+    }
+    let base = RuleExpr::new_rule_from_str("abs(_1)").write_expr(&res.matched_exprs)?;
+    // the exponent is folded here since the root is not simplified again after its children
+    let exponent = match res.matched_exprs[&3] {
+        Expr::Literal(p) if Tolerance::EXACT.is_integer(*p) => return None,
+        Expr::Literal(p) => Expr::Literal(n * p),
+        Expr::Binary {
+            left,
+            op: BinOpKind::Slash,
+            right,
+        } => match (&**left, &**right) {
+            (Expr::Literal(numerator), Expr::Literal(denominator)) => {
+                let numerator = n * numerator;
+                reduce_fraction(numerator, *denominator).unwrap_or_else(|| {
+                    Expr::binary(
+                        Expr::Literal(numerator),
+                        BinOpKind::Slash,
+                        Expr::Literal(*denominator),
+                    )
+                })
+            }
+            _ => Expr::binary(
+                Expr::Literal(n),
+                BinOpKind::Asterisk,
+                res.matched_exprs[&3].clone(),
+            ),
+        },
+        p => Expr::binary(Expr::Literal(n), BinOpKind::Asterisk, p.clone()),
+    };
+    Some(Expr::pow(base, exponent))
+}
+
+/// Rewrites `abs(u) ^ n` to `u ^ n` for an even `n`.
+fn even_power_of_abs(res: &MatchResult) -> Option<Expr> {
+    if !is_even(literal(res, 2)) {
+        return None;
+    }
+    RuleExpr::new_rule_from_str("_1 ^ _int2").write_expr(&res.matched_exprs)
+}
 
 lazy_static! {
     static ref SIMPLIFY_TRANSFORMS: RuleTransformSet<'static> = RuleTransformSet::new_from_str(&[
         // addition with 0
         ("0 + _1", "_1"),
         ("_1 + 0", "_1"),
-        // multiplication with 0
-        ("0 * _1", "0"),
-        ("_1 * 0", "0"),
         // multiplication with 1
         ("1 * _1", "_1"),
         ("_1 * 1", "_1"),
         // division with 1
         ("_1 / 1", "_1"),
 
-        ("_1 - _1", "0"),
-        ("_1 + -_1", "0"),
-        ("_1 / _1", "1"),
         ("_1 + _1", "2 * _1"),
 
         // exponentiation identities
-        ("_1 ^ 0", "1"),
         ("_1 ^ 1", "_1"),
-        ("1 ^ _1", "1"),
-        // ("_1 ^ -1", "1 / _1"),
-        ("(_1 ^ _lit2) ^ _lit3", "_1 ^ (_lit2 * _lit3)"), // fold double exponent, e.g. (x ^ 2) ^ 3 = x ^ 6
+        // fold double exponent, e.g. (x ^ 2) ^ 3 = x ^ 6
+        // only valid for integer outer exponents, e.g. (x ^ 2) ^ 0.5 = abs(x)
+        ("(_1 ^ _lit2) ^ _int3", "_1 ^ (_lit2 * _int3)"),
         ("(_1 ^ _2) * (_1 ^ _3)", "_1 ^ (_2 + _3)"),
 
-        ("(_lit1 * _2) / _lit1", "_2"),
         ("(_lit1 * _2) / _lit3", "(_lit1 / _lit3) * _2"),
 
         ("(_2 * _1) + _1", "_1 * (_2 + 1)"),
 
-        ("(_1 / _2) / _3)", "_1 / (_2 * _3)"),
+        // fractions of fractions
+        ("(_1 / _2) / (_3 / _4)", "(_1 * _4) / (_2 * _3)"),
+        ("(_1 / _2) / _3", "_1 / (_2 * _3)"),
+        ("_1 / (_2 / _3)", "(_1 * _3) / _2"),
 
         ("(_1 / _2) * (_3 / _4)", "(_1 * _3) / (_2 * _4)"),
         ("_1 * _1 ^ _2", "_1 ^ (_2 + 1)"),
@@ -45,13 +239,33 @@ lazy_static! {
         ("(_1 ^ _2) * _1", "_1 ^ (_2 + 1)"),
         ("(_1 ^ _2) / _1", "_1 ^ (_2 - 1)"),
         ("(_1 ^ _2) / (_1 ^ _3)", "_1 ^ (_2 - _3)"),
-        ("(_1 / _2) * _2", "_1"),
 
         // simplify operations with commutativity, e.g. 2 * (3 * x) => 6 * x
         ("_lit1 + (_lit2 + _3)", "(_lit1 + _lit2) + _3"), // addition
         ("_lit1 * (_lit2 * _3)", "(_lit1 * _lit2) * _3"), // multiplication
         ("_lit1 * (_lit2 / _3)", "(_lit1 * _lit2) / _3"), // multiplication
 
+        // negations
+        ("-(-_1)", "_1"),
+        ("-(_1 * -_2)", "_1 * _2"),
+        ("-((-_1) * _2)", "_1 * _2"),
+
+        // functions
+        ("abs(abs(_1))", "abs(_1)"),
+        ("sign(sign(_1))", "sign(_1)"),
+        ("abs(-_1)", "abs(_1)"),
+        // pull out constant factors so that their absolute value is folded, e.g. abs(-2 * x) = 2 * abs(x)
+        ("abs(_lit1 * _2)", "abs(_lit1) * abs(_2)"),
+        ("abs(_lit1 / _lit2)", "abs(_lit1) / abs(_lit2)"),
+        ("sign(_1) * abs(_1)", "_1"),
+        // a function of its inverse, the reverse only holds on the principal range, e.g. asin(sin(4)) != 4
+        ("sin(asin(_1))", "_1"),
+        ("cos(acos(_1))", "_1"),
+        ("tan(atan(_1))", "_1"),
+        ("sin(pi)", "0"),
+        ("cos(pi)", "-1"),
+        ("tan(pi)", "0"),
+
         // for normalization purposes
         // ("(_1 + _2) + _3", "_1 + (_2 + _3)"),
         // ("(_1 * _2) * _3", "_1 * (_2 * _3)"),
@@ -60,37 +274,150 @@ lazy_static! {
         ("_nonlit1 + _lit2", "_lit2 + _nonlit1"),
         ("_1 - _lit2", "-_lit2 + _1"), // change minus into plus to fold in one step
         ("_nonlit1 * _lit2", "_lit2 * _nonlit1"),
+
+        // arithmetic on fractions, e.g. 1 / 3 + 1 = 4 / 3
+        ("(_int1 / _int2) + _int3", "{_int1 + _int3 * _int2} / _int2"),
+        ("_int3 + (_int1 / _int2)", "{_int1 + _int3 * _int2} / _int2"),
+        ("(_int1 / _int2) + (_int3 / _int4)", "{_int1 * _int4 + _int3 * _int2} / {_int2 * _int4}"),
+        ("(_int1 / _int2) * _int3", "{_int1 * _int3} / _int2"),
     ], &[
-        // fold aritmatic operators
-        ("_lit1 + _lit2", &|res| match res.matched_exprs.get(&1).unwrap() {
-            Expr::Literal(num1) => match res.matched_exprs.get(&2).unwrap() {
-                Expr::Literal(num2) => Some(Expr::Literal(num1 + num2)),
-                _ => unreachable!()
-            },
-            _ => unreachable!()
+        // drop an operand unless that hides an undefined operation, e.g. `(x / 0) * 0` is not `0`
+        ("0 * _1", &|res| cancel(res, Expr::Literal(0.0))),
+        ("_1 * 0", &|res| cancel(res, Expr::Literal(0.0))),
+        ("_1 ^ 0", &|res| cancel(res, Expr::Literal(1.0))),
+        ("1 ^ _1", &|res| cancel(res, Expr::Literal(1.0))),
+        ("(_lit1 * _2) / _lit1", &|res| {
+            (literal(res, 1) != 0.0 && !contains_undefined(res.matched_exprs[&2]))
+                .then(|| res.matched_exprs[&2].clone())
         }),
-        ("_lit1 * _lit2", &|res| match res.matched_exprs.get(&1).unwrap() {
-            Expr::Literal(num1) => match res.matched_exprs.get(&2).unwrap() {
-                Expr::Literal(num2) => Some(Expr::Literal(num1*num2)),
-                _ => unreachable!()
-            },
-            _ => unreachable!()
-        }),
-        ("_lit1 / _lit2", &|res| match res.matched_exprs.get(&1).unwrap() {
-            Expr::Literal(num1) => match res.matched_exprs.get(&2).unwrap() {
-                Expr::Literal(num2) => Some(Expr::Literal(num1/num2)),
-                _ => unreachable!()
-            },
-            _ => unreachable!()
+        ("(_1 / _2) * _2", &|res| match res.matched_exprs[&2] {
+            Expr::Literal(num) if *num == 0.0 => None,
+            denominator => (!contains_undefined(denominator)).then(|| res.matched_exprs[&1].clone()),
         }),
-        ("_lit1 ^ _lit2", &|res| match res.matched_exprs.get(&1).unwrap() {
-            Expr::Literal(num1) => match res.matched_exprs.get(&2).unwrap() {
-                Expr::Literal(num2) => Some(Expr::Literal(num1.powf(*num2))),
-                _ => unreachable!()
-            },
-            _ => unreachable!()
+        // cancel an operand with itself, unless that hides an undefined operation, e.g. `0 / 0` is not `1`
+        ("_1 - _1", &|res| cancel(res, Expr::Literal(0.0))),
+        ("_1 + -_1", &|res| cancel(res, Expr::Literal(0.0))),
+        ("_1 / _1", &|res| match res.matched_exprs[&1] {
+            Expr::Literal(num) if *num == 0.0 => None,
+            _ => cancel(res, Expr::Literal(1.0)),
         }),
-    ]);
+        // fold aritmatic operators
+        ("-_lit1", &|res| Some(Expr::Literal(-literal(res, 1)))),
+        ("_lit1 + _lit2", &|res| fold_matched(res, BinOpKind::Plus)),
+        ("_lit1 * _lit2", &|res| fold_matched(res, BinOpKind::Asterisk)),
+        ("_lit1 / _lit2", &|res| fold_matched(res, BinOpKind::Slash)),
+        ("_lit1 ^ _lit2", &|res| fold_matched(res, BinOpKind::Exponent)),
+        ("(_1 ^ _int2) ^ _3", &even_power_of_power),
+        ("abs(_1) ^ _int2", &even_power_of_abs),
+        ("abs(_lit1)", &|res| Some(Expr::Literal(literal(res, 1).abs()))),
+    ])
+    .with_names(&[
+        ("0 + _1", "add zero"),
+        ("_1 + 0", "add zero"),
+        ("0 * _1", "multiply by zero"),
+        ("_1 * 0", "multiply by zero"),
+        ("1 * _1", "multiply by one"),
+        ("_1 * 1", "multiply by one"),
+        ("_1 / 1", "divide by one"),
+        ("_1 + _1", "combine like terms"),
+        ("(_2 * _1) + _1", "combine like terms"),
+        ("_1 ^ 0", "power of zero"),
+        ("_1 ^ 1", "power of one"),
+        ("(_1 ^ _lit2) ^ _int3", "power of a power"),
+        ("(_1 ^ _int2) ^ _3", "even power of a power"),
+        ("abs(_1) ^ _int2", "even power of an absolute value"),
+        ("abs(_lit1)", "absolute value of a constant"),
+        ("abs(-_1)", "absolute value of a negation"),
+        ("abs(_lit1 * _2)", "absolute value of a product"),
+        ("abs(_lit1 / _lit2)", "absolute value of a constant"),
+        ("(_1 ^ _2) * (_1 ^ _3)", "product of powers"),
+        ("_1 * _1", "product of powers"),
+        ("_1 * _1 ^ _2", "product of powers"),
+        ("(_1 ^ _2) * _1", "product of powers"),
+        ("(_1 ^ _2) / _1", "quotient of powers"),
+        ("(_1 ^ _2) / (_1 ^ _3)", "quotient of powers"),
+        ("-_lit1", "combine constants"),
+        ("_lit1 + _lit2", "combine constants"),
+        ("_lit1 * _lit2", "combine constants"),
+        ("_lit1 / _lit2", "combine constants"),
+        ("_lit1 ^ _lit2", "combine constants"),
+        ("_nonlit1 + _lit2", "move constants to the left"),
+        ("_nonlit1 * _lit2", "move constants to the left"),
+        ("(_int1 / _int2) + _int3", "combine fractions"),
+        ("_int3 + (_int1 / _int2)", "combine fractions"),
+        ("(_int1 / _int2) + (_int3 / _int4)", "combine fractions"),
+        ("(_int1 / _int2) * _int3", "combine fractions"),
+        ("(_1 / _2) / (_3 / _4)", "compound fraction"),
+        ("(_1 / _2) / _3", "compound fraction"),
+        ("_1 / (_2 / _3)", "compound fraction"),
+        ("sin(pi)", "value at a constant"),
+        ("cos(pi)", "value at a constant"),
+        ("tan(pi)", "value at a constant"),
+        ("-(-_1)", "double negation"),
+        ("-(_1 * -_2)", "double negation"),
+        ("-((-_1) * _2)", "double negation"),
+    ])
+    .with_identities(logarithm::IDENTITY_RULES, Direction::LeftToRight)
+    .with_names(logarithm::IDENTITY_NAMES);
+}
+
+/// Returns the rules applied by [`Simplify`], e.g. to let the user pick one for [`apply_rule_at`].
+pub fn rules() -> &'static [Transformation<'static>] {
+    SIMPLIFY_TRANSFORMS.rules()
+}
+
+/// Applies the simplification rule called `rule` once at `path`, see [`RuleTransformSet::apply_rule_at`]. Unlike
+/// [`Simplify`], nothing else is simplified, so that the user can drive the simplification step by step.
+pub fn apply_rule_at(expr: &Expr, rule: &str, path: &[usize]) -> Result<Expr, ApplyRuleError> {
+    SIMPLIFY_TRANSFORMS.apply_rule_at(expr, rule, path)
+}
+
+/// What to do with the operations that are undefined, see [`is_undefined_operation`]. They are never folded by
+/// [`Simplify`], e.g. to infinity, since that would change the result of the later simplifications.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum NonFinitePolicy {
+    /// Keep the operations as written. [`crate::pipeline`] reports a diagnostic for each of them.
+    #[default]
+    Keep,
+    /// Replace the operations with [`Expr::Undefined`] with [`FoldUndefined`].
+    Undefined,
+}
+
+/// Returns whether `expr` is undefined whatever the values of the identifiers, i.e. it divides by the literal `0` or
+/// it is an operation on literals whose value is not a finite number, e.g. `0 ^ -1`.
+pub fn is_undefined_operation(expr: &Expr) -> bool {
+    match expr {
+        Expr::Binary {
+            left: _,
+            op: BinOpKind::Slash,
+            right,
+        } if matches!(**right, Expr::Literal(0.0)) => true,
+        Expr::Binary { left, op: _, right }
+            if matches!((&**left, &**right), (Expr::Literal(_), Expr::Literal(_))) =>
+        {
+            eval(expr, &HashMap::new()).is_ok_and(|num| !num.is_finite())
+        }
+        _ => false,
+    }
+}
+
+/// Replaces the undefined operations with [`Expr::Undefined`] and propagates it, i.e. an operation with an undefined
+/// operand is undefined as well, e.g. `0 * (1 / 0)` is undefined rather than `0`.
+pub struct FoldUndefined;
+
+impl ExprVisitor for FoldUndefined {
+    fn visit(&mut self, expr: &mut Expr) {
+        walk_expr(expr, self);
+        if is_undefined_operation(expr) || has_undefined_operand(expr) {
+            *expr = Expr::Undefined;
+        }
+    }
+}
+
+fn has_undefined_operand(expr: &Expr) -> bool {
+    expr.children()
+        .into_iter()
+        .any(|child| *child == Expr::Undefined)
 }
 
 pub struct Simplify;
@@ -98,11 +425,70 @@ pub struct Simplify;
 impl ExprVisitor for Simplify {
     fn visit(&mut self, expr: &mut Expr) {
         walk_expr(expr, self);
+        // the rules do not know about undefined values, e.g. `_1 - _1` would turn `undefined - undefined` into `0`
+        if has_undefined_operand(expr) {
+            *expr = Expr::Undefined;
+            return;
+        }
+
+        // only the newly created ast nodes need to be visited again
+        SIMPLIFY_TRANSFORMS.apply_rules_incremental(expr);
+    }
+}
 
-        *expr = SIMPLIFY_TRANSFORMS.apply_rules(expr);
+/// Same as [`Simplify`] but also uses the `assumptions`, e.g. `abs(x)` is `x` if `x` is assumed nonnegative.
+pub struct SimplifyAssuming<'a>(pub &'a Assumptions);
+
+impl ExprVisitor for SimplifyAssuming<'_> {
+    fn visit(&mut self, expr: &mut Expr) {
+        Simplify.visit(expr);
+        let mut remove_abs = RemoveAbs {
+            assumptions: self.0,
+            removed: false,
+        };
+        remove_abs.visit(expr);
+        // the arguments can simplify further with their neighbours, e.g. `abs(x) * x` is `x ^ 2`
+        if remove_abs.removed {
+            Simplify.visit(expr);
+        }
+    }
+}
 
-        // simplify any newly created ast nodes
+/// Replaces the absolute values of nonnegative expressions with their argument.
+struct RemoveAbs<'a> {
+    assumptions: &'a Assumptions,
+    removed: bool,
+}
+
+impl ExprVisitor for RemoveAbs<'_> {
+    fn visit(&mut self, expr: &mut Expr) {
         walk_expr(expr, self);
+        if let Expr::Call { func, args } = expr {
+            if func == "abs" && args.len() == 1 && self.assumptions.is_nonnegative(&args[0]) {
+                *expr = args.pop().expect("abs should have an argument");
+                self.removed = true;
+            }
+        }
+    }
+}
+
+/// Same as [`Simplify`] but also applies the extra `rules`, e.g. rules defined by the user.
+pub struct SimplifyWith<'a>(pub &'a RuleTransformSet<'a>);
+
+impl ExprVisitor for SimplifyWith<'_> {
+    fn visit(&mut self, expr: &mut Expr) {
+        walk_expr(expr, self);
+        if has_undefined_operand(expr) {
+            *expr = Expr::Undefined;
+            return;
+        }
+
+        SIMPLIFY_TRANSFORMS.apply_rules_incremental(expr);
+        if let Some(rewritten) = self.0.try_apply_rules(expr) {
+            *expr = rewritten;
+            // the extra rules are not applied again to avoid looping with the built-in rules
+            Simplify.visit(expr);
+        }
     }
 }
 
@@ -113,12 +499,221 @@ mod tests {
     use crate::parser::Parser;
     use logos::Logos;
 
+    #[test]
+    fn test_apply_rule_at() {
+        let expr = Parser::from(Token::lexer("-(-x) + -(-y)").spanned()).parse();
+        let res = apply_rule_at(&expr, "double negation", &[1]).unwrap();
+        assert_eq!(res.to_string(), "((-(-x)) + y)");
+        assert!(rules()
+            .iter()
+            .any(|rule| rule.name() == Some("compound fraction")));
+    }
+
     #[test]
     fn test_constant_fold() {
-        let mut expr = Parser::from(Token::lexer("0 + 2 * x")).parse();
+        let mut expr = Parser::from(Token::lexer("0 + 2 * x").spanned()).parse();
+        Simplify.visit(&mut expr);
+
+        let expected = Parser::from(Token::lexer("2 * x").spanned()).parse();
+        assert_eq!(expr, expected);
+    }
+
+    #[test]
+    fn test_simplify_rewritten_nodes() {
+        // `x ^ (2 + 1)` is created by a rewrite and must be simplified again
+        let mut expr = Parser::from(Token::lexer("x * x * x").spanned()).parse();
         Simplify.visit(&mut expr);
 
-        let expected = Parser::from(Token::lexer("2 * x")).parse();
+        let expected = Parser::from(Token::lexer("x ^ 3").spanned()).parse();
         assert_eq!(expr, expected);
+
+        // `x ^ (0.5 * 2)` is `x ^ 1` once its exponent is folded, which is simplified again
+        let mut expr = Parser::from(Token::lexer("(x ^ 0.5) ^ 2").spanned()).parse();
+        Simplify.visit(&mut expr);
+        assert_eq!(expr.to_string(), "x");
+    }
+
+    #[test]
+    fn test_exact_integer_fold() {
+        let check = |input: &str, expected: &str| {
+            let mut expr = Parser::from(Token::lexer(input).spanned()).parse();
+            Simplify.visit(&mut expr);
+            assert_eq!(expr.to_string(), expected);
+        };
+        check("6 / 4", "(3 / 2)");
+        check("-6 / 4", "((-3) / 2)");
+        check("6 / -4", "((-3) / 2)");
+        check("8 / 4", "2");
+        check("1 / 3 + 1", "(4 / 3)");
+        check("1 / 3 + 1 / 6", "(1 / 2)");
+        check("1 / 3 * 6", "2");
+        check("0.5 + 0.25", "0.75");
+        check("2 ^ -1", "(1 / 2)");
+        check("2 ^ -3 * 3", "(3 / 8)");
+        check("(-2) ^ -3", "((-1) / 8)");
+        check("1 ^ -5", "1");
+        check("0 ^ -1", "(0 ^ (-1))");
+        check("0.5 ^ -1", "2");
+        check("10 ^ 15 + 1", "1000000000000001");
+        // not exactly representable
+        check("10 ^ 17 + 1", "(1 + (10 ^ 17))");
+        // only the rounding error of folding is removed, not the fraction of a large value
+        check("0.1 * 3 * 10", "3");
+        check("0.5 + 1000000000000", "1000000000000.5");
+        check("0.5 + 20000000000000", "20000000000000.5");
+    }
+
+    #[test]
+    fn test_power_of_power() {
+        let mut expr = Parser::from(Token::lexer("(x ^ 2) ^ 3").spanned()).parse();
+        Simplify.visit(&mut expr);
+        assert_eq!(expr, Parser::from(Token::lexer("x ^ 6").spanned()).parse());
+
+        // (x ^ 2) ^ 0.5 is abs(x), not x
+        let mut expr = Parser::from(Token::lexer("(x ^ 2) ^ 0.5").spanned()).parse();
+        Simplify.visit(&mut expr);
+        assert_eq!(expr.to_string(), "abs(x)");
+    }
+
+    #[test]
+    fn test_even_powers() {
+        let simplify = |input: &str, assumptions: &Assumptions| {
+            let mut expr = Parser::from(Token::lexer(input).spanned()).parse();
+            SimplifyAssuming(assumptions).visit(&mut expr);
+            expr.to_string()
+        };
+        let none = Assumptions::new();
+        let cases = [
+            ("sqrt(x ^ 2)", "abs(x)"),
+            ("(x ^ 2) ^ (1 / 2)", "abs(x)"),
+            ("sqrt(x ^ 4)", "(x ^ 2)"),
+            ("(x ^ 6) ^ (1 / 3)", "(x ^ 2)"),
+            ("(x ^ 2) ^ 0.25", "(abs(x) ^ 0.5)"),
+            ("sqrt(x ^ 3)", "((x ^ 3) ^ 0.5)"),
+            ("abs(x) ^ 2", "(x ^ 2)"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(simplify(input, &none), expected, "input: {}", input);
+        }
+
+        let nonnegative = Assumptions::new().with_nonnegative("x");
+        assert_eq!(simplify("sqrt(x ^ 2)", &nonnegative), "x");
+        assert_eq!(simplify("(x ^ 2) ^ 0.25", &nonnegative), "(x ^ 0.5)");
+        assert_eq!(
+            simplify("sqrt(y ^ 2) * abs(x + 1)", &nonnegative),
+            "(abs(y) * (1 + x))"
+        );
+        assert_eq!(simplify("sqrt(x ^ 2) * x", &nonnegative), "(x ^ 2)");
+    }
+
+    #[test]
+    fn test_nested_negation() {
+        let cases = [
+            ("-(-x)", "x"),
+            ("-(-(-x))", "(-x)"),
+            ("-(-(-(-x)))", "x"),
+            ("-(a * -b)", "(a * b)"),
+            ("-((-a) * b)", "(a * b)"),
+        ];
+        for (input, expected) in cases {
+            let mut expr = Parser::from(Token::lexer(input).spanned()).parse();
+            Simplify.visit(&mut expr);
+            assert_eq!(expr.to_string(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_negated_literals() {
+        // the parser keeps `-(2)` as written
+        let cases = [
+            ("-(2)", "(-2)"),
+            ("x * -(2)", "((-2) * x)"),
+            ("-(3) ^ 2", "9"),
+        ];
+        for (input, expected) in cases {
+            let mut expr = Parser::from(Token::lexer(input).spanned()).parse();
+            Simplify.visit(&mut expr);
+            assert_eq!(expr.to_string(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_compound_fractions() {
+        let cases = [
+            ("(a / b) / c", "(a / (b * c))"),
+            ("a / (b / c)", "((a * c) / b)"),
+            ("(a / b) / (c / d)", "((a * d) / (b * c))"),
+        ];
+        for (input, expected) in cases {
+            let mut expr = Parser::from(Token::lexer(input).spanned()).parse();
+            Simplify.visit(&mut expr);
+            assert_eq!(expr.to_string(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_non_finite() {
+        let simplify = |input: &str| {
+            let mut expr = Parser::from(Token::lexer(input).spanned()).parse();
+            Simplify.visit(&mut expr);
+            expr
+        };
+        // kept as written rather than folded to infinity or NaN
+        assert_eq!(simplify("1 / 0").to_string(), "(1 / 0)");
+        assert_eq!(simplify("0.5 / 0").to_string(), "(0.5 / 0)");
+        assert_eq!(simplify("0 ^ -1").to_string(), "(0 ^ (-1))");
+        assert_eq!(simplify("10 ^ 400").to_string(), "(10 ^ 400)");
+        // not cancelled with itself
+        assert_eq!(simplify("0 / 0").to_string(), "(0 / 0)");
+        assert_eq!(simplify("(1 - 1) / (1 - 1)").to_string(), "(0 / 0)");
+        assert_eq!(simplify("1 / 0 - 1 / 0").to_string(), "((1 / 0) - (1 / 0))");
+        assert_eq!(simplify("x / x").to_string(), "1");
+        // not dropped
+        assert_eq!(simplify("(x / 0) * 0").to_string(), "(0 * (x / 0))");
+        assert_eq!(simplify("0 * (1 / 0)").to_string(), "(0 / 0)");
+        assert_eq!(simplify("(1 / 0) ^ 0").to_string(), "((1 / 0) ^ 0)");
+        assert_eq!(simplify("1 ^ (x / 0)").to_string(), "(1 ^ (x / 0))");
+        assert_eq!(simplify("(x / y) * y").to_string(), "x");
+        assert!(is_undefined_operation(&simplify("1 / (2 - 2)")));
+        assert!(is_undefined_operation(&simplify("(-8) ^ 0.5")));
+        assert!(is_undefined_operation(&simplify("x / 0")));
+        assert!(!is_undefined_operation(&simplify("2 ^ 0.5")));
+
+        // undefined values propagate
+        assert_eq!(simplify("undefined - undefined"), Expr::Undefined);
+        assert_eq!(simplify("abs(x * undefined)"), Expr::Undefined);
+        let mut expr = Parser::from(Token::lexer("x + 0 * (1 / 0)").spanned()).parse();
+        FoldUndefined.visit(&mut expr);
+        assert_eq!(expr, Expr::Undefined);
+    }
+
+    #[test]
+    fn test_simplify_functions() {
+        let mut expr = Parser::from(Token::lexer("abs(abs(-x))").spanned()).parse();
+        Simplify.visit(&mut expr);
+
+        let expected = Parser::from(Token::lexer("abs(x)").spanned()).parse();
+        assert_eq!(expr, expected);
+
+        let cases = [
+            ("|-3|", "3"),
+            ("abs(-1 / 2)", "(1 / 2)"),
+            ("|-(x + 1)|", "abs(1 + x)"),
+            ("|-2 * x|", "(2 * abs(x))"),
+            ("e ^ 1 + sin(pi)", "e"),
+            ("x * cos(π)", "((-1) * x)"),
+            ("sin(2 * pi)", "sin(2 * pi)"),
+            ("e ^ x * ln(e)", "(e ^ x)"),
+        ];
+        for (input, expected) in cases {
+            let mut expr = Parser::from(Token::lexer(input).spanned()).parse();
+            Simplify.visit(&mut expr);
+            assert_eq!(expr.to_string(), expected, "input: {}", input);
+        }
+
+        let mut expr =
+            Parser::from(Token::lexer("sin(asin(2 * x)) - tan(atan(x))").spanned()).parse();
+        Simplify.visit(&mut expr);
+        assert_eq!(expr.to_string(), "((2 * x) - x)");
     }
 }